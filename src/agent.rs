@@ -0,0 +1,317 @@
+use serde::{Deserialize, Serialize};
+
+use crate::output::{Output, Tabular};
+use crate::serde_util::deserialize_flag;
+
+/// Reply of the guest agent's `network-get-interfaces` command.
+#[derive(Debug, Deserialize)]
+pub struct NetworkInterfaces {
+    pub result: Vec<NetworkInterface>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NetworkInterface {
+    pub name: String,
+    #[serde(rename = "hardware-address", default)]
+    pub hardware_address: Option<String>,
+    #[serde(rename = "ip-addresses", default)]
+    pub ip_addresses: Vec<IpAddress>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IpAddress {
+    #[serde(rename = "ip-address-type")]
+    pub family: String,
+    #[serde(rename = "ip-address")]
+    pub address: String,
+    pub prefix: Option<u8>,
+}
+
+/// An interface of a container, from the `lxc/{vmid}/interfaces` endpoint.
+#[derive(Debug, Deserialize)]
+pub struct ContainerInterface {
+    pub name: String,
+    #[serde(default)]
+    pub hwaddr: Option<String>,
+    /// Address with prefix, e.g. `10.0.0.5/24`.
+    #[serde(default)]
+    pub inet: Option<String>,
+    #[serde(default)]
+    pub inet6: Option<String>,
+}
+
+/// One address of a guest interface.
+#[derive(Debug, Serialize)]
+pub struct InterfaceAddress {
+    pub interface: String,
+    pub mac: Option<String>,
+    pub family: String,
+    /// Address with prefix, e.g. `10.0.0.5/24`.
+    pub address: String,
+}
+
+impl Tabular for InterfaceAddress {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("interface", "Interface"),
+            ("mac", "MAC"),
+            ("family", "Family"),
+            ("address", "Address"),
+        ]
+    }
+
+    fn row(&self, _output: &Output) -> Vec<String> {
+        vec![
+            self.interface.clone(),
+            self.mac.clone().unwrap_or_else(|| "-".to_string()),
+            self.family.clone(),
+            self.address.clone(),
+        ]
+    }
+}
+
+impl InterfaceAddress {
+    pub fn is_loopback(&self) -> bool {
+        self.interface == "lo" || self.address.starts_with("127.") || self.address == "::1/128"
+    }
+}
+
+/// Flattens the agent's interface list into one entry per address.
+pub fn vm_addresses(interfaces: NetworkInterfaces) -> Vec<InterfaceAddress> {
+    interfaces
+        .result
+        .into_iter()
+        .flat_map(|interface| {
+            let NetworkInterface {
+                name,
+                hardware_address,
+                ip_addresses,
+            } = interface;
+            ip_addresses.into_iter().map(move |ip| InterfaceAddress {
+                interface: name.clone(),
+                mac: hardware_address.clone(),
+                family: ip.family,
+                address: match ip.prefix {
+                    Some(prefix) => format!("{}/{}", ip.address, prefix),
+                    None => ip.address,
+                },
+            })
+        })
+        .collect()
+}
+
+/// Flattens the interface list of a container into one entry per address.
+pub fn container_addresses(interfaces: Vec<ContainerInterface>) -> Vec<InterfaceAddress> {
+    interfaces
+        .into_iter()
+        .flat_map(|interface| {
+            let ipv4 = interface.inet.map(|a| ("ipv4", a));
+            let ipv6 = interface.inet6.map(|a| ("ipv6", a));
+            let (name, mac) = (interface.name, interface.hwaddr);
+            ipv4.into_iter()
+                .chain(ipv6)
+                .map(move |(family, address)| InterfaceAddress {
+                    interface: name.clone(),
+                    mac: mac.clone(),
+                    family: family.to_string(),
+                    address,
+                })
+        })
+        .collect()
+}
+
+/// Reply of the guest agent's `info` command.
+#[derive(Debug, Deserialize)]
+pub struct AgentInfo {
+    pub result: AgentInfoResult,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AgentInfoResult {
+    pub version: String,
+    #[serde(default)]
+    pub supported_commands: Vec<SupportedCommand>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SupportedCommand {
+    pub name: String,
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+impl AgentInfoResult {
+    /// Whether the agent can freeze file systems, which consistent
+    /// snapshots of running VMs rely on.
+    pub fn supports_fsfreeze(&self) -> bool {
+        ["guest-fsfreeze-freeze", "guest-fsfreeze-thaw"]
+            .iter()
+            .all(|command| {
+                self.supported_commands
+                    .iter()
+                    .any(|c| c.name == *command && c.enabled)
+            })
+    }
+}
+
+/// Reply of `agent/exec`, identifying the started process.
+#[derive(Debug, Deserialize)]
+pub struct ExecStarted {
+    pub pid: u64,
+}
+
+/// Reply of `agent/exec-status`.
+#[derive(Debug, Deserialize)]
+pub struct ExecStatus {
+    #[serde(deserialize_with = "deserialize_flag")]
+    pub exited: bool,
+    #[serde(default)]
+    pub exitcode: Option<i32>,
+    /// Signal that killed the process, if any.
+    #[serde(default)]
+    pub signal: Option<i32>,
+    #[serde(rename = "out-data", default)]
+    pub out_data: Option<String>,
+    #[serde(rename = "err-data", default)]
+    pub err_data: Option<String>,
+    #[serde(
+        rename = "out-truncated",
+        default,
+        deserialize_with = "deserialize_flag"
+    )]
+    pub out_truncated: bool,
+    #[serde(
+        rename = "err-truncated",
+        default,
+        deserialize_with = "deserialize_flag"
+    )]
+    pub err_truncated: bool,
+}
+
+/// Reply of `agent/file-read`.
+#[derive(Debug, Deserialize)]
+pub struct FileContent {
+    pub content: String,
+    /// Set when the file exceeded what the agent returns at once (16 MiB).
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    pub truncated: bool,
+}
+
+/// Outcome of a command run in a guest.
+#[derive(Debug, Serialize)]
+pub struct ExecResult {
+    pub exitcode: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signal: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    /// Whether the agent cut the output short.
+    pub truncated: bool,
+}
+
+impl From<ExecStatus> for ExecResult {
+    fn from(status: ExecStatus) -> Self {
+        Self {
+            // Shells report a process killed by signal N as 128 + N.
+            exitcode: status
+                .exitcode
+                .or(status.signal.map(|signal| 128 + signal))
+                .unwrap_or(0),
+            signal: status.signal,
+            stdout: status.out_data.unwrap_or_default(),
+            stderr: status.err_data.unwrap_or_default(),
+            truncated: status.out_truncated || status.err_truncated,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vm_addresses() {
+        let interfaces: NetworkInterfaces = serde_json::from_value(serde_json::json!({
+            "result": [
+                {
+                    "name": "lo",
+                    "hardware-address": "00:00:00:00:00:00",
+                    "ip-addresses": [
+                        {"ip-address-type": "ipv4", "ip-address": "127.0.0.1", "prefix": 8}
+                    ]
+                },
+                {
+                    "name": "eth0",
+                    "hardware-address": "bc:24:11:aa:bb:cc",
+                    "ip-addresses": [
+                        {"ip-address-type": "ipv4", "ip-address": "10.0.0.5", "prefix": 24},
+                        {"ip-address-type": "ipv6", "ip-address": "fe80::1", "prefix": 64}
+                    ]
+                }
+            ]
+        }))
+        .unwrap();
+
+        let addresses = vm_addresses(interfaces);
+        assert_eq!(addresses.len(), 3);
+        assert!(addresses[0].is_loopback());
+        assert_eq!(addresses[1].interface, "eth0");
+        assert_eq!(addresses[1].address, "10.0.0.5/24");
+        assert_eq!(addresses[2].family, "ipv6");
+    }
+
+    #[test]
+    fn test_supports_fsfreeze() {
+        let info: AgentInfo = serde_json::from_value(serde_json::json!({
+            "result": {
+                "version": "8.1.3",
+                "supported_commands": [
+                    {"name": "guest-ping", "enabled": true},
+                    {"name": "guest-fsfreeze-freeze", "enabled": true},
+                    {"name": "guest-fsfreeze-thaw", "enabled": false}
+                ]
+            }
+        }))
+        .unwrap();
+        assert!(!info.result.supports_fsfreeze());
+    }
+
+    #[test]
+    fn test_exec_result() {
+        let status: ExecStatus = serde_json::from_value(serde_json::json!({
+            "exited": 1,
+            "exitcode": 3,
+            "out-data": "stopped\n",
+        }))
+        .unwrap();
+        let result = ExecResult::from(status);
+        assert_eq!(result.exitcode, 3);
+        assert_eq!(result.stdout, "stopped\n");
+        assert!(!result.truncated);
+
+        let killed: ExecStatus =
+            serde_json::from_value(serde_json::json!({"exited": true, "signal": 9})).unwrap();
+        assert_eq!(ExecResult::from(killed).exitcode, 137);
+    }
+
+    #[test]
+    fn test_container_addresses() {
+        let interfaces: Vec<ContainerInterface> = serde_json::from_value(serde_json::json!([
+            {"name": "lo", "hwaddr": "00:00:00:00:00:00", "inet": "127.0.0.1/8", "inet6": "::1/128"},
+            {"name": "eth0", "hwaddr": "bc:24:11:aa:bb:cc", "inet": "10.0.0.6/24"}
+        ]))
+        .unwrap();
+
+        let addresses = container_addresses(interfaces);
+        let external: Vec<&str> = addresses
+            .iter()
+            .filter(|a| !a.is_loopback())
+            .map(|a| a.address.as_str())
+            .collect();
+        assert_eq!(external, vec!["10.0.0.6/24"]);
+    }
+}
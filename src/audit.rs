@@ -0,0 +1,81 @@
+use crate::duration::format_duration;
+
+/// Limits every VM is expected to respect.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AuditLimits {
+    /// Maximum age in seconds of the newest snapshot.
+    pub max_age: Option<u64>,
+    pub max_count: Option<usize>,
+}
+
+impl AuditLimits {
+    pub fn is_empty(&self) -> bool {
+        self.max_age.is_none() && self.max_count.is_none()
+    }
+}
+
+/// Returns a description of each limit a VM violates.
+pub fn violations(
+    snapshot_count: usize,
+    newest_age: Option<u64>,
+    limits: &AuditLimits,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if let Some(max_age) = limits.max_age {
+        match newest_age {
+            None => problems.push("no snapshots".to_string()),
+            Some(age) if age > max_age => problems.push(format!(
+                "newest snapshot is {} old (max {})",
+                format_duration(age),
+                format_duration(max_age)
+            )),
+            Some(_) => {}
+        }
+    }
+
+    if let Some(max_count) = limits.max_count
+        && snapshot_count > max_count
+    {
+        problems.push(format!("{} snapshots (max {})", snapshot_count, max_count));
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 86400;
+
+    fn limits() -> AuditLimits {
+        AuditLimits {
+            max_age: Some(7 * DAY),
+            max_count: Some(5),
+        }
+    }
+
+    #[test]
+    fn test_compliant_vm() {
+        assert!(violations(3, Some(DAY), &limits()).is_empty());
+    }
+
+    #[test]
+    fn test_stale_and_too_many() {
+        let problems = violations(8, Some(10 * DAY), &limits());
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("newest snapshot is 10d 0h 0m old"));
+        assert_eq!(problems[1], "8 snapshots (max 5)");
+    }
+
+    #[test]
+    fn test_no_snapshots() {
+        assert_eq!(violations(0, None, &limits()), vec!["no snapshots"]);
+        let count_only = AuditLimits {
+            max_age: None,
+            max_count: Some(5),
+        };
+        assert!(violations(0, None, &count_only).is_empty());
+    }
+}
@@ -0,0 +1,114 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::client::ProxmoxClient;
+use crate::cluster::ClusterManager;
+use crate::output::Output;
+use crate::tasks::TaskManager;
+
+/// Starts vzdump backups of guests.
+pub struct BackupManager {
+    client: ProxmoxClient,
+    cluster: ClusterManager,
+    tasks: TaskManager,
+    output: Output,
+}
+
+/// How vzdump gets a consistent copy of a running guest.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupMode {
+    /// Back up the running guest, using a snapshot of its disks.
+    #[default]
+    Snapshot,
+    /// Suspend the guest for the duration of the backup.
+    Suspend,
+    /// Shut the guest down and start it again afterwards.
+    Stop,
+}
+
+impl BackupMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BackupMode::Snapshot => "snapshot",
+            BackupMode::Suspend => "suspend",
+            BackupMode::Stop => "stop",
+        }
+    }
+}
+
+/// Parameters of a vzdump run, sent only when set.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BackupOptions {
+    /// Defaults to the storage of the node's vzdump configuration.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage: Option<String>,
+    pub mode: BackupMode,
+    /// Notes of the backup, which may use `{{guestname}}`, `{{vmid}}`,
+    /// `{{node}}` and `{{cluster}}`.
+    #[serde(rename = "notes-template", skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+}
+
+impl BackupManager {
+    pub fn new(client: ProxmoxClient, output: Output) -> Self {
+        let cluster = ClusterManager::new(client.clone(), output.clone());
+        let tasks = TaskManager::new(client.clone(), output.clone());
+        Self {
+            client,
+            cluster,
+            tasks,
+            output,
+        }
+    }
+
+    /// Backs a guest up with vzdump, printing the task log as it runs.
+    pub async fn run(&self, vm_identifier: &str, options: &BackupOptions) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let mut data = serde_json::to_value(options)?;
+        data["vmid"] = guest.vmid.into();
+        let task_id: String = self
+            .client
+            .post(&format!("/nodes/{}/vzdump", guest.node), &data)
+            .await?;
+        self.output.print_raw(&task_id)?;
+        self.output.message(&format!(
+            "Backing up VM {} on node {} to {} in {} mode...",
+            guest.vmid,
+            guest.node,
+            options.storage.as_deref().map_or_else(
+                || "the default storage".to_string(),
+                |s| format!("storage {}", s)
+            ),
+            options.mode.as_str()
+        ));
+        self.tasks.follow(&guest.node, &task_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backup_options_serialization() {
+        let options = BackupOptions {
+            storage: Some("pbs".to_string()),
+            mode: BackupMode::Suspend,
+            notes: Some("{{guestname}} before upgrade".to_string()),
+        };
+        assert_eq!(
+            serde_json::to_value(options).unwrap(),
+            serde_json::json!({
+                "storage": "pbs",
+                "mode": "suspend",
+                "notes-template": "{{guestname}} before upgrade"
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(BackupOptions::default()).unwrap(),
+            serde_json::json!({ "mode": "snapshot" })
+        );
+    }
+}
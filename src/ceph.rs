@@ -0,0 +1,357 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::output::{Color, Output, Tabular};
+use crate::serde_util::deserialize_flag;
+
+/// The parts of `/nodes/{node}/ceph/status` shown by `ceph status`.
+#[derive(Debug, Deserialize)]
+pub struct CephStatus {
+    pub health: Health,
+    #[serde(default)]
+    pub osdmap: Option<OsdMapField>,
+    #[serde(default)]
+    pub pgmap: PgMap,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Health {
+    /// `HEALTH_OK`, `HEALTH_WARN` or `HEALTH_ERR`.
+    pub status: String,
+    #[serde(default)]
+    pub checks: BTreeMap<String, HealthCheck>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HealthCheck {
+    pub severity: String,
+    #[serde(default)]
+    pub summary: Summary,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Summary {
+    #[serde(default)]
+    pub message: String,
+}
+
+/// Ceph before Quincy nests the OSD map counters one level deeper.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum OsdMapField {
+    Nested { osdmap: OsdMap },
+    Flat(OsdMap),
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize)]
+pub struct OsdMap {
+    #[serde(default)]
+    pub num_osds: u32,
+    #[serde(default)]
+    pub num_up_osds: u32,
+    #[serde(default)]
+    pub num_in_osds: u32,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PgMap {
+    #[serde(default)]
+    pub num_pgs: u32,
+    #[serde(default)]
+    pub pgs_by_state: Vec<PgState>,
+    #[serde(default)]
+    pub bytes_used: u64,
+    #[serde(default)]
+    pub bytes_total: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PgState {
+    pub state_name: String,
+    pub count: u32,
+}
+
+/// A health check raised by Ceph, e.g. `OSD_DOWN`.
+#[derive(Debug, Serialize)]
+pub struct CephWarning {
+    pub check: String,
+    pub severity: String,
+    pub message: String,
+}
+
+impl Tabular for CephWarning {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("check", "Check"),
+            ("severity", "Severity"),
+            ("message", "Message"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        vec![
+            self.check.clone(),
+            output.paint(severity_color(&self.severity), &self.severity),
+            self.message.clone(),
+        ]
+    }
+}
+
+/// A node of the tree returned by `/nodes/{node}/ceph/osd`: the root, a
+/// host or an OSD.
+#[derive(Debug, Deserialize)]
+pub struct OsdTreeNode {
+    #[serde(default)]
+    pub name: String,
+    #[serde(rename = "type", default)]
+    pub node_type: String,
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(rename = "in", default, deserialize_with = "deserialize_flag")]
+    pub is_in: bool,
+    #[serde(default)]
+    pub device_class: Option<String>,
+    /// Percentage of the OSD used, 0 to 100.
+    #[serde(default)]
+    pub percent_used: Option<f64>,
+    #[serde(default)]
+    pub total_space: Option<u64>,
+    #[serde(default)]
+    pub children: Vec<OsdTreeNode>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OsdTree {
+    pub root: OsdTreeNode,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Osd {
+    pub name: String,
+    pub host: String,
+    pub up: bool,
+    #[serde(rename = "in")]
+    pub is_in: bool,
+    pub device_class: Option<String>,
+    pub percent_used: Option<f64>,
+    pub total_space: Option<u64>,
+}
+
+impl Tabular for Osd {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("name", "OSD"),
+            ("host", "Host"),
+            ("status", "Status"),
+            ("device_class", "Class"),
+            ("total_space", "Size"),
+            ("percent_used", "Used"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        let status = match (self.up, self.is_in) {
+            (true, true) => "up, in".to_string(),
+            (true, false) => output.paint(Color::Yellow, "up, out"),
+            (false, is_in) => {
+                output.paint(Color::Red, if is_in { "down, in" } else { "down, out" })
+            }
+        };
+        vec![
+            self.name.clone(),
+            self.host.clone(),
+            status,
+            self.device_class.clone().unwrap_or_default(),
+            self.total_space
+                .map_or_else(|| "-".to_string(), |size| output.format_bytes(size)),
+            self.percent_used
+                .map_or_else(|| "-".to_string(), |used| format!("{:.0}%", used)),
+        ]
+    }
+}
+
+/// A pool from `/nodes/{node}/ceph/pool`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CephPool {
+    pub pool_name: String,
+    #[serde(default)]
+    pub size: u32,
+    #[serde(default)]
+    pub min_size: u32,
+    #[serde(default)]
+    pub pg_num: u32,
+    #[serde(default)]
+    pub bytes_used: u64,
+    /// Fraction of the pool's capacity used, 0 to 1.
+    #[serde(default)]
+    pub percent_used: f64,
+}
+
+impl Tabular for CephPool {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("pool_name", "Pool"),
+            ("size", "Size/min"),
+            ("pg_num", "PGs"),
+            ("bytes_used", "Used"),
+            ("percent_used", "Usage"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        vec![
+            self.pool_name.clone(),
+            format!("{}/{}", self.size, self.min_size),
+            self.pg_num.to_string(),
+            output.format_bytes(self.bytes_used),
+            format!("{:.1}%", self.percent_used * 100.0),
+        ]
+    }
+}
+
+/// Everything `ceph status` prints, in one document for structured output.
+#[derive(Debug, Serialize)]
+pub struct CephOverview {
+    pub node: String,
+    pub health: String,
+    pub checks: Vec<CephWarning>,
+    pub osdmap: OsdMap,
+    pub num_pgs: u32,
+    pub pgs_by_state: Vec<PgState>,
+    pub bytes_used: u64,
+    pub bytes_total: u64,
+    pub osds: Vec<Osd>,
+    pub pools: Vec<CephPool>,
+}
+
+impl CephOverview {
+    pub fn new(node: String, status: CephStatus, tree: OsdTree, pools: Vec<CephPool>) -> Self {
+        let osdmap = match status.osdmap {
+            Some(OsdMapField::Nested { osdmap }) | Some(OsdMapField::Flat(osdmap)) => osdmap,
+            None => OsdMap::default(),
+        };
+        let checks = status
+            .health
+            .checks
+            .into_iter()
+            .map(|(check, c)| CephWarning {
+                check,
+                severity: c.severity,
+                message: c.summary.message,
+            })
+            .collect();
+        let mut osds = Vec::new();
+        flatten_osds(&tree.root, "", &mut osds);
+        Self {
+            node,
+            health: status.health.status,
+            checks,
+            osdmap,
+            num_pgs: status.pgmap.num_pgs,
+            pgs_by_state: status.pgmap.pgs_by_state,
+            bytes_used: status.pgmap.bytes_used,
+            bytes_total: status.pgmap.bytes_total,
+            osds,
+            pools,
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.health == "HEALTH_OK"
+    }
+}
+
+pub fn severity_color(severity: &str) -> Color {
+    match severity {
+        "HEALTH_OK" => Color::Green,
+        "HEALTH_WARN" => Color::Yellow,
+        _ => Color::Red,
+    }
+}
+
+/// Collects the OSDs of the tree, each with the host it sits under.
+fn flatten_osds(node: &OsdTreeNode, host: &str, osds: &mut Vec<Osd>) {
+    if node.node_type == "osd" {
+        osds.push(Osd {
+            name: node.name.clone(),
+            host: host.to_string(),
+            up: node.status.as_deref() == Some("up"),
+            is_in: node.is_in,
+            device_class: node.device_class.clone(),
+            percent_used: node.percent_used,
+            total_space: node.total_space,
+        });
+        return;
+    }
+    let host = if node.node_type == "host" {
+        &node.name
+    } else {
+        host
+    };
+    for child in &node.children {
+        flatten_osds(child, host, osds);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overview() {
+        let status: CephStatus = serde_json::from_value(serde_json::json!({
+            "health": {
+                "status": "HEALTH_WARN",
+                "checks": {
+                    "OSD_DOWN": { "severity": "HEALTH_WARN", "summary": { "message": "1 osds down" } }
+                }
+            },
+            "osdmap": { "osdmap": { "num_osds": 2, "num_up_osds": 1, "num_in_osds": 2 } },
+            "pgmap": { "num_pgs": 128, "pgs_by_state": [{ "state_name": "active+clean", "count": 128 }] }
+        }))
+        .unwrap();
+        let tree: OsdTree = serde_json::from_value(serde_json::json!({
+            "root": { "name": "default", "type": "root", "children": [
+                { "name": "pve1", "type": "host", "children": [
+                    { "name": "osd.0", "type": "osd", "status": "up", "in": 1 },
+                    { "name": "osd.1", "type": "osd", "status": "down", "in": 1 }
+                ]}
+            ]}
+        }))
+        .unwrap();
+
+        let overview = CephOverview::new("pve1".to_string(), status, tree, Vec::new());
+        assert!(!overview.is_healthy());
+        assert_eq!(overview.checks[0].check, "OSD_DOWN");
+        assert_eq!(overview.checks[0].message, "1 osds down");
+        assert_eq!(overview.osdmap.num_up_osds, 1);
+        assert_eq!(overview.osds.len(), 2);
+        assert_eq!(overview.osds[1].host, "pve1");
+        assert!(!overview.osds[1].up);
+    }
+
+    #[test]
+    fn test_flat_osdmap() {
+        let status: CephStatus = serde_json::from_value(serde_json::json!({
+            "health": { "status": "HEALTH_OK" },
+            "osdmap": { "num_osds": 3, "num_up_osds": 3, "num_in_osds": 3 }
+        }))
+        .unwrap();
+        let tree = OsdTree {
+            root: OsdTreeNode {
+                name: "default".to_string(),
+                node_type: "root".to_string(),
+                status: None,
+                is_in: false,
+                device_class: None,
+                percent_used: None,
+                total_space: None,
+                children: Vec::new(),
+            },
+        };
+        let overview = CephOverview::new("pve1".to_string(), status, tree, Vec::new());
+        assert!(overview.is_healthy());
+        assert_eq!(overview.osdmap.num_osds, 3);
+    }
+}
@@ -1,13 +1,22 @@
 use anyhow::Result;
+use futures_util::{StreamExt, stream};
 use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+
+use crate::websocket;
 
 #[derive(Clone)]
 pub struct ProxmoxClient {
     base_url: String,
     token: Option<String>,
     client: reqwest::Client,
+    /// Print POST, PUT and DELETE requests instead of sending them.
+    dry_run: bool,
 }
 
+/// Returned in place of a task UPID by requests skipped in dry-run mode.
+pub const DRY_RUN_UPID: &str = "dry-run";
+
 impl ProxmoxClient {
     pub fn new(host: &str, port: u16, token: Option<String>, verify_ssl: bool) -> Result<Self> {
         let base_url = format!("https://{}:{}/api2/json", host, port);
@@ -20,9 +29,27 @@ impl ProxmoxClient {
             base_url,
             token,
             client,
+            dry_run: false,
         })
     }
 
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Host name or address of the API endpoint in use.
+    pub fn host(&self) -> String {
+        reqwest::Url::parse(&self.base_url)
+            .ok()
+            .and_then(|url| url.host_str().map(String::from))
+            .unwrap_or_default()
+    }
+
     fn parse_host_port(host: &str, default_port: u16) -> (String, u16) {
         if let Some((h, p)) = host.split_once(':') {
             if let Ok(port) = p.parse::<u16>() {
@@ -52,6 +79,7 @@ impl ProxmoxClient {
                 base_url: base_url.clone(),
                 token: token.clone(),
                 client: client.clone(),
+                dry_run: false,
             };
 
             if test_client
@@ -63,72 +91,309 @@ impl ProxmoxClient {
             }
         }
 
-        anyhow::bail!("All hosts failed")
+        Err(ApiError::AllHostsFailed.into())
     }
 
-    pub async fn get<T: for<'de> Deserialize<'de>>(&self, endpoint: &str) -> Result<T> {
+    pub async fn get<T: for<'de> Deserialize<'de>>(&self, endpoint: &str) -> Result<T, ApiError> {
         let url = format!("{}{}", self.base_url, endpoint);
-        let mut request = self.client.get(&url);
+        self.execute(endpoint, self.client.get(&url)).await
+    }
 
-        if let Some(ref token) = self.token {
-            request = request.header("Authorization", format!("PVEAPIToken={}", token));
+    pub async fn post<T: Serialize, R: for<'de> Deserialize<'de>>(
+        &self,
+        endpoint: &str,
+        data: &T,
+    ) -> Result<R, ApiError> {
+        if self.dry_run {
+            return Self::skip("POST", endpoint, Some(data));
         }
+        let url = format!("{}{}", self.base_url, endpoint);
+        self.execute(endpoint, self.client.post(&url).form(data))
+            .await
+    }
 
-        let response = request.send().await?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text().await?;
-            anyhow::bail!("API request failed with status {}: {}", status, text);
+    pub async fn put<T: Serialize, R: for<'de> Deserialize<'de>>(
+        &self,
+        endpoint: &str,
+        data: &T,
+    ) -> Result<R, ApiError> {
+        if self.dry_run {
+            return Self::skip("PUT", endpoint, Some(data));
         }
+        let url = format!("{}{}", self.base_url, endpoint);
+        self.execute(endpoint, self.client.put(&url).form(data))
+            .await
+    }
 
-        let data: ApiResponse<T> = response.json().await?;
-        Ok(data.data)
+    pub async fn delete<R: for<'de> Deserialize<'de>>(
+        &self,
+        endpoint: &str,
+    ) -> Result<R, ApiError> {
+        if self.dry_run {
+            return Self::skip::<(), _>("DELETE", endpoint, None);
+        }
+        let url = format!("{}{}", self.base_url, endpoint);
+        self.execute(endpoint, self.client.delete(&url)).await
     }
 
-    pub async fn post<T: Serialize, R: for<'de> Deserialize<'de>>(
+    /// Uploads `file` as a `multipart/form-data` POST: the text `fields`
+    /// first, then the file as the `filename` part, which the API expects
+    /// last. The body is streamed, calling `progress` with the number of
+    /// bytes of the file sent so far.
+    pub async fn upload<R: for<'de> Deserialize<'de>>(
         &self,
         endpoint: &str,
-        data: &T,
-    ) -> Result<R> {
+        fields: &[(&str, &str)],
+        file_name: &str,
+        file: tokio::fs::File,
+        size: u64,
+        mut progress: impl FnMut(u64) + Send + 'static,
+    ) -> Result<R, ApiError> {
+        if self.dry_run {
+            let mut data: std::collections::BTreeMap<&str, &str> = fields.iter().copied().collect();
+            data.insert("filename", file_name);
+            return Self::skip("POST", endpoint, Some(&data));
+        }
+
+        let boundary = format!("pve-tool-{:016x}", fastrand::u64(..));
+        let (head, tail) = multipart_parts(&boundary, fields, file_name);
+        let length = head.len() as u64 + size + tail.len() as u64;
+
+        let mut sent = 0;
+        let file = stream::unfold((file, vec![0; 1 << 20]), |(mut file, mut buffer)| async {
+            match file.read(&mut buffer).await {
+                Ok(0) => None,
+                Ok(read) => Some((Ok(buffer[..read].to_vec()), (file, buffer))),
+                Err(err) => Some((Err(err), (file, buffer))),
+            }
+        })
+        .inspect(move |chunk: &std::io::Result<Vec<u8>>| {
+            if let Ok(chunk) = chunk {
+                sent += chunk.len() as u64;
+                progress(sent);
+            }
+        });
+        let body = stream::iter([Ok(head.into_bytes())])
+            .chain(file)
+            .chain(stream::iter([Ok(tail.into_bytes())]));
+
         let url = format!("{}{}", self.base_url, endpoint);
-        let mut request = self.client.post(&url);
+        let request = self
+            .client
+            .post(&url)
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .header(reqwest::header::CONTENT_LENGTH, length)
+            .body(reqwest::Body::wrap_stream(body));
+        self.execute(endpoint, request).await
+    }
 
-        if let Some(ref token) = self.token {
-            request = request.header("Authorization", format!("PVEAPIToken={}", token));
+    /// Opens a websocket on `endpoint`, which carries its query string.
+    pub async fn websocket(&self, endpoint: &str) -> Result<reqwest::Upgraded, ApiError> {
+        let url = format!("{}{}", self.base_url, endpoint);
+        // pveproxy only upgrades HTTP/1.1 connections.
+        let request = self
+            .client
+            .get(&url)
+            .version(reqwest::Version::HTTP_11)
+            .header("Connection", "Upgrade")
+            .header("Upgrade", "websocket")
+            .header("Sec-WebSocket-Version", "13")
+            .header("Sec-WebSocket-Key", websocket::handshake_key())
+            .header("Sec-WebSocket-Protocol", "binary");
+
+        let transport = |source| ApiError::Transport {
+            endpoint: endpoint.to_string(),
+            source,
+        };
+        let response = self.authorize(request).send().await.map_err(transport)?;
+        if response.status() != reqwest::StatusCode::SWITCHING_PROTOCOLS {
+            return Err(Self::status_error(endpoint, response).await);
         }
+        response.upgrade().await.map_err(transport)
+    }
 
-        let response = request.form(data).send().await?;
+    /// Prints a request that dry-run mode does not send, and answers it with
+    /// [`DRY_RUN_UPID`].
+    fn skip<T: Serialize, R: for<'de> Deserialize<'de>>(
+        method: &str,
+        endpoint: &str,
+        data: Option<&T>,
+    ) -> Result<R, ApiError> {
+        let payload = data
+            .and_then(|d| serde_json::to_value(d).ok())
+            .filter(|v| v.as_object().is_some_and(|o| !o.is_empty()))
+            .map(|v| format!(" {}", v))
+            .unwrap_or_default();
+        eprintln!("[dry-run] {} {}{}", method, endpoint, payload);
+
+        serde_json::from_value(serde_json::Value::String(DRY_RUN_UPID.to_string())).map_err(|_| {
+            ApiError::DryRun {
+                endpoint: endpoint.to_string(),
+            }
+        })
+    }
+
+    async fn execute<R: for<'de> Deserialize<'de>>(
+        &self,
+        endpoint: &str,
+        request: reqwest::RequestBuilder,
+    ) -> Result<R, ApiError> {
+        let response =
+            self.authorize(request)
+                .send()
+                .await
+                .map_err(|source| ApiError::Transport {
+                    endpoint: endpoint.to_string(),
+                    source,
+                })?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text().await?;
-            anyhow::bail!("API request failed with status {}: {}", status, text);
+            return Err(Self::status_error(endpoint, response).await);
         }
 
-        let result: ApiResponse<R> = response.json().await?;
-        Ok(result.data)
+        let data: ApiResponse<R> = response.json().await.map_err(|source| ApiError::Decode {
+            endpoint: endpoint.to_string(),
+            source,
+        })?;
+        Ok(data.data)
     }
 
-    pub async fn delete(&self, endpoint: &str) -> Result<String> {
-        let url = format!("{}{}", self.base_url, endpoint);
-        let mut request = self.client.delete(&url);
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match self.token {
+            Some(ref token) => request.header("Authorization", format!("PVEAPIToken={}", token)),
+            None => request,
+        }
+    }
 
-        if let Some(ref token) = self.token {
-            request = request.header("Authorization", format!("PVEAPIToken={}", token));
+    async fn status_error(endpoint: &str, response: reqwest::Response) -> ApiError {
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        let message = if text.trim().is_empty() {
+            status
+                .canonical_reason()
+                .unwrap_or("Unknown error")
+                .to_string()
+        } else {
+            text.trim().to_string()
+        };
+        ApiError::Status {
+            endpoint: endpoint.to_string(),
+            status: status.as_u16(),
+            message,
         }
+    }
+}
 
-        let response = request.send().await?;
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("API request to {endpoint} failed with status {status}: {message}")]
+    Status {
+        endpoint: String,
+        status: u16,
+        message: String,
+    },
+    #[error("Request to {endpoint} failed: {source}")]
+    Transport {
+        endpoint: String,
+        #[source]
+        source: reqwest::Error,
+    },
+    #[error("Invalid response from {endpoint}: {source}")]
+    Decode {
+        endpoint: String,
+        #[source]
+        source: reqwest::Error,
+    },
+    #[error("All hosts failed")]
+    AllHostsFailed,
+    #[error("Cannot simulate the response of {endpoint} in dry-run mode")]
+    DryRun { endpoint: String },
+}
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text().await?;
-            anyhow::bail!("API request failed with status {}: {}", status, text);
+impl ApiError {
+    /// Stable category name that automation can branch on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Status { status, .. } => match status {
+                401 | 403 => "auth",
+                404 => "not_found",
+                500.. => "server",
+                _ => "request",
+            },
+            ApiError::Transport { .. } | ApiError::AllHostsFailed => "connection",
+            ApiError::Decode { .. } => "decode",
+            ApiError::DryRun { .. } => "request",
         }
+    }
+
+    pub fn endpoint(&self) -> Option<&str> {
+        match self {
+            ApiError::Status { endpoint, .. }
+            | ApiError::Transport { endpoint, .. }
+            | ApiError::Decode { endpoint, .. }
+            | ApiError::DryRun { endpoint } => Some(endpoint),
+            ApiError::AllHostsFailed => None,
+        }
+    }
+
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            ApiError::Status { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            ApiError::Status { message, .. } => message.clone(),
+            ApiError::Transport { source, .. } | ApiError::Decode { source, .. } => {
+                let mut message = source.to_string();
+                let mut cause = std::error::Error::source(source);
+                while let Some(err) = cause {
+                    message.push_str(&format!(": {}", err));
+                    cause = err.source();
+                }
+                message
+            }
+            ApiError::AllHostsFailed | ApiError::DryRun { .. } => self.to_string(),
+        }
+    }
+}
+
+/// Percent-encodes everything but unreserved URI characters, for query
+/// parameters and for values the API expects URL-encoded.
+pub fn url_encode(input: &str) -> String {
+    input
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
 
-        let result: ApiResponse<String> = response.json().await?;
-        Ok(result.data)
+/// The parts of a `multipart/form-data` body around the file content: the
+/// text `fields` and the header of the file part, then the closing boundary.
+fn multipart_parts(boundary: &str, fields: &[(&str, &str)], file_name: &str) -> (String, String) {
+    let mut head = String::new();
+    for (name, value) in fields {
+        head.push_str(&format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+            boundary, name, value
+        ));
     }
+    head.push_str(&format!(
+        "--{}\r\nContent-Disposition: form-data; name=\"filename\"; filename=\"{}\"\r\n\
+         Content-Type: application/octet-stream\r\n\r\n",
+        boundary,
+        file_name.replace('"', "%22")
+    ));
+    (head, format!("\r\n--{}--\r\n", boundary))
 }
 
 #[derive(Deserialize)]
@@ -140,6 +405,26 @@ struct ApiResponse<T> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_url_encode() {
+        assert_eq!(
+            url_encode("ssh-ed25519 AAAA+/= me@host\n"),
+            "ssh-ed25519%20AAAA%2B%2F%3D%20me%40host%0A"
+        );
+    }
+
+    #[test]
+    fn test_multipart_parts() {
+        let (head, tail) = multipart_parts("b0", &[("content", "iso")], "debian \"12\".iso");
+        assert_eq!(
+            head,
+            "--b0\r\nContent-Disposition: form-data; name=\"content\"\r\n\r\niso\r\n\
+             --b0\r\nContent-Disposition: form-data; name=\"filename\"; filename=\"debian %2212%22.iso\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n"
+        );
+        assert_eq!(tail, "\r\n--b0--\r\n");
+    }
+
     #[test]
     fn test_parse_host_port_with_valid_port() {
         let (host, port) = ProxmoxClient::parse_host_port("192.168.1.1:9000", 8006);
@@ -175,6 +460,34 @@ mod tests {
         assert_eq!(port, 8006);
     }
 
+    #[test]
+    fn test_api_error_codes() {
+        let error = |status| ApiError::Status {
+            endpoint: "/version".to_string(),
+            status,
+            message: String::new(),
+        };
+        assert_eq!(error(401).code(), "auth");
+        assert_eq!(error(403).code(), "auth");
+        assert_eq!(error(404).code(), "not_found");
+        assert_eq!(error(400).code(), "request");
+        assert_eq!(error(500).code(), "server");
+        assert_eq!(error(596).code(), "server");
+        assert_eq!(ApiError::AllHostsFailed.code(), "connection");
+    }
+
+    #[test]
+    fn test_api_error_fields() {
+        let error = ApiError::Status {
+            endpoint: "/nodes".to_string(),
+            status: 403,
+            message: "Permission check failed".to_string(),
+        };
+        assert_eq!(error.endpoint(), Some("/nodes"));
+        assert_eq!(error.status(), Some(403));
+        assert_eq!(error.message(), "Permission check failed");
+    }
+
     #[test]
     fn test_new_creates_correct_base_url() {
         let client = ProxmoxClient::new("192.168.1.100", 8006, None, false).unwrap();
@@ -190,6 +503,36 @@ mod tests {
         assert_eq!(client.token, Some(token.to_string()));
     }
 
+    #[tokio::test]
+    async fn test_dry_run_skips_mutations() {
+        // Nothing listens on port 1, so any request actually sent would fail.
+        let client = ProxmoxClient::new("127.0.0.1", 1, None, false)
+            .unwrap()
+            .with_dry_run(true);
+        let upid: String = client
+            .post("/nodes/pve1/qemu/100/snapshot", &[("snapname", "test")])
+            .await
+            .unwrap();
+        assert_eq!(upid, DRY_RUN_UPID);
+        assert!(
+            client
+                .put::<_, serde_json::Value>(
+                    "/nodes/pve1/qemu/100/snapshot/test/config",
+                    &[("description", "new")]
+                )
+                .await
+                .is_ok()
+        );
+        assert_eq!(
+            client
+                .delete::<String>("/nodes/pve1/qemu/100/snapshot/test")
+                .await
+                .unwrap(),
+            DRY_RUN_UPID
+        );
+        assert!(client.get::<serde_json::Value>("/version").await.is_err());
+    }
+
     #[test]
     fn test_new_with_custom_port() {
         let client = ProxmoxClient::new("10.0.0.1", 9006, None, true).unwrap();
@@ -1,69 +1,994 @@
+use std::io::IsTerminal;
+use std::time::{Duration, Instant};
+
 use anyhow::Result;
-use serde::Deserialize;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
 
-use crate::client::ProxmoxClient;
+use crate::ceph::{self, CephOverview, CephPool, CephStatus, OsdTree};
+use crate::client::{ProxmoxClient, url_encode};
+use crate::disks::{DiskEntry, DiskHealth, SmartData};
+use crate::duration::format_duration;
+use crate::health::{self, ClusterStatusEntry, HaStatusEntry, HealthCheck};
+use crate::nagios::NagiosState;
+use crate::node::{
+    self, AptUpdate, CertificateInfo, NetworkInterface, NodeCertificate, NodePowerCommand,
+    NodeStatus, NodeSubscription, NodeVersions, PackageVersion, PendingUpdate, Repositories,
+    Subscription,
+};
+use crate::output::{Color, Output, OutputFormat, Tabular};
+use crate::prompt;
+use crate::report::{self, StorageResource};
+use crate::sdn::{self, SdnOverview, Vnet, Zone};
+use crate::serde_util::deserialize_flag;
+use crate::top::{Resource, Top};
 
 pub struct ClusterManager {
     client: ProxmoxClient,
+    output: Output,
+}
+
+/// A guest as listed in the cluster resources.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Guest {
+    pub node: String,
+    pub vmid: u32,
+    pub name: Option<String>,
+    #[serde(rename = "type", default)]
+    pub guest_type: String,
+    /// Tags as returned by the API, separated by `;`.
+    #[serde(default)]
+    pub tags: Option<String>,
+    #[serde(default)]
+    pub pool: Option<String>,
+}
+
+impl Guest {
+    /// The API path of the guest, e.g. `/nodes/pve1/qemu/100` or
+    /// `/nodes/pve1/lxc/200` for a container.
+    pub fn path(&self) -> String {
+        format!("/nodes/{}/{}/{}", self.node, self.guest_type, self.vmid)
+    }
+
+    pub fn is_container(&self) -> bool {
+        self.guest_type == "lxc"
+    }
+
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        split_tags(self.tags.as_deref().unwrap_or_default())
+    }
+
+    /// Whether the guest carries at least one of `tags`.
+    pub fn has_any_tag(&self, tags: &[String]) -> bool {
+        self.tags()
+            .any(|tag| tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GuestType {
+    Qemu,
+    Lxc,
+}
+
+impl GuestType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            GuestType::Qemu => "qemu",
+            GuestType::Lxc => "lxc",
+        }
+    }
+}
+
+/// The guests a batch operation applies to.
+#[derive(Debug, Default)]
+pub struct Selection {
+    pub vm: Option<String>,
+    /// Further VMIDs or names, such as a list read from stdin.
+    pub vms: Vec<String>,
+    pub pool: Option<String>,
+    pub tags: Vec<String>,
+    /// Keeps only guests of this type. On its own, selects every guest of
+    /// the type.
+    pub guest_type: Option<GuestType>,
+}
+
+impl Selection {
+    pub fn is_empty(&self) -> bool {
+        self.guest_type.is_none() && !self.names_guests()
+    }
+
+    /// Whether guests are selected by VM, pool or tag, not by type alone.
+    fn names_guests(&self) -> bool {
+        self.vm.is_some() || !self.vms.is_empty() || self.pool.is_some() || !self.tags.is_empty()
+    }
+}
+
+/// Parses a list of VMs piped into pve-tool: one VMID or name per line, as
+/// printed by `jq '.[].vmid'`, or JSON objects and arrays carrying a `vmid`
+/// field, such as the output of `list-vms --output json`. Blank lines and
+/// `#` comments are ignored.
+pub fn parse_vm_list(input: &str) -> Result<Vec<String>> {
+    fn from_json(value: &serde_json::Value) -> Result<Vec<String>> {
+        Ok(match value {
+            serde_json::Value::Array(items) => items
+                .iter()
+                .map(from_json)
+                .collect::<Result<Vec<_>>>()?
+                .concat(),
+            serde_json::Value::Object(object) => match object.get("vmid") {
+                Some(vmid) => from_json(vmid)?,
+                None => anyhow::bail!("JSON object without a vmid field: {}", value),
+            },
+            serde_json::Value::String(s) => vec![s.clone()],
+            serde_json::Value::Number(n) => vec![n.to_string()],
+            other => anyhow::bail!("Not a VM: {}", other),
+        })
+    }
+
+    let trimmed = input.trim_start();
+    if trimmed.starts_with('[') {
+        return from_json(&serde_json::from_str(trimmed)?);
+    }
+
+    let mut vms = Vec::new();
+    for line in input.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('{') || line.starts_with('"') {
+            vms.extend(from_json(&serde_json::from_str(line)?)?);
+        } else {
+            vms.push(line.to_string());
+        }
+    }
+    Ok(vms)
+}
+
+/// Splits a `tags` value. Proxmox writes `;` but also accepts `,` and
+/// spaces.
+pub fn split_tags(tags: &str) -> impl Iterator<Item = &str> {
+    tags.split([';', ',', ' ']).filter(|t| !t.is_empty())
+}
+
+/// Finds a guest by VMID, then by name.
+fn lookup<'a>(guests: &'a [Guest], vm_identifier: &str) -> Option<&'a Guest> {
+    if let Ok(vmid) = vm_identifier.parse::<u32>()
+        && let Some(guest) = guests.iter().find(|g| g.vmid == vmid)
+    {
+        return Some(guest);
+    }
+    guests
+        .iter()
+        .find(|g| g.name.as_deref() == Some(vm_identifier))
+}
+
+#[derive(Deserialize, Serialize)]
+struct NodeEntry {
+    node: String,
+    status: String,
+    #[serde(default)]
+    cpu: Option<f64>,
+    #[serde(default)]
+    maxcpu: Option<u32>,
+    #[serde(default)]
+    mem: Option<u64>,
+    #[serde(default)]
+    maxmem: Option<u64>,
+    #[serde(default)]
+    uptime: Option<u64>,
+}
+
+impl Tabular for NodeEntry {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("node", "Node"),
+            ("status", "Status"),
+            ("cpu", "CPU"),
+            ("mem", "Memory"),
+            ("uptime", "Uptime"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        let cpu = match (self.cpu, self.maxcpu) {
+            (Some(cpu), Some(maxcpu)) => format!("{:.1}% of {}", cpu * 100.0, maxcpu),
+            _ => "-".to_string(),
+        };
+        let memory = match (self.mem, self.maxmem) {
+            (Some(mem), Some(maxmem)) => format!(
+                "{} / {}",
+                output.format_bytes(mem),
+                output.format_bytes(maxmem)
+            ),
+            _ => "-".to_string(),
+        };
+        let uptime = self
+            .uptime
+            .filter(|&uptime| uptime > 0)
+            .map_or_else(|| "-".to_string(), format_duration);
+        vec![self.node.clone(), self.status.clone(), cpu, memory, uptime]
+    }
+}
+
+/// A resource pool with the guests in it, as listed by `pool list`.
+#[derive(Serialize)]
+struct PoolEntry {
+    poolid: String,
+    comment: Option<String>,
+    vmids: Vec<u32>,
+}
+
+impl Tabular for PoolEntry {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[("poolid", "Pool"), ("vmids", "VMs"), ("comment", "Comment")]
+    }
+
+    fn row(&self, _output: &Output) -> Vec<String> {
+        let vmids: Vec<String> = self.vmids.iter().map(u32::to_string).collect();
+        vec![
+            self.poolid.clone(),
+            vmids.join(", "),
+            self.comment.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+/// A storage as seen by one node, from `/nodes/{node}/storage`.
+#[derive(Deserialize, Serialize)]
+struct StorageEntry {
+    #[serde(default)]
+    node: String,
+    storage: String,
+    #[serde(rename = "type")]
+    storage_type: String,
+    /// Comma-separated content kinds, e.g. `images,rootdir`.
+    #[serde(default)]
+    content: String,
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    shared: bool,
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    active: bool,
+    #[serde(default)]
+    total: Option<u64>,
+    #[serde(default)]
+    used: Option<u64>,
+    #[serde(default)]
+    avail: Option<u64>,
+}
+
+impl Tabular for StorageEntry {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("node", "Node"),
+            ("storage", "Storage"),
+            ("type", "Type"),
+            ("content", "Content"),
+            ("shared", "Shared"),
+            ("used", "Used"),
+            ("avail", "Available"),
+            ("total", "Total"),
+            ("usage", "Usage"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        let size =
+            |bytes: Option<u64>| bytes.map_or_else(|| "-".to_string(), |b| output.format_bytes(b));
+        let usage = match (self.used, self.total) {
+            (Some(used), Some(total)) if total > 0 => {
+                format!("{:.0}%", used as f64 * 100.0 / total as f64)
+            }
+            _ if !self.active => output.paint(Color::Red, "inactive"),
+            _ => "-".to_string(),
+        };
+        vec![
+            self.node.clone(),
+            self.storage.clone(),
+            self.storage_type.clone(),
+            self.content.clone(),
+            if self.shared { "yes" } else { "no" }.to_string(),
+            size(self.used),
+            size(self.avail),
+            size(self.total),
+            usage,
+        ]
+    }
 }
 
 impl ClusterManager {
-    pub fn new(client: ProxmoxClient) -> Self {
-        Self { client }
+    pub fn new(client: ProxmoxClient, output: Output) -> Self {
+        Self { client, output }
+    }
+
+    async fn guests(&self) -> Result<Vec<Guest>> {
+        self.client
+            .get("/cluster/resources?type=vm")
+            .await
+            .map_err(Into::into)
+    }
+
+    /// Lists every VM and container in the cluster.
+    pub async fn vms(&self) -> Result<Vec<Guest>> {
+        let mut guests = self.guests().await?;
+        guests.sort_by_key(|g| g.vmid);
+        Ok(guests)
     }
 
-    pub async fn find_vm_node(&self, vm_identifier: &str) -> Result<(String, u32)> {
+    pub async fn find_vm(&self, vm_identifier: &str) -> Result<Guest> {
+        let guests = self.guests().await?;
+        match lookup(&guests, vm_identifier) {
+            Some(guest) => Ok(guest.clone()),
+            None => anyhow::bail!("VM '{}' not found in cluster", vm_identifier),
+        }
+    }
+
+    /// Lists the VMs and containers that are members of a resource pool.
+    pub async fn pool_members(&self, pool: &str) -> Result<Vec<Guest>> {
         #[derive(Deserialize)]
-        struct Resource {
-            node: String,
-            vmid: u32,
-            name: Option<String>,
+        struct PoolMember {
             #[serde(rename = "type")]
-            _resource_type: String,
+            member_type: String,
+            node: Option<String>,
+            vmid: Option<u32>,
+            name: Option<String>,
         }
 
-        let resources: Vec<Resource> = self.client.get("/cluster/resources?type=vm").await?;
+        #[derive(Deserialize)]
+        struct Pool {
+            #[serde(default)]
+            members: Vec<PoolMember>,
+        }
+
+        let pool_info: Pool = self.client.get(&format!("/pools/{}", pool)).await?;
+
+        Ok(pool_info
+            .members
+            .into_iter()
+            .filter(|m| m.member_type == "qemu" || m.member_type == "lxc")
+            .filter_map(|m| {
+                Some(Guest {
+                    node: m.node?,
+                    vmid: m.vmid?,
+                    name: m.name,
+                    guest_type: m.member_type,
+                    tags: None,
+                    pool: Some(pool.to_string()),
+                })
+            })
+            .collect())
+    }
+
+    /// Lists the resource pools with the guests in each.
+    pub async fn list_pools(&self) -> Result<()> {
+        #[derive(Deserialize)]
+        struct Pool {
+            poolid: String,
+            #[serde(default)]
+            comment: Option<String>,
+        }
+
+        let pools: Vec<Pool> = self.client.get("/pools").await?;
+        let mut entries = Vec::new();
+        for pool in pools {
+            let mut vmids: Vec<u32> = self
+                .pool_members(&pool.poolid)
+                .await?
+                .iter()
+                .map(|g| g.vmid)
+                .collect();
+            vmids.sort_unstable();
+            entries.push(PoolEntry {
+                poolid: pool.poolid,
+                comment: pool.comment,
+                vmids,
+            });
+        }
+        entries.sort_by(|a, b| a.poolid.cmp(&b.poolid));
+        self.output.print_list("Resource pools:", &entries)
+    }
+
+    pub async fn create_pool(&self, pool: &str, comment: Option<&str>) -> Result<()> {
+        let mut data = serde_json::json!({ "poolid": pool });
+        if let Some(comment) = comment {
+            data["comment"] = comment.into();
+        }
+        let _: serde_json::Value = self.client.post("/pools", &data).await?;
+        self.output.message(&format!("Created pool {}", pool));
+        Ok(())
+    }
+
+    /// Deletes a pool, which Proxmox only allows once it is empty.
+    pub async fn delete_pool(&self, pool: &str) -> Result<()> {
+        if !self.pool_members(pool).await?.is_empty() {
+            anyhow::bail!(
+                "Pool {} still has VMs; remove them with 'pool remove-member' first",
+                pool
+            );
+        }
+        let _: serde_json::Value = self.client.delete(&format!("/pools/{}", pool)).await?;
+        self.output.message(&format!("Deleted pool {}", pool));
+        Ok(())
+    }
+
+    /// Adds guests to `pool`, or with `remove` takes them out of it.
+    pub async fn update_pool_members(
+        &self,
+        pool: &str,
+        vm_identifiers: &[String],
+        remove: bool,
+    ) -> Result<()> {
+        let mut vmids = Vec::new();
+        for vm_identifier in vm_identifiers {
+            vmids.push(self.find_vm(vm_identifier).await?.vmid.to_string());
+        }
+        let mut data = serde_json::json!({ "vms": vmids.join(",") });
+        if remove {
+            data["delete"] = 1.into();
+        }
+        let _: serde_json::Value = self.client.put(&format!("/pools/{}", pool), &data).await?;
+        self.output.message(&format!(
+            "{} VMs {} pool {}",
+            if remove { "Removed" } else { "Added" },
+            if remove { "from" } else { "to" },
+            pool
+        ));
+        Ok(())
+    }
 
-        if let Ok(vmid) = vm_identifier.parse::<u32>() {
-            if let Some(resource) = resources.iter().find(|r| r.vmid == vmid) {
-                return Ok((resource.node.clone(), resource.vmid));
+    /// Expands a selection into the guests it designates.
+    pub async fn resolve(&self, selection: &Selection) -> Result<Vec<Guest>> {
+        let mut guests = Vec::new();
+
+        if let Some(vm) = &selection.vm {
+            guests.push(self.find_vm(vm).await?);
+        }
+
+        // One cluster lookup for the whole list.
+        if !selection.vms.is_empty() {
+            let all = self.guests().await?;
+            let mut unknown = Vec::new();
+            for vm in &selection.vms {
+                match lookup(&all, vm) {
+                    Some(guest) => guests.push(guest.clone()),
+                    None => unknown.push(vm.as_str()),
+                }
+            }
+            if !unknown.is_empty() {
+                anyhow::bail!("VMs not found in cluster: {}", unknown.join(", "));
             }
         }
 
-        if let Some(resource) = resources
-            .iter()
-            .find(|r| r.name.as_ref().is_some_and(|n| n == vm_identifier))
-        {
-            return Ok((resource.node.clone(), resource.vmid));
+        if let Some(pool) = &selection.pool {
+            let members = self.pool_members(pool).await?;
+            if members.is_empty() {
+                anyhow::bail!("Pool '{}' has no VM members", pool);
+            }
+            guests.extend(members);
         }
 
-        anyhow::bail!("VM '{}' not found in cluster", vm_identifier)
+        if !selection.tags.is_empty() {
+            let tagged: Vec<Guest> = self
+                .guests()
+                .await?
+                .into_iter()
+                .filter(|g| g.has_any_tag(&selection.tags))
+                .collect();
+            if tagged.is_empty() {
+                anyhow::bail!("No VMs tagged {}", selection.tags.join(", "));
+            }
+            guests.extend(tagged);
+        }
+
+        if let Some(guest_type) = selection.guest_type {
+            if !selection.names_guests() {
+                guests = self.guests().await?;
+            }
+            guests.retain(|g| g.guest_type == guest_type.as_str());
+            if guests.is_empty() {
+                anyhow::bail!("No {} guests selected", guest_type.as_str());
+            }
+        }
+
+        guests.sort_by_key(|g| g.vmid);
+        guests.dedup_by_key(|g| g.vmid);
+        Ok(guests)
     }
 
-    pub async fn list_nodes(&self) -> Result<()> {
+    /// The address of `node` as listed in the cluster status, if known.
+    pub async fn node_address(&self, node: &str) -> Result<Option<String>> {
         #[derive(Deserialize)]
-        struct Node {
-            node: String,
-            status: String,
-            #[serde(default)]
-            _cpu: Option<f64>,
-            #[serde(default)]
-            _maxcpu: Option<u32>,
-            #[serde(default)]
-            _mem: Option<u64>,
+        struct StatusEntry {
+            #[serde(rename = "type")]
+            entry_type: String,
             #[serde(default)]
-            _maxmem: Option<u64>,
+            name: Option<String>,
             #[serde(default)]
-            _uptime: Option<u64>,
+            ip: Option<String>,
         }
 
-        match self.client.get::<Vec<Node>>("/nodes").await {
-            Ok(nodes) => {
-                println!("Cluster nodes:");
-                for node in &nodes {
-                    println!("- {} ({})", node.node, node.status);
+        let entries: Vec<StatusEntry> = self.client.get("/cluster/status").await?;
+        Ok(entries
+            .into_iter()
+            .find(|e| e.entry_type == "node" && e.name.as_deref() == Some(node))
+            .and_then(|e| e.ip))
+    }
+
+    /// Prints the detailed status of one node.
+    pub async fn node_info(&self, node: &str) -> Result<()> {
+        let response: serde_json::Value =
+            self.client.get(&format!("/nodes/{}/status", node)).await?;
+        if self.output.print_raw(&response)? {
+            return Ok(());
+        }
+        let status = NodeStatus {
+            node: node.to_string(),
+            ..serde_json::from_value(response)?
+        };
+        self.output
+            .print_record(&format!("Node {}:", node), &status)
+    }
+
+    /// Lists the subscription and enabled repositories of `node`, or of
+    /// every online node, warning about enterprise repositories on nodes
+    /// without a subscription.
+    pub async fn subscriptions(&self, node: Option<&str>) -> Result<()> {
+        let mut nodes = Vec::new();
+        for node in self.node_or_online(node).await? {
+            let subscription: Subscription = self
+                .client
+                .get(&format!("/nodes/{}/subscription", node))
+                .await?;
+            let repositories: Repositories = self
+                .client
+                .get(&format!("/nodes/{}/apt/repositories", node))
+                .await?;
+            nodes.push(NodeSubscription::new(&node, subscription, repositories));
+        }
+        self.output.print_list("Subscriptions:", &nodes)?;
+
+        for node in nodes.iter().filter(|n| n.enterprise_without_subscription()) {
+            self.output.warn(&format!(
+                "Node {} uses the enterprise repository without an active subscription, so updates fail",
+                node.node
+            ));
+        }
+        Ok(())
+    }
+
+    /// Lists the package updates pending on `node`, or on every online
+    /// node, as of the nodes' last package index refresh. With
+    /// `fail_on_security`, fails when any of them is a security update.
+    pub async fn pending_updates(&self, node: Option<&str>, fail_on_security: bool) -> Result<()> {
+        let mut updates = Vec::new();
+        for node in self.node_or_online(node).await? {
+            let entries: Vec<AptUpdate> = self
+                .client
+                .get(&format!("/nodes/{}/apt/update", node))
+                .await?;
+            updates.extend(entries.into_iter().map(|u| PendingUpdate::new(&node, u)));
+        }
+        self.output.print_list("Pending updates:", &updates)?;
+
+        let security = updates.iter().filter(|u| u.security).count();
+        if fail_on_security && security > 0 {
+            anyhow::bail!("{} security updates are pending", security);
+        }
+        Ok(())
+    }
+
+    /// Lists the certificates of `node`, or of every online node, and fails
+    /// when one expires within `warn_days` days or already has.
+    pub async fn certificates(&self, node: Option<&str>, warn_days: i64) -> Result<()> {
+        let now = chrono::Utc::now().timestamp();
+        let mut certs = Vec::new();
+        for node in self.node_or_online(node).await? {
+            let entries: Vec<CertificateInfo> = self
+                .client
+                .get(&format!("/nodes/{}/certificates/info", node))
+                .await?;
+            certs.extend(
+                entries
+                    .into_iter()
+                    .map(|c| NodeCertificate::new(&node, c, now, warn_days)),
+            );
+        }
+        self.output.print_list("Certificates:", &certs)?;
+
+        let expiring = certs.iter().filter(|c| c.expiring).count();
+        if expiring > 0 {
+            anyhow::bail!("{} certificates expire within {} days", expiring, warn_days);
+        }
+        Ok(())
+    }
+
+    /// Lists the pve-manager, kernel and QEMU versions of every online node,
+    /// and fails when they differ between nodes.
+    pub async fn versions(&self) -> Result<()> {
+        let mut nodes = Vec::new();
+        for node in self.node_or_online(None).await? {
+            let status: NodeStatus = self.client.get(&format!("/nodes/{}/status", node)).await?;
+            let packages: Vec<PackageVersion> = self
+                .client
+                .get(&format!("/nodes/{}/apt/versions", node))
+                .await?;
+            nodes.push(NodeVersions::new(&node, &status, &packages));
+        }
+        let inconsistent = node::mark_mismatches(&mut nodes);
+        self.output.print_list("Versions:", &nodes)?;
+
+        if !inconsistent.is_empty() {
+            anyhow::bail!("Versions differ between nodes: {}", inconsistent.join(", "));
+        }
+        Ok(())
+    }
+
+    /// Lists the network interfaces of `node`: bridges, bonds, VLANs and
+    /// physical ports with their addresses.
+    pub async fn network(&self, node: &str) -> Result<()> {
+        let mut interfaces: Vec<NetworkInterface> =
+            self.client.get(&format!("/nodes/{}/network", node)).await?;
+        interfaces.sort_by(|a, b| a.iface.cmp(&b.iface));
+        self.output.print_list(
+            &format!("Network interfaces of node {}:", node),
+            &interfaces,
+        )
+    }
+
+    /// Reboots or shuts down `node`, refusing while guests run on it unless
+    /// `force` is set.
+    pub async fn node_power(
+        &self,
+        node: &str,
+        command: NodePowerCommand,
+        force: bool,
+        assume_yes: bool,
+    ) -> Result<()> {
+        let running = self.running_on(node).await?;
+        if !running.is_empty() && !force {
+            anyhow::bail!(
+                "Guests are running on node {}: {}; migrate them first, or use --force",
+                node,
+                running.join(", ")
+            );
+        }
+        prompt::confirm_or_abort(
+            &format!("This will {} node {}. Continue?", command.as_str(), node),
+            assume_yes || self.client.is_dry_run(),
+        )?;
+        let _: serde_json::Value = self
+            .client
+            .post(
+                &format!("/nodes/{}/status", node),
+                &serde_json::json!({ "command": command.as_str() }),
+            )
+            .await?;
+        self.output.message(&format!(
+            "{} Node {} is going to {}",
+            self.output.paint(Color::Green, "✓"),
+            node,
+            command.as_str()
+        ));
+        Ok(())
+    }
+
+    /// VMIDs of the guests running on `node`.
+    pub async fn running_on(&self, node: &str) -> Result<Vec<String>> {
+        #[derive(Deserialize)]
+        struct Resource {
+            #[serde(flatten)]
+            guest: Guest,
+            status: String,
+        }
+
+        let resources: Vec<Resource> = self.client.get("/cluster/resources?type=vm").await?;
+        Ok(resources
+            .iter()
+            .filter(|r| r.guest.node == node && r.status == "running")
+            .map(|r| r.guest.vmid.to_string())
+            .collect())
+    }
+
+    /// Polls until no guest is running on `node`, failing with those still
+    /// there after `timeout` seconds.
+    pub async fn wait_node_idle(&self, node: &str, timeout: u64) -> Result<()> {
+        if self.client.is_dry_run() {
+            return Ok(());
+        }
+        let started_at = Instant::now();
+        let mut waiting = false;
+        loop {
+            let running = self.running_on(node).await?;
+            if running.is_empty() {
+                return Ok(());
+            }
+            if started_at.elapsed().as_secs() >= timeout {
+                anyhow::bail!(
+                    "Still running on node {} after {}: {}",
+                    node,
+                    format_duration(timeout),
+                    running.join(", ")
+                );
+            }
+            if !waiting {
+                self.output.message(&format!(
+                    "Waiting for the guests on node {} to move away...",
+                    node
+                ));
+                waiting = true;
+            }
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        }
+    }
+
+    /// `node` if given, else the first online node by name, for commands
+    /// that need some node to talk to.
+    pub async fn node_or_first(&self, node: Option<&str>) -> Result<String> {
+        if let Some(node) = node {
+            return Ok(node.to_string());
+        }
+        let nodes: Vec<NodeEntry> = self.client.get("/nodes").await?;
+        nodes
+            .into_iter()
+            .filter(|n| n.status == "online")
+            .map(|n| n.node)
+            .min()
+            .ok_or_else(|| anyhow::anyhow!("No online node in the cluster"))
+    }
+
+    /// `node` if given, else every online node by name.
+    async fn node_or_online(&self, node: Option<&str>) -> Result<Vec<String>> {
+        if let Some(node) = node {
+            return Ok(vec![node.to_string()]);
+        }
+        let nodes: Vec<NodeEntry> = self.client.get("/nodes").await?;
+        let mut online: Vec<String> = nodes
+            .into_iter()
+            .filter(|n| n.status == "online")
+            .map(|n| n.node)
+            .collect();
+        online.sort();
+        Ok(online)
+    }
+
+    /// Lists the storages of `node`, or of every online node.
+    pub async fn list_storage(&self, node: Option<&str>) -> Result<()> {
+        let nodes = self.node_or_online(node).await?;
+        let mut storages = Vec::new();
+        for node in nodes {
+            let entries: Vec<StorageEntry> =
+                self.client.get(&format!("/nodes/{}/storage", node)).await?;
+            storages.extend(entries.into_iter().map(|entry| StorageEntry {
+                node: node.clone(),
+                ..entry
+            }));
+        }
+        self.output.print_list("Storage:", &storages)
+    }
+
+    /// Summarizes storage usage across the cluster, and fails when a storage
+    /// is above `warn_pct` percent full, for use from monitoring.
+    pub async fn report_storage(&self, warn_pct: f64) -> Result<()> {
+        let resources: Vec<StorageResource> =
+            self.client.get("/cluster/resources?type=storage").await?;
+        let report = report::build_storage(&resources, warn_pct);
+        if !self.output.print_document(&report)? {
+            self.output.print_summary(&format!(
+                "{} of {} used ({:.0}%)",
+                self.output.format_bytes(report.used),
+                self.output.format_bytes(report.total),
+                report.used as f64 * 100.0 / report.total.max(1) as f64
+            ));
+            self.output.print_list("By storage:", &report.storages)?;
+            if !report.warnings.is_empty() {
+                self.output
+                    .print_list(&format!("Storage above {}%:", warn_pct), &report.warnings)?;
+            }
+        }
+
+        if !report.warnings.is_empty() {
+            anyhow::bail!(
+                "{} storages are above {}% usage",
+                report.warnings.len(),
+                warn_pct
+            );
+        }
+        Ok(())
+    }
+
+    /// Checks quorum, nodes, HA and storage usage, prints one line per
+    /// check and returns the worst state. A check whose endpoint fails is
+    /// unknown rather than failing the whole command.
+    pub async fn health(&self, warn_pct: f64) -> Result<NagiosState> {
+        let status: Vec<ClusterStatusEntry> = self.client.get("/cluster/status").await?;
+        let mut checks = vec![health::check_quorum(&status), health::check_nodes(&status)];
+
+        checks.push(
+            match self
+                .client
+                .get::<Vec<HaStatusEntry>>("/cluster/ha/status/current")
+                .await
+            {
+                Ok(entries) => health::check_ha(&entries),
+                Err(e) => HealthCheck::unknown("ha", &e.into()),
+            },
+        );
+        checks.push(
+            match self
+                .client
+                .get::<Vec<StorageResource>>("/cluster/resources?type=storage")
+                .await
+            {
+                Ok(resources) => {
+                    health::check_storage(&report::build_storage(&resources, warn_pct), warn_pct)
+                }
+                Err(e) => HealthCheck::unknown("storage", &e.into()),
+            },
+        );
+
+        let state = health::overall(&checks);
+        self.output.print_list("Cluster health:", &checks)?;
+        if self.output.format() == OutputFormat::Table {
+            println!();
+            println!(
+                "{}",
+                self.output.label(&format!("Overall: {}", state.label()))
+            );
+        }
+        Ok(state)
+    }
+
+    /// Prints Ceph health, OSDs and pools as seen from `node`, and fails
+    /// unless Ceph reports `HEALTH_OK`.
+    pub async fn ceph_status(&self, node: Option<&str>) -> Result<()> {
+        let node = self.node_or_first(node).await?;
+        let status: CephStatus = self
+            .client
+            .get(&format!("/nodes/{}/ceph/status", node))
+            .await?;
+        let tree: OsdTree = self
+            .client
+            .get(&format!("/nodes/{}/ceph/osd", node))
+            .await?;
+        let pools: Vec<CephPool> = self
+            .client
+            .get(&format!("/nodes/{}/ceph/pool", node))
+            .await?;
+        let overview = CephOverview::new(node, status, tree, pools);
+
+        if !self.output.print_document(&overview)? {
+            if self.output.format() == OutputFormat::Table {
+                let osdmap = overview.osdmap;
+                println!(
+                    "{}",
+                    self.output.label(&format!(
+                        "Ceph {}: {} OSDs ({} up, {} in), {} PGs, {} of {} used",
+                        self.output
+                            .paint(ceph::severity_color(&overview.health), &overview.health),
+                        osdmap.num_osds,
+                        osdmap.num_up_osds,
+                        osdmap.num_in_osds,
+                        overview.num_pgs,
+                        self.output.format_bytes(overview.bytes_used),
+                        self.output.format_bytes(overview.bytes_total)
+                    ))
+                );
+                println!();
+            }
+            if !overview.checks.is_empty() {
+                self.output.print_list("Health checks:", &overview.checks)?;
+            }
+            self.output.print_list("OSDs:", &overview.osds)?;
+            self.output.print_list("Pools:", &overview.pools)?;
+        }
+
+        if !overview.is_healthy() {
+            anyhow::bail!("Ceph is {}", overview.health);
+        }
+        Ok(())
+    }
+
+    /// Lists the physical disks of `node`, or of every online node, with
+    /// their SMART status, and fails when a disk's self-assessment failed or
+    /// it has bad sectors.
+    pub async fn disks_health(&self, node: Option<&str>) -> Result<()> {
+        let mut disks = Vec::new();
+        for node in self.node_or_online(node).await? {
+            let entries: Vec<DiskEntry> = self
+                .client
+                .get(&format!("/nodes/{}/disks/list", node))
+                .await?;
+            for disk in entries {
+                let smart: SmartData = match self
+                    .client
+                    .get(&format!(
+                        "/nodes/{}/disks/smart?disk={}",
+                        node,
+                        url_encode(&disk.devpath)
+                    ))
+                    .await
+                {
+                    Ok(smart) => smart,
+                    Err(e) => {
+                        self.output.warn(&format!(
+                            "No SMART data for {} on {}: {}",
+                            disk.devpath, node, e
+                        ));
+                        SmartData::default()
+                    }
+                };
+                disks.push(DiskHealth::new(&node, disk, smart));
+            }
+        }
+        self.output.print_list("Disks:", &disks)?;
+
+        let failing = disks.iter().filter(|d| !d.is_healthy()).count();
+        if failing > 0 {
+            anyhow::bail!("{} of {} disks are failing", failing, disks.len());
+        }
+        Ok(())
+    }
+
+    /// Shows the load of each node and the busiest running guests,
+    /// refreshing every `interval` seconds until interrupted or `count`
+    /// refreshes have been shown.
+    pub async fn top(&self, interval: u64, limit: usize, count: Option<u64>) -> Result<()> {
+        if interval == 0 {
+            anyhow::bail!("The refresh interval must be at least one second");
+        }
+        let redraw = self.output.format() == OutputFormat::Table && std::io::stdout().is_terminal();
+        let mut top = Top::default();
+        let mut previous: Option<Instant> = None;
+        let mut refreshes = 0;
+        loop {
+            let resources: Vec<Resource> = self.client.get("/cluster/resources").await?;
+            let now = Instant::now();
+            let elapsed = previous.map_or(0.0, |previous| (now - previous).as_secs_f64());
+            previous = Some(now);
+            let sample = top.update(resources, elapsed, limit);
+
+            if !self.output.print_document(&sample)? {
+                if redraw {
+                    // Home the cursor and clear below it rather than the whole
+                    // screen, which flickers.
+                    print!("\x1b[H\x1b[J");
+                }
+                println!(
+                    "{}",
+                    self.output.label(&format!(
+                        "Cluster at {}, every {}s",
+                        self.output.format_time_with(chrono::Utc::now(), "%H:%M:%S"),
+                        interval
+                    ))
+                );
+                self.output.print_list("Nodes:", &sample.nodes)?;
+                println!();
+                self.output.print_list(
+                    &format!(
+                        "Busiest guests ({} of {} running):",
+                        sample.guests.len(),
+                        sample.running
+                    ),
+                    &sample.guests,
+                )?;
+            }
+
+            refreshes += 1;
+            if count.is_some_and(|count| refreshes >= count) {
+                return Ok(());
+            }
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+        }
+    }
+
+    pub async fn list_nodes(&self) -> Result<()> {
+        let nodes: Vec<NodeEntry> = match self.client.get::<serde_json::Value>("/nodes").await {
+            Ok(response) => {
+                if self.output.print_raw(&response)? {
+                    return Ok(());
                 }
-                Ok(())
+                serde_json::from_value(response)?
             }
             Err(_) => {
                 // Fallback to cluster/status endpoint
@@ -76,17 +1001,137 @@ impl ClusterManager {
                     status: Option<String>,
                 }
 
-                let items: Vec<NodeInfo> = self.client.get("/cluster/status").await?;
-
-                println!("Cluster nodes:");
-                for item in items.iter().filter(|n| n.node_type == "node") {
-                    if let Some(node_name) = item.node.as_ref().or(item.name.as_ref()) {
-                        let status = item.status.as_deref().unwrap_or("unknown");
-                        println!("- {} ({})", node_name, status);
-                    }
+                let response: serde_json::Value = self.client.get("/cluster/status").await?;
+                if self.output.print_raw(&response)? {
+                    return Ok(());
                 }
-                Ok(())
+
+                let items: Vec<NodeInfo> = serde_json::from_value(response)?;
+
+                items
+                    .into_iter()
+                    .filter(|n| n.node_type == "node")
+                    .filter_map(|item| {
+                        let node = item.node.or(item.name)?;
+                        Some(NodeEntry {
+                            node,
+                            status: item.status.unwrap_or_else(|| "unknown".to_string()),
+                            cpu: None,
+                            maxcpu: None,
+                            mem: None,
+                            maxmem: None,
+                            uptime: None,
+                        })
+                    })
+                    .collect()
             }
+        };
+
+        self.output.print_list("Cluster nodes:", &nodes)
+    }
+
+    /// Lists the SDN zones and vnets, or with `vm_identifier` the vnets the
+    /// NICs of that guest attach to and the nodes their zones span.
+    pub async fn list_sdn(&self, vm_identifier: Option<&str>) -> Result<()> {
+        let zones: Vec<Zone> = self.client.get("/cluster/sdn/zones").await?;
+        let vnets: Vec<Vnet> = self.client.get("/cluster/sdn/vnets").await?;
+
+        let Some(vm_identifier) = vm_identifier else {
+            let overview = SdnOverview { zones, vnets };
+            if !self.output.print_document(&overview)? {
+                self.output.print_list("SDN zones:", &overview.zones)?;
+                self.output.print_list("SDN vnets:", &overview.vnets)?;
+            }
+            return Ok(());
+        };
+        let guest = self.find_vm(vm_identifier).await?;
+        let config: serde_json::Map<String, serde_json::Value> =
+            self.client.get(&format!("{}/config", guest.path())).await?;
+        let nics = sdn::guest_nics(sdn::nic_bridges(&config), &vnets, &zones);
+        self.output
+            .print_list(&format!("Network of VM {}:", guest.vmid), &nics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guest(tags: Option<&str>) -> Guest {
+        Guest {
+            node: "pve1".to_string(),
+            vmid: 100,
+            name: Some("db01".to_string()),
+            guest_type: "qemu".to_string(),
+            tags: tags.map(str::to_string),
+            pool: None,
         }
     }
+
+    #[test]
+    fn test_has_any_tag() {
+        let g = guest(Some("db;critical"));
+        assert!(g.has_any_tag(&["critical".to_string()]));
+        assert!(g.has_any_tag(&["web".to_string(), "DB".to_string()]));
+        assert!(!g.has_any_tag(&["web".to_string()]));
+        assert!(!guest(None).has_any_tag(&["db".to_string()]));
+    }
+
+    #[test]
+    fn test_parse_vm_list() {
+        assert_eq!(
+            parse_vm_list("100\n\n# staging\nweb01\n\"db01\"\n").unwrap(),
+            vec!["100", "web01", "db01"]
+        );
+        assert_eq!(
+            parse_vm_list(r#"[{"vmid": 100, "name": "web"}, {"vmid": 101}]"#).unwrap(),
+            vec!["100", "101"]
+        );
+        assert_eq!(
+            parse_vm_list("{\"vmid\":102}\n{\"vmid\":103}\n").unwrap(),
+            vec!["102", "103"]
+        );
+        assert!(parse_vm_list(r#"[{"name": "web"}]"#).is_err());
+    }
+
+    #[test]
+    fn test_guest_path() {
+        let mut g = guest(None);
+        assert_eq!(g.path(), "/nodes/pve1/qemu/100");
+        g.guest_type = "lxc".to_string();
+        assert_eq!(g.path(), "/nodes/pve1/lxc/100");
+        assert!(g.is_container());
+    }
+
+    #[test]
+    fn test_pool_entry_row() {
+        let output = Output::default();
+        let pool = PoolEntry {
+            poolid: "prod".to_string(),
+            comment: None,
+            vmids: vec![100, 101],
+        };
+        assert_eq!(pool.row(&output), vec!["prod", "100, 101", ""]);
+    }
+
+    #[test]
+    fn test_storage_entry_row() {
+        let entry: StorageEntry = serde_json::from_value(serde_json::json!({
+            "storage": "local-lvm", "type": "lvmthin", "content": "images,rootdir",
+            "active": 1, "enabled": 1, "shared": 0,
+            "total": 400, "used": 100, "avail": 300
+        }))
+        .unwrap();
+        let output = Output::default().with_size_units(crate::size::SizeUnits::Bytes);
+        let row = entry.row(&output);
+        assert_eq!(row[4], "no");
+        assert_eq!(row[8], "25%");
+
+        let offline: StorageEntry = serde_json::from_value(serde_json::json!({
+            "storage": "nfs-backup", "type": "nfs", "shared": 1, "active": 0
+        }))
+        .unwrap();
+        assert_eq!(offline.row(&output)[5], "-");
+        assert_eq!(offline.row(&output)[8], "inactive");
+    }
 }
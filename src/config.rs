@@ -1,12 +1,88 @@
 use serde::Deserialize;
 use std::collections::HashMap;
 
+use crate::cluster::{Guest, GuestType, Selection};
+use crate::prune::RetentionPolicy;
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct ClusterConfig {
     pub hosts: Vec<String>,
     pub port: Option<u16>,
     pub token: Option<String>,
     pub verify_ssl: Option<bool>,
+    pub retention: Option<RetentionConfig>,
+}
+
+/// A `[retention]` section: a default policy, per-type policies for VMs and
+/// containers, and per-VM overrides keyed by VMID or name.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct RetentionConfig {
+    #[serde(flatten)]
+    pub default: RetentionPolicy,
+    /// `[retention.qemu]`, for QEMU VMs only.
+    #[serde(default)]
+    pub qemu: Option<RetentionPolicy>,
+    /// `[retention.lxc]`, for containers only.
+    #[serde(default)]
+    pub lxc: Option<RetentionPolicy>,
+    #[serde(default)]
+    pub vms: HashMap<String, RetentionPolicy>,
+}
+
+impl RetentionConfig {
+    /// Returns the policy that applies to `guest`, if any. A per-VM entry
+    /// replaces the policy of the guest's type, which in turn replaces the
+    /// default policy, each as a whole.
+    pub fn policy_for(&self, guest: &Guest) -> Option<RetentionPolicy> {
+        let by_type = if guest.is_container() {
+            self.lxc
+        } else {
+            self.qemu
+        };
+        self.vms
+            .get(&guest.vmid.to_string())
+            .or_else(|| guest.name.as_ref().and_then(|n| self.vms.get(n)))
+            .copied()
+            .or(by_type)
+            .or(Some(self.default))
+            .filter(|policy| !policy.is_empty())
+    }
+}
+
+/// A `[[schedules]]` entry run by `pve-tool daemon`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ScheduleConfig {
+    /// Label used in logs; defaults to the schedule's position.
+    pub name: Option<String>,
+    /// Cron expression in local time, e.g. `"0 2 * * *"`.
+    pub schedule: String,
+    pub vm: Option<String>,
+    pub pool: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// `qemu` or `lxc` to snapshot only VMs or only containers. On its own,
+    /// selects every guest of the type.
+    #[serde(rename = "type")]
+    pub guest_type: Option<GuestType>,
+    pub name_template: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub vmstate: bool,
+    /// Policy applied after each run. Without one, the `[retention]` section
+    /// is used if present.
+    pub retention: Option<RetentionPolicy>,
+}
+
+impl ScheduleConfig {
+    pub fn selection(&self) -> Selection {
+        Selection {
+            vm: self.vm.clone(),
+            vms: Vec::new(),
+            pool: self.pool.clone(),
+            tags: self.tags.clone(),
+            guest_type: self.guest_type,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Default)]
@@ -16,6 +92,13 @@ pub struct Config {
     pub token: Option<String>,
     pub node: Option<String>,
     pub verify_ssl: Option<bool>,
+    pub utc: Option<bool>,
+    pub time_format: Option<String>,
+    pub snapname_template: Option<String>,
+    pub description_template: Option<String>,
+    pub retention: Option<RetentionConfig>,
+    #[serde(default)]
+    pub schedules: Vec<ScheduleConfig>,
     pub clusters: Option<HashMap<String, ClusterConfig>>,
 }
 
@@ -29,6 +112,7 @@ impl Config {
                 port: self.port,
                 token: self.token.clone(),
                 verify_ssl: self.verify_ssl,
+                retention: None,
             })
         } else if let Some(clusters) = &self.clusters {
             clusters.values().next().cloned()
@@ -36,4 +120,173 @@ impl Config {
             None
         }
     }
+
+    /// Merges the top-level `[retention]` section with the one of `cluster`.
+    /// The cluster's default policy and VM entries take precedence.
+    pub fn retention(&self, cluster: Option<&str>) -> RetentionConfig {
+        let mut retention = self.retention.clone().unwrap_or_default();
+
+        if let Some(name) = cluster
+            && let Some(cluster_retention) = self
+                .clusters
+                .as_ref()
+                .and_then(|c| c.get(name))
+                .and_then(|c| c.retention.as_ref())
+        {
+            if !cluster_retention.default.is_empty() {
+                retention.default = cluster_retention.default;
+            }
+            retention.qemu = cluster_retention.qemu.or(retention.qemu);
+            retention.lxc = cluster_retention.lxc.or(retention.lxc);
+            retention.vms.extend(cluster_retention.vms.clone());
+        }
+
+        retention
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guest(vmid: u32, name: &str) -> Guest {
+        Guest {
+            node: "pve1".to_string(),
+            vmid,
+            name: Some(name.to_string()),
+            guest_type: "qemu".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_retention_overrides() {
+        let config: Config = toml::from_str(
+            r#"
+            [retention]
+            keep_daily = 7
+
+            [retention.vms]
+            100 = { keep_last = 3 }
+            db01 = { keep_weekly = 4 }
+
+            [clusters.prod]
+            hosts = ["10.0.0.1"]
+
+            [clusters.prod.retention]
+            keep_daily = 14
+            "#,
+        )
+        .unwrap();
+
+        let retention = config.retention(None);
+        let policy = retention.policy_for(&guest(100, "web")).unwrap();
+        assert_eq!(policy.keep_last, Some(3));
+        assert_eq!(policy.keep_daily, None);
+        let policy = retention.policy_for(&guest(101, "db01")).unwrap();
+        assert_eq!(policy.keep_weekly, Some(4));
+        let policy = retention.policy_for(&guest(102, "app")).unwrap();
+        assert_eq!(policy.keep_daily, Some(7));
+
+        let retention = config.retention(Some("prod"));
+        let policy = retention.policy_for(&guest(102, "app")).unwrap();
+        assert_eq!(policy.keep_daily, Some(14));
+        assert_eq!(
+            retention.policy_for(&guest(100, "web")).unwrap().keep_last,
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn test_schedules() {
+        let config: Config = toml::from_str(
+            r#"
+            [[schedules]]
+            schedule = "0 2 * * *"
+            tags = ["db"]
+            retention = { keep_daily = 7 }
+
+            [[schedules]]
+            name = "web"
+            schedule = "30 * * * *"
+            vm = "100"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.schedules.len(), 2);
+        let db = &config.schedules[0];
+        assert_eq!(db.selection().tags, vec!["db"]);
+        assert_eq!(db.retention.unwrap().keep_daily, Some(7));
+        let web = &config.schedules[1];
+        assert_eq!(web.name.as_deref(), Some("web"));
+        assert_eq!(web.selection().vm.as_deref(), Some("100"));
+        assert!(web.retention.is_none());
+    }
+
+    #[test]
+    fn test_retention_per_type() {
+        let config: Config = toml::from_str(
+            r#"
+            [retention]
+            keep_daily = 7
+
+            [retention.lxc]
+            keep_last = 24
+            keep_daily = 7
+
+            [retention.vms]
+            200 = { keep_last = 2 }
+
+            [[schedules]]
+            schedule = "0 * * * *"
+            type = "lxc"
+            "#,
+        )
+        .unwrap();
+
+        let retention = config.retention(None);
+        let container = Guest {
+            guest_type: "lxc".to_string(),
+            ..guest(201, "ct")
+        };
+        assert_eq!(
+            retention.policy_for(&container).unwrap().keep_last,
+            Some(24)
+        );
+        let policy = retention.policy_for(&guest(100, "web")).unwrap();
+        assert_eq!(policy.keep_last, None);
+        assert_eq!(policy.keep_daily, Some(7));
+        let pinned = Guest {
+            guest_type: "lxc".to_string(),
+            ..guest(200, "ct")
+        };
+        assert_eq!(retention.policy_for(&pinned).unwrap().keep_last, Some(2));
+
+        let selection = config.schedules[0].selection();
+        assert_eq!(selection.guest_type, Some(GuestType::Lxc));
+        assert!(!selection.is_empty());
+    }
+
+    #[test]
+    fn test_retention_absent() {
+        let config = Config::default();
+        assert!(
+            config
+                .retention(None)
+                .policy_for(&guest(100, "web"))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_retention_rejects_zero() {
+        let result = toml::from_str::<Config>(
+            r#"
+            [retention]
+            keep_last = 0
+            "#,
+        );
+        assert!(result.is_err());
+    }
 }
@@ -0,0 +1,485 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use tokio::io::{AsyncRead, AsyncWrite, copy_bidirectional};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::time::Duration;
+
+use crate::client::{ProxmoxClient, url_encode};
+use crate::cluster::{ClusterManager, Guest};
+use crate::output::{Output, Tabular};
+use crate::websocket::{self, Opcode};
+
+/// Ctrl-], which leaves a terminal console as it does telnet.
+pub const ESCAPE: u8 = 0x1d;
+
+/// Keepalive message of the termproxy protocol.
+pub const TERM_PING: &[u8] = b"2";
+
+/// Opens the VNC, SPICE and terminal consoles of guests.
+pub struct ConsoleManager {
+    client: ProxmoxClient,
+    cluster: ClusterManager,
+    output: Output,
+}
+
+/// Reply of the `vncproxy` endpoint.
+#[derive(Debug, Deserialize)]
+pub struct VncProxy {
+    /// Port the VNC server listens on, on the guest's node.
+    #[serde(deserialize_with = "deserialize_port")]
+    pub port: u16,
+    pub ticket: String,
+    /// Short password for VNC clients, when one was requested.
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+/// Reply of the `termproxy` endpoint.
+#[derive(Debug, Deserialize)]
+pub struct TermProxy {
+    #[serde(deserialize_with = "deserialize_port")]
+    pub port: u16,
+    pub ticket: String,
+    /// User the ticket was issued to, which termproxy expects back with it.
+    pub user: String,
+}
+
+/// Wraps typed input in a termproxy data message.
+pub fn term_input(keys: &[u8]) -> Vec<u8> {
+    let mut message = format!("0:{}:", keys.len()).into_bytes();
+    message.extend_from_slice(keys);
+    message
+}
+
+/// Tells termproxy the size of the local terminal.
+pub fn term_resize(cols: u16, rows: u16) -> Vec<u8> {
+    format!("1:{}:{}:", cols, rows).into_bytes()
+}
+
+/// Renders the reply of the `spiceproxy` endpoint as a `.vv` file for
+/// remote-viewer.
+pub fn remote_viewer_file(settings: &serde_json::Map<String, serde_json::Value>) -> String {
+    let mut file = String::from("[virt-viewer]\n");
+    for (key, value) in settings {
+        let value = match value {
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        file.push_str(&format!("{}={}\n", key, value));
+    }
+    file
+}
+
+/// Keeps the terminal on standard input in raw mode, so that keys such as
+/// Ctrl-C reach the console, until dropped.
+pub struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    pub fn enable() -> Result<Self> {
+        let saved = stty(&["-g"])?.trim().to_string();
+        stty(&["raw", "-echo"])?;
+        Ok(Self { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+/// Columns and rows of the terminal on standard input.
+pub fn terminal_size() -> Option<(u16, u16)> {
+    let size = stty(&["size"]).ok()?;
+    let mut numbers = size.split_whitespace().map(str::parse);
+    let rows = numbers.next()?.ok()?;
+    let cols = numbers.next()?.ok()?;
+    Some((cols, rows))
+}
+
+fn stty(args: &[&str]) -> Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .context("Failed to run stty")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "stty {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Proxmox returns the port as a number or a string depending on the guest
+/// type.
+fn deserialize_port<'de, D>(deserializer: D) -> Result<u16, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Number(n) => n
+            .as_u64()
+            .and_then(|n| u16::try_from(n).ok())
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid port {}", n))),
+        serde_json::Value::String(s) => s
+            .parse()
+            .map_err(|_| serde::de::Error::custom(format!("invalid port '{}'", s))),
+        other => Err(serde::de::Error::custom(format!("invalid port {}", other))),
+    }
+}
+
+/// What a VNC viewer needs to connect.
+#[derive(Debug, Serialize)]
+pub struct VncConnection {
+    pub vmid: u32,
+    pub host: String,
+    pub port: u16,
+    pub password: String,
+}
+
+impl Tabular for VncConnection {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("vmid", "VMID"),
+            ("host", "Host"),
+            ("port", "Port"),
+            ("password", "Password"),
+        ]
+    }
+
+    fn row(&self, _output: &Output) -> Vec<String> {
+        vec![
+            self.vmid.to_string(),
+            self.host.clone(),
+            self.port.to_string(),
+            self.password.clone(),
+        ]
+    }
+}
+
+impl ConsoleManager {
+    pub fn new(client: ProxmoxClient, output: Output) -> Self {
+        let cluster = ClusterManager::new(client.clone(), output.clone());
+        Self {
+            client,
+            cluster,
+            output,
+        }
+    }
+
+    /// Opens a VNC console of a guest. Without `listen`, prints the address
+    /// and one-time password for a VNC viewer. With it, listens on that
+    /// local port and forwards each viewer connection to a fresh console
+    /// until interrupted.
+    pub async fn vnc(&self, vm_identifier: &str, listen: Option<u16>) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let host = self
+            .cluster
+            .node_address(&guest.node)
+            .await?
+            .unwrap_or_else(|| self.client.host());
+
+        let Some(listen) = listen else {
+            let proxy = self.vnc_proxy(&guest).await?;
+            let connection = VncConnection {
+                vmid: guest.vmid,
+                host,
+                port: proxy.port,
+                password: proxy.password.unwrap_or(proxy.ticket),
+            };
+            self.output
+                .print_record(&format!("VNC console of VM {}:", guest.vmid), &connection)?;
+            self.output
+                .message("The console accepts a connection for about 10 seconds.");
+            return Ok(());
+        };
+
+        let listener = TcpListener::bind(("127.0.0.1", listen))
+            .await
+            .with_context(|| format!("Failed to listen on port {}", listen))?;
+        self.output.message(&format!(
+            "Point a VNC viewer at 127.0.0.1:{} for the console of VM {}; Ctrl-C to stop",
+            listen, guest.vmid
+        ));
+
+        loop {
+            let (mut viewer, _) = listener.accept().await?;
+            // A console only waits briefly for its connection, so it is
+            // requested once a viewer has connected. A failure only drops
+            // that viewer; the next one may well succeed.
+            let connected = async {
+                let proxy = self.vnc_proxy(&guest).await?;
+                let server = TcpStream::connect((host.as_str(), proxy.port))
+                    .await
+                    .with_context(|| format!("Failed to connect to {}:{}", host, proxy.port))?;
+                anyhow::Ok((proxy, server))
+            };
+            let (proxy, mut server) = match connected.await {
+                Ok(connected) => connected,
+                Err(e) => {
+                    self.output
+                        .warn(&format!("Cannot open the console for the viewer: {:#}", e));
+                    continue;
+                }
+            };
+            self.output.message(&format!(
+                "Viewer connected; VNC password: {}",
+                proxy.password.as_deref().unwrap_or(&proxy.ticket)
+            ));
+            if let Err(e) = copy_bidirectional(&mut viewer, &mut server).await {
+                self.output
+                    .warn(&format!("Console connection closed: {}", e));
+            }
+            self.output.message("Viewer disconnected");
+        }
+    }
+
+    /// Writes a remote-viewer file for the SPICE console of a VM to `output`,
+    /// or prints it.
+    pub async fn spice(&self, vm_identifier: &str, output: Option<&Path>) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        if guest.is_container() {
+            anyhow::bail!(
+                "VM {} is a container, which has no SPICE console; use --vnc or --term",
+                guest.vmid
+            );
+        }
+
+        let settings: serde_json::Map<String, serde_json::Value> = self
+            .client
+            .post(&format!("{}/spiceproxy", guest.path()), &())
+            .await?;
+        let file = remote_viewer_file(&settings);
+
+        match output {
+            Some(path) => {
+                fs::write(path, file)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                self.output.message(&format!(
+                    "Saved the SPICE console of VM {} to {}; open it with remote-viewer \
+                     right away, its password is only valid briefly",
+                    guest.vmid,
+                    path.display()
+                ));
+            }
+            None => print!("{}", file),
+        }
+        Ok(())
+    }
+
+    /// Attaches this terminal to the serial console of a VM or the console
+    /// of a container until Ctrl-] is pressed or the console closes.
+    pub async fn term(&self, vm_identifier: &str) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        if !guest.is_container() {
+            let config: serde_json::Value =
+                self.client.get(&format!("{}/config", guest.path())).await?;
+            if config.get("serial0").is_none() {
+                anyhow::bail!(
+                    "VM {} has no serial port; add one with 'config set {} serial0=socket' \
+                     and have the guest use it as a console",
+                    guest.vmid,
+                    guest.vmid
+                );
+            }
+        }
+        if !io::stdin().is_terminal() {
+            anyhow::bail!("A terminal console needs standard input to be a terminal");
+        }
+
+        let proxy: TermProxy = self
+            .client
+            .post(&format!("{}/termproxy", guest.path()), &())
+            .await?;
+        let socket = self
+            .client
+            .websocket(&format!(
+                "{}/vncwebsocket?port={}&vncticket={}",
+                guest.path(),
+                proxy.port,
+                url_encode(&proxy.ticket)
+            ))
+            .await?;
+        let (mut reader, mut writer) = tokio::io::split(socket);
+
+        // termproxy checks the ticket again before relaying anything.
+        let login = format!("{}:{}\n", proxy.user, proxy.ticket);
+        websocket::write_frame(&mut writer, Opcode::Binary, login.as_bytes()).await?;
+        let reply = websocket::read_frame(&mut reader).await?;
+        let Some(first_output) = reply.payload.strip_prefix(b"OK") else {
+            anyhow::bail!(
+                "Console of VM {} refused the ticket: {}",
+                guest.vmid,
+                String::from_utf8_lossy(&reply.payload)
+            );
+        };
+        if let Some((cols, rows)) = terminal_size() {
+            websocket::write_frame(&mut writer, Opcode::Binary, &term_resize(cols, rows)).await?;
+        }
+
+        self.output.message(&format!(
+            "Connected to the console of VM {}; press Ctrl-] to leave",
+            guest.vmid
+        ));
+        let mut stdout = io::stdout();
+        stdout.write_all(first_output)?;
+        stdout.flush()?;
+
+        let raw_mode = RawMode::enable()?;
+        let relayed = relay_terminal(reader, &mut writer).await;
+        drop(raw_mode);
+        let _ = websocket::write_frame(&mut writer, Opcode::Close, &[]).await;
+
+        println!();
+        self.output
+            .message(&format!("Left the console of VM {}", guest.vmid));
+        relayed
+    }
+
+    async fn vnc_proxy(&self, guest: &Guest) -> Result<VncProxy> {
+        #[derive(Serialize)]
+        struct VncProxyRequest {
+            /// Asks for a short password VNC clients can type; only VMs
+            /// support it.
+            #[serde(rename = "generate-password", skip_serializing_if = "Option::is_none")]
+            generate_password: Option<u8>,
+        }
+
+        let request = VncProxyRequest {
+            generate_password: (!guest.is_container()).then_some(1),
+        };
+        let proxy = self
+            .client
+            .post(&format!("{}/vncproxy", guest.path()), &request)
+            .await?;
+        Ok(proxy)
+    }
+}
+
+/// Relays keys from standard input to a termproxy websocket and its output
+/// to standard output, until Ctrl-] is typed or the console closes.
+async fn relay_terminal<R, W>(mut reader: R, writer: &mut W) -> Result<()>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+    W: AsyncWrite + Unpin,
+{
+    // Reading standard input blocks, so it gets a thread of its own, which
+    // cannot hold up the exit once the console is left.
+    let (keys_tx, mut keys_rx) = mpsc::channel::<Vec<u8>>(16);
+    std::thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+        let mut buffer = [0u8; 1024];
+        while let Ok(n) = stdin.read(&mut buffer)
+            && n > 0
+        {
+            if keys_tx.blocking_send(buffer[..n].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+
+    // Frames are read on a task of their own, since an unfinished read
+    // cannot be abandoned whenever a key arrives.
+    let (frames_tx, mut frames_rx) = mpsc::channel(16);
+    tokio::spawn(async move {
+        loop {
+            let frame = websocket::read_frame(&mut reader).await;
+            let failed = frame.is_err();
+            if frames_tx.send(frame).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    let mut stdout = io::stdout();
+    let mut keepalive = tokio::time::interval(Duration::from_secs(30));
+    loop {
+        tokio::select! {
+            keys = keys_rx.recv() => {
+                let Some(keys) = keys else { return Ok(()) };
+                let escape = keys.iter().position(|&key| key == ESCAPE);
+                let typed = &keys[..escape.unwrap_or(keys.len())];
+                if !typed.is_empty() {
+                    websocket::write_frame(writer, Opcode::Binary, &term_input(typed))
+                        .await?;
+                }
+                if escape.is_some() {
+                    return Ok(());
+                }
+            }
+            frame = frames_rx.recv() => {
+                let Some(frame) = frame else { return Ok(()) };
+                let frame = frame.context("Console connection lost")?;
+                match frame.opcode {
+                    Opcode::Close => return Ok(()),
+                    Opcode::Ping => {
+                        websocket::write_frame(writer, Opcode::Pong, &frame.payload).await?;
+                    }
+                    Opcode::Pong => {}
+                    _ => {
+                        stdout.write_all(&frame.payload)?;
+                        stdout.flush()?;
+                    }
+                }
+            }
+            _ = keepalive.tick() => {
+                websocket::write_frame(writer, Opcode::Binary, TERM_PING).await?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vnc_proxy_port() {
+        let qemu: VncProxy = serde_json::from_value(serde_json::json!({
+            "port": "5900", "ticket": "PVEVNC:abc", "password": "x7Gk2pQa"
+        }))
+        .unwrap();
+        assert_eq!(qemu.port, 5900);
+
+        let lxc: VncProxy =
+            serde_json::from_value(serde_json::json!({"port": 5901, "ticket": "PVEVNC:def"}))
+                .unwrap();
+        assert_eq!(lxc.port, 5901);
+        assert!(lxc.password.is_none());
+    }
+
+    #[test]
+    fn test_remote_viewer_file() {
+        let reply = serde_json::json!({
+            "type": "spice",
+            "host": "pvespiceproxy:65f1a2b3:100:pve1::abc",
+            "tls-port": 61000,
+            "delete-this-file": 1,
+        });
+        assert_eq!(
+            remote_viewer_file(reply.as_object().unwrap()),
+            "[virt-viewer]\n\
+             delete-this-file=1\n\
+             host=pvespiceproxy:65f1a2b3:100:pve1::abc\n\
+             tls-port=61000\n\
+             type=spice\n"
+        );
+    }
+
+    #[test]
+    fn test_term_messages() {
+        assert_eq!(term_input(b"ls\r"), b"0:3:ls\r");
+        assert_eq!(term_input("é".as_bytes()), "0:2:é".as_bytes());
+        assert_eq!(term_resize(80, 24), b"1:80:24:");
+    }
+}
@@ -0,0 +1,254 @@
+use anyhow::Result;
+use chrono::{DateTime, Local, TimeZone};
+use clap::ValueEnum;
+use croner::Cron;
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+use tracing_subscriber::EnvFilter;
+
+use crate::config::{Config, RetentionConfig, ScheduleConfig};
+use crate::naming;
+use crate::snapshot::{CreateOptions, OnExisting, SnapshotManager};
+
+/// Longest single sleep, so that clock changes are noticed within a minute.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// Sends log events to stderr. `RUST_LOG` overrides the default `info` level.
+pub fn init_logging(format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    match format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().init(),
+    }
+}
+
+/// A validated `[[schedules]]` entry.
+pub struct Job {
+    pub name: String,
+    cron: Cron,
+    pub config: ScheduleConfig,
+}
+
+impl Job {
+    /// Parses the schedule at `index` (zero-based) of the config file.
+    pub fn parse(index: usize, config: &ScheduleConfig) -> Result<Self> {
+        let name = config
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("schedule-{}", index + 1));
+
+        if config.selection().is_empty() {
+            anyhow::bail!(
+                "Schedule '{}' selects no VMs; set vm, pool, tags or type",
+                name
+            );
+        }
+
+        let cron = Cron::new(&config.schedule).parse().map_err(|e| {
+            anyhow::anyhow!(
+                "Invalid schedule '{}' for '{}': {}",
+                config.schedule,
+                name,
+                e
+            )
+        })?;
+
+        Ok(Self {
+            name,
+            cron,
+            config: config.clone(),
+        })
+    }
+
+    /// The first run strictly after `after`.
+    pub fn next_run<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        self.cron.find_next_occurrence(after, false).ok()
+    }
+}
+
+/// Runs the configured schedules until the process is stopped.
+pub struct Daemon {
+    snapshot_mgr: SnapshotManager,
+    jobs: Vec<Job>,
+    retention: RetentionConfig,
+    name_template: String,
+    description_template: String,
+}
+
+impl Daemon {
+    pub fn new(
+        snapshot_mgr: SnapshotManager,
+        config: &Config,
+        cluster: Option<&str>,
+    ) -> Result<Self> {
+        if config.schedules.is_empty() {
+            anyhow::bail!("No [[schedules]] configured in the config file");
+        }
+
+        let jobs = config
+            .schedules
+            .iter()
+            .enumerate()
+            .map(|(index, schedule)| Job::parse(index, schedule))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            snapshot_mgr,
+            jobs,
+            retention: config.retention(cluster),
+            name_template: config
+                .snapname_template
+                .clone()
+                .unwrap_or_else(|| naming::DEFAULT_SNAPNAME_TEMPLATE.to_string()),
+            description_template: config
+                .description_template
+                .clone()
+                .unwrap_or_else(|| naming::DEFAULT_DESCRIPTION_TEMPLATE.to_string()),
+        })
+    }
+
+    pub async fn run(&self) -> Result<()> {
+        let mut after = Local::now();
+        for job in &self.jobs {
+            info!(
+                job = %job.name,
+                schedule = %job.config.schedule,
+                next_run = ?job.next_run(&after).map(|t| t.to_rfc3339()),
+                "schedule loaded"
+            );
+        }
+
+        loop {
+            let Some(next) = self.jobs.iter().filter_map(|j| j.next_run(&after)).min() else {
+                anyhow::bail!("No schedule has a future run");
+            };
+
+            let now = Local::now();
+            if now < next {
+                let wait = (next - now).to_std().unwrap_or_default();
+                tokio::time::sleep(wait.min(MAX_SLEEP)).await;
+                continue;
+            }
+
+            for job in &self.jobs {
+                if job.next_run(&after) == Some(next) {
+                    self.run_job(job).await;
+                }
+            }
+            after = next;
+        }
+    }
+
+    /// Creates the job's snapshots, then prunes. Errors are logged rather
+    /// than returned so that one failing job does not stop the daemon.
+    async fn run_job(&self, job: &Job) {
+        let started = Instant::now();
+        info!(job = %job.name, "job started");
+
+        let selection = job.config.selection();
+        let options = CreateOptions {
+            name_template: job
+                .config
+                .name_template
+                .clone()
+                .unwrap_or_else(|| self.name_template.clone()),
+            description_template: job
+                .config
+                .description
+                .clone()
+                .unwrap_or_else(|| self.description_template.clone()),
+            reason: Some(job.name.clone()),
+            vmstate: job.config.vmstate,
+            existing: OnExisting::Skip,
+            ..Default::default()
+        };
+
+        if let Err(e) = self
+            .snapshot_mgr
+            .create_snapshots(&selection, &options)
+            .await
+        {
+            // Pruning after a failed snapshot would shrink the history of
+            // exactly the VMs that are not getting new snapshots.
+            error!(job = %job.name, error = %format!("{:#}", e), "snapshot failed, skipping prune");
+            return;
+        }
+
+        let pruned = match job.config.retention {
+            Some(policy) if !policy.is_empty() => {
+                Some(self.snapshot_mgr.prune_snapshots(&selection, &policy).await)
+            }
+            Some(_) => None,
+            None if self.retention.default.is_empty() && self.retention.vms.is_empty() => None,
+            None => Some(
+                self.snapshot_mgr
+                    .prune_with_config(&selection, &self.retention)
+                    .await,
+            ),
+        };
+        if let Some(Err(e)) = pruned {
+            error!(job = %job.name, error = %format!("{:#}", e), "prune failed");
+            return;
+        }
+
+        info!(
+            job = %job.name,
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "job finished"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn schedule(expression: &str) -> ScheduleConfig {
+        ScheduleConfig {
+            name: None,
+            schedule: expression.to_string(),
+            vm: Some("100".to_string()),
+            pool: None,
+            tags: Vec::new(),
+            guest_type: None,
+            name_template: None,
+            description: None,
+            vmstate: false,
+            retention: None,
+        }
+    }
+
+    #[test]
+    fn test_next_run() {
+        let job = Job::parse(0, &schedule("0 2 * * *")).unwrap();
+        assert_eq!(job.name, "schedule-1");
+
+        let after = Utc.with_ymd_and_hms(2024, 3, 10, 1, 59, 0).unwrap();
+        let next = job.next_run(&after).unwrap();
+        assert_eq!(next, Utc.with_ymd_and_hms(2024, 3, 10, 2, 0, 0).unwrap());
+        // A run is never scheduled at the instant it was computed from.
+        let next = job.next_run(&next).unwrap();
+        assert_eq!(next, Utc.with_ymd_and_hms(2024, 3, 11, 2, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Job::parse(0, &schedule("0 25 * * *")).is_err());
+        assert!(Job::parse(0, &schedule("not cron")).is_err());
+
+        let mut no_vms = schedule("0 2 * * *");
+        no_vms.vm = None;
+        assert!(Job::parse(0, &no_vms).is_err());
+    }
+}
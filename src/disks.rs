@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+
+use crate::output::{Color, Output, Tabular};
+
+/// SMART attributes whose raw value counts bad sectors; anything above zero
+/// means the disk has started to fail.
+const SECTOR_ATTRIBUTES: &[&str] = &[
+    "Reallocated_Sector_Ct",
+    "Current_Pending_Sector",
+    "Offline_Uncorrectable",
+];
+
+/// A physical disk from `/nodes/{node}/disks/list`.
+#[derive(Debug, Deserialize)]
+pub struct DiskEntry {
+    pub devpath: String,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub serial: Option<String>,
+    #[serde(rename = "type", default)]
+    pub disk_type: Option<String>,
+    #[serde(default)]
+    pub size: u64,
+    /// Percentage of life left, or `N/A` for disks that do not report it.
+    #[serde(default)]
+    pub wearout: Option<serde_json::Value>,
+}
+
+/// The answer of `/nodes/{node}/disks/smart`.
+#[derive(Debug, Default, Deserialize)]
+pub struct SmartData {
+    /// `PASSED` or `OK` when healthy, `FAILED` when the disk's own
+    /// assessment failed, `UNKNOWN` for disks without SMART.
+    #[serde(default)]
+    pub health: Option<String>,
+    /// Set for ATA disks; NVMe disks only return text.
+    #[serde(default)]
+    pub attributes: Vec<SmartAttribute>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SmartAttribute {
+    pub name: String,
+    #[serde(default)]
+    pub raw: String,
+}
+
+/// The health of one disk, as listed by `disks health`.
+#[derive(Debug, Serialize)]
+pub struct DiskHealth {
+    pub node: String,
+    pub devpath: String,
+    pub model: String,
+    pub serial: String,
+    #[serde(rename = "type")]
+    pub disk_type: String,
+    pub size: u64,
+    pub smart: String,
+    pub wearout: Option<u64>,
+    /// Why the disk is flagged; empty for a healthy disk.
+    pub problems: Vec<String>,
+}
+
+impl DiskHealth {
+    pub fn new(node: &str, disk: DiskEntry, smart: SmartData) -> Self {
+        let smart_health = smart.health.unwrap_or_else(|| "UNKNOWN".to_string());
+        let mut problems = Vec::new();
+        if smart_health.starts_with("FAILED") {
+            problems.push(format!("SMART {}", smart_health));
+        }
+        for attribute in &smart.attributes {
+            if SECTOR_ATTRIBUTES.contains(&attribute.name.as_str())
+                && let Some(count) = raw_count(&attribute.raw)
+                && count > 0
+            {
+                problems.push(format!("{} {}", attribute.name, count));
+            }
+        }
+
+        Self {
+            node: node.to_string(),
+            devpath: disk.devpath,
+            model: disk.model.unwrap_or_default(),
+            serial: disk.serial.unwrap_or_default(),
+            disk_type: disk.disk_type.unwrap_or_default(),
+            size: disk.size,
+            smart: smart_health,
+            wearout: disk.wearout.as_ref().and_then(serde_json::Value::as_u64),
+            problems,
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl Tabular for DiskHealth {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("node", "Node"),
+            ("devpath", "Device"),
+            ("model", "Model"),
+            ("type", "Type"),
+            ("size", "Size"),
+            ("wearout", "Life left"),
+            ("smart", "SMART"),
+            ("problems", "Problems"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        let smart = if self.smart.starts_with("FAILED") {
+            output.paint(Color::Red, &self.smart)
+        } else {
+            self.smart.clone()
+        };
+        vec![
+            self.node.clone(),
+            self.devpath.clone(),
+            self.model.clone(),
+            self.disk_type.clone(),
+            output.format_bytes(self.size),
+            self.wearout
+                .map_or_else(|| "-".to_string(), |w| format!("{}%", w)),
+            smart,
+            if self.is_healthy() {
+                String::new()
+            } else {
+                output.paint(Color::Red, &self.problems.join(", "))
+            },
+        ]
+    }
+}
+
+/// The count at the start of a raw SMART value, e.g. `8` in `8 (0 2)`.
+fn raw_count(raw: &str) -> Option<u64> {
+    raw.split_whitespace().next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disk() -> DiskEntry {
+        serde_json::from_value(serde_json::json!({
+            "devpath": "/dev/sda", "model": "ST4000", "type": "hdd",
+            "size": 4_000_000_000_000u64, "wearout": "N/A"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_disk_health() {
+        let smart: SmartData = serde_json::from_value(serde_json::json!({
+            "health": "PASSED",
+            "attributes": [
+                { "name": "Reallocated_Sector_Ct", "raw": "8" },
+                { "name": "Current_Pending_Sector", "raw": "0" },
+                { "name": "Power_On_Hours", "raw": "31000 (12 0 0)" }
+            ]
+        }))
+        .unwrap();
+        let health = DiskHealth::new("pve1", disk(), smart);
+        assert!(!health.is_healthy());
+        assert_eq!(health.problems, vec!["Reallocated_Sector_Ct 8"]);
+        assert_eq!(health.wearout, None);
+
+        let failed = SmartData {
+            health: Some("FAILED".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            DiskHealth::new("pve1", disk(), failed).problems,
+            vec!["SMART FAILED"]
+        );
+
+        let nvme = SmartData {
+            health: Some("OK".to_string()),
+            ..Default::default()
+        };
+        assert!(DiskHealth::new("pve1", disk(), nvme).is_healthy());
+        assert!(DiskHealth::new("pve1", disk(), SmartData::default()).is_healthy());
+    }
+
+    #[test]
+    fn test_raw_count() {
+        assert_eq!(raw_count("12"), Some(12));
+        assert_eq!(raw_count("0 (0 2)"), Some(0));
+        assert_eq!(raw_count(""), None);
+    }
+}
@@ -0,0 +1,82 @@
+use anyhow::Result;
+
+/// Parses a human duration such as `90`, `45s`, `30m`, `12h`, `7d`, `2w` or
+/// `1d12h` into seconds. A bare number is interpreted as seconds.
+pub fn parse_duration(input: &str) -> Result<u64> {
+    let input = input.trim();
+    if input.is_empty() {
+        anyhow::bail!("Empty duration");
+    }
+    if let Ok(seconds) = input.parse::<u64>() {
+        return Ok(seconds);
+    }
+
+    let mut total: u64 = 0;
+    let mut number = String::new();
+    for c in input.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let multiplier = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 604800,
+            _ => anyhow::bail!("Invalid duration '{}': unknown unit '{}'", input, c),
+        };
+        let value: u64 = number
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid duration '{}'", input))?;
+        total += value * multiplier;
+        number.clear();
+    }
+    if !number.is_empty() {
+        anyhow::bail!(
+            "Invalid duration '{}': missing unit after {}",
+            input,
+            number
+        );
+    }
+
+    Ok(total)
+}
+
+/// Formats seconds as `1d 2h 3m`.
+pub fn format_duration(seconds: u64) -> String {
+    let days = seconds / 86400;
+    let hours = (seconds % 86400) / 3600;
+    let minutes = (seconds % 3600) / 60;
+    format!("{}d {}h {}m", days, hours, minutes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration_units() {
+        assert_eq!(parse_duration("90").unwrap(), 90);
+        assert_eq!(parse_duration("45s").unwrap(), 45);
+        assert_eq!(parse_duration("30m").unwrap(), 1800);
+        assert_eq!(parse_duration("12h").unwrap(), 43200);
+        assert_eq!(parse_duration("7d").unwrap(), 604800);
+        assert_eq!(parse_duration("2w").unwrap(), 1209600);
+        assert_eq!(parse_duration("1d12h").unwrap(), 129600);
+    }
+
+    #[test]
+    fn test_parse_duration_invalid() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("7x").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("1d12").is_err());
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(0), "0d 0h 0m");
+        assert_eq!(format_duration(93784), "1d 2h 3m");
+    }
+}
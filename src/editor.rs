@@ -0,0 +1,39 @@
+use anyhow::{Context, Result};
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::process::Command;
+
+/// Opens `initial` in the user's editor (`$VISUAL`, `$EDITOR`, or `vi`) and
+/// returns the saved text. `name` becomes part of the temporary file name.
+pub fn edit(initial: &str, name: &str) -> Result<String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+
+    // Created exclusively with mode 0600, so a file or symlink planted in a
+    // shared temporary directory is never written through. It is removed
+    // when dropped.
+    let mut file = tempfile::Builder::new()
+        .prefix(&format!("pve-tool-{}-", name))
+        .suffix(".md")
+        .tempfile()
+        .context("Failed to create a temporary file")?;
+    file.write_all(initial.as_bytes())
+        .and_then(|()| file.flush())
+        .with_context(|| format!("Failed to write {}", file.path().display()))?;
+    let path = file.path();
+
+    // The editor may carry arguments, as in EDITOR="code --wait".
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = Command::new(program)
+        .args(words)
+        .arg(path)
+        .status()
+        .with_context(|| format!("Failed to run editor '{}'", editor))?;
+    if !status.success() {
+        anyhow::bail!("Editor '{}' exited with {}", editor, status);
+    }
+    fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))
+}
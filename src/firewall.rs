@@ -0,0 +1,293 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::client::{ProxmoxClient, url_encode};
+use crate::cluster::ClusterManager;
+use crate::output::{Color, Output, Tabular};
+use crate::serde_util::deserialize_flag;
+
+pub struct FirewallManager {
+    client: ProxmoxClient,
+    cluster: ClusterManager,
+    output: Output,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Direction {
+    In,
+    Out,
+}
+
+impl Direction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Direction::In => "in",
+            Direction::Out => "out",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RuleAction {
+    Accept,
+    Drop,
+    Reject,
+}
+
+impl RuleAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RuleAction::Accept => "ACCEPT",
+            RuleAction::Drop => "DROP",
+            RuleAction::Reject => "REJECT",
+        }
+    }
+}
+
+/// A rule of a guest firewall, as listed by the `firewall/rules` endpoint.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FirewallRule {
+    pub pos: u32,
+    /// `in`, `out`, or `group` for a security group reference.
+    #[serde(rename = "type")]
+    pub direction: String,
+    /// ACCEPT, DROP or REJECT, or the name of the security group.
+    pub action: String,
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    pub enable: bool,
+    #[serde(rename = "macro", default)]
+    pub macro_name: Option<String>,
+    #[serde(default)]
+    pub proto: Option<String>,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub dest: Option<String>,
+    #[serde(default)]
+    pub sport: Option<String>,
+    #[serde(default)]
+    pub dport: Option<String>,
+    #[serde(default)]
+    pub iface: Option<String>,
+    #[serde(default)]
+    pub comment: Option<String>,
+    /// Digest of the firewall configuration the rule was read from.
+    #[serde(default, skip_serializing)]
+    pub digest: Option<String>,
+}
+
+impl FirewallRule {
+    /// One-line description used in messages, e.g. "in ACCEPT tcp dport 22".
+    pub fn summary(&self) -> String {
+        let mut parts = vec![self.direction.clone(), self.action.clone()];
+        for (label, value) in [
+            ("macro", &self.macro_name),
+            ("", &self.proto),
+            ("from", &self.source),
+            ("to", &self.dest),
+            ("sport", &self.sport),
+            ("dport", &self.dport),
+        ] {
+            if let Some(value) = value {
+                parts.extend((!label.is_empty()).then(|| label.to_string()));
+                parts.push(value.clone());
+            }
+        }
+        parts.join(" ")
+    }
+}
+
+impl Tabular for FirewallRule {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("pos", "Pos"),
+            ("type", "Type"),
+            ("action", "Action"),
+            ("macro", "Macro"),
+            ("proto", "Proto"),
+            ("source", "Source"),
+            ("dest", "Dest"),
+            ("dport", "Port"),
+            ("enable", "Enabled"),
+            ("comment", "Comment"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        let action = match self.action.as_str() {
+            "DROP" | "REJECT" => output.paint(Color::Red, &self.action),
+            _ => self.action.clone(),
+        };
+        vec![
+            self.pos.to_string(),
+            self.direction.clone(),
+            action,
+            self.macro_name.clone().unwrap_or_default(),
+            self.proto.clone().unwrap_or_default(),
+            self.source.clone().unwrap_or_default(),
+            self.dest.clone().unwrap_or_default(),
+            self.dport.clone().unwrap_or_default(),
+            if self.enable { "yes" } else { "no" }.to_string(),
+            self.comment.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+/// Parameters of a new rule. Options left unset match anything.
+#[derive(Debug, Default, Serialize)]
+pub struct NewRule {
+    #[serde(rename = "type")]
+    pub direction: String,
+    pub action: String,
+    pub enable: u8,
+    #[serde(rename = "macro", skip_serializing_if = "Option::is_none")]
+    pub macro_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proto: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dest: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sport: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dport: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iface: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    /// Where to insert the rule; Proxmox puts it first when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pos: Option<u32>,
+}
+
+impl FirewallManager {
+    pub fn new(client: ProxmoxClient, output: Output) -> Self {
+        let cluster = ClusterManager::new(client.clone(), output.clone());
+        Self {
+            client,
+            cluster,
+            output,
+        }
+    }
+
+    /// Lists the firewall rules of a guest, warning when its firewall is
+    /// disabled and the rules therefore not applied.
+    pub async fn list_rules(&self, vm_identifier: &str) -> Result<()> {
+        #[derive(Deserialize)]
+        struct FirewallOptions {
+            #[serde(default, deserialize_with = "deserialize_flag")]
+            enable: bool,
+        }
+
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let rules: Vec<FirewallRule> = self
+            .client
+            .get(&format!("{}/firewall/rules", guest.path()))
+            .await?;
+        self.output
+            .print_list(&format!("Firewall rules of VM {}:", guest.vmid), &rules)?;
+
+        let options: FirewallOptions = self
+            .client
+            .get(&format!("{}/firewall/options", guest.path()))
+            .await?;
+        if !options.enable {
+            self.output.warn(&format!(
+                "The firewall of VM {} is disabled, so these rules are not applied",
+                guest.vmid
+            ));
+        }
+        Ok(())
+    }
+
+    pub async fn add_rule(&self, vm_identifier: &str, rule: &NewRule) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let _: serde_json::Value = self
+            .client
+            .post(&format!("{}/firewall/rules", guest.path()), rule)
+            .await?;
+        self.output.message(&format!(
+            "Added {} {} rule at position {} of the firewall of VM {}",
+            rule.direction,
+            rule.action,
+            rule.pos.unwrap_or(0),
+            guest.vmid
+        ));
+        Ok(())
+    }
+
+    /// Deletes the firewall rule at `pos`. Later rules move up by one. The
+    /// digest of the listing is sent along, so Proxmox refuses the deletion
+    /// if the rules changed in between and `pos` may name another rule.
+    pub async fn delete_rule(&self, vm_identifier: &str, pos: u32) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let endpoint = format!("{}/firewall/rules", guest.path());
+        let rules: Vec<FirewallRule> = self.client.get(&endpoint).await?;
+        let Some(rule) = rules.iter().find(|r| r.pos == pos) else {
+            anyhow::bail!("VM {} has no firewall rule at position {}", guest.vmid, pos);
+        };
+
+        let mut rule_endpoint = format!("{}/{}", endpoint, pos);
+        if let Some(digest) = &rule.digest {
+            rule_endpoint.push_str(&format!("?digest={}", url_encode(digest)));
+        }
+        let _: serde_json::Value = self.client.delete(&rule_endpoint).await?;
+        self.output.message(&format!(
+            "Deleted firewall rule {} of VM {}: {}",
+            pos,
+            guest.vmid,
+            rule.summary()
+        ));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rule_summary() {
+        let rule: FirewallRule = serde_json::from_value(serde_json::json!({
+            "pos": 0, "type": "in", "action": "ACCEPT", "enable": 1,
+            "proto": "tcp", "source": "10.0.0.0/8", "dport": "22"
+        }))
+        .unwrap();
+        assert!(rule.enable);
+        assert_eq!(rule.summary(), "in ACCEPT tcp from 10.0.0.0/8 dport 22");
+
+        let group: FirewallRule = serde_json::from_value(serde_json::json!({
+            "pos": 1, "type": "group", "action": "webservers"
+        }))
+        .unwrap();
+        assert!(!group.enable);
+        assert_eq!(group.summary(), "group webservers");
+    }
+
+    #[test]
+    fn test_rule_digest() {
+        let rule: FirewallRule = serde_json::from_value(serde_json::json!({
+            "pos": 0, "type": "in", "action": "ACCEPT", "digest": "5f1c9e"
+        }))
+        .unwrap();
+        assert_eq!(rule.digest.as_deref(), Some("5f1c9e"));
+        assert!(serde_json::to_value(&rule).unwrap().get("digest").is_none());
+    }
+
+    #[test]
+    fn test_new_rule_serialization() {
+        let rule = NewRule {
+            direction: "in".to_string(),
+            action: "DROP".to_string(),
+            enable: 1,
+            dport: Some("3306".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_value(&rule).unwrap(),
+            serde_json::json!({"type": "in", "action": "DROP", "enable": 1, "dport": "3306"})
+        );
+    }
+}
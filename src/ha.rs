@@ -0,0 +1,294 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::client::ProxmoxClient;
+use crate::cluster::{ClusterManager, Guest};
+use crate::node::MaintenanceOptions;
+use crate::output::{Color, Output, Tabular};
+use crate::prompt;
+use crate::snapshot::SnapshotManager;
+use crate::ssh;
+
+/// Manages the HA resources of the cluster and the HA maintenance mode of
+/// its nodes.
+pub struct HaManager {
+    client: ProxmoxClient,
+    cluster: ClusterManager,
+    output: Output,
+}
+
+/// The state HA keeps a resource in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HaState {
+    Started,
+    Stopped,
+    /// Stopped, and not recovered on node failure.
+    Disabled,
+    /// Left alone by HA, e.g. while snapshotting or rolling back.
+    Ignored,
+}
+
+impl HaState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HaState::Started => "started",
+            HaState::Stopped => "stopped",
+            HaState::Disabled => "disabled",
+            HaState::Ignored => "ignored",
+        }
+    }
+}
+
+/// The HA resource ID of a guest, e.g. `vm:100` or `ct:200`.
+pub fn sid(guest: &Guest) -> String {
+    let prefix = if guest.is_container() { "ct" } else { "vm" };
+    format!("{}:{}", prefix, guest.vmid)
+}
+
+/// A resource managed by HA, as listed by `/cluster/ha/resources`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct HaResource {
+    pub sid: String,
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub max_restart: Option<u32>,
+    #[serde(default)]
+    pub max_relocate: Option<u32>,
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+impl Tabular for HaResource {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("sid", "Resource"),
+            ("state", "State"),
+            ("group", "Group"),
+            ("max_restart", "Max restart"),
+            ("max_relocate", "Max relocate"),
+            ("comment", "Comment"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        // Proxmox omits the state of resources left at the default.
+        let state = self.state.as_deref().unwrap_or("started");
+        let state = match state {
+            "started" => state.to_string(),
+            _ => output.paint(Color::Yellow, state),
+        };
+        let number = |n: Option<u32>| n.map_or_else(|| "-".to_string(), |n| n.to_string());
+        vec![
+            self.sid.clone(),
+            state,
+            self.group.clone().unwrap_or_default(),
+            number(self.max_restart),
+            number(self.max_relocate),
+            self.comment.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+/// Settings of an HA resource; options left unset keep their value, or the
+/// Proxmox default for a new resource.
+#[derive(Debug, Default, Serialize)]
+pub struct HaSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_restart: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_relocate: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+impl HaSettings {
+    pub fn is_empty(&self) -> bool {
+        self.state.is_none()
+            && self.group.is_none()
+            && self.max_restart.is_none()
+            && self.max_relocate.is_none()
+            && self.comment.is_none()
+    }
+}
+
+impl HaManager {
+    pub fn new(client: ProxmoxClient, output: Output) -> Self {
+        let cluster = ClusterManager::new(client.clone(), output.clone());
+        Self {
+            client,
+            cluster,
+            output,
+        }
+    }
+
+    pub async fn list_resources(&self) -> Result<()> {
+        let resources: Vec<HaResource> = self.client.get("/cluster/ha/resources").await?;
+        self.output.print_list("HA resources:", &resources)
+    }
+
+    /// Puts a guest under HA management.
+    pub async fn add_resource(&self, vm_identifier: &str, settings: &HaSettings) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let sid = sid(&guest);
+        let mut data = serde_json::to_value(settings)?;
+        data["sid"] = sid.clone().into();
+        let _: serde_json::Value = self.client.post("/cluster/ha/resources", &data).await?;
+        self.output
+            .message(&format!("Added VM {} to HA as {}", guest.vmid, sid));
+        Ok(())
+    }
+
+    /// Changes the HA settings of a guest, e.g. its state to `ignored`
+    /// before a rollback so that HA does not fight it.
+    pub async fn set_resource(&self, vm_identifier: &str, settings: &HaSettings) -> Result<()> {
+        if settings.is_empty() {
+            anyhow::bail!(
+                "Nothing to change; pass --state, --group, --max-restart, --max-relocate or --comment"
+            );
+        }
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let sid = sid(&guest);
+        let _: serde_json::Value = self
+            .client
+            .put(&format!("/cluster/ha/resources/{}", sid), settings)
+            .await?;
+        self.output.message(&format!("Updated HA resource {}", sid));
+        Ok(())
+    }
+
+    /// Takes a guest out of HA management, leaving it in its current state.
+    pub async fn remove_resource(&self, vm_identifier: &str) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let sid = sid(&guest);
+        let _: serde_json::Value = self
+            .client
+            .delete(&format!("/cluster/ha/resources/{}", sid))
+            .await?;
+        self.output.message(&format!("Removed {} from HA", sid));
+        Ok(())
+    }
+
+    /// Puts `node` in maintenance: evacuates it if asked, turns on its HA
+    /// maintenance mode so that HA moves its managed guests away and keeps
+    /// them off, then waits until no guest is left running on it.
+    pub async fn enter_maintenance(
+        &self,
+        node: &str,
+        options: &MaintenanceOptions,
+        assume_yes: bool,
+    ) -> Result<()> {
+        prompt::confirm_or_abort(
+            &format!("This will put node {} in maintenance mode. Continue?", node),
+            assume_yes || self.client.is_dry_run(),
+        )?;
+        if options.evacuate {
+            let migrations = SnapshotManager::new(self.client.clone(), self.output.clone());
+            migrations
+                .evacuate_node(
+                    node,
+                    options.target.as_deref(),
+                    options.with_local_disks,
+                    options.jobs,
+                    true,
+                )
+                .await?;
+        }
+        self.set_ha_maintenance(node, &options.ssh_user, true)
+            .await?;
+        // HA maintenance stays on after a timeout, so say how to leave it.
+        self.cluster
+            .wait_node_idle(node, options.timeout)
+            .await
+            .with_context(|| {
+                format!(
+                    "Node {} is still in HA maintenance mode; run 'pve-tool node maintenance exit {}' to leave it",
+                    node, node
+                )
+            })?;
+        self.output.message(&format!(
+            "{} Node {} is in maintenance mode",
+            self.output.paint(Color::Green, "✓"),
+            node
+        ));
+        Ok(())
+    }
+
+    /// Takes `node` out of maintenance, after which HA moves back the guests
+    /// it had moved away.
+    pub async fn exit_maintenance(&self, node: &str, ssh_user: &str) -> Result<()> {
+        self.set_ha_maintenance(node, ssh_user, false).await?;
+        self.output.message(&format!(
+            "{} Node {} left maintenance mode",
+            self.output.paint(Color::Green, "✓"),
+            node
+        ));
+        Ok(())
+    }
+
+    /// Runs `ha-manager` over SSH to the node, as the API has no endpoint
+    /// for the maintenance mode.
+    async fn set_ha_maintenance(&self, node: &str, ssh_user: &str, enable: bool) -> Result<()> {
+        let host = self
+            .cluster
+            .node_address(node)
+            .await?
+            .unwrap_or_else(|| node.to_string());
+        let args = ssh::ha_maintenance_args(ssh_user, &host, node, enable);
+        if self.client.is_dry_run() {
+            eprintln!("[dry-run] ssh {}", args.join(" "));
+            return Ok(());
+        }
+        let status = std::process::Command::new("ssh")
+            .args(&args)
+            .status()
+            .context("Failed to run ssh")?;
+        if !status.success() {
+            anyhow::bail!("ha-manager failed on node {} ({})", node, status);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sid() {
+        let vm = Guest {
+            vmid: 100,
+            guest_type: "qemu".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(sid(&vm), "vm:100");
+        let ct = Guest {
+            vmid: 200,
+            guest_type: "lxc".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(sid(&ct), "ct:200");
+    }
+
+    #[test]
+    fn test_settings_serialization() {
+        let settings = HaSettings {
+            state: Some(HaState::Ignored.as_str()),
+            group: Some("g1".to_string()),
+            ..Default::default()
+        };
+        assert!(!settings.is_empty());
+        assert_eq!(
+            serde_json::to_value(&settings).unwrap(),
+            serde_json::json!({"state": "ignored", "group": "g1"})
+        );
+        assert!(HaSettings::default().is_empty());
+    }
+}
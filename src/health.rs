@@ -0,0 +1,272 @@
+use serde::{Deserialize, Serialize};
+
+use crate::nagios::NagiosState;
+use crate::output::{Color, Output, Tabular};
+use crate::report::StorageReport;
+use crate::serde_util::deserialize_flag;
+
+/// An entry of `/cluster/status`: the cluster itself, or one of its nodes.
+#[derive(Debug, Deserialize)]
+pub struct ClusterStatusEntry {
+    #[serde(rename = "type")]
+    pub entry_type: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    pub quorate: bool,
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    pub online: bool,
+}
+
+/// An entry of `/cluster/ha/status/current`: the quorum, the manager, a
+/// node's local resource manager or a service.
+#[derive(Debug, Deserialize)]
+pub struct HaStatusEntry {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub entry_type: String,
+    #[serde(default)]
+    pub status: Option<String>,
+    /// State of a service, e.g. `started` or `error`.
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default)]
+    pub sid: Option<String>,
+}
+
+/// Service states in which HA cannot keep the service running.
+const FAILED_HA_STATES: &[&str] = &["error", "fence", "recovery"];
+
+/// The outcome of one aspect of cluster health.
+#[derive(Debug, Serialize)]
+pub struct HealthCheck {
+    pub check: &'static str,
+    pub state: NagiosState,
+    pub detail: String,
+}
+
+impl HealthCheck {
+    fn new(check: &'static str, state: NagiosState, detail: String) -> Self {
+        Self {
+            check,
+            state,
+            detail,
+        }
+    }
+
+    pub fn unknown(check: &'static str, error: &anyhow::Error) -> Self {
+        Self::new(check, NagiosState::Unknown, format!("{:#}", error))
+    }
+}
+
+impl Tabular for HealthCheck {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[("check", "Check"), ("state", "State"), ("detail", "Detail")]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        let color = match self.state {
+            NagiosState::Ok => Color::Green,
+            NagiosState::Warning => Color::Yellow,
+            NagiosState::Critical | NagiosState::Unknown => Color::Red,
+        };
+        vec![
+            self.check.to_string(),
+            output.paint(color, self.state.label()),
+            self.detail.clone(),
+        ]
+    }
+}
+
+/// Quorum is critical when lost; a standalone node has no cluster entry and
+/// is always quorate.
+pub fn check_quorum(entries: &[ClusterStatusEntry]) -> HealthCheck {
+    match entries.iter().find(|e| e.entry_type == "cluster") {
+        None => HealthCheck::new("quorum", NagiosState::Ok, "standalone node".to_string()),
+        Some(cluster) => {
+            let name = cluster.name.as_deref().unwrap_or("cluster");
+            if cluster.quorate {
+                HealthCheck::new("quorum", NagiosState::Ok, format!("{} is quorate", name))
+            } else {
+                HealthCheck::new(
+                    "quorum",
+                    NagiosState::Critical,
+                    format!("{} has lost quorum", name),
+                )
+            }
+        }
+    }
+}
+
+/// Offline nodes are a warning; losing quorum over them is up to
+/// `check_quorum`.
+pub fn check_nodes(entries: &[ClusterStatusEntry]) -> HealthCheck {
+    let nodes: Vec<&ClusterStatusEntry> =
+        entries.iter().filter(|e| e.entry_type == "node").collect();
+    let offline: Vec<&str> = nodes
+        .iter()
+        .filter(|n| !n.online)
+        .filter_map(|n| n.name.as_deref())
+        .collect();
+    let online = nodes.len() - offline.len();
+    if offline.is_empty() {
+        HealthCheck::new(
+            "nodes",
+            NagiosState::Ok,
+            format!("{}/{} online", online, nodes.len()),
+        )
+    } else {
+        HealthCheck::new(
+            "nodes",
+            NagiosState::Warning,
+            format!(
+                "{}/{} online; offline: {}",
+                online,
+                nodes.len(),
+                offline.join(", ")
+            ),
+        )
+    }
+}
+
+/// Services HA cannot run are critical, an HA quorum that is not `OK` a
+/// warning.
+pub fn check_ha(entries: &[HaStatusEntry]) -> HealthCheck {
+    let services: Vec<&HaStatusEntry> = entries
+        .iter()
+        .filter(|e| e.entry_type == "service")
+        .collect();
+    if services.is_empty() {
+        return HealthCheck::new("ha", NagiosState::Ok, "no HA resources".to_string());
+    }
+
+    let failed: Vec<String> = services
+        .iter()
+        .filter(|s| {
+            s.state
+                .as_deref()
+                .is_some_and(|state| FAILED_HA_STATES.contains(&state))
+        })
+        .map(|s| {
+            format!(
+                "{} ({})",
+                s.sid.as_deref().unwrap_or(&s.id),
+                s.state.as_deref().unwrap_or_default()
+            )
+        })
+        .collect();
+    if !failed.is_empty() {
+        return HealthCheck::new(
+            "ha",
+            NagiosState::Critical,
+            format!("failed: {}", failed.join(", ")),
+        );
+    }
+
+    let quorum = entries
+        .iter()
+        .find(|e| e.entry_type == "quorum")
+        .and_then(|e| e.status.as_deref());
+    match quorum {
+        Some("OK") | None => HealthCheck::new(
+            "ha",
+            NagiosState::Ok,
+            format!("{} resources managed", services.len()),
+        ),
+        Some(status) => HealthCheck::new("ha", NagiosState::Warning, format!("quorum: {}", status)),
+    }
+}
+
+/// Storages above the report's threshold are a warning.
+pub fn check_storage(report: &StorageReport, warn_pct: f64) -> HealthCheck {
+    if report.warnings.is_empty() {
+        return HealthCheck::new(
+            "storage",
+            NagiosState::Ok,
+            format!("{} storages below {}%", report.storages.len(), warn_pct),
+        );
+    }
+    let full: Vec<String> = report
+        .warnings
+        .iter()
+        .map(|w| format!("{}@{} ({:.0}%)", w.storage, w.nodes.join(","), w.percent()))
+        .collect();
+    HealthCheck::new(
+        "storage",
+        NagiosState::Warning,
+        format!("above {}%: {}", warn_pct, full.join(", ")),
+    )
+}
+
+/// The worst state of `checks`.
+pub fn overall(checks: &[HealthCheck]) -> NagiosState {
+    checks
+        .iter()
+        .map(|c| c.state)
+        .fold(NagiosState::Ok, NagiosState::worst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(json: serde_json::Value) -> Vec<ClusterStatusEntry> {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_check_quorum_and_nodes() {
+        let entries = status(serde_json::json!([
+            { "type": "cluster", "name": "prod", "quorate": 1 },
+            { "type": "node", "name": "pve1", "online": 1 },
+            { "type": "node", "name": "pve2", "online": 0 },
+        ]));
+        assert_eq!(check_quorum(&entries).state, NagiosState::Ok);
+        let nodes = check_nodes(&entries);
+        assert_eq!(nodes.state, NagiosState::Warning);
+        assert_eq!(nodes.detail, "1/2 online; offline: pve2");
+
+        let lost = status(serde_json::json!([{ "type": "cluster", "name": "prod", "quorate": 0 }]));
+        assert_eq!(check_quorum(&lost).state, NagiosState::Critical);
+        let standalone =
+            status(serde_json::json!([{ "type": "node", "name": "pve1", "online": 1 }]));
+        assert_eq!(check_quorum(&standalone).state, NagiosState::Ok);
+    }
+
+    #[test]
+    fn test_check_ha() {
+        let entries: Vec<HaStatusEntry> = serde_json::from_value(serde_json::json!([
+            { "id": "quorum", "type": "quorum", "status": "OK" },
+            { "id": "service:100", "type": "service", "sid": "vm:100", "state": "started" },
+            { "id": "service:101", "type": "service", "sid": "vm:101", "state": "error" },
+        ]))
+        .unwrap();
+        let check = check_ha(&entries);
+        assert_eq!(check.state, NagiosState::Critical);
+        assert_eq!(check.detail, "failed: vm:101 (error)");
+
+        assert_eq!(check_ha(&entries[..2]).state, NagiosState::Ok);
+        assert_eq!(check_ha(&[]).detail, "no HA resources");
+    }
+
+    #[test]
+    fn test_overall() {
+        let checks = vec![
+            HealthCheck::new("quorum", NagiosState::Ok, String::new()),
+            HealthCheck::new("storage", NagiosState::Warning, String::new()),
+        ];
+        assert_eq!(overall(&checks), NagiosState::Warning);
+        assert_eq!(overall(&[]), NagiosState::Ok);
+
+        let checks = [
+            HealthCheck::new("quorum", NagiosState::Critical, String::new()),
+            HealthCheck::new("ha", NagiosState::Unknown, String::new()),
+        ];
+        assert_eq!(overall(&checks), NagiosState::Critical);
+        let checks = [
+            HealthCheck::new("ha", NagiosState::Unknown, String::new()),
+            HealthCheck::new("storage", NagiosState::Warning, String::new()),
+        ];
+        assert_eq!(overall(&checks), NagiosState::Unknown);
+    }
+}
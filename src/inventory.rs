@@ -0,0 +1,174 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::cluster::Guest;
+use crate::output::{Output, Tabular};
+
+/// Format version written to exported files.
+pub const VERSION: u32 = 1;
+
+/// Snapshot metadata of a set of VMs, as written by `export`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Inventory {
+    pub version: u32,
+    /// Unix time of the export.
+    pub exported_at: i64,
+    pub vms: Vec<InventoryVm>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InventoryVm {
+    pub vmid: u32,
+    pub name: Option<String>,
+    pub node: String,
+    #[serde(rename = "type")]
+    pub guest_type: String,
+    pub pool: Option<String>,
+    pub snapshots: Vec<InventorySnapshot>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InventorySnapshot {
+    pub name: String,
+    pub snaptime: Option<i64>,
+    pub description: Option<String>,
+    pub parent: Option<String>,
+    #[serde(default)]
+    pub vmstate: bool,
+}
+
+impl InventoryVm {
+    pub fn new(guest: &Guest, snapshots: Vec<InventorySnapshot>) -> Self {
+        Self {
+            vmid: guest.vmid,
+            name: guest.name.clone(),
+            node: guest.node.clone(),
+            guest_type: guest.guest_type.clone(),
+            pool: guest.pool.clone(),
+            snapshots,
+        }
+    }
+
+    /// The cluster VM this entry refers to: the one with the same VMID, or
+    /// failing that the one with the same name, for clusters rebuilt with
+    /// new IDs.
+    pub fn find<'a>(&self, guests: &'a [Guest]) -> Option<&'a Guest> {
+        guests.iter().find(|g| g.vmid == self.vmid).or_else(|| {
+            let name = self.name.as_deref()?;
+            guests.iter().find(|g| g.name.as_deref() == Some(name))
+        })
+    }
+
+    /// Snapshots not among `existing`, oldest first so that recreating them
+    /// in order rebuilds the original parent chain as far as possible.
+    pub fn missing(&self, existing: &HashSet<String>) -> Vec<&InventorySnapshot> {
+        let mut missing: Vec<&InventorySnapshot> = self
+            .snapshots
+            .iter()
+            .filter(|s| !existing.contains(&s.name))
+            .collect();
+        missing.sort_by_key(|s| s.snaptime);
+        missing
+    }
+}
+
+/// What `import` found or did for one snapshot.
+#[derive(Debug, Serialize)]
+pub struct ImportResult {
+    pub vmid: u32,
+    pub name: Option<String>,
+    pub snapshot: String,
+    pub status: String,
+}
+
+impl Tabular for ImportResult {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("vmid", "VMID"),
+            ("name", "Name"),
+            ("snapshot", "Snapshot"),
+            ("status", "Status"),
+        ]
+    }
+
+    fn row(&self, _output: &Output) -> Vec<String> {
+        vec![
+            self.vmid.to_string(),
+            self.name.clone().unwrap_or_default(),
+            self.snapshot.clone(),
+            self.status.clone(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(name: &str, snaptime: i64) -> InventorySnapshot {
+        InventorySnapshot {
+            name: name.to_string(),
+            snaptime: Some(snaptime),
+            description: None,
+            parent: None,
+            vmstate: false,
+        }
+    }
+
+    fn guest(vmid: u32, name: &str) -> Guest {
+        Guest {
+            node: "pve1".to_string(),
+            vmid,
+            name: Some(name.to_string()),
+            guest_type: "qemu".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_missing_oldest_first() {
+        let vm = InventoryVm::new(
+            &guest(100, "web"),
+            vec![
+                snapshot("weekly", 300),
+                snapshot("base", 100),
+                snapshot("present", 200),
+            ],
+        );
+        let existing: HashSet<String> = ["present".to_string()].into();
+        let names: Vec<&str> = vm
+            .missing(&existing)
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["base", "weekly"]);
+    }
+
+    #[test]
+    fn test_find_by_vmid_then_name() {
+        let vm = InventoryVm::new(&guest(100, "web"), Vec::new());
+        let guests = vec![guest(200, "web"), guest(100, "other")];
+        assert_eq!(vm.find(&guests).unwrap().vmid, 100);
+
+        let renumbered = vec![guest(200, "web")];
+        assert_eq!(vm.find(&renumbered).unwrap().vmid, 200);
+
+        assert!(vm.find(&[guest(300, "db")]).is_none());
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let inventory = Inventory {
+            version: VERSION,
+            exported_at: 1_710_000_000,
+            vms: vec![InventoryVm::new(
+                &guest(100, "web"),
+                vec![snapshot("base", 100)],
+            )],
+        };
+        let json = serde_json::to_string(&inventory).unwrap();
+        let parsed: Inventory = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.vms[0].guest_type, "qemu");
+        assert_eq!(parsed.vms[0].snapshots[0].name, "base");
+    }
+}
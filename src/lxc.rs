@@ -0,0 +1,442 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+
+use crate::client::ProxmoxClient;
+use crate::cluster::ClusterManager;
+use crate::output::{Color, Output, Tabular};
+use crate::provision::{self, ip_config};
+use crate::size::parse_size;
+use crate::snapshot::{self, SnapshotManager};
+use crate::ssh;
+use crate::tasks::TaskManager;
+
+/// Manages the containers of the cluster.
+pub struct LxcManager {
+    client: ProxmoxClient,
+    cluster: ClusterManager,
+    tasks: TaskManager,
+    output: Output,
+}
+
+/// An appliance template offered for download, as listed by `aplinfo`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ApplianceTemplate {
+    /// File name, e.g. `debian-12-standard_12.7-1_amd64.tar.zst`.
+    pub template: String,
+    /// `system` for plain distributions, `turnkeylinux` for appliances.
+    pub section: String,
+    #[serde(default)]
+    pub os: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub headline: Option<String>,
+}
+
+impl Tabular for ApplianceTemplate {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("template", "Template"),
+            ("section", "Section"),
+            ("os", "OS"),
+            ("version", "Version"),
+            ("headline", "Description"),
+        ]
+    }
+
+    fn row(&self, _output: &Output) -> Vec<String> {
+        vec![
+            self.template.clone(),
+            self.section.clone(),
+            self.os.clone().unwrap_or_default(),
+            self.version.clone().unwrap_or_default(),
+            self.headline.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+/// A template already on a storage, from its `content` listing.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StoredTemplate {
+    /// Volume ID, e.g. `local:vztmpl/debian-12-standard_12.7-1_amd64.tar.zst`.
+    pub volid: String,
+    #[serde(default)]
+    pub size: u64,
+}
+
+impl Tabular for StoredTemplate {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[("volid", "Volume"), ("size", "Size")]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        vec![self.volid.clone(), output.format_bytes(self.size)]
+    }
+}
+
+/// The volume ID a template downloaded to `storage` gets.
+pub fn template_volid(storage: &str, template: &str) -> String {
+    format!("{}:vztmpl/{}", storage, template)
+}
+
+/// Options for `lxc create`.
+#[derive(Debug, Clone, Default)]
+pub struct ContainerOptions {
+    /// Volume ID of the template, e.g. `local:vztmpl/debian-12-standard_12.7-1_amd64.tar.zst`.
+    pub template: String,
+    pub hostname: String,
+    /// VMID of the new container; the next free one when unset.
+    pub vmid: Option<u32>,
+    pub storage: String,
+    /// Root filesystem size in bytes, a whole number of GiB.
+    pub disk_size: u64,
+    /// Memory in MiB.
+    pub memory: Option<u64>,
+    pub cores: Option<u32>,
+    pub bridge: String,
+    /// Address in CIDR notation, or `dhcp`.
+    pub ip: Option<String>,
+    pub gateway: Option<String>,
+    pub nameserver: Option<String>,
+    /// Files holding public SSH keys for root.
+    pub sshkeys: Vec<PathBuf>,
+    pub unprivileged: bool,
+    pub start: bool,
+}
+
+/// Parameters of `POST /nodes/{node}/lxc`.
+#[derive(Debug, Serialize)]
+pub struct NewContainer {
+    pub vmid: u32,
+    pub ostemplate: String,
+    pub hostname: String,
+    /// `STORAGE:SIZE`, which allocates a new volume of SIZE GiB.
+    pub rootfs: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cores: Option<u32>,
+    pub net0: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nameserver: Option<String>,
+    #[serde(rename = "ssh-public-keys", skip_serializing_if = "Option::is_none")]
+    pub ssh_public_keys: Option<String>,
+    pub unprivileged: u8,
+    /// Start the container once it is created, in the same task.
+    pub start: u8,
+}
+
+impl NewContainer {
+    pub fn from_options(options: &ContainerOptions, vmid: u32) -> Result<Self> {
+        const GIB: u64 = 1 << 30;
+        if !options.template.contains(':') {
+            anyhow::bail!(
+                "Template '{}' is not a volume ID such as local:vztmpl/{}",
+                options.template,
+                options.template
+            );
+        }
+        if options.disk_size == 0 || !options.disk_size.is_multiple_of(GIB) {
+            anyhow::bail!("The root filesystem is allocated in whole GiB, e.g. 8G");
+        }
+
+        let mut net0 = format!("name=eth0,bridge={}", options.bridge);
+        if let Some(ip) = &options.ip {
+            net0.push(',');
+            net0.push_str(&ip_config(ip, options.gateway.as_deref())?);
+        }
+
+        let mut keys = String::new();
+        for path in &options.sshkeys {
+            let key = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read SSH key {}", path.display()))?;
+            keys.push_str(key.trim_end());
+            keys.push('\n');
+        }
+
+        Ok(Self {
+            vmid,
+            ostemplate: options.template.clone(),
+            hostname: options.hostname.clone(),
+            rootfs: format!("{}:{}", options.storage, options.disk_size / GIB),
+            memory: options.memory,
+            cores: options.cores,
+            net0,
+            nameserver: options.nameserver.clone(),
+            ssh_public_keys: (!keys.is_empty()).then_some(keys),
+            unprivileged: options.unprivileged.into(),
+            start: options.start.into(),
+        })
+    }
+}
+
+impl LxcManager {
+    pub fn new(client: ProxmoxClient, output: Output) -> Self {
+        let cluster = ClusterManager::new(client.clone(), output.clone());
+        let tasks = TaskManager::new(client.clone(), output.clone());
+        Self {
+            client,
+            cluster,
+            tasks,
+            output,
+        }
+    }
+
+    /// Grows a disk of a container, such as `rootfs` or `mp0`, to `size` or
+    /// by `+size`. Containers cannot shrink, so smaller sizes are refused.
+    pub async fn resize_disk(&self, ct_identifier: &str, disk: &str, size: &str) -> Result<()> {
+        provision::validate_resize(size)?;
+        let guest = self.cluster.find_vm(ct_identifier).await?;
+        if !guest.is_container() {
+            anyhow::bail!("VM {} is not a container", guest.vmid);
+        }
+
+        let config: serde_json::Value =
+            self.client.get(&format!("{}/config", guest.path())).await?;
+        let Some(current) = snapshot::parse_disks(&config)
+            .into_iter()
+            .find(|d| d.name == disk)
+        else {
+            anyhow::bail!("Container {} has no disk {}", guest.vmid, disk);
+        };
+        if !size.starts_with('+')
+            && let Some(current) = current.size
+            && parse_size(size)? < current
+        {
+            anyhow::bail!(
+                "Containers cannot shrink; {} of container {} is {}",
+                disk,
+                guest.vmid,
+                self.output.format_bytes(current)
+            );
+        }
+
+        let guests = SnapshotManager::new(self.client.clone(), self.output.clone());
+        guests.resize_disk(&guest, disk, size).await?;
+        self.output
+            .message(&format!("Resized {} of container {}", disk, guest.vmid));
+        Ok(())
+    }
+
+    /// Creates a container from a template on `node`, or the first online
+    /// node, and optionally starts it.
+    pub async fn create(&self, node: Option<&str>, options: &ContainerOptions) -> Result<()> {
+        let vmid = match options.vmid {
+            Some(vmid) => vmid,
+            None => {
+                let next: String = self.client.get("/cluster/nextid").await?;
+                next.parse()
+                    .with_context(|| format!("Unexpected next free VMID '{}'", next))?
+            }
+        };
+        let request = NewContainer::from_options(options, vmid)?;
+        let node = self.cluster.node_or_first(node).await?;
+
+        let task_id: String = self
+            .client
+            .post(&format!("/nodes/{}/lxc", node), &request)
+            .await?;
+        self.output.print_raw(&task_id)?;
+        self.output.message(&format!(
+            "Creating container {} ({}) on node {}...",
+            vmid, options.hostname, node
+        ));
+        self.tasks.wait(&node, &task_id).await?;
+
+        self.output.message(&format!(
+            "{} Container {} ({}) created on node {}{}",
+            self.output.paint(Color::Green, "✓"),
+            vmid,
+            options.hostname,
+            node,
+            if options.start { " and started" } else { "" }
+        ));
+        Ok(())
+    }
+
+    /// Runs a command in a container with `pct exec` over SSH to its node, as
+    /// the API has no exec endpoint for containers. Returns the command's
+    /// exit code, or 255 when ssh itself fails.
+    pub async fn exec(
+        &self,
+        ct_identifier: &str,
+        command: &[String],
+        ssh_user: &str,
+    ) -> Result<i32> {
+        let guest = self.cluster.find_vm(ct_identifier).await?;
+        if !guest.is_container() {
+            anyhow::bail!(
+                "VM {} is not a container; use agent exec for VMs",
+                guest.vmid
+            );
+        }
+        let host = self
+            .cluster
+            .node_address(&guest.node)
+            .await?
+            .unwrap_or_else(|| guest.node.clone());
+
+        let args = ssh::pct_exec_args(
+            ssh_user,
+            &host,
+            guest.vmid,
+            command,
+            io::stdin().is_terminal(),
+        );
+        if self.client.is_dry_run() {
+            eprintln!("[dry-run] ssh {}", args.join(" "));
+            return Ok(0);
+        }
+        let status = std::process::Command::new("ssh")
+            .args(&args)
+            .status()
+            .context("Failed to run ssh")?;
+        Ok(status.code().unwrap_or(255))
+    }
+
+    /// Lists the appliance templates `node` can download, optionally of one
+    /// `section` only, or with `storage` the templates already on it.
+    pub async fn list_templates(
+        &self,
+        node: Option<&str>,
+        section: Option<&str>,
+        storage: Option<&str>,
+    ) -> Result<()> {
+        let node = self.cluster.node_or_first(node).await?;
+        if let Some(storage) = storage {
+            let templates: Vec<StoredTemplate> = self
+                .client
+                .get(&format!(
+                    "/nodes/{}/storage/{}/content?content=vztmpl",
+                    node, storage
+                ))
+                .await?;
+            return self
+                .output
+                .print_list(&format!("Templates on storage {}:", storage), &templates);
+        }
+
+        let mut templates: Vec<ApplianceTemplate> =
+            self.client.get(&format!("/nodes/{}/aplinfo", node)).await?;
+        if let Some(section) = section {
+            templates.retain(|t| t.section == section);
+        }
+        templates.sort_by(|a, b| (&a.section, &a.template).cmp(&(&b.section, &b.template)));
+        self.output
+            .print_list("Available container templates:", &templates)
+    }
+
+    /// Downloads an appliance template to `storage`, unless it is already
+    /// there.
+    pub async fn download_template(
+        &self,
+        node: Option<&str>,
+        storage: &str,
+        template: &str,
+    ) -> Result<()> {
+        let node = self.cluster.node_or_first(node).await?;
+        let available: Vec<ApplianceTemplate> =
+            self.client.get(&format!("/nodes/{}/aplinfo", node)).await?;
+        if !available.iter().any(|t| t.template == template) {
+            anyhow::bail!(
+                "Unknown template {}; see 'lxc templates list' for the available ones",
+                template
+            );
+        }
+
+        let volid = template_volid(storage, template);
+        let stored: Vec<StoredTemplate> = self
+            .client
+            .get(&format!(
+                "/nodes/{}/storage/{}/content?content=vztmpl",
+                node, storage
+            ))
+            .await?;
+        if stored.iter().any(|t| t.volid == volid) {
+            self.output
+                .message(&format!("{} is already downloaded", volid));
+            return Ok(());
+        }
+
+        let task_id: String = self
+            .client
+            .post(
+                &format!("/nodes/{}/aplinfo", node),
+                &serde_json::json!({ "storage": storage, "template": template }),
+            )
+            .await?;
+        self.output.print_raw(&task_id)?;
+        self.output.message(&format!(
+            "Downloading {} to storage {} on node {}...",
+            template, storage, node
+        ));
+        self.tasks.follow(&node, &task_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_appliance_template() {
+        let template: ApplianceTemplate = serde_json::from_value(serde_json::json!({
+            "template": "debian-12-standard_12.7-1_amd64.tar.zst",
+            "section": "system",
+            "os": "debian-12",
+            "version": "12.7-1",
+            "headline": "Debian 12 Bookworm (standard)",
+            "sha512sum": "abc",
+            "location": "http://download.proxmox.com/images/system/debian-12-standard_12.7-1_amd64.tar.zst"
+        }))
+        .unwrap();
+        assert_eq!(template.section, "system");
+        assert_eq!(
+            template_volid("local", &template.template),
+            "local:vztmpl/debian-12-standard_12.7-1_amd64.tar.zst"
+        );
+    }
+
+    #[test]
+    fn test_new_container() {
+        let options = ContainerOptions {
+            template: "local:vztmpl/debian-12-standard_12.7-1_amd64.tar.zst".to_string(),
+            hostname: "web".to_string(),
+            storage: "local-lvm".to_string(),
+            disk_size: 8 << 30,
+            memory: Some(1024),
+            bridge: "vmbr0".to_string(),
+            ip: Some("10.0.0.5/24".to_string()),
+            gateway: Some("10.0.0.1".to_string()),
+            unprivileged: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_value(NewContainer::from_options(&options, 200).unwrap()).unwrap(),
+            serde_json::json!({
+                "vmid": 200,
+                "ostemplate": "local:vztmpl/debian-12-standard_12.7-1_amd64.tar.zst",
+                "hostname": "web",
+                "rootfs": "local-lvm:8",
+                "memory": 1024,
+                "net0": "name=eth0,bridge=vmbr0,ip=10.0.0.5/24,gw=10.0.0.1",
+                "unprivileged": 1,
+                "start": 0
+            })
+        );
+
+        let bare = ContainerOptions {
+            template: "debian-12-standard_12.7-1_amd64.tar.zst".to_string(),
+            ..options.clone()
+        };
+        assert!(NewContainer::from_options(&bare, 200).is_err());
+        let partial = ContainerOptions {
+            disk_size: 3 << 29,
+            ..options
+        };
+        assert!(NewContainer::from_options(&partial, 200).is_err());
+    }
+}
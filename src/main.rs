@@ -1,16 +1,79 @@
-use anyhow::Result;
-use clap::{Parser, Subcommand};
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand};
 use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
 
+mod agent;
+mod audit;
+mod backup;
+mod ceph;
 mod client;
 mod cluster;
 mod config;
+mod console;
+mod daemon;
+mod disks;
+mod duration;
+mod editor;
+mod firewall;
+mod ha;
+mod health;
+mod inventory;
+mod lxc;
+mod metrics;
+mod migrate;
+mod nagios;
+mod naming;
+mod node;
+mod output;
+mod pattern;
+mod power;
+mod prompt;
+mod provision;
+mod prune;
+mod replication;
+mod report;
+mod sdn;
+mod serde_util;
+mod size;
 mod snapshot;
+mod ssh;
+mod storage;
+mod systemd;
+mod tasks;
+mod template;
+mod top;
+mod vmconfig;
+mod websocket;
 
+use audit::AuditLimits;
+use backup::{BackupManager, BackupMode, BackupOptions};
 use client::ProxmoxClient;
-use cluster::ClusterManager;
+use cluster::{ClusterManager, GuestType, Selection};
 use config::Config;
-use snapshot::SnapshotManager;
+use console::ConsoleManager;
+use daemon::{Daemon, LogFormat};
+use firewall::{Direction, FirewallManager, NewRule, RuleAction};
+use ha::{HaManager, HaSettings, HaState};
+use lxc::{ContainerOptions, LxcManager};
+use metrics::{MetricsManager, Timeframe};
+use migrate::MigrateOptions;
+use nagios::{CheckFormat, NagiosState, Thresholds};
+use node::{MaintenanceOptions, NodePowerCommand};
+use output::{Color, ColorChoice, Output, OutputFormat};
+use pattern::NamePattern;
+use power::{GuestState, OrderBy, PowerAction, PowerOptions};
+use provision::{CloudInitSection, ProvisionOptions};
+use prune::RetentionPolicy;
+use replication::ReplicationManager;
+use size::SizeUnits;
+use snapshot::{
+    CloneOptions, CreateOptions, NotesEdit, OnExisting, SnapshotManager, VmColumn, VmFilter,
+};
+use storage::{StorageManager, UploadContent};
+use tasks::TaskManager;
+use vmconfig::{DiskBus, ResourceOptions};
 
 #[derive(Parser)]
 #[command(name = "pve-tool")]
@@ -34,88 +97,2517 @@ struct Cli {
     #[arg(short = 'k', long, env = "PROXMOX_VERIFY_SSL")]
     verify_ssl: Option<bool>,
 
-    #[arg(short = 'R', long)]
+    #[arg(short = 'R', long, help = "Print unparsed API responses as JSON")]
     raw: bool,
 
-    #[arg(long, help = "Cluster name from config file")]
-    cluster: Option<String>,
+    #[arg(
+        short = 'q',
+        long,
+        global = true,
+        help = "Suppress progress messages, printing only errors and requested data"
+    )]
+    quiet: bool,
+
+    #[arg(long, global = true, help = "Show sizes as plain byte counts")]
+    bytes: bool,
+
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "bytes",
+        help = "Show sizes in powers of 1000 (kB, MB, GB) instead of 1024"
+    )]
+    si: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Display timestamps in UTC instead of local time"
+    )]
+    utc: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_parser = output::validate_time_format,
+        help = "strftime format for timestamps [default: %Y-%m-%d %H:%M:%S]"
+    )]
+    time_format: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Print the API calls that would change snapshots or VMs instead of making them"
+    )]
+    dry_run: bool,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Cluster name from config file; list-vms, audit and report accept several"
+    )]
+    cluster: Vec<String>,
+
+    #[arg(
+        long,
+        conflicts_with = "cluster",
+        help = "Run list-vms, audit or report on every cluster in the config file"
+    )]
+    all_clusters: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        global = true,
+        default_value_t = OutputFormat::Table,
+        help = "Output format for list and info commands"
+    )]
+    output: OutputFormat,
+
+    #[arg(
+        long,
+        value_enum,
+        global = true,
+        default_value_t = ColorChoice::Auto,
+        help = "Colorize output (honours NO_COLOR in auto mode)"
+    )]
+    color: ColorChoice,
 
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand)]
-enum Commands {
+enum SnapshotCommand {
+    #[command(about = "Change the description of an existing snapshot")]
+    Edit {
+        vm: String,
+        snapname: String,
+        #[arg(short = 'd', long)]
+        description: String,
+    },
+    #[command(about = "Compare the VM configuration stored in two snapshots")]
+    Diff {
+        vm: String,
+        #[arg(help = "Snapshot to compare from")]
+        from: String,
+        #[arg(help = "Snapshot to compare to [default: current configuration]")]
+        to: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum VmConfigCommand {
+    #[command(about = "Show the configuration of a VM, or the options matching a key or glob")]
+    Get { vm: String, key: Option<String> },
+    #[command(about = "Change configuration options of a VM")]
+    Set {
+        vm: String,
+        #[arg(value_name = "KEY=VALUE", value_parser = vmconfig::parse_assignment)]
+        settings: Vec<(String, String)>,
+        #[arg(long, value_name = "KEY", help = "Remove an option (repeatable)")]
+        delete: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum DiskCommand {
+    #[command(about = "Move a disk to another storage")]
+    Move {
+        vm: String,
+        #[arg(help = "Disk to move, e.g. scsi0, or rootfs/mp0 for containers")]
+        disk: String,
+        #[arg(long, help = "Target storage")]
+        storage: String,
+        #[arg(long, help = "Target format, e.g. raw or qcow2")]
+        format: Option<String>,
+        #[arg(long, help = "Remove the source disk instead of keeping it as unused")]
+        delete_source: bool,
+    },
+    #[command(about = "Allocate a new disk and attach it to a VM, or mount it in a container")]
+    Attach {
+        vm: String,
+        #[arg(long, help = "Storage to allocate the disk on")]
+        storage: String,
+        #[arg(long, value_parser = size::parse_size, help = "Disk size in whole GiB, e.g. 50G")]
+        size: u64,
+        #[arg(long, value_enum, default_value_t = DiskBus::Scsi)]
+        bus: DiskBus,
+        #[arg(
+            long,
+            value_name = "PATH",
+            help = "Path to mount the disk at inside a container"
+        )]
+        mount: Option<String>,
+        #[arg(long, help = "Exclude the disk from vzdump backups")]
+        no_backup: bool,
+    },
+    #[command(about = "Detach a disk from a VM, keeping it as an unused disk")]
+    Detach {
+        vm: String,
+        #[arg(help = "Disk to detach, e.g. scsi2")]
+        disk: String,
+        #[arg(long, help = "Delete the volume instead of keeping it as unused")]
+        destroy: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TagCommand {
+    #[command(about = "Add tags to a VM")]
+    Add {
+        vm: String,
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    #[command(about = "Remove tags from a VM")]
+    Remove {
+        vm: String,
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    #[command(about = "List the tags of a VM, or all tags in the cluster")]
+    List { vm: Option<String> },
+}
+
+#[derive(Args)]
+struct RuleArgs {
+    #[arg(long, value_enum, default_value_t = Direction::In, help = "Traffic direction")]
+    direction: Direction,
+    #[arg(
+        long,
+        value_enum,
+        required_unless_present = "group",
+        help = "What to do with matching traffic"
+    )]
+    action: Option<RuleAction>,
+    #[arg(
+        long,
+        conflicts_with = "action",
+        help = "Apply this security group instead of a single rule"
+    )]
+    group: Option<String>,
+    #[arg(
+        long = "macro",
+        value_name = "MACRO",
+        help = "Predefined service, e.g. SSH"
+    )]
+    macro_name: Option<String>,
+    #[arg(long, help = "Protocol, e.g. tcp or udp")]
+    proto: Option<String>,
+    #[arg(long, help = "Source address, range or alias")]
+    source: Option<String>,
+    #[arg(long, help = "Destination address, range or alias")]
+    dest: Option<String>,
+    #[arg(long, help = "Source port or range")]
+    sport: Option<String>,
+    #[arg(long, help = "Destination port or range")]
+    dport: Option<String>,
+    #[arg(long, help = "Network interface, e.g. net0")]
+    iface: Option<String>,
+    #[arg(long)]
+    comment: Option<String>,
+    #[arg(long, help = "Insert at this position instead of first")]
+    pos: Option<u32>,
+    #[arg(long, help = "Add the rule disabled")]
+    disabled: bool,
+}
+
+#[derive(Subcommand)]
+enum FirewallCommand {
+    #[command(about = "List the firewall rules of a VM")]
+    List { vm: String },
+    #[command(about = "Add a firewall rule to a VM")]
+    Add {
+        vm: String,
+        #[command(flatten)]
+        rule: Box<RuleArgs>,
+    },
+    #[command(about = "Delete the firewall rule at a position")]
+    Delete { vm: String, pos: u32 },
+}
+
+#[derive(Subcommand)]
+enum HaCommand {
+    #[command(about = "List the resources managed by HA")]
+    List,
+    #[command(about = "Put a VM under HA management")]
+    Add {
+        vm: String,
+        #[command(flatten)]
+        settings: HaArgs,
+    },
+    #[command(about = "Change the HA settings of a VM")]
+    Set {
+        vm: String,
+        #[command(flatten)]
+        settings: HaArgs,
+    },
+    #[command(about = "Take a VM out of HA management")]
+    Remove { vm: String },
+}
+
+#[derive(Args)]
+struct HaArgs {
+    #[arg(
+        long,
+        value_enum,
+        help = "State HA keeps the VM in; ignored lets it be handled by hand"
+    )]
+    state: Option<HaState>,
+    #[arg(long, help = "HA group restricting the nodes the VM may run on")]
+    group: Option<String>,
+    #[arg(long, help = "Restarts on the same node before relocating")]
+    max_restart: Option<u32>,
+    #[arg(long, help = "Relocations to other nodes before giving up")]
+    max_relocate: Option<u32>,
+    #[arg(long)]
+    comment: Option<String>,
+}
+
+impl HaArgs {
+    fn settings(self) -> HaSettings {
+        HaSettings {
+            state: self.state.map(HaState::as_str),
+            group: self.group,
+            max_restart: self.max_restart,
+            max_relocate: self.max_relocate,
+            comment: self.comment,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum BackupCommand {
+    #[command(about = "Back up a VM with vzdump now")]
+    Run {
+        vm: String,
+        #[arg(long, help = "Backup storage [default: the node's vzdump storage]")]
+        storage: Option<String>,
+        #[arg(long, value_enum, default_value_t = BackupMode::Snapshot)]
+        mode: BackupMode,
+        #[arg(
+            long,
+            help = "Notes of the backup; may use {{guestname}}, {{vmid}} and {{node}}"
+        )]
+        notes: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ReplicationCommand {
+    #[command(about = "List replication jobs and their last and next sync")]
+    List {
+        #[arg(help = "Only the jobs of this VM")]
+        vm: Option<String>,
+    },
+    #[command(about = "Run a replication job now")]
+    Run {
+        #[arg(help = "Job ID, e.g. 100-0")]
+        job: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum SdnCommand {
+    #[command(about = "List SDN zones and vnets, or the vnets a VM attaches to")]
+    List {
+        #[arg(long, help = "Show the NICs of this VM and the vnets they attach to")]
+        vm: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum PoolCommand {
+    #[command(about = "List the resource pools and their VMs")]
+    List,
+    #[command(about = "Create a resource pool")]
     Create {
+        pool: String,
+        #[arg(long)]
+        comment: Option<String>,
+    },
+    #[command(about = "Delete an empty resource pool")]
+    Delete { pool: String },
+    #[command(about = "Add VMs to a resource pool")]
+    AddMember {
+        pool: String,
+        #[arg(required = true)]
+        vms: Vec<String>,
+    },
+    #[command(about = "Remove VMs from a resource pool")]
+    RemoveMember {
+        pool: String,
+        #[arg(required = true)]
+        vms: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum CloudInitCommand {
+    #[command(about = "Print the cloud-init configuration generated for a VM")]
+    Dump {
         vm: String,
+        #[arg(value_enum, default_value_t = CloudInitSection::User)]
+        section: CloudInitSection,
+    },
+    #[command(about = "Rebuild the cloud-init drive of a VM from its settings")]
+    Regenerate { vm: String },
+}
+
+#[derive(Args)]
+struct BulkOptions {
+    #[arg(required_unless_present_any = ["pool", "tag"], help = "VMs to include")]
+    vms: Vec<String>,
+    #[command(flatten)]
+    selector: GuestSelector,
+    #[arg(long, value_enum, default_value_t = OrderBy::Startup, help = "Order to start VMs in")]
+    order_by: OrderBy,
+    #[arg(
+        long,
+        value_parser = duration::parse_duration,
+        default_value = "0",
+        help = "Pause between VMs whose startup option sets no up delay, e.g. 10 or 1m"
+    )]
+    delay: u64,
+}
+
+impl BulkOptions {
+    fn selection(self) -> Selection {
+        let mut selection = self.selector.with_vm(None);
+        selection.vms = self.vms;
+        selection
+    }
+}
+
+#[derive(Subcommand)]
+enum BulkCommand {
+    #[command(about = "Start VMs one after another in boot order")]
+    Start {
+        #[command(flatten)]
+        options: BulkOptions,
+    },
+    #[command(about = "Power off VMs one after another in reverse boot order")]
+    Stop {
+        #[command(flatten)]
+        options: BulkOptions,
+    },
+    #[command(about = "Shut down VMs one after another in reverse boot order")]
+    Shutdown {
+        #[command(flatten)]
+        options: BulkOptions,
+        #[arg(
+            long,
+            value_parser = duration::parse_duration,
+            help = "Time each VM may take to shut down; defaults to its startup down value"
+        )]
+        timeout: Option<u64>,
+        #[arg(
+            long,
+            help = "Power off VMs that have not shut down within the timeout"
+        )]
+        force_stop: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum MetricsCommand {
+    #[command(about = "Print the CPU, memory, network and disk history of a node or VM")]
+    History {
+        #[arg(help = "Node name, or VMID or name of a VM")]
+        target: String,
+        #[arg(long, value_enum, default_value_t = Timeframe::Hour)]
+        timeframe: Timeframe,
+    },
+}
+
+#[derive(Subcommand)]
+enum NodeCommand {
+    #[command(about = "Show versions, load, memory, swap and root FS usage of a node")]
+    Info { node: String },
+    #[command(about = "Show the subscription and enabled repositories of a node")]
+    Subscription {
+        #[arg(required_unless_present = "all")]
+        node: Option<String>,
+        #[arg(long, conflicts_with = "node", help = "Every online node")]
+        all: bool,
+    },
+    #[command(about = "List the package updates pending on a node")]
+    Updates {
+        #[arg(required_unless_present = "all")]
+        node: Option<String>,
+        #[arg(long, conflicts_with = "node", help = "Every online node")]
+        all: bool,
+        #[arg(long, help = "Exit non-zero if security updates are pending")]
+        fail_on_security: bool,
+    },
+    #[command(about = "Check the TLS certificates of the nodes; fails if one expires soon")]
+    Certs {
+        #[arg(help = "Only this node [default: every online node]")]
+        node: Option<String>,
+        #[arg(
+            long,
+            default_value_t = 30,
+            help = "Warn about certificates expiring within this many days"
+        )]
+        warn_days: i64,
+    },
+    #[command(about = "Compare pve-manager, kernel and QEMU versions across nodes")]
+    Versions,
+    #[command(about = "List the bridges, bonds, VLANs and ports of a node")]
+    Network { node: String },
+    #[command(about = "Reboot a node")]
+    Reboot {
+        node: String,
+        #[arg(long, help = "Reboot even though guests are running on the node")]
+        force: bool,
+        #[arg(short = 'y', long, help = "Reboot without asking for confirmation")]
+        yes: bool,
+    },
+    #[command(about = "Shut a node down")]
+    Shutdown {
+        node: String,
+        #[arg(long, help = "Shut down even though guests are running on the node")]
+        force: bool,
+        #[arg(short = 'y', long, help = "Shut down without asking for confirmation")]
+        yes: bool,
+    },
+    #[command(about = "Put a node in or out of HA maintenance mode")]
+    Maintenance {
+        #[command(subcommand)]
+        maintenance: MaintenanceCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum MaintenanceCommand {
+    #[command(
+        about = "Set the HA maintenance mode of a node, optionally evacuating it first, and wait until no guest runs on it"
+    )]
+    Enter {
+        node: String,
+        #[arg(long, help = "Migrate every guest off the node first")]
+        evacuate: bool,
+        #[arg(
+            long,
+            requires = "evacuate",
+            help = "Node to evacuate to [default: spread over the other online nodes by memory]"
+        )]
+        target: Option<String>,
+        #[arg(
+            short = 'j',
+            long,
+            default_value_t = 1,
+            value_parser = clap::value_parser!(u16).range(1..),
+            help = "Number of migrations to run at the same time"
+        )]
+        jobs: u16,
+        #[arg(long, help = "Copy disks on local storage to the target node")]
+        with_local_disks: bool,
+        #[arg(long, default_value = "root", help = "User to log in to the node as")]
+        ssh_user: String,
+        #[arg(
+            long,
+            default_value = "10m",
+            value_parser = duration::parse_duration,
+            help = "Fail if guests are still running on the node after this long"
+        )]
+        timeout: u64,
+        #[arg(short = 'y', long, help = "Proceed without asking for confirmation")]
+        yes: bool,
+    },
+    #[command(about = "Clear the HA maintenance mode of a node")]
+    Exit {
+        node: String,
+        #[arg(long, default_value = "root", help = "User to log in to the node as")]
+        ssh_user: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum StorageCommand {
+    #[command(about = "Upload an ISO image or container template to a storage")]
+    Upload {
+        storage: String,
+        file: PathBuf,
+        #[arg(long, value_enum, default_value_t = UploadContent::Iso)]
+        content: UploadContent,
+    },
+}
+
+#[derive(Subcommand)]
+enum CephCommand {
+    #[command(about = "Show Ceph health, OSDs and pools; fails unless HEALTH_OK")]
+    Status {
+        #[arg(short = 'N', long, help = "Node to query [default: first online node]")]
+        node: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum DisksCommand {
+    #[command(about = "Check the SMART status of the physical disks; fails if one is failing")]
+    Health {
+        #[arg(
+            short = 'N',
+            long,
+            help = "Only this node [default: every online node]"
+        )]
+        node: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TasksCommand {
+    #[command(about = "Print the log of a task")]
+    Log {
+        #[arg(help = "Task ID, e.g. UPID:pve1:0002A5B1:0B2D6F6E:67000000:vzdump:100:root@pam:")]
+        upid: String,
+        #[arg(
+            short = 'f',
+            long,
+            help = "Keep printing new lines until the task ends"
+        )]
+        follow: bool,
+    },
+    #[command(about = "Stop a running task")]
+    Stop {
+        upid: String,
+        #[arg(short = 'y', long, help = "Stop without asking for confirmation")]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum LxcCommand {
+    #[command(about = "Run a command in a container over SSH to its node")]
+    Exec {
+        ct: String,
+        #[arg(long, default_value = "root", help = "User to log in to the node as")]
+        ssh_user: String,
+        #[arg(last = true, required = true, value_name = "COMMAND")]
+        command: Vec<String>,
+    },
+    #[command(about = "Create a container from a template")]
+    Create {
+        #[arg(
+            long,
+            help = "Template volume, e.g. local:vztmpl/debian-12-standard_12.7-1_amd64.tar.zst"
+        )]
+        template: String,
+        #[arg(long, help = "Host name of the new container")]
+        hostname: String,
+        #[arg(long, help = "VMID of the new container [default: next free VMID]")]
+        vmid: Option<u32>,
+        #[arg(long, help = "Storage for the root filesystem")]
+        storage: String,
+        #[arg(
+            long,
+            value_parser = size::parse_size,
+            default_value = "8G",
+            help = "Root filesystem size in whole GiB"
+        )]
+        disk_size: u64,
+        #[arg(long, value_name = "MIB", help = "Memory in MiB")]
+        memory: Option<u64>,
+        #[arg(long, help = "CPU cores")]
+        cores: Option<u32>,
+        #[arg(long, default_value = "vmbr0", help = "Bridge to connect eth0 to")]
+        bridge: String,
+        #[arg(long, help = "IP address in CIDR notation, or dhcp")]
+        ip: Option<String>,
+        #[arg(long, requires = "ip", help = "Default gateway")]
+        gateway: Option<String>,
+        #[arg(long, help = "DNS server")]
+        nameserver: Option<String>,
+        #[arg(long, help = "Public SSH key file for root (repeatable)")]
+        sshkey: Vec<PathBuf>,
+        #[arg(long, help = "Create a privileged container")]
+        privileged: bool,
+        #[arg(long, help = "Start the container once created")]
+        start: bool,
+    },
+    #[command(about = "Grow the root filesystem or a mount point of a container")]
+    Resize {
+        ct: String,
+        #[arg(help = "Disk to grow, e.g. rootfs or mp0")]
+        disk: String,
+        #[arg(
+            allow_hyphen_values = true,
+            help = "New size, or an increase such as +10G"
+        )]
+        size: String,
+    },
+    #[command(about = "List and download container templates")]
+    Templates {
+        #[command(subcommand)]
+        templates: LxcTemplateCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum LxcTemplateCommand {
+    #[command(about = "List the appliance templates available for download")]
+    List {
+        #[arg(long, help = "Only list this section, e.g. system or turnkeylinux")]
+        section: Option<String>,
+        #[arg(
+            long,
+            conflicts_with = "section",
+            help = "List the templates already downloaded to this storage instead"
+        )]
+        storage: Option<String>,
+    },
+    #[command(about = "Download an appliance template to a storage")]
+    Download {
+        storage: String,
+        #[arg(help = "Template file name, as listed by 'lxc templates list'")]
+        template: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum AgentCommand {
+    #[command(about = "Run a command in a VM and print its output")]
+    Exec {
+        vm: String,
+        #[arg(
+            long,
+            value_parser = duration::parse_duration,
+            help = "Give up waiting after this long, e.g. 30s"
+        )]
+        timeout: Option<u64>,
+        #[arg(last = true, required = true, value_name = "COMMAND")]
+        command: Vec<String>,
+    },
+    #[command(about = "Print a file from inside a VM")]
+    FileRead {
+        vm: String,
+        file: String,
+        #[arg(short = 'o', long, help = "Write to this file instead of stdout")]
+        output_file: Option<PathBuf>,
+    },
+    #[command(about = "Write a file inside a VM")]
+    FileWrite {
+        vm: String,
+        file: String,
+        #[arg(
+            long,
+            required_unless_present = "content_file",
+            conflicts_with = "content_file",
+            help = "Text to write"
+        )]
+        content: Option<String>,
+        #[arg(long, help = "Local file whose content is written")]
+        content_file: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ScheduleCommand {
+    #[command(about = "Generate systemd service and timer units for each schedule")]
+    InstallSystemd {
+        #[arg(long, help = "Write the units instead of printing them")]
+        write: bool,
+        #[arg(
+            long,
+            default_value = "/etc/systemd/system",
+            requires = "write",
+            help = "Directory the units are written to"
+        )]
+        dir: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum ReportCommand {
+    #[command(about = "Snapshot totals per node and pool, and snapshots past a cutoff")]
+    Snapshots {
+        #[command(flatten)]
+        selector: GuestSelector,
+        #[arg(
+            long,
+            value_parser = duration::parse_duration,
+            help = "List snapshots older than this, e.g. 30d"
+        )]
+        older_than: Option<u64>,
+    },
+    #[command(about = "Storage usage across nodes; fails if a storage is too full")]
+    Storage {
+        #[arg(
+            long,
+            default_value_t = 85.0,
+            value_parser = report::parse_percent,
+            help = "Warn about storages fuller than this percentage"
+        )]
+        warn_pct: f64,
+    },
+}
+
+/// Options selecting a group of VMs instead of a single one.
+#[derive(Args, Clone)]
+#[command(group(clap::ArgGroup::new("named").args(["pool", "tag"]).multiple(true)))]
+struct GuestSelector {
+    #[arg(long, help = "Apply to every VM in this resource pool")]
+    pool: Option<String>,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Apply to every VM carrying any of these tags"
+    )]
+    tag: Vec<String>,
+
+    #[arg(
+        long = "type",
+        value_enum,
+        requires = "named",
+        help = "Keep only the VMs or only the containers of the pool or tags"
+    )]
+    guest_type: Option<GuestType>,
+}
+
+impl GuestSelector {
+    fn is_empty(&self) -> bool {
+        self.pool.is_none() && self.tag.is_empty()
+    }
+
+    fn with_vm(self, vm: Option<String>) -> Selection {
+        Selection {
+            vm,
+            vms: Vec::new(),
+            pool: self.pool,
+            tags: self.tag,
+            guest_type: self.guest_type,
+        }
+    }
+
+    /// Like `with_vm`, but reads the VMs from stdin with `--stdin` or when
+    /// the VM is given as `-`.
+    fn with_vm_or_stdin(self, vm: Option<String>, stdin: bool) -> Result<Selection> {
+        if !stdin && vm.as_deref() != Some("-") {
+            return Ok(self.with_vm(vm));
+        }
+
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input)?;
+        let vms = cluster::parse_vm_list(&input)?;
+        if vms.is_empty() {
+            anyhow::bail!("No VMs read from stdin");
+        }
+
+        let mut selection = self.with_vm(None);
+        selection.vms = vms;
+        Ok(selection)
+    }
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    Create {
+        #[arg(
+            required_unless_present_any = ["pool", "tag", "stdin"],
+            help = "VMID or name, or - to read a list from stdin"
+        )]
+        vm: Option<String>,
+        #[command(flatten)]
+        selector: GuestSelector,
+        #[arg(
+            long,
+            conflicts_with = "vm",
+            help = "Read VMIDs or names from stdin, one per line or as JSON"
+        )]
+        stdin: bool,
         #[arg(short = 's', long)]
         snapname: Option<String>,
-        #[arg(short = 'd', long)]
+        #[arg(
+            long,
+            conflicts_with = "snapname",
+            help = "Template for the generated name, e.g. \"auto-{name}-{date}\" [default: snapshot-{date}-{time}]"
+        )]
+        name_template: Option<String>,
+        #[arg(
+            short = 'd',
+            long,
+            help = "Description; may use {vm_name}, {node}, {user}, {hostname}, {reason} and more"
+        )]
         description: Option<String>,
+        #[arg(long, help = "Text for the {reason} placeholder of the description")]
+        reason: Option<String>,
         #[arg(short = 'm', long)]
         vmstate: bool,
+        #[arg(
+            long,
+            requires = "vmstate",
+            help = "Refuse a RAM-state snapshot when the guest agent check fails"
+        )]
+        strict: bool,
+        #[arg(long, help = "Skip VMs that already have a snapshot with this name")]
+        if_not_exists: bool,
+        #[arg(
+            long,
+            conflicts_with = "if_not_exists",
+            help = "Delete an existing snapshot with this name and create it again"
+        )]
+        replace: bool,
+        #[arg(
+            long,
+            help = "Freeze guest file systems through the QEMU guest agent while snapshotting"
+        )]
+        fsfreeze: bool,
+        #[arg(
+            long,
+            requires = "fsfreeze",
+            default_value = "5m",
+            value_parser = duration::parse_duration,
+            help = "Thaw and fail if the snapshot takes longer than this"
+        )]
+        freeze_timeout: u64,
     },
     Delete {
+        #[arg(
+            value_name = "VM",
+            help = "VM (or - for a list on stdin) and snapshot name, or only the snapshot name with --pool, --tag or --stdin"
+        )]
         vm: String,
-        snapname: String,
+        #[arg(help = "Snapshot name, or a glob such as 'pre-patch-*'")]
+        snapname: Option<String>,
+        #[command(flatten)]
+        selector: GuestSelector,
+        #[arg(long, help = "Read VMIDs or names from stdin, one per line or as JSON")]
+        stdin: bool,
+        #[arg(long, help = "Treat the snapshot name as a regular expression")]
+        regex: bool,
+        #[arg(
+            long,
+            conflicts_with_all = ["snapname", "regex", "pool", "tag", "stdin"],
+            help = "Delete every snapshot of the VM"
+        )]
+        all: bool,
+        #[arg(short = 'y', long, help = "Delete without asking for confirmation")]
+        yes: bool,
     },
     List {
-        vm: String,
+        #[arg(required_unless_present_any = ["pool", "tag"])]
+        vm: Option<String>,
+        #[command(flatten)]
+        selector: GuestSelector,
+        #[arg(long, help = "Show the snapshot hierarchy as a tree")]
+        tree: bool,
+        #[arg(
+            long,
+            conflicts_with = "tree",
+            help = "Show the disks stored in each snapshot (one extra API call per snapshot)"
+        )]
+        disks: bool,
+        #[arg(
+            long,
+            conflicts_with = "tree",
+            help = "Print each snapshot using a template, e.g. \"{name}\\t{snaptime}\""
+        )]
+        format: Option<String>,
+    },
+    #[command(about = "Delete snapshots not covered by a retention policy")]
+    Prune {
+        #[arg(required_unless_present_any = ["pool", "tag", "apply_config"])]
+        vm: Option<String>,
+        #[command(flatten)]
+        selector: GuestSelector,
+        #[command(flatten)]
+        policy: RetentionPolicy,
+        #[arg(
+            long,
+            conflicts_with_all = ["keep_last", "keep_daily", "keep_weekly", "keep_monthly"],
+            help = "Use the retention policies from the config file"
+        )]
+        apply_config: bool,
     },
     Rollback {
         vm: String,
         snapname: String,
+        #[arg(long, help = "Start the VM once the rollback has completed")]
+        start: bool,
+        #[arg(short = 'y', long, help = "Roll back without asking for confirmation")]
+        yes: bool,
     },
     Info {
         vm: String,
     },
+    #[command(about = "Inspect and regenerate the cloud-init data of a VM")]
+    Cloudinit {
+        #[command(subcommand)]
+        cloudinit: CloudInitCommand,
+    },
+    #[command(about = "Talk to the QEMU guest agent of a VM")]
+    Agent {
+        #[command(subcommand)]
+        agent: AgentCommand,
+    },
+    #[command(about = "Manage LXC containers")]
+    Lxc {
+        #[command(subcommand)]
+        lxc: LxcCommand,
+    },
+    #[command(about = "Open the console of a VM")]
+    Console {
+        vm: String,
+        #[arg(long, group = "mode", required = true, help = "Use a VNC console")]
+        vnc: bool,
+        #[arg(
+            long,
+            group = "mode",
+            help = "Attach this terminal to the serial console of a VM or a container's console"
+        )]
+        term: bool,
+        #[arg(
+            long,
+            group = "mode",
+            help = "Write a remote-viewer file for a SPICE console"
+        )]
+        spice: bool,
+        #[arg(
+            long,
+            value_name = "PORT",
+            conflicts_with_all = ["term", "spice"],
+            help = "Forward VNC viewers connecting to this local port to the console"
+        )]
+        listen: Option<u16>,
+        #[arg(
+            short = 'o',
+            long,
+            conflicts_with_all = ["vnc", "term"],
+            help = "Write the remote-viewer file here instead of stdout"
+        )]
+        output_file: Option<PathBuf>,
+    },
+    #[command(about = "Show the IP addresses a guest reports")]
+    Ip {
+        vm: String,
+        #[arg(long, help = "Include loopback addresses")]
+        all: bool,
+    },
+    #[command(about = "Move a VM to another node")]
+    Migrate {
+        vm: String,
+        target: String,
+        #[arg(long, help = "Live-migrate a running VM")]
+        online: bool,
+        #[arg(long, help = "Copy disks on local storage to the target node")]
+        with_local_disks: bool,
+    },
+    #[command(about = "Delete a VM and its disks")]
+    Destroy {
+        vm: String,
+        #[arg(
+            long,
+            help = "Also remove the VM from backup, replication and HA jobs, and delete unreferenced disks"
+        )]
+        purge: bool,
+        #[arg(long, help = "Destroy the VM even if it has snapshots")]
+        force: bool,
+        #[arg(
+            long,
+            value_name = "NAME",
+            help = "Skip the prompt by giving the VM name, or the VMID of an unnamed VM"
+        )]
+        confirm: Option<String>,
+    },
+    #[command(about = "Back up VMs with vzdump")]
+    Backup {
+        #[command(subcommand)]
+        backup: BackupCommand,
+    },
+    #[command(about = "Convert a VM into a template for linked clones")]
+    Template {
+        vm: String,
+        #[arg(short = 'y', long, help = "Convert without asking for confirmation")]
+        yes: bool,
+    },
+    #[command(about = "Clone a VM, optionally from one of its snapshots")]
+    Clone {
+        vm: String,
+        #[arg(long, help = "VMID of the clone")]
+        newid: u32,
+        #[arg(short = 's', long, help = "Clone the state of this snapshot")]
+        snapname: Option<String>,
+        #[arg(long, help = "Name of the clone")]
+        name: Option<String>,
+        #[arg(long, help = "Copy all disks instead of creating a linked clone")]
+        full: bool,
+        #[arg(
+            long,
+            conflicts_with = "full",
+            help = "Create a linked clone, failing unless the VM is a template"
+        )]
+        linked: bool,
+        #[arg(long, help = "Node to create the clone on")]
+        target: Option<String>,
+        #[arg(long, help = "Storage for the disks of a full clone")]
+        storage: Option<String>,
+    },
+    #[command(about = "Create a VM from a cloud-init template and start it")]
+    Provision {
+        #[arg(long, help = "Template to clone")]
+        template: String,
+        #[arg(long, help = "Name of the new VM")]
+        name: String,
+        #[arg(long, help = "VMID of the new VM [default: next free VMID]")]
+        newid: Option<u32>,
+        #[arg(long, help = "Copy all disks instead of creating a linked clone")]
+        full: bool,
+        #[arg(long, help = "Node to create the VM on")]
+        target: Option<String>,
+        #[arg(long, help = "Storage for the disks of a full clone")]
+        storage: Option<String>,
+        #[arg(long, help = "IP address in CIDR notation, or dhcp")]
+        ip: Option<String>,
+        #[arg(long, requires = "ip", help = "Default gateway")]
+        gateway: Option<String>,
+        #[arg(long, help = "DNS server")]
+        nameserver: Option<String>,
+        #[arg(long, help = "Cloud-init user name")]
+        user: Option<String>,
+        #[arg(long, help = "Public SSH key file for the user (repeatable)")]
+        sshkey: Vec<PathBuf>,
+        #[arg(long, help = "New disk size, or an increase such as +10G")]
+        resize: Option<String>,
+        #[arg(long, default_value = "scsi0", help = "Disk to resize")]
+        disk: String,
+        #[arg(long, help = "Leave the VM stopped")]
+        no_start: bool,
+    },
+    #[command(about = "Migrate every VM off a node, e.g. before maintenance")]
+    Evacuate {
+        node: String,
+        #[arg(
+            long,
+            required_unless_present = "balance",
+            conflicts_with = "balance",
+            help = "Node to move all VMs to"
+        )]
+        target: Option<String>,
+        #[arg(long, help = "Spread the VMs over the other online nodes by memory")]
+        balance: bool,
+        #[arg(
+            short = 'j',
+            long,
+            default_value_t = 1,
+            value_parser = clap::value_parser!(u16).range(1..),
+            help = "Number of migrations to run at the same time"
+        )]
+        jobs: u16,
+        #[arg(long, help = "Copy disks on local storage to the target node")]
+        with_local_disks: bool,
+        #[arg(short = 'y', long, help = "Evacuate without asking for confirmation")]
+        yes: bool,
+    },
+    #[command(about = "Start or stop groups of VMs in order, one at a time")]
+    Bulk {
+        #[command(subcommand)]
+        bulk: BulkCommand,
+    },
+    #[command(about = "Start VMs")]
+    Start {
+        #[arg(required_unless_present_any = ["pool", "tag"])]
+        vm: Option<String>,
+        #[command(flatten)]
+        selector: GuestSelector,
+    },
+    #[command(about = "Power off VMs immediately, without a clean shutdown")]
+    Stop {
+        #[arg(required_unless_present_any = ["pool", "tag"])]
+        vm: Option<String>,
+        #[command(flatten)]
+        selector: GuestSelector,
+    },
+    #[command(about = "Shut VMs down cleanly")]
+    Shutdown {
+        #[arg(required_unless_present_any = ["pool", "tag"])]
+        vm: Option<String>,
+        #[command(flatten)]
+        selector: GuestSelector,
+        #[arg(
+            long,
+            value_parser = duration::parse_duration,
+            help = "How long to wait for the guest to shut down, e.g. 3m"
+        )]
+        timeout: Option<u64>,
+        #[arg(
+            long,
+            requires = "timeout",
+            help = "Power off VMs that have not shut down within the timeout"
+        )]
+        force_stop: bool,
+    },
+    #[command(about = "Pause VMs, keeping their memory in RAM")]
+    Suspend {
+        #[arg(required_unless_present_any = ["pool", "tag"])]
+        vm: Option<String>,
+        #[command(flatten)]
+        selector: GuestSelector,
+    },
+    #[command(about = "Resume suspended VMs")]
+    Resume {
+        #[arg(required_unless_present_any = ["pool", "tag"])]
+        vm: Option<String>,
+        #[command(flatten)]
+        selector: GuestSelector,
+    },
+    #[command(about = "Suspend VMs to disk and stop them")]
+    Hibernate {
+        #[arg(required_unless_present_any = ["pool", "tag"])]
+        vm: Option<String>,
+        #[command(flatten)]
+        selector: GuestSelector,
+    },
+    #[command(about = "Reboot VMs cleanly")]
+    Reboot {
+        #[arg(required_unless_present_any = ["pool", "tag"])]
+        vm: Option<String>,
+        #[command(flatten)]
+        selector: GuestSelector,
+        #[arg(
+            long,
+            value_parser = duration::parse_duration,
+            help = "How long to wait for the guest to shut down, e.g. 3m"
+        )]
+        timeout: Option<u64>,
+    },
+    #[command(about = "Show the snapshot the current state descends from, back to the root")]
+    Current {
+        vm: String,
+    },
+    #[command(about = "Wait until a VM is running, stopped or paused")]
+    WaitStatus {
+        vm: String,
+        #[arg(value_enum)]
+        state: GuestState,
+        #[arg(
+            long,
+            value_parser = duration::parse_duration,
+            help = "Fail after this long, e.g. 300 or 5m"
+        )]
+        timeout: Option<u64>,
+    },
+    #[command(about = "Clear the lock a crashed task left on a VM")]
+    Unlock {
+        vm: String,
+        #[arg(short = 'y', long, help = "Unlock without asking for confirmation")]
+        yes: bool,
+    },
+    #[command(about = "Show configuration changes waiting for a reboot")]
+    Pending {
+        vm: String,
+    },
+    #[command(about = "Change the CPU and memory of a VM")]
+    SetResources {
+        vm: String,
+        #[arg(long, help = "CPU cores per socket")]
+        cores: Option<u32>,
+        #[arg(
+            long,
+            help = "Active vCPUs, which CPU hotplug can change while running"
+        )]
+        vcpus: Option<u32>,
+        #[arg(long, value_name = "MIB", help = "Memory in MiB")]
+        memory: Option<u64>,
+        #[arg(long, help = "Fail instead of leaving changes for the next reboot")]
+        live: bool,
+    },
+    #[command(about = "Show or change the notes of a VM")]
+    Notes {
+        vm: String,
+        #[arg(
+            long,
+            value_name = "TEXT",
+            group = "change",
+            help = "Replace the notes"
+        )]
+        set: Option<String>,
+        #[arg(
+            long,
+            value_name = "TEXT",
+            group = "change",
+            help = "Add a line to the notes"
+        )]
+        append: Option<String>,
+        #[arg(long, group = "change", help = "Edit the notes in $EDITOR")]
+        edit: bool,
+    },
+    #[command(about = "Manage VM tags")]
+    Tag {
+        #[command(subcommand)]
+        tag: TagCommand,
+    },
+    #[command(about = "Review and edit the firewall rules of a VM")]
+    Firewall {
+        #[command(subcommand)]
+        firewall: FirewallCommand,
+    },
+    #[command(about = "Manage the HA resources of the cluster")]
+    Ha {
+        #[command(subcommand)]
+        ha: HaCommand,
+    },
+    #[command(about = "Show and trigger storage replication jobs")]
+    Replication {
+        #[command(subcommand)]
+        replication: ReplicationCommand,
+    },
+    #[command(about = "Inspect the software-defined network")]
+    Sdn {
+        #[command(subcommand)]
+        sdn: SdnCommand,
+    },
+    #[command(about = "Manage resource pools")]
+    Pool {
+        #[command(subcommand)]
+        pool: PoolCommand,
+    },
+    #[command(about = "Manage the disks of a VM")]
+    Disk {
+        #[command(subcommand)]
+        disk: DiskCommand,
+    },
+    #[command(about = "Read or change the configuration of a VM")]
+    Config {
+        #[command(subcommand)]
+        config: VmConfigCommand,
+    },
+    #[command(about = "Modify existing snapshots")]
+    Snapshot {
+        #[command(subcommand)]
+        snapshot: SnapshotCommand,
+    },
+    #[command(about = "Flag VMs whose snapshots are missing, too old or too many")]
+    Audit {
+        #[command(flatten)]
+        selector: GuestSelector,
+        #[arg(
+            long,
+            value_parser = duration::parse_duration,
+            help = "Flag VMs without a snapshot newer than this, e.g. 7d"
+        )]
+        max_age: Option<u64>,
+        #[arg(long, help = "Flag VMs with more snapshots than this")]
+        max_count: Option<usize>,
+    },
+    #[command(about = "Cluster-wide reports")]
+    Report {
+        #[command(subcommand)]
+        report: ReportCommand,
+    },
+    #[command(about = "Check that snapshots are intact and their volumes still exist")]
+    Verify {
+        vm: String,
+        #[arg(help = "Snapshot to verify [default: all snapshots]")]
+        snapname: Option<String>,
+    },
+    #[command(about = "Write the snapshot metadata of VMs to a JSON inventory")]
+    Export {
+        #[arg(required_unless_present_any = ["pool", "tag", "all"])]
+        vm: Option<String>,
+        #[command(flatten)]
+        selector: GuestSelector,
+        #[arg(
+            long,
+            conflicts_with_all = ["vm", "pool", "tag"],
+            help = "Export every VM in the cluster"
+        )]
+        all: bool,
+        #[arg(short = 'o', long, help = "Write to this file instead of stdout")]
+        output_file: Option<PathBuf>,
+    },
+    #[command(about = "Compare a JSON inventory with the cluster")]
+    Import {
+        file: PathBuf,
+        #[arg(
+            long,
+            help = "Create the snapshots of the inventory that no longer exist"
+        )]
+        recreate_missing: bool,
+    },
     Check {
         vm: String,
+        #[arg(long, value_enum, default_value_t = CheckFormat::Text)]
+        format: CheckFormat,
+        #[arg(
+            long,
+            value_parser = duration::parse_duration,
+            help = "Warn when the newest snapshot is older than this (e.g. 2d)"
+        )]
+        warn_age: Option<u64>,
+        #[arg(
+            long,
+            value_parser = duration::parse_duration,
+            help = "Critical when the newest snapshot is older than this (e.g. 7d)"
+        )]
+        crit_age: Option<u64>,
+        #[arg(long, help = "Also check that the QEMU guest agent responds")]
+        agent: bool,
     },
     Test,
+    #[command(
+        about = "Summarize quorum, nodes, HA and storage; exits 0/1/2/3 like a Nagios plugin"
+    )]
+    Health {
+        #[arg(
+            long,
+            default_value_t = 85.0,
+            value_parser = report::parse_percent,
+            help = "Warn about storages fuller than this percentage"
+        )]
+        warn_pct: f64,
+    },
     ListVms {
         #[arg(short = 'N', long)]
         node: Option<String>,
+        #[arg(long, value_enum, help = "Only VMs with this status")]
+        status: Option<GuestState>,
+        #[arg(
+            long,
+            value_name = "REGEX",
+            value_parser = NamePattern::regex,
+            help = "Only VMs whose name matches this regular expression"
+        )]
+        name_regex: Option<NamePattern>,
+        #[arg(
+            long,
+            value_delimiter = ',',
+            help = "Only VMs carrying any of these tags"
+        )]
+        tag: Vec<String>,
+        #[arg(long, help = "Only VMs in this resource pool")]
+        pool: Option<String>,
+        #[arg(long = "type", value_enum, help = "Only VMs or only containers")]
+        guest_type: Option<GuestType>,
+        #[arg(
+            long,
+            value_enum,
+            value_delimiter = ',',
+            help = "Comma-separated list of columns to display"
+        )]
+        columns: Option<Vec<VmColumn>>,
+        #[arg(long, value_enum, help = "Column to sort by")]
+        sort: Option<VmColumn>,
+        #[arg(long, requires = "sort", help = "Reverse the sort order")]
+        reverse: bool,
+        #[arg(
+            long,
+            conflicts_with = "columns",
+            help = "Print each VM using a template, e.g. \"{vmid}\\t{name}\\t{status}\""
+        )]
+        format: Option<String>,
     },
     ListNodes,
+    #[command(about = "Show node load and the busiest guests, refreshing until interrupted")]
+    Top {
+        #[arg(
+            long,
+            value_parser = duration::parse_duration,
+            default_value = "3",
+            help = "Time between refreshes, e.g. 5 or 1m"
+        )]
+        interval: u64,
+        #[arg(long, default_value_t = 10, help = "Number of guests to show")]
+        limit: usize,
+        #[arg(long, help = "Exit after this many refreshes")]
+        count: Option<u64>,
+    },
+    #[command(about = "List storages with their content kinds and free space")]
+    ListStorage {
+        #[arg(
+            short = 'N',
+            long,
+            help = "Only this node [default: every online node]"
+        )]
+        node: Option<String>,
+    },
+    #[command(about = "Manage the content of a storage")]
+    Storage {
+        #[command(subcommand)]
+        storage: StorageCommand,
+    },
+    #[command(about = "Inspect the Ceph cluster of hyperconverged nodes")]
+    Ceph {
+        #[command(subcommand)]
+        ceph: CephCommand,
+    },
+    #[command(about = "Inspect the physical disks of the nodes")]
+    Disks {
+        #[command(subcommand)]
+        disks: DisksCommand,
+    },
+    #[command(about = "Follow or stop tasks running on the nodes")]
+    Tasks {
+        #[command(subcommand)]
+        tasks: TasksCommand,
+    },
+    #[command(about = "Show details of a cluster node")]
+    Node {
+        #[command(subcommand)]
+        node: NodeCommand,
+    },
+    #[command(about = "Run the [[schedules]] of the config file, creating and pruning snapshots")]
+    Daemon {
+        #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+        log_format: LogFormat,
+    },
+    #[command(about = "Manage the [[schedules]] of the config file")]
+    Schedule {
+        #[command(subcommand)]
+        schedule: ScheduleCommand,
+    },
+    #[command(
+        about = "Export cluster, VM and snapshot gauges in Prometheus format",
+        args_conflicts_with_subcommands = true
+    )]
+    Metrics {
+        #[command(subcommand)]
+        metrics: Option<MetricsCommand>,
+        #[arg(
+            short = 'o',
+            long,
+            help = "Write to this file (atomically) instead of stdout"
+        )]
+        output_file: Option<PathBuf>,
+    },
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let mut cli = Cli::parse();
+async fn main() {
+    let cli = Cli::parse();
+    let output = Output::new(cli.output)
+        .with_raw(cli.raw)
+        .with_quiet(cli.quiet)
+        .with_color(cli.color)
+        .with_size_units(if cli.bytes {
+            SizeUnits::Bytes
+        } else if cli.si {
+            SizeUnits::Si
+        } else {
+            SizeUnits::Binary
+        });
+
+    if let Err(e) = run(cli, output.clone()).await {
+        output.print_error(&e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(mut cli: Cli, output: Output) -> Result<()> {
+    let mut config = Config::default();
+    if let Some(config_path) = &cli.config
+        && let Ok(config_str) = fs::read_to_string(config_path)
+    {
+        config = toml::from_str::<Config>(&config_str)?;
+    }
+
+    if cli.host == "192.168.1.1"
+        && std::env::var("PROXMOX_HOST").is_err()
+        && let Some(host) = &config.host
+    {
+        cli.host = host.clone();
+    }
+
+    if cli.port == 8006
+        && std::env::var("PROXMOX_PORT").is_err()
+        && let Some(port) = config.port
+    {
+        cli.port = port;
+    }
+
+    if cli.token.is_none() && std::env::var("PROXMOX_API_TOKEN").is_err() {
+        cli.token = config.token.clone();
+    }
+
+    if cli.node.is_none() && std::env::var("PROXMOX_NODE").is_err() {
+        cli.node = config.node.clone();
+    }
+
+    if cli.verify_ssl.is_none() && std::env::var("PROXMOX_VERIFY_SSL").is_err() {
+        cli.verify_ssl = config.verify_ssl;
+    }
+
+    let time_format = match cli.time_format.take().or(config.time_format.clone()) {
+        Some(format) => output::validate_time_format(&format)?,
+        None => output::DEFAULT_TIME_FORMAT.to_string(),
+    };
+    let output = output
+        .with_utc(cli.utc || config.utc.unwrap_or(false))
+        .with_time_format(&time_format);
+
+    let clusters = if cli.all_clusters {
+        let mut names: Vec<String> = config
+            .clusters
+            .as_ref()
+            .map(|c| c.keys().cloned().collect())
+            .unwrap_or_default();
+        if names.is_empty() {
+            anyhow::bail!("No clusters defined in the config file");
+        }
+        names.sort();
+        names
+    } else {
+        cli.cluster.clone()
+    };
+
+    if clusters.len() > 1 || cli.all_clusters {
+        return run_on_clusters(&cli, &config, &clusters, &output).await;
+    }
+    let cluster = clusters.first().map(String::as_str);
+
+    // Generating units needs the config file but no connection.
+    if let Commands::Schedule {
+        schedule: ScheduleCommand::InstallSystemd { write, dir },
+    } = &cli.command
+    {
+        let Some(config_path) = &cli.config else {
+            anyhow::bail!("install-systemd needs the config file passed with --config");
+        };
+        let config_path = fs::canonicalize(config_path)
+            .with_context(|| format!("Failed to resolve config file {}", config_path))?;
+
+        let mut base = vec![
+            std::env::current_exe()?.display().to_string(),
+            "--config".to_string(),
+            config_path.display().to_string(),
+        ];
+        if let Some(cluster) = cluster {
+            base.extend(["--cluster".to_string(), cluster.to_string()]);
+        }
+
+        return systemd::install(
+            &config,
+            cluster,
+            &base,
+            write.then_some(dir.as_path()),
+            &output,
+        );
+    }
+
+    let client = connect(&cli, &config, cluster).await?;
+
+    let snapshot_mgr = SnapshotManager::new(client.clone(), output.clone());
+
+    match cli.command {
+        Commands::Create {
+            vm,
+            selector,
+            stdin,
+            snapname,
+            name_template,
+            description,
+            reason,
+            vmstate,
+            if_not_exists,
+            replace,
+            fsfreeze,
+            freeze_timeout,
+            strict,
+        } => {
+            let selection = selector.with_vm_or_stdin(vm, stdin)?;
+            let options = CreateOptions {
+                snapname,
+                name_template: name_template
+                    .or(config.snapname_template.clone())
+                    .unwrap_or_else(|| naming::DEFAULT_SNAPNAME_TEMPLATE.to_string()),
+                description_template: description
+                    .or(config.description_template.clone())
+                    .unwrap_or_else(|| naming::DEFAULT_DESCRIPTION_TEMPLATE.to_string()),
+                reason,
+                vmstate,
+                existing: if replace {
+                    OnExisting::Replace
+                } else if if_not_exists {
+                    OnExisting::Skip
+                } else {
+                    OnExisting::Fail
+                },
+                fsfreeze: fsfreeze.then_some(freeze_timeout),
+                strict,
+            };
+            snapshot_mgr.create_snapshots(&selection, &options).await?;
+        }
+        Commands::Delete {
+            vm,
+            snapname,
+            selector,
+            stdin,
+            regex,
+            all,
+            yes,
+        } => {
+            if all {
+                snapshot_mgr.delete_all(&vm, yes).await?;
+                return Ok(());
+            }
 
-    let mut config = Config::default();
-    if let Some(config_path) = &cli.config {
-        if let Ok(config_str) = fs::read_to_string(config_path) {
-            config = toml::from_str::<Config>(&config_str)?;
+            // With --pool, --tag or --stdin the only positional argument is
+            // the snapshot name.
+            let (selection, snapname) = match (selector.is_empty() && !stdin, snapname) {
+                (false, None) => (selector.with_vm_or_stdin(None, stdin)?, vm),
+                (true, Some(snapname)) => (selector.with_vm_or_stdin(Some(vm), false)?, snapname),
+                (false, Some(_)) => {
+                    anyhow::bail!("Specify either a VM or --pool/--tag/--stdin, not both")
+                }
+                (true, None) => anyhow::bail!("Missing snapshot name"),
+            };
+            if regex {
+                let pattern = NamePattern::regex(&snapname)?;
+                snapshot_mgr
+                    .delete_matching(&selection, &pattern, yes)
+                    .await?;
+            } else if NamePattern::is_glob(&snapname) {
+                let pattern = NamePattern::glob(&snapname)?;
+                snapshot_mgr
+                    .delete_matching(&selection, &pattern, yes)
+                    .await?;
+            } else {
+                snapshot_mgr
+                    .delete_snapshots(&selection, &snapname, yes)
+                    .await?;
+            }
         }
-    }
-
-    if cli.host == "192.168.1.1" && std::env::var("PROXMOX_HOST").is_err() {
-        if let Some(host) = &config.host {
-            cli.host = host.clone();
+        Commands::List {
+            vm,
+            selector,
+            tree,
+            disks,
+            format,
+        } => {
+            snapshot_mgr
+                .list_snapshots(&selector.with_vm(vm), tree, disks, format.as_deref())
+                .await?;
         }
-    }
-
-    if cli.port == 8006 && std::env::var("PROXMOX_PORT").is_err() {
-        if let Some(port) = config.port {
-            cli.port = port;
+        Commands::Prune {
+            vm,
+            selector,
+            policy,
+            apply_config,
+        } => {
+            let selection = selector.with_vm(vm);
+            if apply_config {
+                let retention = config.retention(cluster);
+                snapshot_mgr
+                    .prune_with_config(&selection, &retention)
+                    .await?;
+            } else {
+                snapshot_mgr.prune_snapshots(&selection, &policy).await?;
+            }
+        }
+        Commands::Rollback {
+            vm,
+            snapname,
+            start,
+            yes,
+        } => {
+            snapshot_mgr
+                .rollback_snapshot(&vm, &snapname, start, yes)
+                .await?;
+        }
+        Commands::Info { vm } => {
+            snapshot_mgr.show_vm_info(&vm).await?;
+        }
+        Commands::Agent {
+            agent:
+                AgentCommand::Exec {
+                    vm,
+                    timeout,
+                    command,
+                },
+        } => {
+            let code = snapshot_mgr.agent_exec(&vm, &command, timeout).await?;
+            if code != 0 {
+                std::process::exit(code);
+            }
+        }
+        Commands::Agent {
+            agent:
+                AgentCommand::FileRead {
+                    vm,
+                    file,
+                    output_file,
+                },
+        } => {
+            snapshot_mgr
+                .agent_file_read(&vm, &file, output_file.as_deref())
+                .await?;
+        }
+        Commands::Agent {
+            agent:
+                AgentCommand::FileWrite {
+                    vm,
+                    file,
+                    content,
+                    content_file,
+                },
+        } => {
+            let content = match (content, content_file) {
+                (Some(content), _) => content,
+                (None, Some(path)) => fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?,
+                (None, None) => unreachable!("clap requires --content or --content-file"),
+            };
+            snapshot_mgr.agent_file_write(&vm, &file, &content).await?;
+        }
+        Commands::Lxc {
+            lxc:
+                LxcCommand::Exec {
+                    ct,
+                    ssh_user,
+                    command,
+                },
+        } => {
+            let lxc = LxcManager::new(client, output);
+            let code = lxc.exec(&ct, &command, &ssh_user).await?;
+            if code != 0 {
+                std::process::exit(code);
+            }
+        }
+        Commands::Lxc {
+            lxc:
+                LxcCommand::Create {
+                    template,
+                    hostname,
+                    vmid,
+                    storage,
+                    disk_size,
+                    memory,
+                    cores,
+                    bridge,
+                    ip,
+                    gateway,
+                    nameserver,
+                    sshkey,
+                    privileged,
+                    start,
+                },
+        } => {
+            let options = ContainerOptions {
+                template,
+                hostname,
+                vmid,
+                storage,
+                disk_size,
+                memory,
+                cores,
+                bridge,
+                ip,
+                gateway,
+                nameserver,
+                sshkeys: sshkey,
+                unprivileged: !privileged,
+                start,
+            };
+            let lxc = LxcManager::new(client, output);
+            lxc.create(cli.node.as_deref(), &options).await?;
+        }
+        Commands::Lxc {
+            lxc: LxcCommand::Resize { ct, disk, size },
+        } => {
+            let lxc = LxcManager::new(client, output);
+            lxc.resize_disk(&ct, &disk, &size).await?;
+        }
+        Commands::Lxc {
+            lxc:
+                LxcCommand::Templates {
+                    templates: LxcTemplateCommand::List { section, storage },
+                },
+        } => {
+            let lxc = LxcManager::new(client, output);
+            lxc.list_templates(cli.node.as_deref(), section.as_deref(), storage.as_deref())
+                .await?;
+        }
+        Commands::Lxc {
+            lxc:
+                LxcCommand::Templates {
+                    templates: LxcTemplateCommand::Download { storage, template },
+                },
+        } => {
+            let lxc = LxcManager::new(client, output);
+            lxc.download_template(cli.node.as_deref(), &storage, &template)
+                .await?;
+        }
+        Commands::Console {
+            vm,
+            term,
+            spice,
+            listen,
+            output_file,
+            ..
+        } => {
+            let console_mgr = ConsoleManager::new(client, output);
+            if term {
+                console_mgr.term(&vm).await?;
+            } else if spice {
+                console_mgr.spice(&vm, output_file.as_deref()).await?;
+            } else {
+                console_mgr.vnc(&vm, listen).await?;
+            }
+        }
+        Commands::Ip { vm, all } => {
+            snapshot_mgr.show_addresses(&vm, all).await?;
+        }
+        Commands::Migrate {
+            vm,
+            target,
+            online,
+            with_local_disks,
+        } => {
+            let options = MigrateOptions {
+                online: online.then_some(1),
+                with_local_disks: with_local_disks.then_some(1),
+                ..Default::default()
+            };
+            snapshot_mgr.migrate_vm(&vm, &target, &options).await?;
+        }
+        Commands::Destroy {
+            vm,
+            purge,
+            force,
+            confirm,
+        } => {
+            snapshot_mgr
+                .destroy_vm(&vm, purge, force, confirm.as_deref())
+                .await?;
+        }
+        Commands::Backup {
+            backup:
+                BackupCommand::Run {
+                    vm,
+                    storage,
+                    mode,
+                    notes,
+                },
+        } => {
+            let options = BackupOptions {
+                storage,
+                mode,
+                notes,
+            };
+            BackupManager::new(client, output)
+                .run(&vm, &options)
+                .await?;
+        }
+        Commands::Template { vm, yes } => {
+            snapshot_mgr.make_template(&vm, yes).await?;
+        }
+        Commands::Clone {
+            vm,
+            newid,
+            snapname,
+            name,
+            full,
+            linked,
+            target,
+            storage,
+        } => {
+            let options = CloneOptions {
+                snapname,
+                name,
+                full,
+                linked,
+                target,
+                storage,
+            };
+            snapshot_mgr.clone_vm(&vm, newid, &options).await?;
+        }
+        Commands::Provision {
+            template,
+            name,
+            newid,
+            full,
+            target,
+            storage,
+            ip,
+            gateway,
+            nameserver,
+            user,
+            sshkey,
+            resize,
+            disk,
+            no_start,
+        } => {
+            let options = ProvisionOptions {
+                template,
+                name,
+                newid,
+                full,
+                target,
+                storage,
+                ip,
+                gateway,
+                nameserver,
+                user,
+                sshkeys: sshkey,
+                resize,
+                disk,
+                start: !no_start,
+            };
+            snapshot_mgr.provision(&options).await?;
+        }
+        Commands::Evacuate {
+            node,
+            target,
+            jobs,
+            with_local_disks,
+            yes,
+            ..
+        } => {
+            snapshot_mgr
+                .evacuate_node(&node, target.as_deref(), with_local_disks, jobs.into(), yes)
+                .await?;
+        }
+        Commands::Audit { .. } | Commands::Report { .. } | Commands::ListVms { .. } => {
+            query(&cli.command, client, output).await?;
+        }
+        Commands::Bulk { bulk } => {
+            let (action, options, power_options) = match bulk {
+                BulkCommand::Start { options } => {
+                    (PowerAction::Start, options, PowerOptions::default())
+                }
+                BulkCommand::Stop { options } => {
+                    (PowerAction::Stop, options, PowerOptions::default())
+                }
+                BulkCommand::Shutdown {
+                    options,
+                    timeout,
+                    force_stop,
+                } => (
+                    PowerAction::Shutdown,
+                    options,
+                    PowerOptions {
+                        timeout,
+                        force_stop: force_stop.then_some(1),
+                        ..Default::default()
+                    },
+                ),
+            };
+            let (order_by, delay) = (options.order_by, options.delay);
+            snapshot_mgr
+                .power_sequence(
+                    &options.selection(),
+                    action,
+                    &power_options,
+                    order_by,
+                    delay,
+                )
+                .await?;
+        }
+        Commands::Start { vm, selector } => {
+            snapshot_mgr
+                .power(
+                    &selector.with_vm(vm),
+                    PowerAction::Start,
+                    &PowerOptions::default(),
+                )
+                .await?;
+        }
+        Commands::Stop { vm, selector } => {
+            snapshot_mgr
+                .power(
+                    &selector.with_vm(vm),
+                    PowerAction::Stop,
+                    &PowerOptions::default(),
+                )
+                .await?;
+        }
+        Commands::Shutdown {
+            vm,
+            selector,
+            timeout,
+            force_stop,
+        } => {
+            let options = PowerOptions {
+                timeout,
+                force_stop: force_stop.then_some(1),
+                ..Default::default()
+            };
+            snapshot_mgr
+                .power(&selector.with_vm(vm), PowerAction::Shutdown, &options)
+                .await?;
+        }
+        Commands::Reboot {
+            vm,
+            selector,
+            timeout,
+        } => {
+            let options = PowerOptions {
+                timeout,
+                ..Default::default()
+            };
+            snapshot_mgr
+                .power(&selector.with_vm(vm), PowerAction::Reboot, &options)
+                .await?;
+        }
+        Commands::Suspend { vm, selector } => {
+            snapshot_mgr
+                .power(
+                    &selector.with_vm(vm),
+                    PowerAction::Suspend,
+                    &PowerOptions::default(),
+                )
+                .await?;
+        }
+        Commands::Resume { vm, selector } => {
+            snapshot_mgr
+                .power(
+                    &selector.with_vm(vm),
+                    PowerAction::Resume,
+                    &PowerOptions::default(),
+                )
+                .await?;
+        }
+        Commands::Hibernate { vm, selector } => {
+            snapshot_mgr
+                .power(
+                    &selector.with_vm(vm),
+                    PowerAction::Hibernate,
+                    &PowerOptions::default(),
+                )
+                .await?;
+        }
+        Commands::Current { vm } => {
+            snapshot_mgr.show_lineage(&vm).await?;
+        }
+        Commands::Disk {
+            disk:
+                DiskCommand::Move {
+                    vm,
+                    disk,
+                    storage,
+                    format,
+                    delete_source,
+                },
+        } => {
+            snapshot_mgr
+                .move_disk(&vm, &disk, &storage, format.as_deref(), delete_source)
+                .await?;
+        }
+        Commands::Disk {
+            disk:
+                DiskCommand::Attach {
+                    vm,
+                    storage,
+                    size,
+                    bus,
+                    mount,
+                    no_backup,
+                },
+        } => {
+            snapshot_mgr
+                .attach_disk(&vm, &storage, size, bus, mount.as_deref(), !no_backup)
+                .await?;
+        }
+        Commands::Disk {
+            disk: DiskCommand::Detach { vm, disk, destroy },
+        } => {
+            snapshot_mgr.detach_disk(&vm, &disk, destroy).await?;
+        }
+        Commands::Notes {
+            vm,
+            set,
+            append,
+            edit,
+        } => {
+            let edit = match (set, append) {
+                (Some(text), _) => NotesEdit::Set(text),
+                (_, Some(text)) => NotesEdit::Append(text),
+                _ if edit => NotesEdit::Editor,
+                _ => NotesEdit::Show,
+            };
+            snapshot_mgr.notes(&vm, edit).await?;
+        }
+        Commands::Tag { tag } => match tag {
+            TagCommand::Add { vm, tags } => snapshot_mgr.edit_tags(&vm, &tags, &[]).await?,
+            TagCommand::Remove { vm, tags } => snapshot_mgr.edit_tags(&vm, &[], &tags).await?,
+            TagCommand::List { vm } => snapshot_mgr.list_tags(vm.as_deref()).await?,
+        },
+        Commands::Cloudinit { cloudinit } => match cloudinit {
+            CloudInitCommand::Dump { vm, section } => {
+                snapshot_mgr.cloudinit_dump(&vm, section).await?;
+            }
+            CloudInitCommand::Regenerate { vm } => {
+                snapshot_mgr.cloudinit_regenerate(&vm).await?;
+            }
+        },
+        Commands::Ha { ha } => {
+            let ha_mgr = HaManager::new(client, output);
+            match ha {
+                HaCommand::List => ha_mgr.list_resources().await?,
+                HaCommand::Add { vm, settings } => {
+                    ha_mgr.add_resource(&vm, &settings.settings()).await?
+                }
+                HaCommand::Set { vm, settings } => {
+                    ha_mgr.set_resource(&vm, &settings.settings()).await?
+                }
+                HaCommand::Remove { vm } => ha_mgr.remove_resource(&vm).await?,
+            }
+        }
+        Commands::Replication { replication } => {
+            let replication_mgr = ReplicationManager::new(client, output);
+            match replication {
+                ReplicationCommand::List { vm } => replication_mgr.list_jobs(vm.as_deref()).await?,
+                ReplicationCommand::Run { job } => replication_mgr.run_job(&job).await?,
+            }
+        }
+        Commands::Sdn {
+            sdn: SdnCommand::List { vm },
+        } => {
+            let cluster = ClusterManager::new(client, output);
+            cluster.list_sdn(vm.as_deref()).await?;
+        }
+        Commands::Firewall { firewall } => {
+            let firewall_mgr = FirewallManager::new(client, output);
+            match firewall {
+                FirewallCommand::List { vm } => firewall_mgr.list_rules(&vm).await?,
+                FirewallCommand::Add { vm, rule } => {
+                    let RuleArgs {
+                        direction,
+                        action,
+                        group,
+                        macro_name,
+                        proto,
+                        source,
+                        dest,
+                        sport,
+                        dport,
+                        iface,
+                        comment,
+                        pos,
+                        disabled,
+                    } = *rule;
+                    let (direction, action) = match (group, action) {
+                        (Some(group), _) => ("group".to_string(), group),
+                        (None, Some(action)) => {
+                            (direction.as_str().to_string(), action.as_str().to_string())
+                        }
+                        (None, None) => unreachable!("clap requires --action or --group"),
+                    };
+                    let rule = NewRule {
+                        direction,
+                        action,
+                        enable: u8::from(!disabled),
+                        macro_name,
+                        proto,
+                        source,
+                        dest,
+                        sport,
+                        dport,
+                        iface,
+                        comment,
+                        pos,
+                    };
+                    firewall_mgr.add_rule(&vm, &rule).await?;
+                }
+                FirewallCommand::Delete { vm, pos } => {
+                    firewall_mgr.delete_rule(&vm, pos).await?;
+                }
+            }
+        }
+        Commands::SetResources {
+            vm,
+            cores,
+            vcpus,
+            memory,
+            live,
+        } => {
+            let resources = ResourceOptions {
+                cores,
+                vcpus,
+                memory,
+            };
+            snapshot_mgr.set_resources(&vm, &resources, live).await?;
+        }
+        Commands::WaitStatus { vm, state, timeout } => {
+            snapshot_mgr.wait_for_status(&vm, state, timeout).await?;
+        }
+        Commands::Unlock { vm, yes } => {
+            snapshot_mgr.unlock_vm(&vm, yes).await?;
+        }
+        Commands::Pending { vm } => {
+            snapshot_mgr.show_pending(&vm).await?;
+        }
+        Commands::Config {
+            config: VmConfigCommand::Get { vm, key },
+        } => {
+            snapshot_mgr.show_config(&vm, key.as_deref()).await?;
+        }
+        Commands::Config {
+            config:
+                VmConfigCommand::Set {
+                    vm,
+                    settings,
+                    delete,
+                },
+        } => {
+            snapshot_mgr.set_config(&vm, &settings, &delete).await?;
+        }
+        Commands::Snapshot {
+            snapshot:
+                SnapshotCommand::Edit {
+                    vm,
+                    snapname,
+                    description,
+                },
+        } => {
+            snapshot_mgr
+                .edit_description(&vm, &snapname, &description)
+                .await?;
+        }
+        Commands::Snapshot {
+            snapshot: SnapshotCommand::Diff { vm, from, to },
+        } => {
+            snapshot_mgr
+                .diff_snapshots(&vm, &from, to.as_deref())
+                .await?;
+        }
+        Commands::Verify { vm, snapname } => {
+            snapshot_mgr
+                .verify_snapshots(&vm, snapname.as_deref())
+                .await?;
+        }
+        Commands::Export {
+            vm,
+            selector,
+            all,
+            output_file,
+        } => {
+            let selection = (!all).then(|| selector.with_vm(vm));
+            snapshot_mgr
+                .export_inventory(selection.as_ref(), output_file.as_deref())
+                .await?;
+        }
+        Commands::Import {
+            file,
+            recreate_missing,
+        } => {
+            snapshot_mgr
+                .import_inventory(&file, recreate_missing)
+                .await?;
+        }
+        Commands::Check {
+            vm,
+            format: CheckFormat::Nagios,
+            warn_age,
+            crit_age,
+            agent,
+        } => {
+            let thresholds = Thresholds { warn_age, crit_age };
+            let (state, line) = match snapshot_mgr.check_nagios(&vm, &thresholds, agent).await {
+                Ok(result) => result,
+                Err(e) => (NagiosState::Unknown, format!("UNKNOWN - {:#}", e)),
+            };
+            println!("{}", line);
+            std::process::exit(state.exit_code());
+        }
+        Commands::Check { vm, .. } => {
+            snapshot_mgr.check_vm_status(&vm).await?;
+        }
+        Commands::Test => {
+            test_connection(client, &output).await?;
+        }
+        Commands::Health { warn_pct } => {
+            let cluster = ClusterManager::new(client, output);
+            let state = match cluster.health(warn_pct).await {
+                Ok(state) => state,
+                Err(e) => {
+                    eprintln!("UNKNOWN - {:#}", e);
+                    NagiosState::Unknown
+                }
+            };
+            std::process::exit(state.exit_code());
+        }
+        Commands::ListNodes => {
+            let cluster = ClusterManager::new(client, output);
+            cluster.list_nodes().await?;
+        }
+        Commands::Top {
+            interval,
+            limit,
+            count,
+        } => {
+            let cluster = ClusterManager::new(client, output);
+            cluster.top(interval, limit, count).await?;
+        }
+        Commands::ListStorage { node } => {
+            let cluster = ClusterManager::new(client, output);
+            cluster.list_storage(node.as_deref()).await?;
+        }
+        Commands::Storage {
+            storage:
+                StorageCommand::Upload {
+                    storage,
+                    file,
+                    content,
+                },
+        } => {
+            let storages = StorageManager::new(client, output);
+            storages
+                .upload(cli.node.as_deref(), &storage, &file, content)
+                .await?;
+        }
+        Commands::Ceph {
+            ceph: CephCommand::Status { node },
+        } => {
+            let cluster = ClusterManager::new(client, output);
+            cluster.ceph_status(node.as_deref()).await?;
+        }
+        Commands::Disks {
+            disks: DisksCommand::Health { node },
+        } => {
+            let cluster = ClusterManager::new(client, output);
+            cluster.disks_health(node.as_deref()).await?;
+        }
+        Commands::Tasks {
+            tasks: TasksCommand::Log { upid, follow },
+        } => {
+            TaskManager::new(client, output).log(&upid, follow).await?;
+        }
+        Commands::Tasks {
+            tasks: TasksCommand::Stop { upid, yes },
+        } => {
+            TaskManager::new(client, output).stop(&upid, yes).await?;
+        }
+        Commands::Pool { pool } => {
+            let cluster = ClusterManager::new(client, output);
+            match pool {
+                PoolCommand::List => cluster.list_pools().await?,
+                PoolCommand::Create { pool, comment } => {
+                    cluster.create_pool(&pool, comment.as_deref()).await?
+                }
+                PoolCommand::Delete { pool } => cluster.delete_pool(&pool).await?,
+                PoolCommand::AddMember { pool, vms } => {
+                    cluster.update_pool_members(&pool, &vms, false).await?
+                }
+                PoolCommand::RemoveMember { pool, vms } => {
+                    cluster.update_pool_members(&pool, &vms, true).await?
+                }
+            }
+        }
+        Commands::Node {
+            node: NodeCommand::Info { node },
+        } => {
+            let cluster = ClusterManager::new(client, output);
+            cluster.node_info(&node).await?;
+        }
+        Commands::Node {
+            node: NodeCommand::Subscription { node, .. },
+        } => {
+            let cluster = ClusterManager::new(client, output);
+            cluster.subscriptions(node.as_deref()).await?;
+        }
+        Commands::Node {
+            node:
+                NodeCommand::Updates {
+                    node,
+                    fail_on_security,
+                    ..
+                },
+        } => {
+            let cluster = ClusterManager::new(client, output);
+            cluster
+                .pending_updates(node.as_deref(), fail_on_security)
+                .await?;
+        }
+        Commands::Node {
+            node: NodeCommand::Certs { node, warn_days },
+        } => {
+            let cluster = ClusterManager::new(client, output);
+            cluster.certificates(node.as_deref(), warn_days).await?;
+        }
+        Commands::Node {
+            node: NodeCommand::Versions,
+        } => {
+            let cluster = ClusterManager::new(client, output);
+            cluster.versions().await?;
+        }
+        Commands::Node {
+            node: NodeCommand::Network { node },
+        } => {
+            let cluster = ClusterManager::new(client, output);
+            cluster.network(&node).await?;
+        }
+        Commands::Node {
+            node: NodeCommand::Reboot { node, force, yes },
+        } => {
+            let cluster = ClusterManager::new(client, output);
+            cluster
+                .node_power(&node, NodePowerCommand::Reboot, force, yes)
+                .await?;
+        }
+        Commands::Node {
+            node: NodeCommand::Shutdown { node, force, yes },
+        } => {
+            let cluster = ClusterManager::new(client, output);
+            cluster
+                .node_power(&node, NodePowerCommand::Shutdown, force, yes)
+                .await?;
+        }
+        Commands::Node {
+            node:
+                NodeCommand::Maintenance {
+                    maintenance:
+                        MaintenanceCommand::Enter {
+                            node,
+                            evacuate,
+                            target,
+                            jobs,
+                            with_local_disks,
+                            ssh_user,
+                            timeout,
+                            yes,
+                        },
+                },
+        } => {
+            let options = MaintenanceOptions {
+                evacuate,
+                target,
+                jobs: jobs.into(),
+                with_local_disks,
+                ssh_user,
+                timeout,
+            };
+            let ha = HaManager::new(client, output);
+            ha.enter_maintenance(&node, &options, yes).await?;
+        }
+        Commands::Node {
+            node:
+                NodeCommand::Maintenance {
+                    maintenance: MaintenanceCommand::Exit { node, ssh_user },
+                },
+        } => {
+            let ha = HaManager::new(client, output);
+            ha.exit_maintenance(&node, &ssh_user).await?;
+        }
+        Commands::Daemon { log_format } => {
+            daemon::init_logging(log_format);
+            let daemon = Daemon::new(snapshot_mgr, &config, cluster)?;
+            daemon.run().await?;
+        }
+        Commands::Schedule { .. } => unreachable!("handled before connecting"),
+        Commands::Metrics {
+            metrics: Some(MetricsCommand::History { target, timeframe }),
+            ..
+        } => {
+            let metrics = MetricsManager::new(client, output);
+            metrics.history(&target, timeframe).await?;
+        }
+        Commands::Metrics {
+            metrics: None,
+            output_file,
+        } => {
+            let metrics = MetricsManager::new(client, output);
+            metrics.export(output_file.as_deref()).await?;
         }
     }
 
-    if cli.token.is_none() && std::env::var("PROXMOX_API_TOKEN").is_err() {
-        cli.token = config.token.clone();
-    }
-
-    if cli.node.is_none() && std::env::var("PROXMOX_NODE").is_err() {
-        cli.node = config.node.clone();
-    }
-
-    if cli.verify_ssl.is_none() && std::env::var("PROXMOX_VERIFY_SSL").is_err() {
-        cli.verify_ssl = config.verify_ssl;
-    }
+    Ok(())
+}
 
-    let client = if let Some(cluster_config) = config.get_cluster(cli.cluster.as_deref()) {
+async fn connect(cli: &Cli, config: &Config, cluster: Option<&str>) -> Result<ProxmoxClient> {
+    let client = if let Some(cluster_config) = config.get_cluster(cluster) {
         let port = cluster_config.port.unwrap_or(cli.port);
         let token = cluster_config.token.or(cli.token.clone());
         let verify_ssl = cluster_config
@@ -138,63 +2630,143 @@ async fn main() -> Result<()> {
         let verify_ssl = cli.verify_ssl.unwrap_or(false);
         ProxmoxClient::new(&cli.host, cli.port, cli.token.clone(), verify_ssl)?
     };
+    Ok(client.with_dry_run(cli.dry_run))
+}
 
-    let snapshot_mgr = SnapshotManager::new(client.clone());
-
-    match cli.command {
-        Commands::Create {
-            vm,
-            snapname,
-            description,
-            vmstate,
+/// Runs the commands that can span several clusters.
+async fn query(command: &Commands, client: ProxmoxClient, output: Output) -> Result<()> {
+    let snapshot_mgr = SnapshotManager::new(client.clone(), output.clone());
+    match command {
+        Commands::ListVms {
+            node,
+            status,
+            name_regex,
+            tag,
+            pool,
+            guest_type,
+            columns,
+            sort,
+            reverse,
+            format,
         } => {
+            let filter = VmFilter {
+                node: node.clone(),
+                status: *status,
+                name: name_regex.clone(),
+                tags: tag.clone(),
+                pool: pool.clone(),
+                guest_type: *guest_type,
+            };
             snapshot_mgr
-                .create_snapshot(&vm, snapname, description, vmstate)
-                .await?;
+                .list_vms(
+                    &filter,
+                    columns.as_deref(),
+                    *sort,
+                    *reverse,
+                    format.as_deref(),
+                )
+                .await
         }
-        Commands::Delete { vm, snapname } => {
-            snapshot_mgr.delete_snapshot(&vm, &snapname).await?;
-        }
-        Commands::List { vm } => {
-            snapshot_mgr.list_snapshots(&vm).await?;
-        }
-        Commands::Rollback { vm, snapname } => {
-            snapshot_mgr.rollback_snapshot(&vm, &snapname).await?;
-        }
-        Commands::Info { vm } => {
-            snapshot_mgr.show_vm_info(&vm).await?;
+        Commands::Audit {
+            selector,
+            max_age,
+            max_count,
+        } => {
+            let limits = AuditLimits {
+                max_age: *max_age,
+                max_count: *max_count,
+            };
+            snapshot_mgr
+                .audit(&selector.clone().with_vm(None), &limits)
+                .await
         }
-        Commands::Check { vm } => {
-            snapshot_mgr.check_vm_status(&vm).await?;
+        Commands::Report {
+            report:
+                ReportCommand::Snapshots {
+                    selector,
+                    older_than,
+                },
+        } => {
+            snapshot_mgr
+                .report_snapshots(&selector.clone().with_vm(None), *older_than)
+                .await
         }
-        Commands::Test => {
-            test_connection(client).await?;
+        Commands::Report {
+            report: ReportCommand::Storage { warn_pct },
+        } => {
+            let cluster = ClusterManager::new(client, output);
+            cluster.report_storage(*warn_pct).await
         }
-        Commands::ListVms { node } => {
-            snapshot_mgr.list_vms(node.as_deref()).await?;
+        _ => anyhow::bail!("Only list-vms, audit and report can run on several clusters"),
+    }
+}
+
+/// Runs a query on each cluster in turn, labelling its output with the
+/// cluster name. A failing cluster does not stop the others. JSON and YAML
+/// output is gathered into one array, printed once all clusters answered.
+async fn run_on_clusters(
+    cli: &Cli,
+    config: &Config,
+    clusters: &[String],
+    output: &Output,
+) -> Result<()> {
+    if !matches!(
+        cli.command,
+        Commands::ListVms { .. } | Commands::Audit { .. } | Commands::Report { .. }
+    ) {
+        anyhow::bail!("Only list-vms, audit and report can run on several clusters");
+    }
+
+    let collector = output.clone().collecting();
+    let mut failed = 0;
+    for name in clusters {
+        if config.get_cluster(Some(name)).is_none() {
+            anyhow::bail!("Cluster '{}' not found in config file", name);
         }
-        Commands::ListNodes => {
-            let cluster = ClusterManager::new(client);
-            cluster.list_nodes().await?;
+
+        let output = collector.clone().with_cluster(Some(name));
+        let result = match connect(cli, config, Some(name)).await {
+            Ok(client) => query(&cli.command, client, output.clone()).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            failed += 1;
+            eprintln!(
+                "{} cluster {}: {:#}",
+                output.paint(Color::Red, "✗"),
+                name,
+                e
+            );
         }
     }
+    collector.flush_collected()?;
 
+    if failed > 0 {
+        anyhow::bail!(
+            "Command failed on {} of {} clusters",
+            failed,
+            clusters.len()
+        );
+    }
     Ok(())
 }
 
-async fn test_connection(client: ProxmoxClient) -> Result<()> {
-    println!("Testing connection to Proxmox server...");
+async fn test_connection(client: ProxmoxClient, output: &Output) -> Result<()> {
+    output.message("Testing connection to Proxmox server...");
 
     match client.get::<serde_json::Value>("/version").await {
         Ok(version) => {
-            println!("✓ Connection successful!");
+            if output.print_raw(&version)? {
+                return Ok(());
+            }
+            println!("{}", output.paint(Color::Green, "✓ Connection successful!"));
             if let Some(ver) = version.get("version").and_then(|v| v.as_str()) {
                 println!("  Proxmox VE version: {}", ver);
             }
         }
         Err(e) => {
-            eprintln!("✗ Connection failed: {}", e);
-            return Err(e);
+            eprintln!("{} {}", output.paint(Color::Red, "✗ Connection failed:"), e);
+            return Err(e.into());
         }
     }
 
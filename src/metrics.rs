@@ -0,0 +1,453 @@
+use anyhow::Result;
+use chrono::DateTime;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use crate::client::ProxmoxClient;
+use crate::cluster::ClusterManager;
+use crate::output::{Output, OutputFormat, Tabular};
+
+pub struct MetricsManager {
+    client: ProxmoxClient,
+    cluster: ClusterManager,
+    output: Output,
+}
+
+#[derive(Deserialize)]
+struct ClusterResource {
+    #[serde(rename = "type")]
+    resource_type: String,
+    node: Option<String>,
+    vmid: Option<u32>,
+    name: Option<String>,
+    status: Option<String>,
+    mem: Option<u64>,
+    maxmem: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct Snapshot {
+    name: String,
+    snaptime: Option<i64>,
+}
+
+/// A gauge with its samples, rendered in Prometheus exposition format.
+struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    samples: Vec<(Vec<(&'static str, String)>, f64)>,
+}
+
+impl Gauge {
+    fn new(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            samples: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, labels: Vec<(&'static str, String)>, value: f64) {
+        self.samples.push((labels, value));
+    }
+}
+
+impl MetricsManager {
+    pub fn new(client: ProxmoxClient, output: Output) -> Self {
+        let cluster = ClusterManager::new(client.clone(), output.clone());
+        Self {
+            client,
+            cluster,
+            output,
+        }
+    }
+
+    /// Collects the gauges and writes them to stdout, or atomically to `file`
+    /// so node_exporter never reads a partially written textfile.
+    pub async fn export(&self, file: Option<&Path>) -> Result<()> {
+        let text = render(&self.collect().await?);
+
+        match file {
+            Some(path) => {
+                let mut tmp = path.as_os_str().to_owned();
+                tmp.push(".tmp");
+                fs::write(&tmp, text)?;
+                fs::rename(&tmp, path)?;
+            }
+            None => print!("{}", text),
+        }
+
+        Ok(())
+    }
+
+    async fn collect(&self) -> Result<Vec<Gauge>> {
+        let resources: Vec<ClusterResource> = self.client.get("/cluster/resources").await?;
+        let now = chrono::Utc::now().timestamp();
+
+        let mut vm_status = Gauge::new(
+            "pve_vm_status",
+            "Whether the guest is running (1) or not (0)",
+        );
+        let mut snapshot_count =
+            Gauge::new("pve_snapshot_count", "Number of snapshots of the guest");
+        let mut snapshot_age = Gauge::new(
+            "pve_snapshot_age_seconds",
+            "Age of each snapshot in seconds",
+        );
+        let mut scrape_error = Gauge::new(
+            "pve_scrape_error",
+            "Whether the snapshots of the guest could not be read (1) or could (0)",
+        );
+        let mut node_mem = Gauge::new("pve_node_mem_bytes", "Memory used on the node in bytes");
+        let mut node_maxmem = Gauge::new(
+            "pve_node_maxmem_bytes",
+            "Memory available on the node in bytes",
+        );
+
+        for resource in &resources {
+            let node = resource.node.clone().unwrap_or_default();
+            match resource.resource_type.as_str() {
+                "node" => {
+                    let labels = vec![("node", node)];
+                    if let Some(mem) = resource.mem {
+                        node_mem.add(labels.clone(), mem as f64);
+                    }
+                    if let Some(maxmem) = resource.maxmem {
+                        node_maxmem.add(labels, maxmem as f64);
+                    }
+                }
+                "qemu" | "lxc" => {
+                    let Some(vmid) = resource.vmid else {
+                        continue;
+                    };
+                    let labels = vec![
+                        ("vmid", vmid.to_string()),
+                        ("name", resource.name.clone().unwrap_or_default()),
+                        ("node", node.clone()),
+                        ("type", resource.resource_type.clone()),
+                    ];
+                    let running = resource.status.as_deref() == Some("running");
+                    vm_status.add(labels.clone(), if running { 1.0 } else { 0.0 });
+
+                    // A guest whose snapshots cannot be read, e.g. on a node
+                    // that just went down, must not fail the whole scrape.
+                    let snapshots = match self
+                        .client
+                        .get::<Vec<Snapshot>>(&format!(
+                            "/nodes/{}/{}/{}/snapshot",
+                            node, resource.resource_type, vmid
+                        ))
+                        .await
+                    {
+                        Ok(snapshots) => {
+                            scrape_error.add(labels.clone(), 0.0);
+                            snapshots
+                        }
+                        Err(e) => {
+                            self.output
+                                .warn(&format!("Cannot read the snapshots of VM {}: {}", vmid, e));
+                            scrape_error.add(labels, 1.0);
+                            continue;
+                        }
+                    };
+                    let snapshots: Vec<&Snapshot> =
+                        snapshots.iter().filter(|s| s.name != "current").collect();
+
+                    snapshot_count.add(labels.clone(), snapshots.len() as f64);
+                    for snap in snapshots {
+                        if let Some(time) = snap.snaptime {
+                            let mut labels = labels.clone();
+                            labels.push(("snapshot", snap.name.clone()));
+                            snapshot_age.add(labels, (now - time).max(0) as f64);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(vec![
+            vm_status,
+            snapshot_count,
+            snapshot_age,
+            scrape_error,
+            node_mem,
+            node_maxmem,
+        ])
+    }
+
+    /// Prints the performance history of a node, or else of a guest, as
+    /// averages over each sample period of `timeframe`.
+    pub async fn history(&self, target: &str, timeframe: Timeframe) -> Result<()> {
+        #[derive(Deserialize)]
+        struct NodeName {
+            node: String,
+        }
+
+        let nodes: Vec<NodeName> = self.client.get("/nodes").await?;
+        let query = format!("rrddata?timeframe={}&cf=AVERAGE", timeframe.as_str());
+        if nodes.iter().any(|n| n.node == target) {
+            let samples: Vec<NodeSample> = self
+                .client
+                .get(&format!("/nodes/{}/{}", target, query))
+                .await?;
+            return self.output.print_list(
+                &format!("History of node {} ({}):", target, timeframe.as_str()),
+                &samples,
+            );
+        }
+
+        let guest = self.cluster.find_vm(target).await?;
+        let samples: Vec<GuestSample> = self
+            .client
+            .get(&format!("{}/{}", guest.path(), query))
+            .await?;
+        self.output.print_list(
+            &format!("History of VM {} ({}):", guest.vmid, timeframe.as_str()),
+            &samples,
+        )
+    }
+}
+
+fn render(gauges: &[Gauge]) -> String {
+    let mut out = String::new();
+    for gauge in gauges {
+        let _ = writeln!(out, "# HELP {} {}", gauge.name, gauge.help);
+        let _ = writeln!(out, "# TYPE {} gauge", gauge.name);
+        for (labels, value) in &gauge.samples {
+            let labels = labels
+                .iter()
+                .map(|(k, v)| format!("{}=\"{}\"", k, escape_label(v)))
+                .collect::<Vec<_>>()
+                .join(",");
+            let _ = writeln!(out, "{}{{{}}} {}", gauge.name, labels, value);
+        }
+    }
+    out
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// How far back `metrics history` reaches. Proxmox keeps coarser samples
+/// the longer the timeframe: one per minute for an hour, one per week for a
+/// decade.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Timeframe {
+    #[default]
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl Timeframe {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Timeframe::Hour => "hour",
+            Timeframe::Day => "day",
+            Timeframe::Week => "week",
+            Timeframe::Month => "month",
+            Timeframe::Year => "year",
+        }
+    }
+}
+
+/// One sample of a guest's `rrddata`. Samples from before the guest existed
+/// or while it was stopped lack some values.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GuestSample {
+    pub time: i64,
+    /// CPU usage as a fraction of `maxcpu`.
+    #[serde(default)]
+    pub cpu: Option<f64>,
+    #[serde(default)]
+    pub maxcpu: Option<f64>,
+    #[serde(default)]
+    pub mem: Option<f64>,
+    #[serde(default)]
+    pub maxmem: Option<f64>,
+    /// Network and disk rates, in bytes per second.
+    #[serde(default)]
+    pub netin: Option<f64>,
+    #[serde(default)]
+    pub netout: Option<f64>,
+    #[serde(default)]
+    pub diskread: Option<f64>,
+    #[serde(default)]
+    pub diskwrite: Option<f64>,
+}
+
+impl Tabular for GuestSample {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("time", "Time"),
+            ("cpu", "CPU %"),
+            ("mem", "Memory"),
+            ("maxmem", "Max memory"),
+            ("netin", "Net in/s"),
+            ("netout", "Net out/s"),
+            ("diskread", "Disk read/s"),
+            ("diskwrite", "Disk write/s"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        vec![
+            sample_time(self.time, output),
+            percent(self.cpu),
+            bytes(self.mem, output),
+            bytes(self.maxmem, output),
+            bytes(self.netin, output),
+            bytes(self.netout, output),
+            bytes(self.diskread, output),
+            bytes(self.diskwrite, output),
+        ]
+    }
+}
+
+/// One sample of a node's `rrddata`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct NodeSample {
+    pub time: i64,
+    #[serde(default)]
+    pub cpu: Option<f64>,
+    /// Fraction of CPU time spent waiting for I/O.
+    #[serde(default)]
+    pub iowait: Option<f64>,
+    #[serde(default)]
+    pub loadavg: Option<f64>,
+    #[serde(default)]
+    pub memused: Option<f64>,
+    #[serde(default)]
+    pub memtotal: Option<f64>,
+    #[serde(default)]
+    pub swapused: Option<f64>,
+    #[serde(default)]
+    pub rootused: Option<f64>,
+    #[serde(default)]
+    pub netin: Option<f64>,
+    #[serde(default)]
+    pub netout: Option<f64>,
+}
+
+impl Tabular for NodeSample {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("time", "Time"),
+            ("cpu", "CPU %"),
+            ("iowait", "IO wait %"),
+            ("loadavg", "Load"),
+            ("memused", "Memory"),
+            ("memtotal", "Total memory"),
+            ("swapused", "Swap"),
+            ("rootused", "Root FS"),
+            ("netin", "Net in/s"),
+            ("netout", "Net out/s"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        vec![
+            sample_time(self.time, output),
+            percent(self.cpu),
+            percent(self.iowait),
+            self.loadavg
+                .map_or_else(String::new, |l| format!("{:.2}", l)),
+            bytes(self.memused, output),
+            bytes(self.memtotal, output),
+            bytes(self.swapused, output),
+            bytes(self.rootused, output),
+            bytes(self.netin, output),
+            bytes(self.netout, output),
+        ]
+    }
+}
+
+/// CSV gets RFC 3339 times and plain byte counts, which spreadsheets and
+/// scripts can parse whatever `--time-format` and `--units` are.
+fn sample_time(time: i64, output: &Output) -> String {
+    DateTime::from_timestamp(time, 0)
+        .map(|t| match output.format() {
+            OutputFormat::Csv => output.format_time_with(t, "%Y-%m-%dT%H:%M:%S%:z"),
+            _ => output.format_time(t),
+        })
+        .unwrap_or_default()
+}
+
+/// Missing values stay empty, so that spreadsheets see a gap, not a zero.
+fn percent(fraction: Option<f64>) -> String {
+    fraction.map_or_else(String::new, |f| format!("{:.1}", f * 100.0))
+}
+
+fn bytes(value: Option<f64>, output: &Output) -> String {
+    value.map_or_else(String::new, |v| match output.format() {
+        OutputFormat::Csv => (v.round() as u64).to_string(),
+        _ => output.format_bytes(v.round() as u64),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_gauge() {
+        let mut gauge = Gauge::new("pve_vm_status", "Whether the guest is running");
+        gauge.add(
+            vec![("vmid", "100".to_string()), ("name", "web".to_string())],
+            1.0,
+        );
+        assert_eq!(
+            render(&[gauge]),
+            "# HELP pve_vm_status Whether the guest is running\n\
+             # TYPE pve_vm_status gauge\n\
+             pve_vm_status{vmid=\"100\",name=\"web\"} 1\n"
+        );
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label(r#"a"b\c"#), r#"a\"b\\c"#);
+        assert_eq!(escape_label("line\nbreak"), "line\\nbreak");
+    }
+
+    #[test]
+    fn test_guest_sample_gaps() {
+        let samples: Vec<GuestSample> = serde_json::from_value(serde_json::json!([
+            {"time": 1_710_000_000, "cpu": 0.25, "maxcpu": 2, "mem": 536870912.4,
+             "maxmem": 1073741824, "netin": 1024.0, "netout": 0},
+            {"time": 1_710_000_060}
+        ]))
+        .unwrap();
+        let output = Output::default().with_utc(true);
+        let row = samples[0].row(&output);
+        assert_eq!(row[0], "2024-03-09 16:00:00");
+        assert_eq!(row[1], "25.0");
+        assert_eq!(row[2], output.format_bytes(1 << 29));
+        let gap = samples[1].row(&output);
+        assert!(gap[1..].iter().all(String::is_empty));
+    }
+
+    #[test]
+    fn test_guest_sample_csv() {
+        let sample: GuestSample = serde_json::from_value(serde_json::json!({
+            "time": 1_710_000_000, "cpu": 0.25, "mem": 536870912.4, "netin": 1024.0
+        }))
+        .unwrap();
+        let output = Output::new(OutputFormat::Csv).with_utc(true);
+        let row = sample.row(&output);
+        assert_eq!(row[0], "2024-03-09T16:00:00+00:00");
+        assert_eq!(row[1], "25.0");
+        assert_eq!(row[2], "536870912");
+        assert_eq!(row[4], "1024");
+    }
+}
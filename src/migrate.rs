@@ -0,0 +1,120 @@
+use serde::Serialize;
+
+/// Parameters of a migration, sent only when set.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct MigrateOptions {
+    /// Live-migrate a running guest instead of refusing to move it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub online: Option<u8>,
+    /// Copy disks on local storage to the target node.
+    #[serde(rename = "with-local-disks", skip_serializing_if = "Option::is_none")]
+    pub with_local_disks: Option<u8>,
+    /// Stop a running container, move it and start it again.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restart: Option<u8>,
+}
+
+impl MigrateOptions {
+    /// The equivalent options for a container. Containers cannot move live,
+    /// so a running one is stopped, moved and started again instead, and
+    /// their local volumes always move along.
+    pub fn for_container(self) -> Self {
+        Self {
+            restart: self.online,
+            ..Default::default()
+        }
+    }
+}
+
+/// Memory use of a candidate target node, in bytes.
+#[derive(Debug, Clone)]
+pub struct NodeLoad {
+    pub node: String,
+    pub mem: u64,
+    pub maxmem: u64,
+}
+
+impl NodeLoad {
+    fn usage_with(&self, extra: u64) -> f64 {
+        (self.mem + extra) as f64 / self.maxmem.max(1) as f64
+    }
+}
+
+/// Picks a target node for each guest, given their memory sizes. Guests are
+/// placed largest first, each on the node with the lowest memory usage after
+/// receiving it. Returns one node per entry of `sizes`; `nodes` must not be
+/// empty.
+pub fn balance(sizes: &[u64], mut nodes: Vec<NodeLoad>) -> Vec<String> {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(sizes[i]));
+
+    let mut targets = vec![String::new(); sizes.len()];
+    for i in order {
+        let Some(node) = nodes
+            .iter_mut()
+            .min_by(|a, b| a.usage_with(sizes[i]).total_cmp(&b.usage_with(sizes[i])))
+        else {
+            break;
+        };
+        node.mem += sizes[i];
+        targets[i] = node.node.clone();
+    }
+    targets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate_options_serialization() {
+        let options = MigrateOptions {
+            online: Some(1),
+            with_local_disks: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_value(options).unwrap(),
+            serde_json::json!({"online": 1, "with-local-disks": 1})
+        );
+    }
+
+    #[test]
+    fn test_balance() {
+        const GIB: u64 = 1 << 30;
+        let nodes = vec![
+            NodeLoad {
+                node: "pve1".to_string(),
+                mem: 0,
+                maxmem: 32 * GIB,
+            },
+            NodeLoad {
+                node: "pve2".to_string(),
+                mem: 0,
+                maxmem: 16 * GIB,
+            },
+        ];
+        // The 8 GiB guest goes first and lands on the larger node.
+        assert_eq!(
+            balance(&[4 * GIB, 8 * GIB, 4 * GIB], nodes),
+            vec!["pve2", "pve1", "pve1"]
+        );
+    }
+
+    #[test]
+    fn test_options_for_container() {
+        let options = MigrateOptions {
+            online: Some(1),
+            with_local_disks: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_value(options.for_container()).unwrap(),
+            serde_json::json!({"restart": 1})
+        );
+        assert_eq!(
+            serde_json::to_value(MigrateOptions::default().for_container()).unwrap(),
+            serde_json::json!({})
+        );
+    }
+}
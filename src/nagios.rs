@@ -0,0 +1,209 @@
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::duration::format_duration;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum CheckFormat {
+    #[default]
+    Text,
+    Nagios,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum NagiosState {
+    Ok,
+    Warning,
+    Critical,
+    Unknown,
+}
+
+impl NagiosState {
+    /// The more severe of two states. As in the Nagios plugins, CRITICAL
+    /// outranks UNKNOWN, which outranks WARNING: a check that could not run
+    /// must not hide a failed one.
+    pub fn worst(self, other: NagiosState) -> NagiosState {
+        let rank = |state| match state {
+            NagiosState::Ok => 0,
+            NagiosState::Warning => 1,
+            NagiosState::Unknown => 2,
+            NagiosState::Critical => 3,
+        };
+        if rank(other) > rank(self) {
+            other
+        } else {
+            self
+        }
+    }
+
+    pub fn exit_code(self) -> i32 {
+        match self {
+            NagiosState::Ok => 0,
+            NagiosState::Warning => 1,
+            NagiosState::Critical => 2,
+            NagiosState::Unknown => 3,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            NagiosState::Ok => "OK",
+            NagiosState::Warning => "WARNING",
+            NagiosState::Critical => "CRITICAL",
+            NagiosState::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Thresholds {
+    pub warn_age: Option<u64>,
+    pub crit_age: Option<u64>,
+}
+
+/// Facts gathered about a VM for a plugin run.
+pub struct CheckInput {
+    pub vmid: u32,
+    pub name: String,
+    pub status: String,
+    pub snapshot_count: usize,
+    /// Age in seconds of the newest snapshot, if any.
+    pub newest_snapshot_age: Option<u64>,
+    /// Guest agent reachability, when it was checked.
+    pub agent_ok: Option<bool>,
+}
+
+/// Evaluates the gathered facts and renders the plugin output line.
+pub fn evaluate(input: &CheckInput, thresholds: &Thresholds) -> (NagiosState, String) {
+    let mut state = NagiosState::Ok;
+    let mut details = Vec::new();
+
+    if input.status == "running" {
+        details.push(format!("VM {} ({}) is running", input.vmid, input.name));
+    } else {
+        state = state.worst(NagiosState::Critical);
+        details.push(format!(
+            "VM {} ({}) is {}",
+            input.vmid, input.name, input.status
+        ));
+    }
+
+    let checks_age = thresholds.warn_age.is_some() || thresholds.crit_age.is_some();
+    match input.newest_snapshot_age {
+        Some(age) => {
+            if thresholds.crit_age.is_some_and(|crit| age > crit) {
+                state = state.worst(NagiosState::Critical);
+            } else if thresholds.warn_age.is_some_and(|warn| age > warn) {
+                state = state.worst(NagiosState::Warning);
+            }
+            details.push(format!("newest snapshot {} old", format_duration(age)));
+        }
+        None if checks_age => {
+            let missing = if thresholds.crit_age.is_some() {
+                NagiosState::Critical
+            } else {
+                NagiosState::Warning
+            };
+            state = state.worst(missing);
+            details.push("no snapshots".to_string());
+        }
+        None => details.push("no snapshots".to_string()),
+    }
+
+    match input.agent_ok {
+        Some(true) => details.push("guest agent responding".to_string()),
+        Some(false) => {
+            state = state.worst(NagiosState::Warning);
+            details.push("guest agent not responding".to_string());
+        }
+        None => {}
+    }
+
+    let threshold = |value: Option<u64>| value.map(|v| v.to_string()).unwrap_or_default();
+    let mut perfdata = format!("snapshots={}", input.snapshot_count);
+    if let Some(age) = input.newest_snapshot_age {
+        perfdata.push_str(&format!(
+            " snapshot_age={}s;{};{}",
+            age,
+            threshold(thresholds.warn_age),
+            threshold(thresholds.crit_age)
+        ));
+    }
+
+    let line = format!("{} - {} | {}", state.label(), details.join(", "), perfdata);
+    (state, line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input() -> CheckInput {
+        CheckInput {
+            vmid: 100,
+            name: "web".to_string(),
+            status: "running".to_string(),
+            snapshot_count: 2,
+            newest_snapshot_age: Some(3600),
+            agent_ok: None,
+        }
+    }
+
+    #[test]
+    fn test_running_vm_is_ok() {
+        let (state, line) = evaluate(&input(), &Thresholds::default());
+        assert_eq!(state, NagiosState::Ok);
+        assert_eq!(
+            line,
+            "OK - VM 100 (web) is running, newest snapshot 0d 1h 0m old | snapshots=2 snapshot_age=3600s;;"
+        );
+    }
+
+    #[test]
+    fn test_stopped_vm_is_critical() {
+        let mut input = input();
+        input.status = "stopped".to_string();
+        let (state, _) = evaluate(&input, &Thresholds::default());
+        assert_eq!(state, NagiosState::Critical);
+        assert_eq!(state.exit_code(), 2);
+    }
+
+    #[test]
+    fn test_snapshot_age_thresholds() {
+        let thresholds = Thresholds {
+            warn_age: Some(1800),
+            crit_age: Some(7200),
+        };
+        let (state, _) = evaluate(&input(), &thresholds);
+        assert_eq!(state, NagiosState::Warning);
+
+        let mut old = input();
+        old.newest_snapshot_age = Some(10000);
+        let (state, _) = evaluate(&old, &thresholds);
+        assert_eq!(state, NagiosState::Critical);
+    }
+
+    #[test]
+    fn test_missing_snapshots_with_thresholds() {
+        let mut input = input();
+        input.newest_snapshot_age = None;
+        input.snapshot_count = 0;
+        let thresholds = Thresholds {
+            warn_age: Some(1800),
+            crit_age: None,
+        };
+        let (state, line) = evaluate(&input, &thresholds);
+        assert_eq!(state, NagiosState::Warning);
+        assert!(line.contains("no snapshots"));
+    }
+
+    #[test]
+    fn test_agent_unreachable_is_warning() {
+        let mut input = input();
+        input.agent_ok = Some(false);
+        let (state, line) = evaluate(&input, &Thresholds::default());
+        assert_eq!(state, NagiosState::Warning);
+        assert!(line.contains("guest agent not responding"));
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::Result;
+
+use crate::template;
+
+/// The name given to snapshots created without `--snapname`.
+pub const DEFAULT_SNAPNAME_TEMPLATE: &str = "snapshot-{date}-{time}";
+
+/// Placeholders available in snapshot name templates.
+pub const PLACEHOLDERS: &[&str] = &["vmid", "name", "node", "tag", "date", "time"];
+
+const MAX_LEN: usize = 40;
+
+/// The description given to snapshots created without `--description`.
+pub const DEFAULT_DESCRIPTION_TEMPLATE: &str = "Snapshot created on {timestamp}";
+
+/// Placeholders available in description templates.
+pub const DESCRIPTION_PLACEHOLDERS: &[&str] = &[
+    "vmid",
+    "vm_name",
+    "node",
+    "user",
+    "hostname",
+    "reason",
+    "snapname",
+    "timestamp",
+];
+
+/// Checks `name` against Proxmox's snapshot name rules: a letter followed by
+/// letters, digits, `-` or `_`, at most 40 characters in total.
+pub fn validate_snapname(name: &str) -> Result<()> {
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        && name.len() >= 2
+        && name.len() <= MAX_LEN;
+
+    if !valid {
+        anyhow::bail!(
+            "Invalid snapshot name '{}': must start with a letter, contain only letters, digits, '-' and '_', and be 2 to {} characters long",
+            name,
+            MAX_LEN
+        );
+    }
+    Ok(())
+}
+
+/// Renders a snapshot name template, replacing characters Proxmox does not
+/// allow in the substituted values (such as the dots of a FQDN VM name) with
+/// `-`, then validates the result.
+pub fn render_snapname(template: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let name = template::render(template, |key| {
+        if !PLACEHOLDERS.contains(&key) {
+            return None;
+        }
+        lookup(key).map(|value| sanitize(&value))
+    })?;
+    validate_snapname(&name)?;
+    Ok(name)
+}
+
+/// Renders a description template. Unlike names, descriptions may contain
+/// any character, so values are inserted unchanged.
+pub fn render_description(
+    template: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String> {
+    template::render(template, |key| {
+        if !DESCRIPTION_PLACEHOLDERS.contains(&key) {
+            return None;
+        }
+        lookup(key)
+    })
+}
+
+/// Replaces characters not allowed in snapshot names with `-`.
+pub fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(key: &str) -> Option<String> {
+        match key {
+            "vmid" => Some("100".to_string()),
+            "name" => Some("web.example.com".to_string()),
+            "date" => Some("20240115".to_string()),
+            "time" => Some("020000".to_string()),
+            "tag" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_render_snapname() {
+        assert_eq!(
+            render_snapname(DEFAULT_SNAPNAME_TEMPLATE, lookup).unwrap(),
+            "snapshot-20240115-020000"
+        );
+        assert_eq!(
+            render_snapname("auto-{name}-{date}", lookup).unwrap(),
+            "auto-web-example-com-20240115"
+        );
+    }
+
+    #[test]
+    fn test_render_snapname_invalid() {
+        // Must start with a letter.
+        assert!(render_snapname("{vmid}-{date}", lookup).is_err());
+        assert!(render_snapname("snap-{unknown}", lookup).is_err());
+        assert!(render_snapname("a-very-long-prefix-{name}-{date}-{time}", lookup).is_err());
+    }
+
+    #[test]
+    fn test_render_description() {
+        let lookup = |key: &str| match key {
+            "user" => Some("alice".to_string()),
+            "hostname" => Some("admin01".to_string()),
+            "reason" => Some("kernel upgrade".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            render_description("{reason} by {user}@{hostname}", lookup).unwrap(),
+            "kernel upgrade by alice@admin01"
+        );
+        // Name placeholders are not description placeholders.
+        assert!(render_description("{date}", |_| Some(String::new())).is_err());
+    }
+
+    #[test]
+    fn test_validate_snapname() {
+        assert!(validate_snapname("daily-backup_1").is_ok());
+        assert!(validate_snapname("pre upgrade").is_err());
+        assert!(validate_snapname("1st").is_err());
+        assert!(validate_snapname("x").is_err());
+    }
+}
@@ -0,0 +1,757 @@
+use serde::{Deserialize, Serialize};
+
+use crate::duration::format_duration;
+use crate::output::{Color, Output, Tabular};
+use crate::serde_util::deserialize_flag;
+
+/// Used and total amounts of a resource, in bytes.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Usage {
+    #[serde(default)]
+    pub used: u64,
+    #[serde(default)]
+    pub total: u64,
+}
+
+impl Usage {
+    fn format(&self, output: &Output) -> String {
+        if self.total == 0 {
+            return "-".to_string();
+        }
+        format!(
+            "{} / {} ({:.0}%)",
+            output.format_bytes(self.used),
+            output.format_bytes(self.total),
+            self.used as f64 * 100.0 / self.total as f64
+        )
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct CpuInfo {
+    #[serde(default)]
+    pub model: String,
+    #[serde(default)]
+    pub cpus: u32,
+    #[serde(default)]
+    pub sockets: u32,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Kernel {
+    #[serde(default)]
+    pub release: String,
+}
+
+/// A node as reported by `/nodes/{node}/status`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct NodeStatus {
+    #[serde(default)]
+    pub node: String,
+    #[serde(default)]
+    pub pveversion: Option<String>,
+    /// Full kernel version string, e.g. `Linux 6.8.12-4-pve #1 SMP ...`.
+    #[serde(default)]
+    pub kversion: Option<String>,
+    #[serde(rename = "current-kernel", default)]
+    pub current_kernel: Option<Kernel>,
+    #[serde(default)]
+    pub cpuinfo: CpuInfo,
+    /// CPU usage as a fraction of all CPUs.
+    #[serde(default)]
+    pub cpu: f64,
+    /// Load averages over 1, 5 and 15 minutes.
+    #[serde(default)]
+    pub loadavg: Vec<String>,
+    #[serde(default)]
+    pub memory: Usage,
+    #[serde(default)]
+    pub swap: Usage,
+    #[serde(default)]
+    pub rootfs: Usage,
+    #[serde(default)]
+    pub uptime: u64,
+}
+
+impl NodeStatus {
+    /// The kernel release, e.g. `6.8.12-4-pve`, falling back to the full
+    /// version string on releases that do not report it separately.
+    pub fn kernel(&self) -> String {
+        match (&self.current_kernel, &self.kversion) {
+            (Some(kernel), _) if !kernel.release.is_empty() => kernel.release.clone(),
+            (_, Some(kversion)) => kversion.clone(),
+            _ => "-".to_string(),
+        }
+    }
+}
+
+impl Tabular for NodeStatus {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("node", "Node"),
+            ("pveversion", "Version"),
+            ("kernel", "Kernel"),
+            ("cpuinfo", "CPU"),
+            ("cpu", "CPU usage"),
+            ("loadavg", "Load"),
+            ("memory", "Memory"),
+            ("swap", "Swap"),
+            ("rootfs", "Root FS"),
+            ("uptime", "Uptime"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        vec![
+            self.node.clone(),
+            self.pveversion.clone().unwrap_or_else(|| "-".to_string()),
+            self.kernel(),
+            format!(
+                "{} ({} CPUs, {} sockets)",
+                self.cpuinfo.model, self.cpuinfo.cpus, self.cpuinfo.sockets
+            ),
+            format!("{:.1}%", self.cpu * 100.0),
+            self.loadavg.join(" "),
+            self.memory.format(output),
+            self.swap.format(output),
+            self.rootfs.format(output),
+            format_duration(self.uptime),
+        ]
+    }
+}
+
+/// The subscription of a node, from `/nodes/{node}/subscription`.
+#[derive(Debug, Default, Deserialize)]
+pub struct Subscription {
+    /// `active`, or e.g. `notfound`, `expired` or `invalid`.
+    #[serde(default)]
+    pub status: String,
+    /// `c`, `b`, `s` or `p` for Community, Basic, Standard or Premium.
+    #[serde(default)]
+    pub level: Option<String>,
+    #[serde(default)]
+    pub nextduedate: Option<String>,
+}
+
+/// A repository Proxmox knows about, from the `standard-repos` of
+/// `/nodes/{node}/apt/repositories`.
+#[derive(Debug, Deserialize)]
+pub struct StandardRepo {
+    /// E.g. `enterprise`, `no-subscription` or `ceph-reef-enterprise`.
+    pub handle: String,
+    /// 1 when configured and enabled, 0 when disabled, unset when absent.
+    #[serde(default)]
+    pub status: Option<u8>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Repositories {
+    #[serde(rename = "standard-repos", default)]
+    pub standard_repos: Vec<StandardRepo>,
+}
+
+/// Subscription and repository state of a node, as listed by `node
+/// subscription`.
+#[derive(Debug, Serialize)]
+pub struct NodeSubscription {
+    pub node: String,
+    pub status: String,
+    pub level: Option<&'static str>,
+    pub nextduedate: Option<String>,
+    /// Handles of the enabled standard repositories.
+    pub repos: Vec<String>,
+}
+
+impl NodeSubscription {
+    pub fn new(node: &str, subscription: Subscription, repositories: Repositories) -> Self {
+        let level = subscription.level.as_deref().and_then(|level| match level {
+            "c" => Some("Community"),
+            "b" => Some("Basic"),
+            "s" => Some("Standard"),
+            "p" => Some("Premium"),
+            _ => None,
+        });
+        Self {
+            node: node.to_string(),
+            status: subscription.status,
+            level,
+            nextduedate: subscription.nextduedate.filter(|d| !d.is_empty()),
+            repos: repositories
+                .standard_repos
+                .into_iter()
+                .filter(|r| r.status == Some(1))
+                .map(|r| r.handle)
+                .collect(),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.status == "active"
+    }
+
+    /// Enterprise repositories fail `apt update` without an active
+    /// subscription.
+    pub fn enterprise_without_subscription(&self) -> bool {
+        !self.is_active() && self.repos.iter().any(|r| r.ends_with("enterprise"))
+    }
+}
+
+impl Tabular for NodeSubscription {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("node", "Node"),
+            ("status", "Status"),
+            ("level", "Level"),
+            ("nextduedate", "Next due"),
+            ("repos", "Repositories"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        let status = if self.is_active() {
+            output.paint(Color::Green, &self.status)
+        } else {
+            output.paint(Color::Yellow, &self.status)
+        };
+        let repos = self.repos.join(", ");
+        vec![
+            self.node.clone(),
+            status,
+            self.level.unwrap_or("-").to_string(),
+            self.nextduedate.clone().unwrap_or_else(|| "-".to_string()),
+            if self.enterprise_without_subscription() {
+                output.paint(Color::Red, &repos)
+            } else {
+                repos
+            },
+        ]
+    }
+}
+
+/// A pending package update, from `/nodes/{node}/apt/update`.
+#[derive(Debug, Deserialize)]
+pub struct AptUpdate {
+    #[serde(rename = "Package")]
+    pub package: String,
+    #[serde(rename = "OldVersion", default)]
+    pub old_version: Option<String>,
+    #[serde(rename = "Version", default)]
+    pub version: Option<String>,
+    /// Archive origin, e.g. `Proxmox`, `Debian` or `Debian-Security`.
+    #[serde(rename = "Origin", default)]
+    pub origin: Option<String>,
+    #[serde(rename = "Label", default)]
+    pub label: Option<String>,
+}
+
+/// A pending update on a node, as listed by `node updates`.
+#[derive(Debug, Serialize)]
+pub struct PendingUpdate {
+    pub node: String,
+    pub package: String,
+    pub old_version: String,
+    pub version: String,
+    pub origin: String,
+    pub security: bool,
+}
+
+impl PendingUpdate {
+    pub fn new(node: &str, update: AptUpdate) -> Self {
+        let security = [&update.origin, &update.label]
+            .into_iter()
+            .flatten()
+            .any(|s| s.to_ascii_lowercase().contains("security"));
+        Self {
+            node: node.to_string(),
+            package: update.package,
+            old_version: update.old_version.unwrap_or_default(),
+            version: update.version.unwrap_or_default(),
+            origin: update.origin.unwrap_or_default(),
+            security,
+        }
+    }
+}
+
+impl Tabular for PendingUpdate {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("node", "Node"),
+            ("package", "Package"),
+            ("old_version", "Installed"),
+            ("version", "Available"),
+            ("origin", "Origin"),
+            ("security", "Security"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        vec![
+            self.node.clone(),
+            self.package.clone(),
+            self.old_version.clone(),
+            self.version.clone(),
+            self.origin.clone(),
+            if self.security {
+                output.paint(Color::Red, "yes")
+            } else {
+                "no".to_string()
+            },
+        ]
+    }
+}
+
+/// A certificate of a node, from `/nodes/{node}/certificates/info`.
+#[derive(Debug, Deserialize)]
+pub struct CertificateInfo {
+    pub filename: String,
+    #[serde(default)]
+    pub subject: Option<String>,
+    /// Expiry as a Unix timestamp.
+    #[serde(default)]
+    pub notafter: Option<i64>,
+}
+
+/// A node certificate with the days left before it expires, as listed by
+/// `node certs`.
+#[derive(Debug, Serialize)]
+pub struct NodeCertificate {
+    pub node: String,
+    pub filename: String,
+    pub subject: String,
+    pub notafter: Option<i64>,
+    /// Negative once expired.
+    pub days_left: Option<i64>,
+    pub expiring: bool,
+}
+
+impl NodeCertificate {
+    pub fn new(node: &str, cert: CertificateInfo, now: i64, warn_days: i64) -> Self {
+        let days_left = cert.notafter.map(|t| (t - now).div_euclid(86_400));
+        Self {
+            node: node.to_string(),
+            filename: cert.filename,
+            subject: cert.subject.unwrap_or_default(),
+            notafter: cert.notafter,
+            days_left,
+            expiring: days_left.is_some_and(|days| days < warn_days),
+        }
+    }
+}
+
+impl Tabular for NodeCertificate {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("node", "Node"),
+            ("filename", "File"),
+            ("subject", "Subject"),
+            ("notafter", "Expires"),
+            ("days_left", "Days left"),
+            ("expiring", "Expiring"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        let expires = self
+            .notafter
+            .and_then(|t| chrono::DateTime::from_timestamp(t, 0))
+            .map_or_else(|| "-".to_string(), |dt| output.format_time(dt));
+        let days_left = self
+            .days_left
+            .map_or_else(|| "-".to_string(), |days| days.to_string());
+        vec![
+            self.node.clone(),
+            self.filename.clone(),
+            self.subject.clone(),
+            expires,
+            if self.expiring {
+                output.paint(Color::Red, &days_left)
+            } else {
+                days_left
+            },
+            if self.expiring {
+                output.paint(Color::Red, "yes")
+            } else {
+                "no".to_string()
+            },
+        ]
+    }
+}
+
+/// An installed package, from `/nodes/{node}/apt/versions`.
+#[derive(Debug, Deserialize)]
+pub struct PackageVersion {
+    #[serde(rename = "Package")]
+    pub package: String,
+    /// The installed version; `Version` is the candidate.
+    #[serde(rename = "OldVersion", default)]
+    pub old_version: Option<String>,
+}
+
+/// The versions that must agree across nodes for migrations and snapshots
+/// with RAM state to work, as listed by `node versions`.
+#[derive(Debug, Serialize)]
+pub struct NodeVersions {
+    pub node: String,
+    pub manager: String,
+    pub kernel: String,
+    pub qemu: String,
+    /// Components whose version differs from the one most nodes run.
+    pub mismatched: Vec<&'static str>,
+}
+
+impl NodeVersions {
+    pub fn new(node: &str, status: &NodeStatus, packages: &[PackageVersion]) -> Self {
+        // pveversion reads e.g. pve-manager/8.2.7/3e0176e6bb2ade3b.
+        let manager = status
+            .pveversion
+            .as_deref()
+            .and_then(|v| v.split('/').nth(1))
+            .unwrap_or("-");
+        let qemu = packages
+            .iter()
+            .find(|p| p.package == "pve-qemu-kvm")
+            .and_then(|p| p.old_version.as_deref())
+            .unwrap_or("-");
+        Self {
+            node: node.to_string(),
+            manager: manager.to_string(),
+            kernel: status.kernel(),
+            qemu: qemu.to_string(),
+            mismatched: Vec::new(),
+        }
+    }
+
+    fn component(&self, component: &str) -> &str {
+        match component {
+            "manager" => &self.manager,
+            "kernel" => &self.kernel,
+            _ => &self.qemu,
+        }
+    }
+}
+
+/// Marks on each node the components whose version differs from the one
+/// most nodes run, and returns the components that are not consistent.
+pub fn mark_mismatches(nodes: &mut [NodeVersions]) -> Vec<&'static str> {
+    let mut inconsistent = Vec::new();
+    for component in ["manager", "kernel", "qemu"] {
+        let mut counts: Vec<(String, usize)> = Vec::new();
+        for node in nodes.iter() {
+            let version = node.component(component);
+            match counts.iter_mut().find(|(v, _)| v == version) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((version.to_string(), 1)),
+            }
+        }
+        if counts.len() < 2 {
+            continue;
+        }
+        inconsistent.push(component);
+        let common = counts
+            .iter()
+            .max_by_key(|(_, count)| *count)
+            .map(|(v, _)| v.clone())
+            .unwrap_or_default();
+        for node in nodes.iter_mut() {
+            if node.component(component) != common {
+                node.mismatched.push(component);
+            }
+        }
+    }
+    inconsistent
+}
+
+impl Tabular for NodeVersions {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("node", "Node"),
+            ("manager", "pve-manager"),
+            ("kernel", "Kernel"),
+            ("qemu", "QEMU"),
+            ("mismatched", "Mismatch"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        let cell = |component: &str| {
+            let version = self.component(component);
+            if self.mismatched.contains(&component) {
+                output.paint(Color::Yellow, version)
+            } else {
+                version.to_string()
+            }
+        };
+        vec![
+            self.node.clone(),
+            cell("manager"),
+            cell("kernel"),
+            cell("qemu"),
+            if self.mismatched.is_empty() {
+                "-".to_string()
+            } else {
+                output.paint(Color::Yellow, &self.mismatched.join(", "))
+            },
+        ]
+    }
+}
+
+/// A network interface of a node, from `/nodes/{node}/network`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct NetworkInterface {
+    pub iface: String,
+    /// `bridge`, `bond`, `eth`, `vlan`, or an `OVS*` type.
+    #[serde(rename = "type")]
+    pub iface_type: String,
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    pub active: bool,
+    /// IPv4 address with prefix length, e.g. `10.0.0.2/24`.
+    #[serde(default)]
+    pub cidr: Option<String>,
+    #[serde(default)]
+    pub cidr6: Option<String>,
+    #[serde(default)]
+    pub gateway: Option<String>,
+    #[serde(default)]
+    pub bridge_ports: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    pub bridge_vlan_aware: bool,
+    #[serde(default)]
+    pub slaves: Option<String>,
+    #[serde(default)]
+    pub bond_mode: Option<String>,
+    #[serde(rename = "vlan-raw-device", default)]
+    pub vlan_raw_device: Option<String>,
+    #[serde(default)]
+    pub comments: Option<String>,
+}
+
+impl NetworkInterface {
+    /// What the interface is built on: the ports of a bridge, the slaves of
+    /// a bond or the device of a VLAN.
+    fn members(&self) -> String {
+        let mut members = [&self.bridge_ports, &self.slaves, &self.vlan_raw_device]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if let Some(mode) = &self.bond_mode {
+            members = format!("{} ({})", members, mode);
+        }
+        if self.bridge_vlan_aware {
+            members = format!("{} (VLAN aware)", members);
+        }
+        members.trim().to_string()
+    }
+}
+
+impl Tabular for NetworkInterface {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("iface", "Interface"),
+            ("type", "Type"),
+            ("active", "Active"),
+            ("cidr", "Address"),
+            ("gateway", "Gateway"),
+            ("members", "Ports/Slaves"),
+            ("comments", "Comment"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        let addresses = [&self.cidr, &self.cidr6]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(", ");
+        vec![
+            self.iface.clone(),
+            self.iface_type.clone(),
+            if self.active {
+                "yes".to_string()
+            } else {
+                output.paint(Color::Yellow, "no")
+            },
+            addresses,
+            self.gateway.clone().unwrap_or_default(),
+            self.members(),
+            self.comments.clone().unwrap_or_default().trim().to_string(),
+        ]
+    }
+}
+
+/// A power command for a whole node, sent to `POST /nodes/{node}/status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodePowerCommand {
+    Reboot,
+    Shutdown,
+}
+
+impl NodePowerCommand {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NodePowerCommand::Reboot => "reboot",
+            NodePowerCommand::Shutdown => "shutdown",
+        }
+    }
+}
+
+/// How `node maintenance enter` empties a node.
+#[derive(Debug, Clone)]
+pub struct MaintenanceOptions {
+    /// Migrate every guest off the node before setting the HA state.
+    pub evacuate: bool,
+    /// Node to evacuate to; guests are spread by memory when unset.
+    pub target: Option<String>,
+    pub jobs: usize,
+    pub with_local_disks: bool,
+    pub ssh_user: String,
+    /// Seconds to wait for the guests left running to move away.
+    pub timeout: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_status() {
+        let status: NodeStatus = serde_json::from_value(serde_json::json!({
+            "pveversion": "pve-manager/8.2.7/3e0176e6bb2ade3b",
+            "kversion": "Linux 6.8.12-4-pve #1 SMP PREEMPT_DYNAMIC PMX 6.8.12-4",
+            "current-kernel": {"release": "6.8.12-4-pve", "sysname": "Linux"},
+            "cpuinfo": {"model": "AMD EPYC 7302P", "cpus": 32, "sockets": 1, "mhz": "3000"},
+            "cpu": 0.125,
+            "loadavg": ["0.52", "0.61", "0.70"],
+            "memory": {"used": 1u64 << 30, "total": 4u64 << 30, "free": 3u64 << 30},
+            "swap": {"used": 0, "total": 0, "free": 0},
+            "rootfs": {"used": 10, "total": 100, "avail": 90, "free": 90},
+            "uptime": 93784
+        }))
+        .unwrap();
+        assert_eq!(status.kernel(), "6.8.12-4-pve");
+
+        let row = status.row(&Output::default());
+        assert_eq!(row[4], "12.5%");
+        assert_eq!(row[5], "0.52 0.61 0.70");
+        assert!(row[6].ends_with("(25%)"));
+        assert_eq!(row[7], "-");
+        assert_eq!(row[9], "1d 2h 3m");
+
+        let old = NodeStatus {
+            kversion: Some("Linux 5.15.0-1-pve".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(old.kernel(), "Linux 5.15.0-1-pve");
+    }
+
+    #[test]
+    fn test_node_subscription() {
+        let subscription: Subscription = serde_json::from_value(serde_json::json!({
+            "status": "notfound", "message": "There is no subscription key"
+        }))
+        .unwrap();
+        let repositories: Repositories = serde_json::from_value(serde_json::json!({
+            "standard-repos": [
+                { "handle": "enterprise", "name": "Enterprise", "status": 1 },
+                { "handle": "no-subscription", "name": "No-Subscription", "status": 0 },
+                { "handle": "test", "name": "Test" }
+            ]
+        }))
+        .unwrap();
+        let node = NodeSubscription::new("pve1", subscription, repositories);
+        assert_eq!(node.repos, vec!["enterprise"]);
+        assert!(node.enterprise_without_subscription());
+
+        let active = Subscription {
+            status: "active".to_string(),
+            level: Some("c".to_string()),
+            ..Default::default()
+        };
+        let node = NodeSubscription::new("pve1", active, Repositories::default());
+        assert_eq!(node.level, Some("Community"));
+        assert!(!node.enterprise_without_subscription());
+    }
+
+    #[test]
+    fn test_pending_update() {
+        let update: AptUpdate = serde_json::from_value(serde_json::json!({
+            "Package": "openssl", "OldVersion": "3.0.14-1~deb12u1",
+            "Version": "3.0.15-1~deb12u1", "Origin": "Debian", "Label": "Debian-Security"
+        }))
+        .unwrap();
+        let update = PendingUpdate::new("pve1", update);
+        assert!(update.security);
+        assert_eq!(update.version, "3.0.15-1~deb12u1");
+        assert_eq!(update.row(&Output::default())[5], "yes");
+
+        let update: AptUpdate = serde_json::from_value(serde_json::json!({
+            "Package": "pve-manager", "Version": "8.2.8", "Origin": "Proxmox"
+        }))
+        .unwrap();
+        assert!(!PendingUpdate::new("pve1", update).security);
+    }
+
+    #[test]
+    fn test_node_certificate() {
+        let now = 1_700_000_000;
+        let cert = |days: i64| CertificateInfo {
+            filename: "pveproxy-ssl.pem".to_string(),
+            subject: None,
+            notafter: Some(now + days * 86_400 + 60),
+        };
+        let soon = NodeCertificate::new("pve1", cert(10), now, 30);
+        assert_eq!(soon.days_left, Some(10));
+        assert!(soon.expiring);
+        assert_eq!(soon.row(&Output::default())[5], "yes");
+        assert!(!NodeCertificate::new("pve1", cert(300), now, 30).expiring);
+        let expired = NodeCertificate::new("pve1", cert(-2), now, 30);
+        assert_eq!(expired.days_left, Some(-2));
+        assert!(expired.expiring);
+    }
+
+    #[test]
+    fn test_mark_mismatches() {
+        let node = |name: &str, manager: &str, qemu: &str| {
+            let status = NodeStatus {
+                pveversion: Some(format!("pve-manager/{}/3e0176e6", manager)),
+                kversion: Some("6.8.12-4-pve".to_string()),
+                ..Default::default()
+            };
+            let packages = vec![PackageVersion {
+                package: "pve-qemu-kvm".to_string(),
+                old_version: Some(qemu.to_string()),
+            }];
+            NodeVersions::new(name, &status, &packages)
+        };
+        let mut nodes = vec![
+            node("pve1", "8.2.7", "9.0.2-3"),
+            node("pve2", "8.2.7", "9.0.2-3"),
+            node("pve3", "8.2.4", "9.0.2-3"),
+        ];
+        assert_eq!(nodes[0].manager, "8.2.7");
+        assert_eq!(mark_mismatches(&mut nodes), vec!["manager"]);
+        assert!(nodes[0].mismatched.is_empty());
+        assert_eq!(nodes[2].mismatched, vec!["manager"]);
+        assert_eq!(nodes[0].row(&Output::default())[4], "-");
+        assert_eq!(nodes[2].row(&Output::default())[4], "manager");
+    }
+
+    #[test]
+    fn test_network_interface_members() {
+        let bridge: NetworkInterface = serde_json::from_value(serde_json::json!({
+            "iface": "vmbr0", "type": "bridge", "active": 1, "cidr": "10.0.0.2/24",
+            "bridge_ports": "bond0", "bridge_vlan_aware": 1
+        }))
+        .unwrap();
+        assert!(bridge.active);
+        assert_eq!(bridge.members(), "bond0 (VLAN aware)");
+
+        let bond: NetworkInterface = serde_json::from_value(serde_json::json!({
+            "iface": "bond0", "type": "bond", "slaves": "eno1 eno2", "bond_mode": "802.3ad"
+        }))
+        .unwrap();
+        assert_eq!(bond.members(), "eno1 eno2 (802.3ad)");
+    }
+}
@@ -0,0 +1,774 @@
+use anyhow::Result;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, Utc};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::{IsTerminal, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::client::ApiError;
+use crate::size::{self, SizeUnits};
+use crate::template;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+    Csv,
+    Yaml,
+    Jsonl,
+    Markdown,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Resolves the choice against the terminal and the `NO_COLOR` convention.
+    fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                    && std::io::stdout().is_terminal()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Color {
+    Green,
+    Red,
+    Yellow,
+}
+
+impl Color {
+    fn code(self) -> &'static str {
+        match self {
+            Color::Green => "32",
+            Color::Red => "31",
+            Color::Yellow => "33",
+        }
+    }
+
+    fn for_status(status: &str) -> Self {
+        match status {
+            "running" | "online" | "OK" | "ok" => Color::Green,
+            "stopped" | "offline" | "failed" | "error" => Color::Red,
+            _ => Color::Yellow,
+        }
+    }
+}
+
+/// A value that can be rendered as a row of a table.
+///
+/// `columns` lists `(key, header)` pairs; `row` must return one cell per column,
+/// in the same order.
+pub trait Tabular: Serialize {
+    fn columns() -> &'static [(&'static str, &'static str)];
+    fn row(&self, output: &Output) -> Vec<String>;
+
+    /// Keys of the columns shown when the user did not select any.
+    fn default_columns() -> Vec<&'static str> {
+        Self::columns().iter().map(|(k, _)| *k).collect()
+    }
+}
+
+pub const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+#[derive(Debug, Clone)]
+pub struct Output {
+    format: OutputFormat,
+    raw: bool,
+    quiet: bool,
+    color: bool,
+    size_units: SizeUnits,
+    utc: bool,
+    time_format: String,
+    /// Cluster whose output this is, when a command spans several.
+    cluster: Option<String>,
+    /// JSON and YAML values held back until `flush_collected`, shared by
+    /// the clones made for each cluster.
+    collected: Option<Arc<Mutex<Vec<serde_json::Value>>>>,
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self::new(OutputFormat::default())
+    }
+}
+
+impl Output {
+    pub fn new(format: OutputFormat) -> Self {
+        Self {
+            format,
+            raw: false,
+            quiet: false,
+            color: false,
+            size_units: SizeUnits::default(),
+            utc: false,
+            time_format: DEFAULT_TIME_FORMAT.to_string(),
+            cluster: None,
+            collected: None,
+        }
+    }
+
+    /// Labels titles, messages and records with a cluster name.
+    pub fn with_cluster(mut self, cluster: Option<&str>) -> Self {
+        self.cluster = cluster.map(str::to_string);
+        self
+    }
+
+    /// Makes JSON and YAML output accumulate instead of printing, so that
+    /// the output of several clusters forms a single array. Other formats
+    /// are unaffected.
+    pub fn collecting(mut self) -> Self {
+        if matches!(self.format, OutputFormat::Json | OutputFormat::Yaml) {
+            self.collected = Some(Arc::default());
+        }
+        self
+    }
+
+    /// Prints the values accumulated since `collecting` as one array.
+    pub fn flush_collected(&self) -> Result<()> {
+        let Some(collected) = &self.collected else {
+            return Ok(());
+        };
+        let values = std::mem::take(&mut *collected.lock().unwrap());
+        let output = Self {
+            collected: None,
+            ..self.clone()
+        };
+        output.print_serialized(&values)
+    }
+
+    /// Prefixes `text` with the cluster name, if any.
+    pub fn label(&self, text: &str) -> String {
+        match &self.cluster {
+            Some(cluster) => format!("[{}] {}", cluster, text),
+            None => text.to_string(),
+        }
+    }
+
+    /// Adds a `cluster` field to a serialized object when labelling.
+    fn with_cluster_field(&self, value: &mut serde_json::Value) {
+        if let Some(cluster) = &self.cluster
+            && let Some(object) = value.as_object_mut()
+        {
+            object.insert("cluster".to_string(), cluster.clone().into());
+        }
+    }
+
+    pub fn with_color(mut self, choice: ColorChoice) -> Self {
+        self.color = choice.enabled();
+        self
+    }
+
+    pub fn with_size_units(mut self, size_units: SizeUnits) -> Self {
+        self.size_units = size_units;
+        self
+    }
+
+    pub fn with_utc(mut self, utc: bool) -> Self {
+        self.utc = utc;
+        self
+    }
+
+    /// Sets the strftime format used for timestamps; see [`validate_time_format`].
+    pub fn with_time_format(mut self, time_format: &str) -> Self {
+        self.time_format = time_format.to_string();
+        self
+    }
+
+    pub fn format_time(&self, time: DateTime<Utc>) -> String {
+        self.format_time_with(time, &self.time_format)
+    }
+
+    /// Formats `time` in UTC or local time, depending on `--utc`.
+    pub fn format_time_with(&self, time: DateTime<Utc>, format: &str) -> String {
+        if self.utc {
+            time.format(format).to_string()
+        } else {
+            time.with_timezone(&Local).format(format).to_string()
+        }
+    }
+
+    pub fn format_bytes(&self, bytes: u64) -> String {
+        size::format_bytes(bytes, self.size_units)
+    }
+
+    pub fn paint(&self, color: Color, text: &str) -> String {
+        if self.color {
+            format!("\x1b[{}m{}\x1b[0m", color.code(), text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Colors a VM, node or task status according to its meaning.
+    pub fn paint_status(&self, status: &str) -> String {
+        self.paint(Color::for_status(status.trim()), status)
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+
+    pub fn with_raw(mut self, raw: bool) -> Self {
+        self.raw = raw;
+        self
+    }
+
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    fn is_silent(&self) -> bool {
+        self.raw || self.quiet
+    }
+
+    /// Prints the API response body wrapping `data` when raw mode is enabled.
+    ///
+    /// Returns `true` if the data was printed, in which case the caller should
+    /// skip its own rendering.
+    pub fn print_raw<T: Serialize>(&self, data: &T) -> Result<bool> {
+        if !self.raw {
+            return Ok(false);
+        }
+        let body = serde_json::json!({ "data": data });
+        println!("{}", serde_json::to_string_pretty(&body)?);
+        Ok(true)
+    }
+
+    /// Prints a progress or status message, unless raw or quiet mode is enabled.
+    pub fn message(&self, msg: &str) {
+        if !self.is_silent() {
+            println!("{}", self.label(msg));
+        }
+    }
+
+    /// Prints a warning to stderr. Warnings are shown even in quiet mode.
+    pub fn warn(&self, msg: &str) {
+        eprintln!("{} {}", self.paint(Color::Yellow, "Warning:"), msg);
+    }
+
+    /// Prints a single progress dot without a trailing newline.
+    pub fn progress(&self) -> Result<()> {
+        if !self.is_silent() {
+            print!(".");
+            std::io::stdout().flush()?;
+        }
+        Ok(())
+    }
+
+    /// Redraws a progress bar for a transfer of `total` bytes on stderr,
+    /// ending the line once `done` reaches it. Nothing is drawn unless
+    /// stderr is a terminal.
+    pub fn transfer(&self, done: u64, total: u64) {
+        const WIDTH: u64 = 30;
+        if self.is_silent() || !std::io::stderr().is_terminal() {
+            return;
+        }
+        let done = done.min(total);
+        let filled = (done * WIDTH).checked_div(total).unwrap_or(WIDTH) as usize;
+        let percent = (done * 100).checked_div(total).unwrap_or(100);
+        eprint!(
+            "\r[{}{}] {:>3}% {} / {}",
+            "#".repeat(filled),
+            " ".repeat(WIDTH as usize - filled),
+            percent,
+            self.format_bytes(done),
+            self.format_bytes(total)
+        );
+        if done == total {
+            eprintln!();
+        }
+    }
+
+    pub fn print_list<T: Tabular>(&self, title: &str, items: &[T]) -> Result<()> {
+        self.print_list_columns(title, items, None)
+    }
+
+    /// Prints a list restricted to the given column keys.
+    ///
+    /// Without a selection, tables show `T::default_columns()` and JSON shows
+    /// every field.
+    pub fn print_list_columns<T: Tabular>(
+        &self,
+        title: &str,
+        items: &[T],
+        columns: Option<&[&str]>,
+    ) -> Result<()> {
+        let selected: Vec<&str> = match columns {
+            Some(columns) => columns.to_vec(),
+            None => T::default_columns(),
+        };
+        let indices = selected
+            .iter()
+            .map(|key| {
+                T::columns()
+                    .iter()
+                    .position(|(k, _)| k == key)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown column '{}'", key))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let rows = || {
+            items
+                .iter()
+                .map(|item| {
+                    let row = item.row(self);
+                    indices.iter().map(|&i| row[i].clone()).collect()
+                })
+                .collect::<Vec<Vec<String>>>()
+        };
+        let title = self.label(title);
+
+        match self.format {
+            OutputFormat::Table => {
+                println!("{}", title);
+                if items.is_empty() {
+                    println!("  (none)");
+                } else {
+                    let headers = indices
+                        .iter()
+                        .map(|&i| T::columns()[i].1.to_string())
+                        .collect();
+                    let status_column = selected.iter().position(|&k| k == "status");
+                    let paint = |column: usize, cell: &str| {
+                        if Some(column) == status_column {
+                            self.paint_status(cell)
+                        } else {
+                            cell.to_string()
+                        }
+                    };
+                    print!("{}", render_table(headers, &rows(), &paint));
+                }
+            }
+            OutputFormat::Jsonl => {
+                // Each line is written as soon as it is serialized, so that
+                // consumers can start on a long list before it ends.
+                for item in items {
+                    let mut value = serde_json::to_value(item)?;
+                    if columns.is_some()
+                        && let Some(value) = value.as_object_mut()
+                    {
+                        value.retain(|k, _| selected.contains(&k.as_str()));
+                    }
+                    self.with_cluster_field(&mut value);
+                    print_line(&value)?;
+                }
+            }
+            OutputFormat::Json | OutputFormat::Yaml => {
+                let mut values = serde_json::to_value(items)?;
+                if columns.is_some()
+                    && let Some(values) = values.as_array_mut()
+                {
+                    for value in values.iter_mut().filter_map(|v| v.as_object_mut()) {
+                        value.retain(|k, _| selected.contains(&k.as_str()));
+                    }
+                }
+                if let Some(values) = values.as_array_mut() {
+                    values.iter_mut().for_each(|v| self.with_cluster_field(v));
+                }
+                self.print_serialized(&values)?;
+            }
+            OutputFormat::Csv => {
+                let mut headers: Vec<String> = selected.iter().map(|k| k.to_string()).collect();
+                let mut rows = rows();
+                if let Some(cluster) = &self.cluster {
+                    headers.insert(0, "cluster".to_string());
+                    rows.iter_mut()
+                        .for_each(|row| row.insert(0, cluster.clone()));
+                }
+                print!("{}", render_csv(headers, &rows));
+            }
+            OutputFormat::Markdown => {
+                let headers = indices
+                    .iter()
+                    .map(|&i| T::columns()[i].1.to_string())
+                    .collect();
+                println!("{}\n", title);
+                print!("{}", render_markdown(headers, &rows()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints the summary line that heads the sections of a table, followed
+    /// by a blank line. Other formats carry the summary in their own fields.
+    pub fn print_summary(&self, text: &str) {
+        if self.format == OutputFormat::Table {
+            println!("{}\n", self.label(text));
+        }
+    }
+
+    /// Prints a document that fits neither the list nor the record shape.
+    /// Only structured formats can represent it; returns false for the others
+    /// so the caller can print it section by section.
+    pub fn print_document<T: Serialize>(&self, value: &T) -> Result<bool> {
+        let mut value = serde_json::to_value(value)?;
+        self.with_cluster_field(&mut value);
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => self.print_serialized(&value)?,
+            OutputFormat::Jsonl => print_line(&value)?,
+            OutputFormat::Table | OutputFormat::Csv | OutputFormat::Markdown => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    pub fn print_record<T: Tabular>(&self, title: &str, item: &T) -> Result<()> {
+        match self.format {
+            OutputFormat::Table => {
+                println!("{}", title);
+                for ((key, header), value) in T::columns().iter().zip(item.row(self)) {
+                    if *key == "status" {
+                        println!("  {}: {}", header, self.paint_status(&value));
+                    } else {
+                        println!("  {}: {}", header, value);
+                    }
+                }
+            }
+            OutputFormat::Json | OutputFormat::Yaml => self.print_serialized(item)?,
+            OutputFormat::Jsonl => print_line(item)?,
+            OutputFormat::Csv => {
+                let headers = T::columns().iter().map(|(k, _)| k.to_string()).collect();
+                print!("{}", render_csv(headers, &[item.row(self)]));
+            }
+            OutputFormat::Markdown => {
+                let rows = T::columns()
+                    .iter()
+                    .zip(item.row(self))
+                    .map(|((_, header), value)| vec![header.to_string(), value])
+                    .collect::<Vec<_>>();
+                println!("{}\n", title);
+                print!(
+                    "{}",
+                    render_markdown(vec!["Field".to_string(), "Value".to_string()], &rows)
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints one line per item by substituting `{field}` placeholders in
+    /// `template` with the item's serialized fields.
+    pub fn print_template<T: Serialize>(&self, items: &[T], template: &str) -> Result<()> {
+        let template = template::unescape(template);
+        for item in items {
+            let value = serde_json::to_value(item)?;
+            let line = template::render(&template, |key| template::json_lookup(&value, key))?;
+            println!("{}", line);
+        }
+        Ok(())
+    }
+
+    /// Reports a command failure on stderr.
+    ///
+    /// Machine-readable formats get a `{code, endpoint, status, message}`
+    /// object so automation can branch on the error category.
+    pub fn print_error(&self, error: &anyhow::Error) {
+        if !matches!(self.format, OutputFormat::Json | OutputFormat::Jsonl) {
+            eprintln!("Error: {:?}", error);
+            if let Some(hint) = hint(error) {
+                eprintln!("{} {}", self.paint(Color::Yellow, "Hint:"), hint);
+            }
+            return;
+        }
+
+        let body = match error.downcast_ref::<ApiError>() {
+            Some(api) => serde_json::json!({
+                "code": api.code(),
+                "endpoint": api.endpoint(),
+                "status": api.status(),
+                "message": api.message(),
+            }),
+            None => serde_json::json!({
+                "code": "error",
+                "endpoint": null,
+                "status": null,
+                "message": format!("{:#}", error),
+            }),
+        };
+        eprintln!("{}", body);
+    }
+
+    fn print_serialized<T: Serialize + ?Sized>(&self, value: &T) -> Result<()> {
+        if let Some(collected) = &self.collected {
+            let mut values = match serde_json::to_value(value)? {
+                serde_json::Value::Array(values) => values,
+                value => vec![value],
+            };
+            values.iter_mut().for_each(|v| self.with_cluster_field(v));
+            collected.lock().unwrap().extend(values);
+            return Ok(());
+        }
+        if self.format == OutputFormat::Yaml {
+            print!("{}", serde_norway::to_string(value)?);
+        } else {
+            println!("{}", serde_json::to_string_pretty(value)?);
+        }
+        Ok(())
+    }
+}
+
+/// Advice for errors with a well-known cause.
+fn hint(error: &anyhow::Error) -> Option<&'static str> {
+    // Proxmox reports e.g. "VM is locked (snapshot)" or "CT is locked (backup)".
+    let message = format!("{:#}", error);
+    message.contains("is locked (").then_some(
+        "A task that crashed may have left the lock behind; once no task runs \
+         on the VM any more, 'pve-tool unlock <vm>' clears it",
+    )
+}
+
+/// Rejects strftime formats chrono cannot render, which would otherwise
+/// panic at display time.
+pub fn validate_time_format(format: &str) -> Result<String> {
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        anyhow::bail!("Invalid time format '{}'", format);
+    }
+    Ok(format.to_string())
+}
+
+/// Writes `value` as a single JSON line and flushes, so consumers see each
+/// record as soon as it is available.
+fn print_line<T: Serialize>(value: &T) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    serde_json::to_writer(&mut stdout, value)?;
+    writeln!(stdout)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Renders an aligned table; `paint` may decorate each padded body cell.
+fn render_table(
+    headers: Vec<String>,
+    rows: &[Vec<String>],
+    paint: &dyn Fn(usize, &str) -> String,
+) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_line = |cells: &[String], paint: &dyn Fn(usize, &str) -> String| {
+        let last = cells.len().saturating_sub(1);
+        let line = cells
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(i, (cell, width))| {
+                let padded = if i == last {
+                    cell.clone()
+                } else {
+                    format!("{:<width$}", cell, width = width)
+                };
+                paint(i, &padded)
+            })
+            .collect::<Vec<_>>()
+            .join("  ");
+        format!("{}\n", line)
+    };
+
+    let total = widths.iter().sum::<usize>() + 2 * widths.len().saturating_sub(1);
+    let mut out = format_line(&headers, &|_, cell| cell.to_string());
+    out.push_str(&"-".repeat(total));
+    out.push('\n');
+    for row in rows {
+        out.push_str(&format_line(row, paint));
+    }
+    out
+}
+
+fn render_csv(headers: Vec<String>, rows: &[Vec<String>]) -> String {
+    let format_line = |cells: &[String]| {
+        let line = cells
+            .iter()
+            .map(|c| csv_escape(c))
+            .collect::<Vec<_>>()
+            .join(",");
+        format!("{}\n", line)
+    };
+
+    let mut out = format_line(&headers);
+    for row in rows {
+        out.push_str(&format_line(row));
+    }
+    out
+}
+
+fn render_markdown(headers: Vec<String>, rows: &[Vec<String>]) -> String {
+    let format_line = |cells: &[String]| {
+        let cells = cells
+            .iter()
+            .map(|c| markdown_escape(c))
+            .collect::<Vec<_>>()
+            .join(" | ");
+        format!("| {} |\n", cells)
+    };
+
+    let mut out = format_line(&headers);
+    out.push_str(&format!("|{}\n", " --- |".repeat(headers.len())));
+    for row in rows {
+        out.push_str(&format_line(row));
+    }
+    out
+}
+
+fn markdown_escape(cell: &str) -> String {
+    cell.trim()
+        .replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace('\n', "<br>")
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_table_aligns_columns() {
+        let headers = vec!["VMID".to_string(), "Name".to_string()];
+        let rows = vec![
+            vec!["100".to_string(), "web".to_string()],
+            vec!["1001".to_string(), "database".to_string()],
+        ];
+        let table = render_table(headers, &rows, &|_, cell| cell.to_string());
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "VMID  Name");
+        assert_eq!(lines[1], "-".repeat(14));
+        assert_eq!(lines[2], "100   web");
+        assert_eq!(lines[3], "1001  database");
+    }
+
+    #[test]
+    fn test_render_table_paints_cells() {
+        let headers = vec!["Name".to_string(), "Status".to_string()];
+        let rows = vec![vec!["web".to_string(), "running".to_string()]];
+        let table = render_table(headers, &rows, &|column, cell| {
+            if column == 1 {
+                format!("<{}>", cell)
+            } else {
+                cell.to_string()
+            }
+        });
+        assert_eq!(table.lines().nth(2), Some("web   <running>"));
+    }
+
+    #[test]
+    fn test_paint_disabled() {
+        let output = Output::new(OutputFormat::Table).with_color(ColorChoice::Never);
+        assert_eq!(output.paint_status("running"), "running");
+    }
+
+    #[test]
+    fn test_paint_enabled() {
+        let output = Output::new(OutputFormat::Table).with_color(ColorChoice::Always);
+        assert_eq!(output.paint_status("running"), "\x1b[32mrunning\x1b[0m");
+        assert_eq!(output.paint_status("stopped"), "\x1b[31mstopped\x1b[0m");
+        assert_eq!(output.paint_status("paused"), "\x1b[33mpaused\x1b[0m");
+    }
+
+    #[test]
+    fn test_cluster_label() {
+        let output = Output::default().with_cluster(Some("prod"));
+        assert_eq!(output.label("VMs in cluster:"), "[prod] VMs in cluster:");
+        assert_eq!(Output::default().label("VMs"), "VMs");
+
+        let mut value = serde_json::json!({"vmid": 100});
+        output.with_cluster_field(&mut value);
+        assert_eq!(value["cluster"], "prod");
+    }
+
+    #[test]
+    fn test_collecting_across_clusters() {
+        let collector = Output::new(OutputFormat::Json).collecting();
+        for cluster in ["a", "b"] {
+            let output = collector.clone().with_cluster(Some(cluster));
+            output
+                .print_serialized(&serde_json::json!([{"vmid": 100}, {"vmid": 101}]))
+                .unwrap();
+        }
+        let collected = collector.collected.as_ref().unwrap().lock().unwrap();
+        assert_eq!(collected.len(), 4);
+        assert_eq!(collected[0]["cluster"], "a");
+        assert_eq!(collected[3]["cluster"], "b");
+
+        assert!(
+            Output::new(OutputFormat::Jsonl)
+                .collecting()
+                .collected
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_format_time_utc() {
+        let time = DateTime::from_timestamp(1705312800, 0).unwrap();
+        let output = Output::default().with_utc(true);
+        assert_eq!(output.format_time(time), "2024-01-15 10:00:00");
+
+        let output = output.with_time_format("%d/%m/%Y %H:%M");
+        assert_eq!(output.format_time(time), "15/01/2024 10:00");
+    }
+
+    #[test]
+    fn test_validate_time_format() {
+        assert!(validate_time_format("%Y-%m-%dT%H:%M:%S%z").is_ok());
+        assert!(validate_time_format("%Y-%Q").is_err());
+    }
+
+    #[test]
+    fn test_render_csv() {
+        let headers = vec!["vmid".to_string(), "name".to_string()];
+        let rows = vec![vec!["100".to_string(), "web".to_string()]];
+        assert_eq!(render_csv(headers, &rows), "vmid,name\n100,web\n");
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let headers = vec!["VMID".to_string(), "Name".to_string()];
+        let rows = vec![vec!["100".to_string(), "a|b\nc".to_string()]];
+        assert_eq!(
+            render_markdown(headers, &rows),
+            "| VMID | Name |\n| --- | --- |\n| 100 | a\\|b<br>c |\n"
+        );
+    }
+
+    #[test]
+    fn test_csv_escape() {
+        assert_eq!(csv_escape("plain"), "plain");
+        assert_eq!(csv_escape("a,b"), "\"a,b\"");
+        assert_eq!(csv_escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn test_hint_for_locked_vm() {
+        let locked = anyhow::anyhow!(
+            "API request to /nodes/pve1/qemu/100/snapshot failed with status 500: \
+             VM is locked (snapshot)"
+        );
+        assert!(hint(&locked).unwrap().contains("pve-tool unlock"));
+        assert!(hint(&anyhow::anyhow!("VM 100 not found")).is_none());
+    }
+}
@@ -0,0 +1,86 @@
+use anyhow::Result;
+use regex::Regex;
+
+/// Matches snapshot names against a shell-style glob or a regular expression.
+#[derive(Debug, Clone)]
+pub struct NamePattern {
+    source: String,
+    regex: Regex,
+}
+
+impl NamePattern {
+    /// Builds a pattern where `*` matches any run of characters and `?` a
+    /// single one. The whole name must match.
+    pub fn glob(pattern: &str) -> Result<Self> {
+        let mut regex = String::from("^");
+        for c in pattern.chars() {
+            match c {
+                '*' => regex.push_str(".*"),
+                '?' => regex.push('.'),
+                c => regex.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        regex.push('$');
+
+        Ok(Self {
+            source: pattern.to_string(),
+            regex: Regex::new(&regex)?,
+        })
+    }
+
+    /// Builds a pattern from a regular expression, which matches anywhere in
+    /// the name unless anchored.
+    pub fn regex(pattern: &str) -> Result<Self> {
+        let regex = Regex::new(pattern)
+            .map_err(|e| anyhow::anyhow!("Invalid regular expression '{}': {}", pattern, e))?;
+        Ok(Self {
+            source: pattern.to_string(),
+            regex,
+        })
+    }
+
+    /// Whether `name` contains glob characters. Proxmox snapshot names cannot
+    /// contain them, so such a name is always meant as a pattern.
+    pub fn is_glob(name: &str) -> bool {
+        name.contains(['*', '?'])
+    }
+
+    pub fn is_match(&self, name: &str) -> bool {
+        self.regex.is_match(name)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob() {
+        let pattern = NamePattern::glob("pre-patch-*").unwrap();
+        assert!(pattern.is_match("pre-patch-2024"));
+        assert!(pattern.is_match("pre-patch-"));
+        assert!(!pattern.is_match("daily-pre-patch-1"));
+
+        let pattern = NamePattern::glob("snap-?.0").unwrap();
+        assert!(pattern.is_match("snap-1.0"));
+        assert!(!pattern.is_match("snap-1x0"));
+    }
+
+    #[test]
+    fn test_regex() {
+        let pattern = NamePattern::regex(r"^daily-\d+$").unwrap();
+        assert!(pattern.is_match("daily-20240101"));
+        assert!(!pattern.is_match("daily-latest"));
+        assert!(NamePattern::regex("(").is_err());
+    }
+
+    #[test]
+    fn test_is_glob() {
+        assert!(NamePattern::is_glob("pre-*"));
+        assert!(!NamePattern::is_glob("daily-backup"));
+    }
+}
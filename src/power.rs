@@ -0,0 +1,203 @@
+use clap::ValueEnum;
+use serde::Serialize;
+
+/// A power state change, each backed by a `status/*` endpoint that QEMU VMs
+/// and LXC containers share.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerAction {
+    Start,
+    /// Immediate power-off, like pulling the plug.
+    Stop,
+    /// Clean shutdown through ACPI or the guest agent.
+    Shutdown,
+    Reboot,
+    /// Pause the guest, keeping its memory in RAM.
+    Suspend,
+    Resume,
+    /// Suspend to disk: the memory is saved to storage and the guest stops.
+    Hibernate,
+}
+
+impl PowerAction {
+    pub fn endpoint(self) -> &'static str {
+        match self {
+            PowerAction::Start => "start",
+            PowerAction::Stop => "stop",
+            PowerAction::Shutdown => "shutdown",
+            PowerAction::Reboot => "reboot",
+            PowerAction::Suspend | PowerAction::Hibernate => "suspend",
+            PowerAction::Resume => "resume",
+        }
+    }
+
+    /// Progress message verb, e.g. "Starting VM 100".
+    pub fn verb(self) -> &'static str {
+        match self {
+            PowerAction::Start => "Starting",
+            PowerAction::Stop => "Stopping",
+            PowerAction::Shutdown => "Shutting down",
+            PowerAction::Reboot => "Rebooting",
+            PowerAction::Suspend => "Suspending",
+            PowerAction::Resume => "Resuming",
+            PowerAction::Hibernate => "Hibernating",
+        }
+    }
+}
+
+/// A state `wait-status` waits for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GuestState {
+    Running,
+    Stopped,
+    /// Suspended with its memory kept in RAM.
+    Paused,
+}
+
+impl GuestState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            GuestState::Running => "running",
+            GuestState::Stopped => "stopped",
+            GuestState::Paused => "paused",
+        }
+    }
+
+    /// Whether `status/current` reports this state. A paused VM still has
+    /// the status `running`; only its QEMU status tells it apart.
+    pub fn matches(self, status: &str, qmpstatus: Option<&str>) -> bool {
+        let paused = matches!(qmpstatus, Some("paused" | "suspended"));
+        match self {
+            GuestState::Running => status == "running" && !paused,
+            GuestState::Stopped => status == "stopped",
+            GuestState::Paused => status == "running" && paused,
+        }
+    }
+}
+
+/// How `bulk` orders the guests it starts; stopping goes in reverse.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OrderBy {
+    /// The boot order of the guests' `startup` option.
+    #[default]
+    Startup,
+    Vmid,
+}
+
+/// The `startup` option of a guest, e.g. `order=2,up=30,down=60`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Startup {
+    pub order: Option<u32>,
+    /// Seconds to wait after starting the guest before starting the next.
+    pub up: Option<u64>,
+    /// Seconds to allow the guest to shut down.
+    pub down: Option<u64>,
+}
+
+impl Startup {
+    /// Parses a `startup` value, ignoring parts it does not know.
+    pub fn parse(value: &str) -> Self {
+        let mut startup = Self::default();
+        for part in value.split(',') {
+            let (key, value) = part.split_once('=').unwrap_or(("order", part));
+            match key.trim() {
+                "order" => startup.order = value.trim().parse().ok(),
+                "up" => startup.up = value.trim().parse().ok(),
+                "down" => startup.down = value.trim().parse().ok(),
+                _ => {}
+            }
+        }
+        startup
+    }
+
+    /// Sort key of the boot order. As on node boot, guests with an order
+    /// come first, lowest first, and ties go by VMID.
+    pub fn boot_key(&self, vmid: u32) -> (bool, u32, u32) {
+        (self.order.is_none(), self.order.unwrap_or(0), vmid)
+    }
+
+    /// Timeout of `action` on the guest: `timeout` when given, otherwise the
+    /// `down` value for a shutdown, whatever order the guests go in.
+    pub fn timeout(&self, action: PowerAction, timeout: Option<u64>) -> Option<u64> {
+        match action {
+            PowerAction::Shutdown => timeout.or(self.down),
+            _ => timeout,
+        }
+    }
+}
+
+/// Parameters of a power request, sent only when set.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct PowerOptions {
+    /// Seconds to wait for a shutdown or reboot.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    /// Stop the guest if the shutdown did not finish within the timeout.
+    #[serde(rename = "forceStop", skip_serializing_if = "Option::is_none")]
+    pub force_stop: Option<u8>,
+    /// Save the memory to disk when suspending; set for hibernation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub todisk: Option<u8>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_power_options_serialization() {
+        let options = PowerOptions {
+            timeout: Some(120),
+            force_stop: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_value(options).unwrap(),
+            serde_json::json!({"timeout": 120, "forceStop": 1})
+        );
+        assert_eq!(
+            serde_json::to_value(PowerOptions::default()).unwrap(),
+            serde_json::json!({})
+        );
+    }
+
+    #[test]
+    fn test_guest_state_matches() {
+        assert!(GuestState::Running.matches("running", Some("running")));
+        assert!(GuestState::Running.matches("running", None));
+        assert!(!GuestState::Running.matches("running", Some("paused")));
+        assert!(GuestState::Paused.matches("running", Some("paused")));
+        assert!(GuestState::Stopped.matches("stopped", Some("stopped")));
+        assert!(!GuestState::Stopped.matches("running", None));
+    }
+
+    #[test]
+    fn test_startup_boot_order() {
+        let db = Startup::parse("order=1,up=30,down=120");
+        assert_eq!(
+            db,
+            Startup {
+                order: Some(1),
+                up: Some(30),
+                down: Some(120)
+            }
+        );
+        let app = Startup::parse("order=2");
+        let unordered = Startup::parse("");
+        assert_eq!(unordered, Startup::default());
+
+        let mut guests = [(100, unordered), (300, app), (200, db), (50, unordered)];
+        guests.sort_by_key(|(vmid, startup)| startup.boot_key(*vmid));
+        let vmids: Vec<u32> = guests.iter().map(|(vmid, _)| *vmid).collect();
+        assert_eq!(vmids, vec![200, 300, 50, 100]);
+    }
+
+    #[test]
+    fn test_startup_shutdown_timeout() {
+        let startup = Startup::parse("order=1,down=120");
+        assert_eq!(startup.timeout(PowerAction::Shutdown, None), Some(120));
+        assert_eq!(startup.timeout(PowerAction::Shutdown, Some(30)), Some(30));
+        assert_eq!(startup.timeout(PowerAction::Stop, None), None);
+        let unordered = Startup::parse("down=60");
+        assert_eq!(unordered.timeout(PowerAction::Shutdown, None), Some(60));
+    }
+}
@@ -0,0 +1,48 @@
+use anyhow::Result;
+use std::io::{self, BufRead, IsTerminal, Write};
+
+/// Asks a yes/no question on the terminal, defaulting to no.
+///
+/// Fails when stdin is not a terminal so that scripts never block on a
+/// prompt nobody can answer.
+pub fn confirm(question: &str) -> Result<bool> {
+    let answer = ask(&format!("{} [y/N] ", question), "--yes")?;
+    Ok(matches!(answer.to_ascii_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Asks `question` unless `skip` is set, failing when the answer is no.
+pub fn confirm_or_abort(question: &str, skip: bool) -> Result<()> {
+    if !skip && !confirm(question)? {
+        anyhow::bail!("Aborted");
+    }
+    Ok(())
+}
+
+/// Asks the user to type `expected` back, for operations that cannot be
+/// undone. Fails like [`confirm`] when stdin is not a terminal, naming the
+/// `--confirm` option that answers the prompt in advance.
+pub fn confirm_typed(question: &str, expected: &str) -> Result<bool> {
+    let answer = ask(
+        &format!("{}\nType '{}' to confirm: ", question, expected),
+        &format!("--confirm {}", expected),
+    )?;
+    Ok(answer == expected)
+}
+
+/// Reads one answer from the terminal. `flag` is the option that skips the
+/// prompt, suggested when there is no terminal to ask on.
+fn ask(prompt: &str, flag: &str) -> Result<String> {
+    if !io::stdin().is_terminal() {
+        anyhow::bail!(
+            "Refusing to continue without confirmation; pass {} to proceed",
+            flag
+        );
+    }
+
+    eprint!("{}", prompt);
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(answer.trim().to_string())
+}
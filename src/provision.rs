@@ -0,0 +1,139 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::client::url_encode;
+use crate::size::parse_size;
+
+/// Options for `provision`.
+#[derive(Debug, Clone, Default)]
+pub struct ProvisionOptions {
+    pub template: String,
+    pub name: String,
+    /// VMID of the new VM; the next free one when unset.
+    pub newid: Option<u32>,
+    pub full: bool,
+    pub target: Option<String>,
+    pub storage: Option<String>,
+    /// Address in CIDR notation, or `dhcp`.
+    pub ip: Option<String>,
+    pub gateway: Option<String>,
+    pub nameserver: Option<String>,
+    pub user: Option<String>,
+    /// Files holding public SSH keys for the cloud-init user.
+    pub sshkeys: Vec<PathBuf>,
+    /// New disk size, or an increase such as `+10G`.
+    pub resize: Option<String>,
+    pub disk: String,
+    pub start: bool,
+}
+
+/// Cloud-init settings written to the VM configuration, sent only when set.
+#[derive(Debug, Default, Serialize)]
+pub struct CloudInit {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ciuser: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipconfig0: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nameserver: Option<String>,
+    /// Public keys, URL-encoded as the API requires.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sshkeys: Option<String>,
+}
+
+impl CloudInit {
+    pub fn from_options(options: &ProvisionOptions) -> Result<Self> {
+        let ipconfig0 = options
+            .ip
+            .as_deref()
+            .map(|ip| ip_config(ip, options.gateway.as_deref()))
+            .transpose()?;
+
+        let mut keys = String::new();
+        for path in &options.sshkeys {
+            let key = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read SSH key {}", path.display()))?;
+            keys.push_str(key.trim_end());
+            keys.push('\n');
+        }
+
+        Ok(Self {
+            ciuser: options.user.clone(),
+            ipconfig0,
+            nameserver: options.nameserver.clone(),
+            sshkeys: (!keys.is_empty()).then(|| url_encode(&keys)),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ciuser.is_none()
+            && self.ipconfig0.is_none()
+            && self.nameserver.is_none()
+            && self.sshkeys.is_none()
+    }
+}
+
+/// A part of the cloud-init configuration Proxmox generates for a VM.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum CloudInitSection {
+    #[default]
+    User,
+    Network,
+    Meta,
+}
+
+impl CloudInitSection {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CloudInitSection::User => "user",
+            CloudInitSection::Network => "network",
+            CloudInitSection::Meta => "meta",
+        }
+    }
+}
+
+/// Builds an `ipconfig0` value such as `ip=10.0.0.5/24,gw=10.0.0.1`.
+pub fn ip_config(ip: &str, gateway: Option<&str>) -> Result<String> {
+    if ip != "dhcp" && !ip.contains('/') {
+        anyhow::bail!(
+            "IP address '{}' needs a prefix length, e.g. 10.0.0.5/24",
+            ip
+        );
+    }
+    Ok(match gateway {
+        Some(gateway) => format!("ip={},gw={}", ip, gateway),
+        None => format!("ip={}", ip),
+    })
+}
+
+/// Checks a disk size such as `20G` or `+10G` before anything is cloned.
+pub fn validate_resize(size: &str) -> Result<()> {
+    parse_size(size.strip_prefix('+').unwrap_or(size))
+        .with_context(|| format!("Invalid disk size '{}'", size))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ip_config() {
+        assert_eq!(
+            ip_config("10.0.0.5/24", Some("10.0.0.1")).unwrap(),
+            "ip=10.0.0.5/24,gw=10.0.0.1"
+        );
+        assert_eq!(ip_config("dhcp", None).unwrap(), "ip=dhcp");
+        assert!(ip_config("10.0.0.5", None).is_err());
+    }
+
+    #[test]
+    fn test_validate_resize() {
+        assert!(validate_resize("+10G").is_ok());
+        assert!(validate_resize("32G").is_ok());
+        assert!(validate_resize("big").is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use chrono::{DateTime, TimeZone};
+use clap::Args;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashSet;
+
+/// How many snapshots to keep, following vzdump's prune semantics.
+///
+/// Each option keeps the newest snapshot of its last N periods. Periods that
+/// already contain a snapshot kept by an earlier option are skipped and do
+/// not count towards N.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Args, Deserialize)]
+pub struct RetentionPolicy {
+    #[arg(long, value_parser = count_parser(), help = "Keep the N most recent snapshots")]
+    #[serde(default, deserialize_with = "deserialize_count")]
+    pub keep_last: Option<usize>,
+    #[arg(
+        long,
+        value_parser = count_parser(),
+        help = "Keep the newest snapshot of each of the last N days"
+    )]
+    #[serde(default, deserialize_with = "deserialize_count")]
+    pub keep_daily: Option<usize>,
+    #[arg(
+        long,
+        value_parser = count_parser(),
+        help = "Keep the newest snapshot of each of the last N weeks"
+    )]
+    #[serde(default, deserialize_with = "deserialize_count")]
+    pub keep_weekly: Option<usize>,
+    #[arg(
+        long,
+        value_parser = count_parser(),
+        help = "Keep the newest snapshot of each of the last N months"
+    )]
+    #[serde(default, deserialize_with = "deserialize_count")]
+    pub keep_monthly: Option<usize>,
+}
+
+/// A keep count of 0 would make a policy that deletes every snapshot; leaving
+/// the option out is the way to disable it.
+fn count_parser() -> clap::builder::RangedU64ValueParser<usize> {
+    clap::builder::RangedU64ValueParser::new().range(1..)
+}
+
+fn deserialize_count<'de, D>(deserializer: D) -> Result<Option<usize>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<usize>::deserialize(deserializer)? {
+        Some(0) => Err(serde::de::Error::custom(
+            "keep counts must be at least 1; leave the key out instead of 0",
+        )),
+        count => Ok(count),
+    }
+}
+
+impl RetentionPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.keep_last.is_none()
+            && self.keep_daily.is_none()
+            && self.keep_weekly.is_none()
+            && self.keep_monthly.is_none()
+    }
+}
+
+/// The fate of one snapshot under a policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decision {
+    pub name: String,
+    pub snaptime: i64,
+    pub keep: bool,
+}
+
+/// Decides which of `snapshots` (name, creation time) the policy keeps.
+///
+/// Periods are computed in `tz`. The result is ordered newest first.
+pub fn plan<Tz: TimeZone>(
+    snapshots: &[(String, i64)],
+    policy: &RetentionPolicy,
+    tz: &Tz,
+) -> Vec<Decision> {
+    let mut sorted: Vec<&(String, i64)> = snapshots.iter().collect();
+    sorted.sort_by_key(|s| std::cmp::Reverse(s.1));
+
+    // Each snapshot is its own period for keep-last.
+    let periods = [
+        (policy.keep_last, None),
+        (policy.keep_daily, Some("%Y-%m-%d")),
+        (policy.keep_weekly, Some("%G/%V")),
+        (policy.keep_monthly, Some("%Y-%m")),
+    ];
+
+    let mut keep = vec![false; sorted.len()];
+    for (count, format) in periods {
+        let Some(count) = count else {
+            continue;
+        };
+        let period_of = |timestamp: i64| match format {
+            Some(format) => period(tz, timestamp, format),
+            None => timestamp.to_string(),
+        };
+
+        let already_kept: HashSet<String> = sorted
+            .iter()
+            .zip(&keep)
+            .filter(|(_, kept)| **kept)
+            .map(|(s, _)| period_of(s.1))
+            .collect();
+
+        let mut selected = HashSet::new();
+        for (i, snapshot) in sorted.iter().enumerate() {
+            if keep[i] {
+                continue;
+            }
+            let id = period_of(snapshot.1);
+            if already_kept.contains(&id) || selected.contains(&id) {
+                continue;
+            }
+            if selected.len() >= count {
+                break;
+            }
+            selected.insert(id);
+            keep[i] = true;
+        }
+    }
+
+    sorted
+        .into_iter()
+        .zip(keep)
+        .map(|((name, snaptime), keep)| Decision {
+            name: name.clone(),
+            snaptime: *snaptime,
+            keep,
+        })
+        .collect()
+}
+
+fn period<Tz: TimeZone>(tz: &Tz, timestamp: i64, format: &str) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|dt| {
+            dt.with_timezone(tz)
+                .naive_local()
+                .format(format)
+                .to_string()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    const DAY: i64 = 86400;
+    // 2024-01-01T12:00:00Z, a Monday.
+    const START: i64 = 1704110400;
+
+    fn daily_snapshots(days: i64) -> Vec<(String, i64)> {
+        (0..days)
+            .map(|d| (format!("snap-{}", d), START + d * DAY))
+            .collect()
+    }
+
+    fn kept(decisions: &[Decision]) -> Vec<&str> {
+        decisions
+            .iter()
+            .filter(|d| d.keep)
+            .map(|d| d.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_keep_last() {
+        let policy = RetentionPolicy {
+            keep_last: Some(2),
+            ..Default::default()
+        };
+        let decisions = plan(&daily_snapshots(5), &policy, &Utc);
+        assert_eq!(kept(&decisions), vec!["snap-4", "snap-3"]);
+        assert_eq!(decisions.len(), 5);
+    }
+
+    #[test]
+    fn test_keep_daily_takes_newest_per_day() {
+        let mut snapshots = daily_snapshots(3);
+        snapshots.push(("snap-2-early".to_string(), START + 2 * DAY - 3600));
+        let policy = RetentionPolicy {
+            keep_daily: Some(2),
+            ..Default::default()
+        };
+        let decisions = plan(&snapshots, &policy, &Utc);
+        assert_eq!(kept(&decisions), vec!["snap-2", "snap-1"]);
+    }
+
+    #[test]
+    fn test_options_combine() {
+        // 21 daily snapshots spanning three ISO weeks.
+        let policy = RetentionPolicy {
+            keep_last: Some(1),
+            keep_weekly: Some(2),
+            ..Default::default()
+        };
+        let decisions = plan(&daily_snapshots(21), &policy, &Utc);
+        // The newest snapshot already covers the current week, so weekly
+        // retention keeps the newest of each of the two weeks before it.
+        assert_eq!(kept(&decisions), vec!["snap-20", "snap-13", "snap-6"]);
+    }
+
+    #[test]
+    fn test_keep_monthly() {
+        let snapshots = vec![
+            ("jan".to_string(), START),
+            ("jan-late".to_string(), START + 20 * DAY),
+            ("feb".to_string(), START + 40 * DAY),
+        ];
+        let policy = RetentionPolicy {
+            keep_monthly: Some(5),
+            ..Default::default()
+        };
+        assert_eq!(
+            kept(&plan(&snapshots, &policy, &Utc)),
+            vec!["feb", "jan-late"]
+        );
+    }
+}
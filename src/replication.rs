@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::client::ProxmoxClient;
+use crate::cluster::ClusterManager;
+use crate::duration::format_duration;
+use crate::output::{Color, Output, Tabular};
+use crate::serde_util::deserialize_flag;
+
+/// Lists and runs the storage replication jobs of the cluster.
+pub struct ReplicationManager {
+    client: ProxmoxClient,
+    cluster: ClusterManager,
+    output: Output,
+}
+
+/// A replication job as configured in `/cluster/replication`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct JobConfig {
+    pub id: String,
+    pub guest: u32,
+    pub target: String,
+    #[serde(default)]
+    pub schedule: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    pub disable: bool,
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+/// The state of a job on its source node, from `/nodes/{node}/replication`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct JobStatus {
+    pub id: String,
+    #[serde(default)]
+    pub last_sync: Option<i64>,
+    #[serde(default)]
+    pub next_sync: Option<i64>,
+    /// Seconds the last run took.
+    #[serde(default)]
+    pub duration: Option<f64>,
+    #[serde(default)]
+    pub fail_count: u32,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// A replication job with its state, as listed by `replication list`.
+#[derive(Debug, Serialize)]
+pub struct ReplicationJob {
+    pub id: String,
+    pub guest: u32,
+    /// The node the guest runs on, which replicates it.
+    pub source: String,
+    pub target: String,
+    /// Proxmox replicates every 15 minutes when no schedule is set.
+    pub schedule: String,
+    pub enabled: bool,
+    pub last_sync: Option<i64>,
+    pub next_sync: Option<i64>,
+    pub duration: Option<f64>,
+    pub fail_count: u32,
+    pub error: Option<String>,
+    /// False when the state of the job could not be read from its source
+    /// node, in which case the fields above are unset.
+    pub status_known: bool,
+    pub comment: Option<String>,
+}
+
+impl Tabular for ReplicationJob {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("id", "Job"),
+            ("guest", "VMID"),
+            ("source", "Source"),
+            ("target", "Target"),
+            ("schedule", "Schedule"),
+            ("last_sync", "Last sync"),
+            ("next_sync", "Next sync"),
+            ("duration", "Duration"),
+            ("state", "State"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        let time = |t: Option<i64>| {
+            t.filter(|&t| t > 0)
+                .and_then(|t| chrono::DateTime::from_timestamp(t, 0))
+                .map_or_else(|| "-".to_string(), |dt| output.format_time(dt))
+        };
+        let state = match (&self.error, self.enabled) {
+            (Some(error), _) => output.paint(
+                Color::Red,
+                &format!("failed {}x: {}", self.fail_count, error),
+            ),
+            (None, false) => output.paint(Color::Yellow, "disabled"),
+            (None, true) if !self.status_known => output.paint(Color::Yellow, "unknown"),
+            (None, true) => "OK".to_string(),
+        };
+        vec![
+            self.id.clone(),
+            self.guest.to_string(),
+            self.source.clone(),
+            self.target.clone(),
+            self.schedule.clone(),
+            time(self.last_sync),
+            time(self.next_sync),
+            self.duration
+                .map_or_else(|| "-".to_string(), |d| format_duration(d.round() as u64)),
+            state,
+        ]
+    }
+}
+
+/// Joins each job to the node its guest runs on and to its state there.
+/// Jobs of guests that no longer exist keep an empty source, and jobs without
+/// a state are marked as unknown.
+pub fn merge(
+    configs: Vec<JobConfig>,
+    nodes: &HashMap<u32, String>,
+    statuses: &HashMap<String, JobStatus>,
+) -> Vec<ReplicationJob> {
+    configs
+        .into_iter()
+        .map(|config| {
+            let known = statuses.get(&config.id);
+            let status_known = known.is_some();
+            let status = known.cloned().unwrap_or_default();
+            ReplicationJob {
+                source: nodes.get(&config.guest).cloned().unwrap_or_default(),
+                schedule: config.schedule.unwrap_or_else(|| "*/15".to_string()),
+                enabled: !config.disable,
+                last_sync: status.last_sync,
+                next_sync: status.next_sync,
+                duration: status.duration,
+                fail_count: status.fail_count,
+                error: status.error,
+                status_known,
+                comment: config.comment,
+                id: config.id,
+                guest: config.guest,
+                target: config.target,
+            }
+        })
+        .collect()
+}
+
+impl ReplicationManager {
+    pub fn new(client: ProxmoxClient, output: Output) -> Self {
+        let cluster = ClusterManager::new(client.clone(), output.clone());
+        Self {
+            client,
+            cluster,
+            output,
+        }
+    }
+
+    /// Lists the replication jobs of the cluster, or of one guest, with
+    /// their state on the node the guest runs on.
+    pub async fn list_jobs(&self, vm_identifier: Option<&str>) -> Result<()> {
+        let mut configs: Vec<JobConfig> = self.client.get("/cluster/replication").await?;
+        if let Some(vm_identifier) = vm_identifier {
+            let guest = self.cluster.find_vm(vm_identifier).await?;
+            configs.retain(|c| c.guest == guest.vmid);
+        }
+
+        let nodes: HashMap<u32, String> = self
+            .cluster
+            .vms()
+            .await?
+            .into_iter()
+            .map(|g| (g.vmid, g.node))
+            .collect();
+        let mut sources: Vec<&String> =
+            configs.iter().filter_map(|c| nodes.get(&c.guest)).collect();
+        sources.sort();
+        sources.dedup();
+
+        let mut statuses = HashMap::new();
+        for node in sources {
+            // One unreachable node must not hide the jobs of the others.
+            match self
+                .client
+                .get::<Vec<JobStatus>>(&format!("/nodes/{}/replication", node))
+                .await
+            {
+                Ok(entries) => statuses.extend(entries.into_iter().map(|s| (s.id.clone(), s))),
+                Err(e) => self.output.warn(&format!(
+                    "Cannot read the replication state on node {}: {:#}",
+                    node, e
+                )),
+            }
+        }
+
+        let jobs = merge(configs, &nodes, &statuses);
+        self.output.print_list("Replication jobs:", &jobs)
+    }
+
+    /// Runs a replication job now instead of at its next scheduled time,
+    /// e.g. to have a fresh copy before a rollback.
+    pub async fn run_job(&self, job_id: &str) -> Result<()> {
+        let configs: Vec<JobConfig> = self.client.get("/cluster/replication").await?;
+        let Some(config) = configs.iter().find(|c| c.id == job_id) else {
+            anyhow::bail!("No replication job {}", job_id);
+        };
+        let guest = self.cluster.find_vm(&config.guest.to_string()).await?;
+        let _: serde_json::Value = self
+            .client
+            .post(
+                &format!("/nodes/{}/replication/{}/schedule_now", guest.node, job_id),
+                &(),
+            )
+            .await?;
+        self.output.message(&format!(
+            "Replication job {} of VM {} scheduled to run now on node {}",
+            job_id, guest.vmid, guest.node
+        ));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let configs: Vec<JobConfig> = serde_json::from_value(serde_json::json!([
+            { "id": "100-0", "guest": 100, "target": "pve2", "schedule": "*/5" },
+            { "id": "101-0", "guest": 101, "target": "pve3", "disable": 1 },
+        ]))
+        .unwrap();
+        let nodes = HashMap::from([(100, "pve1".to_string())]);
+        let statuses = HashMap::from([(
+            "100-0".to_string(),
+            JobStatus {
+                id: "100-0".to_string(),
+                last_sync: Some(1_700_000_000),
+                fail_count: 2,
+                error: Some("no space left".to_string()),
+                ..Default::default()
+            },
+        )]);
+
+        let jobs = merge(configs, &nodes, &statuses);
+        assert_eq!(jobs[0].source, "pve1");
+        assert_eq!(jobs[0].schedule, "*/5");
+        assert_eq!(jobs[0].fail_count, 2);
+        assert_eq!(jobs[0].error.as_deref(), Some("no space left"));
+        assert!(jobs[0].status_known);
+        assert_eq!(jobs[1].source, "");
+        assert_eq!(jobs[1].schedule, "*/15");
+        assert!(!jobs[1].enabled);
+        assert_eq!(jobs[1].last_sync, None);
+        assert!(!jobs[1].status_known);
+    }
+}
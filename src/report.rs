@@ -0,0 +1,409 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::cluster::Guest;
+use crate::duration::format_duration;
+use crate::output::{Color, Output, Tabular};
+use crate::serde_util::deserialize_flag;
+
+/// One snapshot in the report, with the VM it belongs to.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportSnapshot {
+    pub vmid: u32,
+    pub name: Option<String>,
+    pub node: String,
+    pub snapshot: String,
+    pub snaptime: i64,
+    /// Age in seconds when the report was built.
+    pub age: u64,
+}
+
+impl Tabular for ReportSnapshot {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("vmid", "VMID"),
+            ("name", "Name"),
+            ("node", "Node"),
+            ("snapshot", "Snapshot"),
+            ("snaptime", "Created"),
+            ("age", "Age"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        vec![
+            self.vmid.to_string(),
+            self.name.clone().unwrap_or_default(),
+            self.node.clone(),
+            self.snapshot.clone(),
+            format_timestamp(output, Some(self.snaptime)),
+            format_duration(self.age),
+        ]
+    }
+}
+
+/// Snapshot totals for a node or a pool.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReportGroup {
+    pub group: String,
+    pub vms: usize,
+    pub snapshots: usize,
+    pub oldest: Option<i64>,
+    pub newest: Option<i64>,
+}
+
+impl Tabular for ReportGroup {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("group", "Group"),
+            ("vms", "VMs"),
+            ("snapshots", "Snapshots"),
+            ("oldest", "Oldest"),
+            ("newest", "Newest"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        vec![
+            self.group.clone(),
+            self.vms.to_string(),
+            self.snapshots.to_string(),
+            format_timestamp(output, self.oldest),
+            format_timestamp(output, self.newest),
+        ]
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotReport {
+    pub vms: usize,
+    pub snapshots: usize,
+    pub oldest: Option<ReportSnapshot>,
+    pub newest: Option<ReportSnapshot>,
+    pub by_node: Vec<ReportGroup>,
+    pub by_pool: Vec<ReportGroup>,
+    /// Snapshots older than the cutoff, oldest first.
+    pub stale: Vec<ReportSnapshot>,
+}
+
+/// Builds the report from each guest's snapshots as (name, creation time).
+/// Snapshots older than `older_than` seconds are listed as stale.
+pub fn build(
+    inventory: &[(Guest, Vec<(String, i64)>)],
+    now: i64,
+    older_than: Option<u64>,
+) -> SnapshotReport {
+    let mut all = Vec::new();
+    let mut by_node: BTreeMap<String, ReportGroup> = BTreeMap::new();
+    let mut by_pool: BTreeMap<String, ReportGroup> = BTreeMap::new();
+
+    for (guest, snapshots) in inventory {
+        let entries: Vec<ReportSnapshot> = snapshots
+            .iter()
+            .map(|(snapshot, snaptime)| ReportSnapshot {
+                vmid: guest.vmid,
+                name: guest.name.clone(),
+                node: guest.node.clone(),
+                snapshot: snapshot.clone(),
+                snaptime: *snaptime,
+                age: (now - snaptime).max(0) as u64,
+            })
+            .collect();
+
+        let pool = guest.pool.clone().unwrap_or_else(|| "(none)".to_string());
+        for (key, groups) in [(&guest.node, &mut by_node), (&pool, &mut by_pool)] {
+            let group = groups.entry(key.clone()).or_insert_with(|| ReportGroup {
+                group: key.clone(),
+                ..Default::default()
+            });
+            group.vms += 1;
+            group.snapshots += entries.len();
+            for entry in &entries {
+                group.oldest = Some(
+                    group
+                        .oldest
+                        .map_or(entry.snaptime, |t| t.min(entry.snaptime)),
+                );
+                group.newest = Some(
+                    group
+                        .newest
+                        .map_or(entry.snaptime, |t| t.max(entry.snaptime)),
+                );
+            }
+        }
+
+        all.extend(entries);
+    }
+
+    all.sort_by_key(|s| s.snaptime);
+
+    SnapshotReport {
+        vms: inventory.len(),
+        snapshots: all.len(),
+        oldest: all.first().cloned(),
+        newest: all.last().cloned(),
+        by_node: by_node.into_values().collect(),
+        by_pool: by_pool.into_values().collect(),
+        stale: older_than
+            .map(|cutoff| all.iter().filter(|s| s.age > cutoff).cloned().collect())
+            .unwrap_or_default(),
+    }
+}
+
+/// A storage on one node, from `/cluster/resources?type=storage`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct StorageResource {
+    pub storage: String,
+    pub node: String,
+    #[serde(default)]
+    pub plugintype: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    pub shared: bool,
+    /// Used bytes.
+    #[serde(default)]
+    pub disk: u64,
+    /// Size in bytes; zero when the storage is unavailable.
+    #[serde(default)]
+    pub maxdisk: u64,
+}
+
+/// Usage of a storage, or of one node's instance of it.
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageUsage {
+    pub storage: String,
+    #[serde(rename = "type")]
+    pub storage_type: String,
+    pub shared: bool,
+    /// The nodes counted: all of those having a local storage, or the first
+    /// that reported a shared one.
+    pub nodes: Vec<String>,
+    pub used: u64,
+    pub total: u64,
+    pub warning: bool,
+}
+
+impl StorageUsage {
+    pub fn percent(&self) -> f64 {
+        percent(self.used, self.total)
+    }
+}
+
+impl Tabular for StorageUsage {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("storage", "Storage"),
+            ("type", "Type"),
+            ("nodes", "Nodes"),
+            ("used", "Used"),
+            ("total", "Total"),
+            ("usage", "Usage"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        let usage = format!("{:.0}%", self.percent());
+        vec![
+            self.storage.clone(),
+            self.storage_type.clone(),
+            if self.shared {
+                "shared".to_string()
+            } else {
+                self.nodes.join(", ")
+            },
+            output.format_bytes(self.used),
+            output.format_bytes(self.total),
+            if self.warning {
+                output.paint(Color::Red, &usage)
+            } else {
+                usage
+            },
+        ]
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct StorageReport {
+    pub used: u64,
+    pub total: u64,
+    /// Each storage once, local ones summed over their nodes.
+    pub storages: Vec<StorageUsage>,
+    /// Storage instances above the warning threshold, fullest first.
+    pub warnings: Vec<StorageUsage>,
+}
+
+/// Aggregates storage usage across nodes. Shared storages, which every node
+/// reports, are counted once. An instance of a local storage warns on its
+/// own when above `warn_pct`, as a full node is not offset by empty ones.
+pub fn build_storage(resources: &[StorageResource], warn_pct: f64) -> StorageReport {
+    let mut storages: BTreeMap<String, StorageUsage> = BTreeMap::new();
+    let mut warnings = Vec::new();
+
+    for resource in resources.iter().filter(|r| r.maxdisk > 0) {
+        let instance = StorageUsage {
+            storage: resource.storage.clone(),
+            storage_type: resource.plugintype.clone().unwrap_or_default(),
+            shared: resource.shared,
+            nodes: vec![resource.node.clone()],
+            used: resource.disk,
+            total: resource.maxdisk,
+            warning: percent(resource.disk, resource.maxdisk) > warn_pct,
+        };
+
+        match storages.get_mut(&resource.storage) {
+            Some(_) if resource.shared => continue,
+            Some(usage) => {
+                usage.nodes.push(resource.node.clone());
+                usage.used += resource.disk;
+                usage.total += resource.maxdisk;
+                usage.warning |= instance.warning;
+            }
+            None => {
+                storages.insert(resource.storage.clone(), instance.clone());
+            }
+        }
+        if instance.warning {
+            warnings.push(instance);
+        }
+    }
+
+    warnings.sort_by(|a, b| b.percent().total_cmp(&a.percent()));
+    let storages: Vec<StorageUsage> = storages.into_values().collect();
+    StorageReport {
+        used: storages.iter().map(|s| s.used).sum(),
+        total: storages.iter().map(|s| s.total).sum(),
+        storages,
+        warnings,
+    }
+}
+
+/// Parses a `--warn-pct` threshold, a percentage from 0 to 100.
+pub fn parse_percent(input: &str) -> Result<f64, String> {
+    let value: f64 = input
+        .parse()
+        .map_err(|_| format!("'{}' is not a number", input))?;
+    if !(0.0..=100.0).contains(&value) {
+        return Err(format!("{} is not a percentage from 0 to 100", input));
+    }
+    Ok(value)
+}
+
+fn percent(used: u64, total: u64) -> f64 {
+    used as f64 * 100.0 / total.max(1) as f64
+}
+
+fn format_timestamp(output: &Output, timestamp: Option<i64>) -> String {
+    timestamp
+        .and_then(|t| chrono::DateTime::from_timestamp(t, 0))
+        .map(|dt| output.format_time(dt))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 86400;
+    const NOW: i64 = 1_710_000_000;
+
+    fn guest(vmid: u32, node: &str, pool: Option<&str>) -> Guest {
+        Guest {
+            node: node.to_string(),
+            vmid,
+            guest_type: "qemu".to_string(),
+            pool: pool.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_build_report() {
+        let inventory = vec![
+            (
+                guest(100, "pve1", Some("prod")),
+                vec![
+                    ("old".to_string(), NOW - 40 * DAY),
+                    ("recent".to_string(), NOW - DAY),
+                ],
+            ),
+            (
+                guest(101, "pve2", Some("prod")),
+                vec![("weekly".to_string(), NOW - 10 * DAY)],
+            ),
+            (guest(102, "pve1", None), vec![]),
+        ];
+
+        let report = build(&inventory, NOW, Some(30 * DAY as u64));
+        assert_eq!(report.vms, 3);
+        assert_eq!(report.snapshots, 3);
+        assert_eq!(report.oldest.as_ref().unwrap().snapshot, "old");
+        assert_eq!(report.newest.as_ref().unwrap().snapshot, "recent");
+
+        assert_eq!(report.by_node.len(), 2);
+        assert_eq!(report.by_node[0].group, "pve1");
+        assert_eq!(report.by_node[0].vms, 2);
+        assert_eq!(report.by_node[0].snapshots, 2);
+
+        let pools: Vec<&str> = report.by_pool.iter().map(|g| g.group.as_str()).collect();
+        assert_eq!(pools, vec!["(none)", "prod"]);
+        assert_eq!(report.by_pool[1].snapshots, 3);
+        assert_eq!(report.by_pool[1].oldest, Some(NOW - 40 * DAY));
+
+        assert_eq!(report.stale.len(), 1);
+        assert_eq!(report.stale[0].vmid, 100);
+    }
+
+    #[test]
+    fn test_build_report_without_cutoff() {
+        let inventory = vec![(
+            guest(100, "pve1", None),
+            vec![("old".to_string(), NOW - 400 * DAY)],
+        )];
+        assert!(build(&inventory, NOW, None).stale.is_empty());
+    }
+
+    fn storage(storage: &str, node: &str, shared: bool, used: u64, total: u64) -> StorageResource {
+        StorageResource {
+            storage: storage.to_string(),
+            node: node.to_string(),
+            plugintype: None,
+            shared,
+            disk: used,
+            maxdisk: total,
+        }
+    }
+
+    #[test]
+    fn test_build_storage_report() {
+        let resources = vec![
+            storage("local", "pve1", false, 50, 100),
+            storage("local", "pve2", false, 95, 100),
+            storage("ceph", "pve1", true, 300, 1000),
+            storage("ceph", "pve2", true, 300, 1000),
+            storage("nfs", "pve1", true, 0, 0),
+        ];
+        let report = build_storage(&resources, 90.0);
+
+        let names: Vec<&str> = report.storages.iter().map(|s| s.storage.as_str()).collect();
+        assert_eq!(names, vec!["ceph", "local"]);
+        assert_eq!(report.storages[0].total, 1000);
+        let local = &report.storages[1];
+        assert_eq!((local.used, local.total), (145, 200));
+        assert_eq!(local.nodes, vec!["pve1", "pve2"]);
+        assert!(local.warning);
+        assert_eq!((report.used, report.total), (445, 1200));
+
+        assert_eq!(report.warnings.len(), 1);
+        assert_eq!(report.warnings[0].nodes, vec!["pve2"]);
+    }
+
+    #[test]
+    fn test_parse_percent() {
+        assert_eq!(parse_percent("85"), Ok(85.0));
+        assert_eq!(parse_percent("0"), Ok(0.0));
+        assert_eq!(parse_percent("99.5"), Ok(99.5));
+        assert!(parse_percent("150").is_err());
+        assert!(parse_percent("-1").is_err());
+        assert!(parse_percent("NaN").is_err());
+        assert!(parse_percent("full").is_err());
+    }
+}
@@ -0,0 +1,205 @@
+use serde::{Deserialize, Serialize};
+
+use crate::output::{Color, Output, Tabular};
+
+/// An SDN zone, from `/cluster/sdn/zones`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Zone {
+    pub zone: String,
+    /// `simple`, `vlan`, `qinq`, `vxlan` or `evpn`.
+    #[serde(rename = "type")]
+    pub zone_type: String,
+    /// Nodes the zone is deployed on; all of them when unset.
+    #[serde(default)]
+    pub nodes: Option<String>,
+    #[serde(default)]
+    pub bridge: Option<String>,
+    #[serde(default)]
+    pub ipam: Option<String>,
+    /// Set while the configuration has changes not yet applied.
+    #[serde(default)]
+    pub state: Option<String>,
+}
+
+impl Tabular for Zone {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("zone", "Zone"),
+            ("type", "Type"),
+            ("bridge", "Bridge"),
+            ("nodes", "Nodes"),
+            ("ipam", "IPAM"),
+            ("state", "State"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        vec![
+            self.zone.clone(),
+            self.zone_type.clone(),
+            self.bridge.clone().unwrap_or_default(),
+            self.nodes.clone().unwrap_or_else(|| "all".to_string()),
+            self.ipam.clone().unwrap_or_default(),
+            self.state
+                .as_deref()
+                .map_or_else(String::new, |state| output.paint(Color::Yellow, state)),
+        ]
+    }
+}
+
+/// An SDN vnet, from `/cluster/sdn/vnets`. Guests attach to it like to a
+/// bridge of the same name.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Vnet {
+    pub vnet: String,
+    pub zone: String,
+    #[serde(default)]
+    pub tag: Option<u32>,
+    #[serde(default)]
+    pub alias: Option<String>,
+}
+
+impl Tabular for Vnet {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("vnet", "VNet"),
+            ("zone", "Zone"),
+            ("tag", "Tag"),
+            ("alias", "Alias"),
+        ]
+    }
+
+    fn row(&self, _output: &Output) -> Vec<String> {
+        vec![
+            self.vnet.clone(),
+            self.zone.clone(),
+            self.tag.map_or_else(|| "-".to_string(), |t| t.to_string()),
+            self.alias.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+/// A NIC of a guest and the vnet it attaches to, if its bridge is one.
+#[derive(Debug, Serialize)]
+pub struct GuestNic {
+    pub nic: String,
+    pub bridge: String,
+    pub zone: Option<String>,
+    pub zone_type: Option<String>,
+    /// Nodes the vnet's zone is limited to; all of them when unset.
+    pub nodes: Option<String>,
+}
+
+impl Tabular for GuestNic {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("nic", "NIC"),
+            ("bridge", "Bridge/VNet"),
+            ("zone", "Zone"),
+            ("zone_type", "Zone type"),
+            ("nodes", "Nodes"),
+        ]
+    }
+
+    fn row(&self, _output: &Output) -> Vec<String> {
+        let sdn = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+        vec![
+            self.nic.clone(),
+            self.bridge.clone(),
+            sdn(&self.zone),
+            sdn(&self.zone_type),
+            match (&self.zone, &self.nodes) {
+                (None, _) => "-".to_string(),
+                (Some(_), nodes) => nodes.clone().unwrap_or_else(|| "all".to_string()),
+            },
+        ]
+    }
+}
+
+/// The bridge of each `netN` option of a guest configuration, e.g. `vmbr0`
+/// in `virtio=BC:24:11:00:00:01,bridge=vmbr0,tag=10`, sorted by NIC.
+pub fn nic_bridges(config: &serde_json::Map<String, serde_json::Value>) -> Vec<(String, String)> {
+    let mut nics: Vec<(String, String)> = config
+        .iter()
+        .filter(|(key, _)| {
+            key.strip_prefix("net")
+                .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+        })
+        .filter_map(|(key, value)| {
+            let bridge = value
+                .as_str()?
+                .split(',')
+                .find_map(|part| part.strip_prefix("bridge="))?;
+            Some((key.clone(), bridge.to_string()))
+        })
+        .collect();
+    nics.sort_by_key(|(key, _)| key[3..].parse::<u32>().unwrap_or(u32::MAX));
+    nics
+}
+
+/// The SDN configuration of the cluster, as printed by `sdn list` in the
+/// structured formats.
+#[derive(Debug, Serialize)]
+pub struct SdnOverview {
+    pub zones: Vec<Zone>,
+    pub vnets: Vec<Vnet>,
+}
+
+/// Looks up the vnet and zone behind each NIC bridge.
+pub fn guest_nics(bridges: Vec<(String, String)>, vnets: &[Vnet], zones: &[Zone]) -> Vec<GuestNic> {
+    bridges
+        .into_iter()
+        .map(|(nic, bridge)| {
+            let zone = vnets
+                .iter()
+                .find(|v| v.vnet == bridge)
+                .and_then(|v| zones.iter().find(|z| z.zone == v.zone));
+            GuestNic {
+                nic,
+                bridge,
+                zone: zone.map(|z| z.zone.clone()),
+                zone_type: zone.map(|z| z.zone_type.clone()),
+                nodes: zone.and_then(|z| z.nodes.clone()),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guest_nics() {
+        let config = serde_json::json!({
+            "net10": "virtio=BC:24:11:00:00:03,bridge=vmbr0",
+            "net0": "virtio=BC:24:11:00:00:01,bridge=vnet1,tag=10",
+            "net1": "name=eth1,bridge=vnet2,ip=dhcp",
+            "netmask": "ignored",
+            "scsi0": "local-lvm:vm-100-disk-0"
+        });
+        let bridges = nic_bridges(config.as_object().unwrap());
+        assert_eq!(
+            bridges,
+            vec![
+                ("net0".to_string(), "vnet1".to_string()),
+                ("net1".to_string(), "vnet2".to_string()),
+                ("net10".to_string(), "vmbr0".to_string()),
+            ]
+        );
+
+        let zones: Vec<Zone> = serde_json::from_value(serde_json::json!([
+            { "zone": "evpn1", "type": "evpn", "nodes": "pve1,pve2" }
+        ]))
+        .unwrap();
+        let vnets: Vec<Vnet> = serde_json::from_value(serde_json::json!([
+            { "vnet": "vnet1", "zone": "evpn1", "type": "vnet", "tag": 10000 }
+        ]))
+        .unwrap();
+        let nics = guest_nics(bridges, &vnets, &zones);
+        assert_eq!(nics[0].zone.as_deref(), Some("evpn1"));
+        assert_eq!(nics[0].nodes.as_deref(), Some("pve1,pve2"));
+        assert_eq!(nics[1].zone, None);
+        assert_eq!(nics[2].zone, None);
+    }
+}
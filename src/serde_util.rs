@@ -0,0 +1,42 @@
+use serde::{Deserialize, Deserializer};
+
+/// Accepts the `0`/`1` integers Proxmox uses for flags, as well as booleans
+/// and strings.
+pub fn deserialize_flag<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Bool(b) => b,
+        serde_json::Value::Number(n) => n.as_u64().is_some_and(|n| n != 0),
+        serde_json::Value::String(s) => s == "1" || s == "true",
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Flagged {
+        #[serde(default, deserialize_with = "deserialize_flag")]
+        flag: bool,
+    }
+
+    fn flag(value: serde_json::Value) -> bool {
+        serde_json::from_value::<Flagged>(serde_json::json!({ "flag": value }))
+            .unwrap()
+            .flag
+    }
+
+    #[test]
+    fn test_deserialize_flag() {
+        assert!(flag(serde_json::json!(1)));
+        assert!(!flag(serde_json::json!(0)));
+        assert!(flag(serde_json::json!(true)));
+        assert!(flag(serde_json::json!("1")));
+        assert!(!flag(serde_json::json!("0")));
+        assert!(!flag(serde_json::Value::Null));
+    }
+}
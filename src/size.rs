@@ -0,0 +1,88 @@
+use anyhow::Result;
+
+/// How byte quantities are displayed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SizeUnits {
+    /// Powers of 1024 (KiB, MiB, GiB, ...).
+    #[default]
+    Binary,
+    /// Powers of 1000 (kB, MB, GB, ...).
+    Si,
+    /// Plain byte counts.
+    Bytes,
+}
+
+pub fn format_bytes(bytes: u64, units: SizeUnits) -> String {
+    let (base, suffixes): (f64, &[&str]) = match units {
+        SizeUnits::Bytes => return bytes.to_string(),
+        SizeUnits::Binary => (1024.0, &["B", "KiB", "MiB", "GiB", "TiB", "PiB"]),
+        SizeUnits::Si => (1000.0, &["B", "kB", "MB", "GB", "TB", "PB"]),
+    };
+
+    let mut value = bytes as f64;
+    let mut suffix = 0;
+    while value >= base && suffix < suffixes.len() - 1 {
+        value /= base;
+        suffix += 1;
+    }
+
+    if suffix == 0 {
+        format!("{} {}", bytes, suffixes[0])
+    } else {
+        format!("{:.1} {}", value, suffixes[suffix])
+    }
+}
+
+/// Parses a Proxmox size such as `32G`, `512M` or `4096` (bytes) into bytes.
+///
+/// Proxmox always uses binary multiples for these suffixes.
+pub fn parse_size(input: &str) -> Result<u64> {
+    let input = input.trim();
+    let (number, multiplier) = match input.chars().last() {
+        Some('K') | Some('k') => (&input[..input.len() - 1], 1u64 << 10),
+        Some('M') | Some('m') => (&input[..input.len() - 1], 1 << 20),
+        Some('G') | Some('g') => (&input[..input.len() - 1], 1 << 30),
+        Some('T') | Some('t') => (&input[..input.len() - 1], 1 << 40),
+        _ => (input, 1),
+    };
+
+    let value: f64 = number
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid size '{}'", input))?;
+    if value < 0.0 {
+        anyhow::bail!("Invalid size '{}'", input);
+    }
+    Ok((value * multiplier as f64) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_bytes_binary() {
+        assert_eq!(format_bytes(512, SizeUnits::Binary), "512 B");
+        assert_eq!(format_bytes(1536, SizeUnits::Binary), "1.5 KiB");
+        assert_eq!(format_bytes(4 << 30, SizeUnits::Binary), "4.0 GiB");
+    }
+
+    #[test]
+    fn test_format_bytes_si() {
+        assert_eq!(format_bytes(1500, SizeUnits::Si), "1.5 kB");
+        assert_eq!(format_bytes(4_000_000_000, SizeUnits::Si), "4.0 GB");
+    }
+
+    #[test]
+    fn test_format_bytes_raw() {
+        assert_eq!(format_bytes(4294967296, SizeUnits::Bytes), "4294967296");
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+        assert_eq!(parse_size("512M").unwrap(), 512 << 20);
+        assert_eq!(parse_size("32G").unwrap(), 32 << 30);
+        assert_eq!(parse_size("1.5T").unwrap(), 3 << 39);
+        assert!(parse_size("lots").is_err());
+    }
+}
@@ -1,297 +1,3986 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
-use tokio::time::{Duration, sleep};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::future::Future;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio::time::{Duration, Instant, sleep, timeout_at};
 
-use crate::client::ProxmoxClient;
-use crate::cluster::ClusterManager;
+use crate::agent::{
+    self, AgentInfo, ExecResult, ExecStarted, ExecStatus, FileContent, InterfaceAddress,
+};
+use crate::audit::{self, AuditLimits};
+use crate::client::{ProxmoxClient, url_encode};
+use crate::cluster::{ClusterManager, Guest, GuestType, Selection, split_tags};
+use crate::config::RetentionConfig;
+use crate::duration::format_duration;
+use crate::editor;
+use crate::inventory::{self, ImportResult, Inventory, InventorySnapshot, InventoryVm};
+use crate::migrate::{self, MigrateOptions, NodeLoad};
+use crate::nagios::{self, CheckInput, NagiosState, Thresholds};
+use crate::naming;
+use crate::output::{Color, Output, OutputFormat, Tabular};
+use crate::pattern::NamePattern;
+use crate::power::{GuestState, OrderBy, PowerAction, PowerOptions, Startup};
+use crate::prompt;
+use crate::provision::{self, CloudInit, CloudInitSection, ProvisionOptions};
+use crate::prune::{self, RetentionPolicy};
+use crate::report;
+use crate::serde_util::deserialize_flag;
+use crate::size::parse_size;
+use crate::tasks::{TaskManager, TaskState};
+use crate::vmconfig::{self, DiskBus, PendingEntry, ResourceOptions, TagCount};
 
 pub struct SnapshotManager {
     client: ProxmoxClient,
     cluster: ClusterManager,
+    tasks: TaskManager,
+    output: Output,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+struct Snapshot {
+    name: String,
+    description: Option<String>,
+    snaptime: Option<i64>,
+    /// Whether the snapshot includes the VM's RAM state.
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    vmstate: bool,
+    #[serde(default)]
+    parent: Option<String>,
+    /// Disks stored in the snapshot, only fetched for `list --disks`.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    disks: Option<Vec<VmDisk>>,
+}
+
+impl Tabular for Snapshot {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("name", "Name"),
+            ("snaptime", "Created"),
+            ("vmstate", "RAM"),
+            ("parent", "Parent"),
+            ("disks", "Disks"),
+            ("description", "Description"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        let disks = match &self.disks {
+            Some(disks) if !disks.is_empty() => disks
+                .iter()
+                .map(|disk| match disk.size {
+                    Some(size) => format!("{} ({})", disk.name, output.format_bytes(size)),
+                    None => disk.name.clone(),
+                })
+                .collect::<Vec<_>>()
+                .join(", "),
+            _ => "-".to_string(),
+        };
+        vec![
+            self.name.clone(),
+            format_snaptime(output, self.snaptime),
+            if self.vmstate { "yes" } else { "no" }.to_string(),
+            self.parent.clone().unwrap_or_else(|| "-".to_string()),
+            disks,
+            self.description
+                .as_deref()
+                .unwrap_or("No description")
+                .trim_end()
+                .to_string(),
+        ]
+    }
+
+    fn default_columns() -> Vec<&'static str> {
+        vec!["name", "snaptime", "vmstate", "parent", "description"]
+    }
+}
+
+/// Whether the `agent` option of a VM config enables the guest agent. The
+/// option is either a bare flag (`1`) or a property string
+/// (`enabled=1,fstrim_cloned_disks=1`).
+fn agent_enabled(config: &serde_json::Value) -> bool {
+    let agent = match config.get("agent") {
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(serde_json::Value::Number(n)) => n.to_string(),
+        _ => return false,
+    };
+    agent.split(',').any(|part| {
+        let value = part.strip_prefix("enabled=").unwrap_or(part);
+        value == "1"
+    })
+}
+
+/// Adds the disks column, which is only shown on request, before the
+/// description.
+fn with_disks_column(mut columns: Vec<&'static str>) -> Vec<&'static str> {
+    let position = columns.len().saturating_sub(1);
+    columns.insert(position, "disks");
+    columns
+}
+
+/// A snapshot listed alongside the VM it belongs to, used when several VMs
+/// are listed at once.
+#[derive(Serialize)]
+struct GuestSnapshot {
+    vmid: u32,
+    node: String,
+    #[serde(flatten)]
+    snapshot: Snapshot,
+}
+
+impl Tabular for GuestSnapshot {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("vmid", "VMID"),
+            ("name", "Name"),
+            ("snaptime", "Created"),
+            ("vmstate", "RAM"),
+            ("parent", "Parent"),
+            ("disks", "Disks"),
+            ("description", "Description"),
+        ]
+    }
+
+    fn default_columns() -> Vec<&'static str> {
+        let mut columns = vec!["vmid"];
+        columns.extend(Snapshot::default_columns());
+        columns
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        let mut row = vec![self.vmid.to_string()];
+        row.extend(self.snapshot.row(output));
+        row
+    }
+}
+
+/// A configuration key that differs between two snapshots.
+#[derive(Debug, PartialEq, Serialize)]
+struct ConfigChange {
+    key: String,
+    old: Option<String>,
+    new: Option<String>,
+}
+
+impl Tabular for ConfigChange {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[("key", "Key"), ("old", "Old"), ("new", "New")]
+    }
+
+    fn row(&self, _output: &Output) -> Vec<String> {
+        vec![
+            self.key.clone(),
+            self.old.clone().unwrap_or_else(|| "-".to_string()),
+            self.new.clone().unwrap_or_else(|| "-".to_string()),
+        ]
+    }
+}
+
+/// Compares two VM configurations key by key, ignoring the metadata Proxmox
+/// stores alongside each snapshot.
+fn diff_configs(old: &serde_json::Value, new: &serde_json::Value) -> Vec<ConfigChange> {
+    const IGNORED: &[&str] = &["digest", "snaptime", "parent", "vmstate", "snapstate"];
+
+    let empty = serde_json::Map::new();
+    let old = old.as_object().unwrap_or(&empty);
+    let new = new.as_object().unwrap_or(&empty);
+
+    let value = |v: &serde_json::Value| match v {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+
+    let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    keys.into_iter()
+        .filter(|key| !IGNORED.contains(&key.as_str()))
+        .filter_map(|key| {
+            let old = old.get(key).map(value);
+            let new = new.get(key).map(value);
+            (old != new).then(|| ConfigChange {
+                key: key.clone(),
+                old,
+                new,
+            })
+        })
+        .collect()
+}
+
+/// The outcome of verifying one snapshot.
+#[derive(Serialize)]
+struct Verification {
+    snapshot: String,
+    ok: bool,
+    problems: Vec<String>,
+}
+
+impl Tabular for Verification {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("snapshot", "Snapshot"),
+            ("ok", "Status"),
+            ("problems", "Problems"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        vec![
+            self.snapshot.clone(),
+            if self.ok {
+                output.paint(Color::Green, "OK")
+            } else {
+                output.paint(Color::Red, "FAILED")
+            },
+            if self.problems.is_empty() {
+                "-".to_string()
+            } else {
+                self.problems.join("; ")
+            },
+        ]
+    }
+}
+
+/// Storage volumes a snapshot config references: its disks and, for
+/// RAM-state snapshots, the saved memory. Volumes outside Proxmox storage,
+/// such as passed-through devices, are left out.
+fn snapshot_volumes(config: &serde_json::Value) -> Vec<(String, String)> {
+    let mut volumes: Vec<(String, String)> = parse_disks(config)
+        .into_iter()
+        .map(|disk| (disk.name, disk.volume))
+        .collect();
+    if let Some(vmstate) = config.get("vmstate").and_then(|v| v.as_str()) {
+        volumes.push(("vmstate".to_string(), vmstate.to_string()));
+    }
+    volumes.retain(|(_, volume)| volume.contains(':'));
+    volumes
+}
+
+/// Checks a snapshot config for an operation that never finished and for
+/// volumes missing from storage. `volume_exists` answers for one volume ID,
+/// or explains why it cannot.
+fn snapshot_problems(
+    config: &serde_json::Value,
+    volume_exists: impl Fn(&str) -> Result<bool, String>,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    // Proxmox sets snapstate while creating or deleting a snapshot and clears
+    // it on success.
+    if let Some(state) = config.get("snapstate").and_then(|v| v.as_str()) {
+        problems.push(format!("left in state '{}' by an interrupted task", state));
+    }
+
+    if parse_disks(config).is_empty() {
+        problems.push("configuration lists no disks".to_string());
+    }
+
+    for (name, volume) in snapshot_volumes(config) {
+        match volume_exists(&volume) {
+            Ok(true) => {}
+            Ok(false) => problems.push(format!("{} volume {} not found on storage", name, volume)),
+            Err(e) => problems.push(format!("cannot check {} volume {}: {}", name, volume, e)),
+        }
+    }
+
+    problems
+}
+
+/// A VM that violates the audit limits.
+#[derive(Serialize)]
+struct AuditFinding {
+    vmid: u32,
+    name: Option<String>,
+    node: String,
+    snapshots: usize,
+    /// Age in seconds of the newest snapshot.
+    newest_age: Option<u64>,
+    problems: Vec<String>,
+}
+
+impl Tabular for AuditFinding {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("vmid", "VMID"),
+            ("name", "Name"),
+            ("node", "Node"),
+            ("snapshots", "Snapshots"),
+            ("newest_age", "Newest"),
+            ("problems", "Problems"),
+        ]
+    }
+
+    fn row(&self, _output: &Output) -> Vec<String> {
+        vec![
+            self.vmid.to_string(),
+            self.name.clone().unwrap_or_default(),
+            self.node.clone(),
+            self.snapshots.to_string(),
+            self.newest_age
+                .map(format_duration)
+                .unwrap_or_else(|| "-".to_string()),
+            self.problems.join("; "),
+        ]
+    }
+}
+
+fn format_snaptime(output: &Output, snaptime: Option<i64>) -> String {
+    snaptime
+        .and_then(|t| chrono::DateTime::from_timestamp(t, 0))
+        .map(|dt| output.format_time(dt))
+        .unwrap_or_else(|| "Unknown".to_string())
+}
+
+/// Follows parent links from the `current` state back to the root snapshot.
+/// The first element is the snapshot the VM's current state descends from.
+fn lineage(snapshots: &[Snapshot]) -> Vec<&Snapshot> {
+    let find = |name: &str| snapshots.iter().find(|s| s.name == name);
+
+    let mut chain: Vec<&Snapshot> = Vec::new();
+    let mut parent = find("current").and_then(|c| c.parent.as_deref());
+    while let Some(name) = parent {
+        // Guard against a malformed parent loop.
+        if chain.iter().any(|s| s.name == name) {
+            break;
+        }
+        let Some(snapshot) = find(name) else {
+            break;
+        };
+        chain.push(snapshot);
+        parent = snapshot.parent.as_deref();
+    }
+    chain
+}
+
+/// Orders snapshots for deleting them all: descendants before their
+/// ancestors, so that Proxmox never has to merge a snapshot into children
+/// that are about to be deleted anyway.
+fn deletion_order(snapshots: &[Snapshot]) -> Vec<&Snapshot> {
+    let depth = |snapshot: &Snapshot| {
+        let mut depth = 0;
+        let mut parent = snapshot.parent.as_deref();
+        while let Some(name) = parent
+            && depth < snapshots.len()
+        {
+            depth += 1;
+            parent = snapshots
+                .iter()
+                .find(|s| s.name == name)
+                .and_then(|s| s.parent.as_deref());
+        }
+        depth
+    };
+
+    let mut ordered: Vec<&Snapshot> = snapshots.iter().filter(|s| s.name != "current").collect();
+    ordered.sort_by_key(|s| (std::cmp::Reverse(depth(s)), std::cmp::Reverse(s.snaptime)));
+    ordered
+}
+
+/// Renders the snapshot hierarchy, including the `current` state, as an
+/// indented tree. Snapshots whose parent is unknown are treated as roots.
+fn render_tree(output: &Output, snapshots: &[Snapshot]) -> String {
+    fn render_node(
+        output: &Output,
+        snapshots: &[Snapshot],
+        snap: &Snapshot,
+        prefix: &str,
+        is_last: bool,
+        is_root: bool,
+        out: &mut String,
+    ) {
+        let (branch, child_prefix) = if is_root {
+            ("", String::new())
+        } else if is_last {
+            ("└─ ", format!("{}   ", prefix))
+        } else {
+            ("├─ ", format!("{}│  ", prefix))
+        };
+
+        if snap.name == "current" {
+            out.push_str(&format!("{}{}NOW (current state)\n", prefix, branch));
+        } else {
+            out.push_str(&format!(
+                "{}{}{} ({})\n",
+                prefix,
+                branch,
+                snap.name,
+                format_snaptime(output, snap.snaptime)
+            ));
+        }
+
+        let children: Vec<&Snapshot> = snapshots
+            .iter()
+            .filter(|s| s.parent.as_deref() == Some(snap.name.as_str()))
+            .collect();
+        for (i, child) in children.iter().enumerate() {
+            render_node(
+                output,
+                snapshots,
+                child,
+                &child_prefix,
+                i == children.len() - 1,
+                false,
+                out,
+            );
+        }
+    }
+
+    let mut out = String::new();
+    let roots = snapshots.iter().filter(|s| {
+        s.parent
+            .as_deref()
+            .is_none_or(|p| !snapshots.iter().any(|other| other.name == p))
+    });
+    for root in roots {
+        render_node(output, snapshots, root, "", true, true, &mut out);
+    }
+    out
+}
+
+#[derive(Serialize)]
+struct VmInfo {
+    node: String,
+    vmid: u32,
+    name: Option<String>,
+    status: Option<String>,
+    cpu: Option<f64>,
+    mem: Option<u64>,
+    maxmem: Option<u64>,
+    disks: Vec<VmDisk>,
+    /// Addresses reported by the guest, empty when it cannot be asked.
+    addresses: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VmDisk {
+    pub name: String,
+    pub volume: String,
+    pub size: Option<u64>,
+}
+
+/// Extracts the disks (not CD-ROM drives) from a VM or snapshot config.
+pub fn parse_disks(config: &serde_json::Value) -> Vec<VmDisk> {
+    const BUSES: &[&str] = &[
+        "scsi", "virtio", "sata", "ide", "efidisk", "tpmstate", "rootfs", "mp",
+    ];
+
+    let Some(config) = config.as_object() else {
+        return Vec::new();
+    };
+
+    let mut disks: Vec<VmDisk> = config
+        .iter()
+        .filter(|(key, _)| {
+            BUSES.iter().any(|bus| {
+                key.strip_prefix(bus)
+                    .is_some_and(|n| (n.is_empty() && *bus == "rootfs") || n.parse::<u32>().is_ok())
+            })
+        })
+        .filter_map(|(key, value)| {
+            let value = value.as_str()?;
+            let mut parts = value.split(',');
+            let volume = parts.next()?.to_string();
+            let options: Vec<&str> = parts.collect();
+            if volume == "none" || options.contains(&"media=cdrom") {
+                return None;
+            }
+            let size = options
+                .iter()
+                .find_map(|o| o.strip_prefix("size="))
+                .and_then(|s| parse_size(s).ok());
+            Some(VmDisk {
+                name: key.clone(),
+                volume,
+                size,
+            })
+        })
+        .collect();
+    disks.sort_by(|a, b| a.name.cmp(&b.name));
+    disks
+}
+
+impl Tabular for VmInfo {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("node", "Node"),
+            ("vmid", "VMID"),
+            ("name", "Name"),
+            ("status", "Status"),
+            ("cpu", "CPU Usage"),
+            ("mem", "Memory"),
+            ("disks", "Disks"),
+            ("addresses", "IP Addresses"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        let memory = match (self.mem, self.maxmem) {
+            (Some(mem), Some(maxmem)) if maxmem > 0 => format!(
+                "{} / {} ({:.1}%)",
+                output.format_bytes(mem),
+                output.format_bytes(maxmem),
+                (mem as f64 / maxmem as f64) * 100.0
+            ),
+            _ => "-".to_string(),
+        };
+
+        let disks = if self.disks.is_empty() {
+            "-".to_string()
+        } else {
+            self.disks
+                .iter()
+                .map(|d| match d.size {
+                    Some(size) => format!("{} {}", d.name, output.format_bytes(size)),
+                    None => d.name.clone(),
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        vec![
+            self.node.clone(),
+            self.vmid.to_string(),
+            self.name.clone().unwrap_or_else(|| "-".to_string()),
+            self.status.clone().unwrap_or_else(|| "-".to_string()),
+            self.cpu
+                .map(|cpu| format!("{:.2}%", cpu * 100.0))
+                .unwrap_or_else(|| "-".to_string()),
+            memory,
+            disks,
+            if self.addresses.is_empty() {
+                "-".to_string()
+            } else {
+                self.addresses.join(", ")
+            },
+        ]
+    }
+}
+
+/// What `create` does when a snapshot with the requested name exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnExisting {
+    #[default]
+    Fail,
+    Skip,
+    /// Delete the existing snapshot, then create it again.
+    Replace,
+}
+
+/// Options for `create`.
+#[derive(Debug, Clone, Default)]
+pub struct CreateOptions {
+    pub snapname: Option<String>,
+    pub name_template: String,
+    /// Description, which may contain placeholders such as `{user}`.
+    pub description_template: String,
+    /// Value of the `{reason}` placeholder.
+    pub reason: Option<String>,
+    pub vmstate: bool,
+    pub existing: OnExisting,
+    /// Freeze guest file systems around the snapshot, for at most this many
+    /// seconds.
+    pub fsfreeze: Option<u64>,
+    /// Refuse RAM-state snapshots when the guest agent check fails.
+    pub strict: bool,
+}
+
+/// What `notes` does with the notes of a guest.
+#[derive(Debug, Clone)]
+pub enum NotesEdit {
+    Show,
+    Set(String),
+    Append(String),
+    /// Open them in `$EDITOR`.
+    Editor,
+}
+
+/// Options for `clone`.
+#[derive(Debug, Clone, Default)]
+pub struct CloneOptions {
+    /// Clone the state of this snapshot instead of the current one.
+    pub snapname: Option<String>,
+    pub name: Option<String>,
+    /// Copy all disks rather than linking them to a template's disks.
+    pub full: bool,
+    /// Require a linked clone, which only templates support. Without `full`
+    /// or `linked`, Proxmox links clones of templates and copies the rest.
+    pub linked: bool,
+    pub target: Option<String>,
+    pub storage: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum VmColumn {
+    Vmid,
+    Name,
+    Node,
+    Status,
+    Mem,
+    Cpu,
+    Disk,
+    Uptime,
+}
+
+impl VmColumn {
+    fn key(self) -> &'static str {
+        match self {
+            VmColumn::Vmid => "vmid",
+            VmColumn::Name => "name",
+            VmColumn::Node => "node",
+            VmColumn::Status => "status",
+            VmColumn::Mem => "mem",
+            VmColumn::Cpu => "cpu",
+            VmColumn::Disk => "maxdisk",
+            VmColumn::Uptime => "uptime",
+        }
+    }
+}
+
+/// Conditions `list-vms` selects VMs by; every condition set must hold.
+#[derive(Debug, Default)]
+pub struct VmFilter {
+    pub node: Option<String>,
+    pub status: Option<GuestState>,
+    pub name: Option<NamePattern>,
+    /// Keeps VMs carrying any of these tags.
+    pub tags: Vec<String>,
+    pub pool: Option<String>,
+    pub guest_type: Option<GuestType>,
+}
+
+impl VmFilter {
+    /// Whether the cluster resources entry of `vm` passes every filter but
+    /// the status, which may need the QEMU status on top.
+    fn matches_resource(&self, vm: &VmResource) -> bool {
+        self.node.as_ref().is_none_or(|node| &vm.node == node)
+            && self
+                .name
+                .as_ref()
+                .is_none_or(|pattern| pattern.is_match(vm.name.as_deref().unwrap_or_default()))
+            && (self.tags.is_empty()
+                || split_tags(vm.tags.as_deref().unwrap_or_default())
+                    .any(|tag| self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))))
+            && self
+                .pool
+                .as_ref()
+                .is_none_or(|pool| vm.pool.as_ref() == Some(pool))
+            && self
+                .guest_type
+                .is_none_or(|guest_type| vm.guest_type == guest_type.as_str())
+    }
+
+    fn matches_status(&self, vm: &VmResource) -> bool {
+        self.status
+            .is_none_or(|state| state.matches(&vm.status, vm.qmpstatus.as_deref()))
+    }
+
+    /// Whether telling paused VMs from running ones takes their QEMU status.
+    fn needs_qmpstatus(&self) -> bool {
+        matches!(self.status, Some(GuestState::Running | GuestState::Paused))
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct VmResource {
+    node: String,
+    vmid: u32,
+    name: Option<String>,
+    #[serde(rename = "type", default)]
+    guest_type: String,
+    status: String,
+    /// QEMU status, which tells paused VMs from running ones. Only read
+    /// when filtering on either.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    qmpstatus: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tags: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pool: Option<String>,
+    #[serde(default)]
+    mem: Option<u64>,
+    #[serde(default)]
+    maxmem: Option<u64>,
+    #[serde(default)]
+    cpu: Option<f64>,
+    #[serde(default)]
+    maxcpu: Option<f64>,
+    #[serde(default)]
+    maxdisk: Option<u64>,
+    #[serde(default)]
+    uptime: Option<u64>,
+}
+
+impl Tabular for VmResource {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("vmid", "VMID"),
+            ("name", "Name"),
+            ("node", "Node"),
+            ("status", "Status"),
+            ("mem", "Memory"),
+            ("cpu", "CPU"),
+            ("maxdisk", "Disk"),
+            ("uptime", "Uptime"),
+        ]
+    }
+
+    fn default_columns() -> Vec<&'static str> {
+        vec!["vmid", "name", "node", "status"]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        let memory = match (self.mem, self.maxmem) {
+            (Some(mem), Some(maxmem)) => format!(
+                "{} / {}",
+                output.format_bytes(mem),
+                output.format_bytes(maxmem)
+            ),
+            _ => "-".to_string(),
+        };
+
+        vec![
+            self.vmid.to_string(),
+            self.name.clone().unwrap_or_else(|| "-".to_string()),
+            self.node.clone(),
+            self.status.clone(),
+            memory,
+            self.cpu
+                .map(|cpu| format!("{:.1}%", cpu * 100.0))
+                .unwrap_or_else(|| "-".to_string()),
+            self.maxdisk
+                .filter(|&d| d > 0)
+                .map(|d| output.format_bytes(d))
+                .unwrap_or_else(|| "-".to_string()),
+            self.uptime
+                .filter(|&u| u > 0)
+                .map(format_duration)
+                .unwrap_or_else(|| "-".to_string()),
+        ]
+    }
 }
 
 impl SnapshotManager {
-    pub fn new(client: ProxmoxClient) -> Self {
-        let cluster = ClusterManager::new(client.clone());
-        Self { client, cluster }
+    pub fn new(client: ProxmoxClient, output: Output) -> Self {
+        let cluster = ClusterManager::new(client.clone(), output.clone());
+        let tasks = TaskManager::new(client.clone(), output.clone());
+        Self {
+            client,
+            cluster,
+            tasks,
+            output,
+        }
     }
 
-    pub async fn create_snapshot(
+    pub async fn create_snapshots(
         &self,
-        vm_identifier: &str,
-        snapname: Option<String>,
-        description: Option<String>,
+        selection: &Selection,
+        options: &CreateOptions,
+    ) -> Result<()> {
+        if let Some(snapname) = &options.snapname {
+            naming::validate_snapname(snapname)?;
+        }
+        // Catch template typos before touching any VM.
+        naming::render_description(&options.description_template, |_| Some(String::new()))?;
+
+        let guests = self.cluster.resolve(selection).await?;
+
+        // All VMs of a batch share one timestamp.
+        let now = chrono::Utc::now();
+        let date = self.output.format_time_with(now, "%Y%m%d");
+        let time = self.output.format_time_with(now, "%H%M%S");
+
+        let timestamp = self.output.format_time(now);
+        let user = whoami::username();
+        let hostname = whoami::fallible::hostname().unwrap_or_default();
+
+        self.run_batch(&guests, |guest| async {
+            let snapname = match &options.snapname {
+                Some(snapname) => snapname.clone(),
+                None => naming::render_snapname(&options.name_template, |key| match key {
+                    "vmid" => Some(guest.vmid.to_string()),
+                    "name" => Some(guest.name.clone().unwrap_or_default()),
+                    "node" => Some(guest.node.clone()),
+                    "tag" => Some(guest.tags().next().unwrap_or_default().to_string()),
+                    "date" => Some(date.clone()),
+                    "time" => Some(time.clone()),
+                    _ => None,
+                })?,
+            };
+
+            let description =
+                naming::render_description(&options.description_template, |key| match key {
+                    "vmid" => Some(guest.vmid.to_string()),
+                    "vm_name" => Some(guest.name.clone().unwrap_or_default()),
+                    "node" => Some(guest.node.clone()),
+                    "user" => Some(user.clone()),
+                    "hostname" => Some(hostname.clone()),
+                    "reason" => Some(options.reason.clone().unwrap_or_default()),
+                    "snapname" => Some(snapname.clone()),
+                    "timestamp" => Some(timestamp.clone()),
+                    _ => None,
+                })?;
+
+            if self.snapshot_exists(guest, &snapname).await? {
+                match options.existing {
+                    OnExisting::Fail => anyhow::bail!(
+                        "Snapshot '{}' already exists on VM {}; use --if-not-exists or --replace",
+                        snapname,
+                        guest.vmid
+                    ),
+                    OnExisting::Skip => {
+                        self.output.message(&format!(
+                            "Snapshot '{}' already exists on VM {}, skipping",
+                            snapname, guest.vmid
+                        ));
+                        return Ok(());
+                    }
+                    OnExisting::Replace => self.delete_snapshot(guest, &snapname).await?,
+                }
+            }
+
+            if options.vmstate && !guest.is_container() {
+                self.check_agent_for_vmstate(guest, options.strict).await?;
+            }
+
+            match options.fsfreeze {
+                Some(timeout) => {
+                    self.create_frozen(guest, &snapname, &description, options.vmstate, timeout)
+                        .await
+                }
+                None => {
+                    self.create_snapshot(guest, &snapname, &description, options.vmstate)
+                        .await
+                }
+            }
+        })
+        .await
+    }
+
+    /// Checks that the guest agent is configured and responding before a
+    /// RAM-state snapshot, which tends to hang on guests without one. Problems
+    /// are warnings, or errors when `strict` is set.
+    async fn check_agent_for_vmstate(&self, guest: &Guest, strict: bool) -> Result<()> {
+        let config: serde_json::Value =
+            self.client.get(&format!("{}/config", guest.path())).await?;
+
+        let problem = if !agent_enabled(&config) {
+            "the QEMU guest agent is not enabled in the VM options"
+        } else if self
+            .client
+            .post::<_, serde_json::Value>(&format!("{}/agent/ping", guest.path()), &())
+            .await
+            .is_err()
+        {
+            "the QEMU guest agent does not respond"
+        } else {
+            return Ok(());
+        };
+
+        let text = format!(
+            "VM {}: {}; RAM-state snapshots may hang",
+            guest.vmid, problem
+        );
+        if strict {
+            anyhow::bail!("{} (refusing because of --strict)", text);
+        }
+        self.output.warn(&text);
+        Ok(())
+    }
+
+    /// Freezes the guest file systems through the QEMU guest agent, takes
+    /// the snapshot and thaws them again, whatever the outcome. The frozen
+    /// window is bounded by `timeout` seconds.
+    async fn create_frozen(
+        &self,
+        guest: &Guest,
+        snapname: &str,
+        description: &str,
         vmstate: bool,
+        timeout: u64,
     ) -> Result<()> {
-        let (node, vmid) = self.cluster.find_vm_node(vm_identifier).await?;
+        if guest.is_container() {
+            anyhow::bail!("Container {} has no guest agent to freeze", guest.vmid);
+        }
 
-        let snapname = snapname.unwrap_or_else(|| {
-            format!("snapshot-{}", chrono::Local::now().format("%Y%m%d-%H%M%S"))
-        });
+        // The freeze and the snapshot share one deadline, so that the guest
+        // is never frozen for longer than `timeout`.
+        let deadline = Instant::now() + Duration::from_secs(timeout);
+        let endpoint = format!("{}/agent/fsfreeze-freeze", guest.path());
+        let freeze = self.client.post::<_, serde_json::Value>(&endpoint, &());
 
-        let description = description.unwrap_or_else(|| {
-            format!(
-                "Snapshot created on {}",
-                chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
-            )
-        });
+        // A freeze that times out may still complete in the guest, so thaw
+        // whenever it did not cleanly fail.
+        let result = match timeout_at(deadline, freeze).await {
+            Ok(Err(e)) => {
+                return Err(anyhow::Error::new(e).context(format!(
+                    "Could not freeze file systems of VM {}",
+                    guest.vmid
+                )));
+            }
+            Ok(Ok(_)) => {
+                self.output
+                    .message(&format!("Froze file systems of VM {}", guest.vmid));
+                timeout_at(
+                    deadline,
+                    self.create_snapshot(guest, snapname, description, vmstate),
+                )
+                .await
+                .unwrap_or_else(|_| {
+                    Err(anyhow::anyhow!(
+                        "Freezing and snapshotting VM {} took longer than {}",
+                        guest.vmid,
+                        format_duration(timeout)
+                    ))
+                })
+            }
+            Err(_) => Err(anyhow::anyhow!(
+                "Freezing file systems of VM {} timed out",
+                guest.vmid
+            )),
+        };
+
+        let thaw = self.thaw(guest).await;
+        result.and(thaw)
+    }
+
+    async fn thaw(&self, guest: &Guest) -> Result<()> {
+        const ATTEMPTS: u32 = 3;
+
+        let endpoint = format!("{}/agent/fsfreeze-thaw", guest.path());
+        let mut last_error = None;
+        for attempt in 1..=ATTEMPTS {
+            match self
+                .client
+                .post::<_, serde_json::Value>(&endpoint, &())
+                .await
+            {
+                Ok(_) => {
+                    self.output
+                        .message(&format!("Thawed file systems of VM {}", guest.vmid));
+                    return Ok(());
+                }
+                Err(e) => last_error = Some(e),
+            }
+            if attempt < ATTEMPTS {
+                sleep(Duration::from_secs(2)).await;
+            }
+        }
+
+        Err(anyhow::Error::new(last_error.expect("at least one attempt")).context(format!(
+            "Could not thaw file systems of VM {}; run 'qm guest cmd {} fsfreeze-thaw' on node {}",
+            guest.vmid, guest.vmid, guest.node
+        )))
+    }
+
+    async fn snapshot_exists(&self, guest: &Guest, snapname: &str) -> Result<bool> {
+        let snapshots: Vec<Snapshot> = self
+            .client
+            .get(&format!("{}/snapshot", guest.path()))
+            .await?;
+        Ok(snapshots.iter().any(|s| s.name == snapname))
+    }
+
+    async fn create_snapshot(
+        &self,
+        guest: &Guest,
+        snapname: &str,
+        description: &str,
+        vmstate: bool,
+    ) -> Result<()> {
+        let (node, vmid) = (&guest.node, guest.vmid);
+
+        if vmstate && guest.is_container() {
+            anyhow::bail!("Container {} cannot be snapshotted with --vmstate", vmid);
+        }
 
         #[derive(Serialize)]
-        struct SnapshotRequest {
-            snapname: String,
-            description: String,
+        struct SnapshotRequest<'a> {
+            snapname: &'a str,
+            description: &'a str,
             #[serde(skip_serializing_if = "Option::is_none")]
             vmstate: Option<u8>,
         }
 
         let request = SnapshotRequest {
-            snapname: snapname.clone(),
+            snapname,
             description,
             vmstate: if vmstate { Some(1) } else { None },
         };
 
-        let task_id: String = self
+        let task_id: String = self
+            .client
+            .post(&format!("{}/snapshot", guest.path()), &request)
+            .await?;
+
+        self.output.print_raw(&task_id)?;
+        self.output.message(&format!(
+            "Creating snapshot '{}' on node {} for VM {}...",
+            snapname, node, vmid
+        ));
+        self.tasks.wait(node, &task_id).await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_snapshots(
+        &self,
+        selection: &Selection,
+        snapname: &str,
+        assume_yes: bool,
+    ) -> Result<()> {
+        let guests = self.cluster.resolve(selection).await?;
+
+        let target = match guests.as_slice() {
+            [guest] => format!("VM {}", guest.vmid),
+            guests => format!("{} VMs", guests.len()),
+        };
+        self.confirm(
+            assume_yes,
+            &format!("Delete snapshot '{}' from {}?", snapname, target),
+        )?;
+
+        self.run_batch(&guests, |guest| self.delete_snapshot(guest, snapname))
+            .await
+    }
+
+    /// Deletes every snapshot whose name matches `pattern`, after listing them
+    /// and asking for confirmation unless `assume_yes` is set.
+    pub async fn delete_matching(
+        &self,
+        selection: &Selection,
+        pattern: &NamePattern,
+        assume_yes: bool,
+    ) -> Result<()> {
+        let guests = self.cluster.resolve(selection).await?;
+
+        let mut matches: Vec<(Guest, Vec<String>)> = Vec::new();
+        for guest in guests {
+            let snapshots: Vec<Snapshot> = self
+                .client
+                .get(&format!("{}/snapshot", guest.path()))
+                .await?;
+            let names: Vec<String> = snapshots
+                .into_iter()
+                .filter(|s| s.name != "current" && pattern.is_match(&s.name))
+                .map(|s| s.name)
+                .collect();
+            if !names.is_empty() {
+                matches.push((guest, names));
+            }
+        }
+
+        let total: usize = matches.iter().map(|(_, names)| names.len()).sum();
+        if total == 0 {
+            anyhow::bail!("No snapshots match '{}'", pattern.as_str());
+        }
+
+        for (guest, names) in &matches {
+            self.output.warn(&format!(
+                "This deletes the snapshots of VM {} matching '{}': {}",
+                guest.vmid,
+                pattern.as_str(),
+                names.join(", ")
+            ));
+        }
+
+        self.confirm(assume_yes, &format!("Delete {} snapshots?", total))?;
+
+        let guests: Vec<Guest> = matches.iter().map(|(g, _)| g.clone()).collect();
+        self.run_batch(&guests, |guest| async {
+            let names = matches
+                .iter()
+                .find(|(g, _)| g.vmid == guest.vmid)
+                .map(|(_, names)| names.as_slice())
+                .unwrap_or_default();
+            for name in names {
+                self.delete_snapshot(guest, name).await?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Deletes every snapshot of one VM, children before parents, after a
+    /// single lookup of the VM.
+    pub async fn delete_all(&self, vm_identifier: &str, assume_yes: bool) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let snapshots: Vec<Snapshot> = self
+            .client
+            .get(&format!("{}/snapshot", guest.path()))
+            .await?;
+
+        let ordered = deletion_order(&snapshots);
+        if ordered.is_empty() {
+            self.output
+                .message(&format!("VM {} has no snapshots", guest.vmid));
+            return Ok(());
+        }
+
+        let names: Vec<&str> = ordered.iter().map(|s| s.name.as_str()).collect();
+        self.output.warn(&format!(
+            "This deletes every snapshot of VM {}: {}",
+            guest.vmid,
+            names.join(", ")
+        ));
+        self.confirm(
+            assume_yes,
+            &format!(
+                "Delete all {} snapshots of VM {}?",
+                ordered.len(),
+                guest.vmid
+            ),
+        )?;
+
+        for (deleted, snapshot) in ordered.iter().enumerate() {
+            self.delete_snapshot(&guest, &snapshot.name)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Stopped after deleting {} of {} snapshots",
+                        deleted,
+                        ordered.len()
+                    )
+                })?;
+        }
+        Ok(())
+    }
+
+    async fn delete_snapshot(&self, guest: &Guest, snapname: &str) -> Result<()> {
+        let (node, vmid) = (&guest.node, guest.vmid);
+
+        let task_id: String = self
+            .client
+            .delete(&format!("{}/snapshot/{}", guest.path(), snapname))
+            .await?;
+
+        self.output.print_raw(&task_id)?;
+        self.output.message(&format!(
+            "Deleting snapshot '{}' on node {} for VM {}...",
+            snapname, node, vmid
+        ));
+        self.tasks.wait(node, &task_id).await?;
+
+        Ok(())
+    }
+
+    pub async fn prune_snapshots(
+        &self,
+        selection: &Selection,
+        policy: &RetentionPolicy,
+    ) -> Result<()> {
+        if policy.is_empty() {
+            anyhow::bail!(
+                "No retention options given; use --keep-last, --keep-daily, --keep-weekly or --keep-monthly"
+            );
+        }
+
+        let guests = self.cluster.resolve(selection).await?;
+        self.run_batch(&guests, |guest| self.prune_guest(guest, policy))
+            .await
+    }
+
+    /// Prunes using the policies from the config file. Without a VM, pool or
+    /// tag, every VM that has a policy is pruned.
+    pub async fn prune_with_config(
+        &self,
+        selection: &Selection,
+        retention: &RetentionConfig,
+    ) -> Result<()> {
+        let guests = if selection.is_empty() {
+            self.cluster.vms().await?
+        } else {
+            self.cluster.resolve(selection).await?
+        };
+
+        let guests: Vec<Guest> = guests
+            .into_iter()
+            .filter(|guest| retention.policy_for(guest).is_some())
+            .collect();
+
+        if guests.is_empty() {
+            anyhow::bail!("No retention policy in the config file applies to the selected VMs");
+        }
+
+        self.run_batch(&guests, |guest| async move {
+            let policy = retention.policy_for(guest).unwrap_or_default();
+            self.prune_guest(guest, &policy).await
+        })
+        .await
+    }
+
+    async fn prune_guest(&self, guest: &Guest, policy: &RetentionPolicy) -> Result<()> {
+        let snapshots: Vec<Snapshot> = self
+            .client
+            .get(&format!("{}/snapshot", guest.path()))
+            .await?;
+
+        // Snapshots without a timestamp cannot be placed in a period and are
+        // always kept.
+        let candidates: Vec<(String, i64)> = snapshots
+            .into_iter()
+            .filter(|s| s.name != "current")
+            .filter_map(|s| Some((s.name, s.snaptime?)))
+            .collect();
+
+        let decisions = prune::plan(&candidates, policy, &chrono::Local);
+        let removed = decisions.iter().filter(|d| !d.keep).count();
+        self.output.message(&format!(
+            "VM {}: keeping {} snapshots, removing {}",
+            guest.vmid,
+            decisions.len() - removed,
+            removed
+        ));
+
+        for decision in decisions.iter().filter(|d| !d.keep) {
+            self.delete_snapshot(guest, &decision.name).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Deletes a guest and its disks. Guests with snapshots are refused
+    /// unless `force` is set, and the name must be typed back, or given in
+    /// advance as `confirm_name`, before anything is removed.
+    pub async fn destroy_vm(
+        &self,
+        vm_identifier: &str,
+        purge: bool,
+        force: bool,
+        confirm_name: Option<&str>,
+    ) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+
+        let snapshots: Vec<Snapshot> = self
+            .client
+            .get(&format!("{}/snapshot", guest.path()))
+            .await?;
+        let count = snapshots.iter().filter(|s| s.name != "current").count();
+        if count > 0 && !force {
+            anyhow::bail!(
+                "VM {} has {} snapshots; pass --force to destroy it anyway",
+                guest.vmid,
+                count
+            );
+        }
+
+        let expected = guest.name.clone().unwrap_or_else(|| guest.vmid.to_string());
+        match confirm_name {
+            Some(name) if name == expected => {}
+            Some(name) => anyhow::bail!(
+                "'{}' does not match the name of VM {}, '{}'",
+                name,
+                guest.vmid,
+                expected
+            ),
+            None if self.client.is_dry_run() => {}
+            None => {
+                let question = format!(
+                    "This will permanently destroy VM {} ({}) on node {} and all its disks.",
+                    guest.vmid, expected, guest.node
+                );
+                if !prompt::confirm_typed(&question, &expected)? {
+                    anyhow::bail!("Aborted");
+                }
+            }
+        }
+
+        let endpoint = if purge {
+            format!("{}?purge=1&destroy-unreferenced-disks=1", guest.path())
+        } else {
+            guest.path()
+        };
+        let task_id: String = self.client.delete(&endpoint).await?;
+
+        self.output.print_raw(&task_id)?;
+        self.output.message(&format!(
+            "Destroying VM {} on node {}...",
+            guest.vmid, guest.node
+        ));
+        self.tasks.wait(&guest.node, &task_id).await
+    }
+
+    /// Changes the power state of the selected guests and waits for each
+    /// task to finish.
+    pub async fn power(
+        &self,
+        selection: &Selection,
+        action: PowerAction,
+        options: &PowerOptions,
+    ) -> Result<()> {
+        let options = &PowerOptions {
+            todisk: (action == PowerAction::Hibernate).then_some(1),
+            ..*options
+        };
+        let guests = self.cluster.resolve(selection).await?;
+        self.run_batch(&guests, |guest| async move {
+            if action == PowerAction::Hibernate && guest.is_container() {
+                anyhow::bail!("VM {} is a container, which cannot hibernate", guest.vmid);
+            }
+            let task_id: String = self
+                .client
+                .post(
+                    &format!("{}/status/{}", guest.path(), action.endpoint()),
+                    options,
+                )
+                .await?;
+
+            self.output.print_raw(&task_id)?;
+            self.output.message(&format!(
+                "{} VM {} on node {}...",
+                action.verb(),
+                guest.vmid,
+                guest.node
+            ));
+            self.tasks.wait(&guest.node, &task_id).await
+        })
+        .await
+    }
+
+    /// Starts, stops or shuts down guests one at a time in boot order, and
+    /// stops at the first failure so that no guest starts while one it may
+    /// depend on is down. Stopping goes in reverse order. Between guests it
+    /// waits for their `startup` up delay, or `delay` seconds.
+    pub async fn power_sequence(
+        &self,
+        selection: &Selection,
+        action: PowerAction,
+        options: &PowerOptions,
+        order_by: OrderBy,
+        delay: u64,
+    ) -> Result<()> {
+        let guests = self.cluster.resolve(selection).await?;
+        let mut sequence = Vec::new();
+        for guest in guests {
+            let config: serde_json::Value =
+                self.client.get(&format!("{}/config", guest.path())).await?;
+            let startup = config
+                .get("startup")
+                .and_then(|v| v.as_str())
+                .map(Startup::parse)
+                .unwrap_or_default();
+            sequence.push((guest, startup));
+        }
+        sequence.sort_by_key(|(guest, startup)| match order_by {
+            OrderBy::Startup => startup.boot_key(guest.vmid),
+            OrderBy::Vmid => (false, 0, guest.vmid),
+        });
+        let starting = action == PowerAction::Start;
+        if !starting {
+            sequence.reverse();
+        }
+
+        let count = sequence.len();
+        for (i, (guest, startup)) in sequence.into_iter().enumerate() {
+            let status: serde_json::Value = self
+                .client
+                .get(&format!("{}/status/current", guest.path()))
+                .await?;
+            let state = status.get("status").and_then(|v| v.as_str());
+            if state == Some(if starting { "running" } else { "stopped" }) {
+                self.output.message(&format!(
+                    "VM {} is already {}",
+                    guest.vmid,
+                    state.unwrap_or_default()
+                ));
+                continue;
+            }
+
+            let options = PowerOptions {
+                timeout: startup.timeout(action, options.timeout),
+                ..*options
+            };
+            let task_id: String = self
+                .client
+                .post(
+                    &format!("{}/status/{}", guest.path(), action.endpoint()),
+                    &options,
+                )
+                .await?;
+            self.output.print_raw(&task_id)?;
+            self.output.message(&format!(
+                "{} VM {} on node {}...",
+                action.verb(),
+                guest.vmid,
+                guest.node
+            ));
+            self.tasks
+                .wait(&guest.node, &task_id)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Stopped the sequence at VM {} ({} of {})",
+                        guest.vmid,
+                        i + 1,
+                        count
+                    )
+                })?;
+
+            let pause = match startup.up {
+                Some(up) if starting && order_by == OrderBy::Startup => up,
+                _ => delay,
+            };
+            if i + 1 < count && pause > 0 && !self.client.is_dry_run() {
+                self.output.message(&format!(
+                    "Waiting {} before the next VM",
+                    format_duration(pause)
+                ));
+                sleep(Duration::from_secs(pause)).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves a guest to another node, printing the task log as it goes.
+    pub async fn migrate_vm(
+        &self,
+        vm_identifier: &str,
+        target: &str,
+        options: &MigrateOptions,
+    ) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        if guest.node == target {
+            anyhow::bail!("VM {} is already on node {}", guest.vmid, target);
+        }
+
+        let options = if guest.is_container() {
+            options.for_container()
+        } else {
+            *options
+        };
+        // Only a running container is restarted; a stopped one just moves.
+        let restarts = options.restart.is_some() && {
+            let status: serde_json::Value = self
+                .client
+                .get(&format!("{}/status/current", guest.path()))
+                .await?;
+            status.get("status").and_then(|v| v.as_str()) == Some("running")
+        };
+        let task_id = self.start_migration(&guest, target, &options).await?;
+        self.output.print_raw(&task_id)?;
+        self.output.message(&format!(
+            "Migrating VM {} from node {} to {}...",
+            guest.vmid, guest.node, target
+        ));
+        if restarts {
+            self.output
+                .message("Containers cannot migrate live; it is restarted on the target node.");
+        }
+        self.tasks.follow(&guest.node, &task_id).await
+    }
+
+    /// Converts a guest into a template, the base of linked clones. The
+    /// conversion cannot be undone, so it asks first unless `--yes` is given.
+    pub async fn make_template(&self, vm_identifier: &str, assume_yes: bool) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        if self.is_template(&guest).await? {
+            anyhow::bail!("VM {} is already a template", guest.vmid);
+        }
+
+        self.confirm(
+            assume_yes,
+            &format!(
+                "VM {} will become a template and can no longer be started. Continue?",
+                guest.vmid
+            ),
+        )?;
+
+        // VMs are converted by a task, containers directly.
+        let task_id: Option<String> = self
+            .client
+            .post(&format!("{}/template", guest.path()), &())
+            .await?;
+
+        self.output.message(&format!(
+            "Converting VM {} on node {} to a template...",
+            guest.vmid, guest.node
+        ));
+        match task_id {
+            Some(task_id) => {
+                self.output.print_raw(&task_id)?;
+                self.tasks.wait(&guest.node, &task_id).await
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Clears the lock a crashed task left on a guest. Refuses while a task
+    /// on the guest is still running, since that task may hold the lock.
+    pub async fn unlock_vm(&self, vm_identifier: &str, assume_yes: bool) -> Result<()> {
+        #[derive(Deserialize)]
+        struct LockConfig {
+            lock: Option<String>,
+        }
+
+        #[derive(Deserialize)]
+        struct ActiveTask {
+            upid: String,
+            #[serde(rename = "type")]
+            task_type: String,
+        }
+
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let endpoint = format!("{}/config", guest.path());
+        let config: LockConfig = self.client.get(&endpoint).await?;
+        let Some(lock) = config.lock else {
+            self.output
+                .message(&format!("VM {} is not locked", guest.vmid));
+            return Ok(());
+        };
+
+        let active: Vec<ActiveTask> = self
+            .client
+            .get(&format!(
+                "/nodes/{}/tasks?vmid={}&source=active",
+                guest.node, guest.vmid
+            ))
+            .await?;
+        if let Some(task) = active.first() {
+            anyhow::bail!(
+                "VM {} is locked ({}) while a {} task is still running: {}",
+                guest.vmid,
+                lock,
+                task.task_type,
+                task.upid
+            );
+        }
+
+        self.output.warn(&format!(
+            "VM {} is locked ({}). Clear the lock only if the task that set it \
+             crashed or was killed; the interrupted operation may have left \
+             snapshots or disks half done, so check them afterwards.",
+            guest.vmid, lock
+        ));
+        self.confirm(assume_yes, &format!("Unlock VM {}?", guest.vmid))?;
+
+        let _: serde_json::Value = self
+            .client
+            .put(&endpoint, &serde_json::json!({ "delete": "lock" }))
+            .await?;
+        self.output.message(&format!(
+            "Unlocked VM {}; review it with 'pve-tool list {}'",
+            guest.vmid, guest.vmid
+        ));
+        Ok(())
+    }
+
+    async fn is_template(&self, guest: &Guest) -> Result<bool> {
+        #[derive(Deserialize)]
+        struct GuestConfig {
+            #[serde(default, deserialize_with = "deserialize_flag")]
+            template: bool,
+        }
+
+        let config: GuestConfig = self.client.get(&format!("{}/config", guest.path())).await?;
+        Ok(config.template)
+    }
+
+    /// Clones a guest to `newid`, printing the task log as it goes.
+    pub async fn clone_vm(
+        &self,
+        vm_identifier: &str,
+        newid: u32,
+        options: &CloneOptions,
+    ) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        self.clone_guest(&guest, newid, options).await?;
+        Ok(())
+    }
+
+    /// Clones `guest` and returns the clone once the task has finished.
+    async fn clone_guest(
+        &self,
+        guest: &Guest,
+        newid: u32,
+        options: &CloneOptions,
+    ) -> Result<Guest> {
+        let template = self.is_template(guest).await?;
+        if options.linked && !template {
+            anyhow::bail!(
+                "VM {} is not a template; linked clones need one (see `pve-tool template {}`)",
+                guest.vmid,
+                guest.vmid
+            );
+        }
+
+        #[derive(Serialize)]
+        struct CloneRequest<'a> {
+            newid: u32,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            snapname: Option<&'a str>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            name: Option<&'a str>,
+            /// Containers take a hostname where VMs take a name.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            hostname: Option<&'a str>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            full: Option<u8>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            target: Option<&'a str>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            storage: Option<&'a str>,
+        }
+
+        let name = options.name.as_deref();
+        let request = CloneRequest {
+            newid,
+            snapname: options.snapname.as_deref(),
+            name: name.filter(|_| !guest.is_container()),
+            hostname: name.filter(|_| guest.is_container()),
+            full: match (options.full, options.linked) {
+                (true, _) => Some(1),
+                (_, true) => Some(0),
+                _ => None,
+            },
+            target: options.target.as_deref(),
+            storage: options.storage.as_deref(),
+        };
+
+        let task_id: String = self
+            .client
+            .post(&format!("{}/clone", guest.path()), &request)
+            .await?;
+
+        self.output.print_raw(&task_id)?;
+        let source = match &options.snapname {
+            Some(snapname) => format!("VM {} at snapshot '{}'", guest.vmid, snapname),
+            None if template && !options.full => format!("template {} (linked)", guest.vmid),
+            None => format!("VM {}", guest.vmid),
+        };
+        self.output.message(&format!(
+            "Cloning {} to {} on node {}...",
+            source, newid, guest.node
+        ));
+        self.tasks.follow(&guest.node, &task_id).await?;
+
+        Ok(Guest {
+            node: options.target.clone().unwrap_or_else(|| guest.node.clone()),
+            vmid: newid,
+            name: options.name.clone(),
+            guest_type: guest.guest_type.clone(),
+            ..Default::default()
+        })
+    }
+
+    /// Creates a VM from a cloud-init template: clones it, writes the
+    /// cloud-init settings, grows the disk and starts the VM.
+    pub async fn provision(&self, options: &ProvisionOptions) -> Result<()> {
+        // Catch bad input before the clone is created.
+        let cloud_init = CloudInit::from_options(options)?;
+        if let Some(size) = &options.resize {
+            provision::validate_resize(size)?;
+        }
+
+        let template = self.cluster.find_vm(&options.template).await?;
+        if template.is_container() {
+            anyhow::bail!("provision needs a VM template with cloud-init, not a container");
+        }
+
+        let newid = match options.newid {
+            Some(newid) => newid,
+            None => {
+                let next: String = self.client.get("/cluster/nextid").await?;
+                next.parse()
+                    .with_context(|| format!("Unexpected next free VMID '{}'", next))?
+            }
+        };
+
+        let clone_options = CloneOptions {
+            name: Some(options.name.clone()),
+            full: options.full,
+            target: options.target.clone(),
+            storage: options.storage.clone(),
+            ..Default::default()
+        };
+        let vm = self.clone_guest(&template, newid, &clone_options).await?;
+
+        if !cloud_init.is_empty() {
+            let _: serde_json::Value = self
+                .client
+                .put(&format!("{}/config", vm.path()), &cloud_init)
+                .await?;
+            self.output
+                .message(&format!("Applied cloud-init settings to VM {}", newid));
+        }
+
+        if let Some(size) = &options.resize {
+            self.resize_disk(&vm, &options.disk, size).await?;
+        }
+
+        if options.start {
+            let task_id: String = self
+                .client
+                .post(&format!("{}/status/start", vm.path()), &())
+                .await?;
+            self.output
+                .message(&format!("Starting VM {} on node {}...", newid, vm.node));
+            self.tasks.wait(&vm.node, &task_id).await?;
+        }
+
+        self.output.message(&format!(
+            "{} VM {} ({}) provisioned on node {}",
+            self.output.paint(Color::Green, "✓"),
+            newid,
+            options.name,
+            vm.node
+        ));
+        Ok(())
+    }
+
+    pub async fn resize_disk(&self, guest: &Guest, disk: &str, size: &str) -> Result<()> {
+        #[derive(Serialize)]
+        struct ResizeRequest<'a> {
+            disk: &'a str,
+            size: &'a str,
+        }
+
+        // Recent Proxmox versions resize in a task, older ones directly.
+        let task_id: Option<String> = self
+            .client
+            .put(
+                &format!("{}/resize", guest.path()),
+                &ResizeRequest { disk, size },
+            )
+            .await?;
+        self.output.message(&format!(
+            "Resizing disk {} of VM {} to {}...",
+            disk, guest.vmid, size
+        ));
+        if let Some(task_id) = task_id {
+            self.tasks.wait(&guest.node, &task_id).await?;
+        }
+        Ok(())
+    }
+
+    /// Migrates every guest off `node`, either to `target` or spread over
+    /// the other online nodes by memory. At most `jobs` migrations run at a
+    /// time; afterwards the guests are looked up again to check that each
+    /// one arrived where it was sent.
+    pub async fn evacuate_node(
+        &self,
+        node: &str,
+        target: Option<&str>,
+        with_local_disks: bool,
+        jobs: usize,
+        assume_yes: bool,
+    ) -> Result<()> {
+        #[derive(Deserialize)]
+        struct Candidate {
+            #[serde(flatten)]
+            guest: Guest,
+            status: String,
+            #[serde(default)]
+            maxmem: Option<u64>,
+        }
+
+        let candidates: Vec<Candidate> = self.client.get("/cluster/resources?type=vm").await?;
+        let candidates: Vec<Candidate> = candidates
+            .into_iter()
+            .filter(|c| c.guest.node == node)
+            .collect();
+        if candidates.is_empty() {
+            self.output.message(&format!("No VMs on node {}", node));
+            return Ok(());
+        }
+
+        let targets = match target {
+            Some(target) if target == node => {
+                anyhow::bail!("Cannot evacuate node {} onto itself", node)
+            }
+            Some(target) => vec![target.to_string(); candidates.len()],
+            None => {
+                #[derive(Deserialize)]
+                struct NodeResource {
+                    node: String,
+                    #[serde(default)]
+                    status: String,
+                    #[serde(default)]
+                    mem: Option<u64>,
+                    #[serde(default)]
+                    maxmem: Option<u64>,
+                }
+
+                let nodes: Vec<NodeResource> =
+                    self.client.get("/cluster/resources?type=node").await?;
+                let loads: Vec<NodeLoad> = nodes
+                    .into_iter()
+                    .filter(|n| n.node != node && n.status == "online")
+                    .map(|n| NodeLoad {
+                        node: n.node,
+                        mem: n.mem.unwrap_or(0),
+                        maxmem: n.maxmem.unwrap_or(0),
+                    })
+                    .collect();
+                if loads.is_empty() {
+                    anyhow::bail!("No other online node to migrate to");
+                }
+                let sizes: Vec<u64> = candidates.iter().map(|c| c.maxmem.unwrap_or(0)).collect();
+                migrate::balance(&sizes, loads)
+            }
+        };
+
+        self.confirm(
+            assume_yes,
+            &format!(
+                "This will migrate {} VMs off node {}. Continue?",
+                candidates.len(),
+                node
+            ),
+        )?;
+
+        let mut pending: VecDeque<(&Candidate, &str)> = candidates
+            .iter()
+            .zip(targets.iter().map(String::as_str))
+            .collect();
+        let mut running: Vec<(&Candidate, &str, String)> = Vec::new();
+        let mut failed: HashSet<u32> = HashSet::new();
+        let report_failure = |vmid: u32, e: &anyhow::Error| {
+            eprintln!(
+                "{} VM {}: {:#}",
+                self.output.paint(Color::Red, "✗"),
+                vmid,
+                e
+            );
+        };
+
+        loop {
+            while running.len() < jobs
+                && let Some((candidate, target)) = pending.pop_front()
+            {
+                let guest = &candidate.guest;
+                let online = candidate.status == "running";
+                let options = MigrateOptions {
+                    online: online.then_some(1),
+                    with_local_disks: with_local_disks.then_some(1),
+                    ..Default::default()
+                };
+                let options = if guest.is_container() {
+                    options.for_container()
+                } else {
+                    options
+                };
+
+                match self.start_migration(guest, target, &options).await {
+                    Ok(task_id) => {
+                        self.output
+                            .message(&format!("Migrating VM {} to {}...", guest.vmid, target));
+                        running.push((candidate, target, task_id));
+                    }
+                    Err(e) => {
+                        report_failure(guest.vmid, &e);
+                        failed.insert(guest.vmid);
+                    }
+                }
+            }
+            if running.is_empty() {
+                break;
+            }
+
+            let mut still_running = Vec::new();
+            for (candidate, target, task_id) in running {
+                let guest = &candidate.guest;
+                match self.tasks.state(&guest.node, &task_id).await {
+                    Ok(TaskState::Running) => still_running.push((candidate, target, task_id)),
+                    Ok(TaskState::Finished(Ok(()))) => self.output.message(&format!(
+                        "{} VM {} migrated to {}",
+                        self.output.paint(Color::Green, "✓"),
+                        guest.vmid,
+                        target
+                    )),
+                    Ok(TaskState::Finished(Err(e))) | Err(e) => {
+                        report_failure(guest.vmid, &e);
+                        failed.insert(guest.vmid);
+                    }
+                }
+            }
+            running = still_running;
+            if !running.is_empty() {
+                sleep(Duration::from_secs(2)).await;
+            }
+        }
+
+        if !self.client.is_dry_run() {
+            let guests: Vec<Guest> = self.client.get("/cluster/resources?type=vm").await?;
+            for (candidate, target) in candidates.iter().zip(&targets) {
+                let vmid = candidate.guest.vmid;
+                if failed.contains(&vmid) {
+                    continue;
+                }
+                match guests.iter().find(|g| g.vmid == vmid) {
+                    Some(guest) if guest.node == *target => {}
+                    found => {
+                        let location = found.map_or("nowhere", |g| g.node.as_str());
+                        eprintln!(
+                            "{} VM {} is on {} instead of {}",
+                            self.output.paint(Color::Red, "✗"),
+                            vmid,
+                            location,
+                            target
+                        );
+                        failed.insert(vmid);
+                    }
+                }
+            }
+        }
+
+        if !failed.is_empty() {
+            anyhow::bail!(
+                "{} of {} VMs were not evacuated from node {}",
+                failed.len(),
+                candidates.len(),
+                node
+            );
+        }
+        self.output.message(&format!(
+            "{} Node {} evacuated ({} VMs)",
+            self.output.paint(Color::Green, "✓"),
+            node,
+            candidates.len()
+        ));
+        Ok(())
+    }
+
+    /// Asks Proxmox to migrate `guest` and returns the task ID.
+    async fn start_migration(
+        &self,
+        guest: &Guest,
+        target: &str,
+        options: &MigrateOptions,
+    ) -> Result<String> {
+        #[derive(Serialize)]
+        struct MigrateRequest<'a> {
+            target: &'a str,
+            #[serde(flatten)]
+            options: &'a MigrateOptions,
+        }
+
+        let task_id = self
+            .client
+            .post(
+                &format!("{}/migrate", guest.path()),
+                &MigrateRequest { target, options },
+            )
+            .await?;
+        Ok(task_id)
+    }
+
+    /// Asks before a destructive operation unless `--yes` was given. Dry runs
+    /// change nothing and never ask.
+    fn confirm(&self, assume_yes: bool, question: &str) -> Result<()> {
+        prompt::confirm_or_abort(question, assume_yes || self.client.is_dry_run())
+    }
+
+    /// Runs `action` on every guest, continuing past failures so that one
+    /// broken VM does not stop a pool-wide operation.
+    async fn run_batch<'a, F, Fut>(&self, guests: &'a [Guest], action: F) -> Result<()>
+    where
+        F: Fn(&'a Guest) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        if let [guest] = guests {
+            return action(guest).await;
+        }
+
+        let mut failed = 0;
+        for guest in guests {
+            if let Err(e) = action(guest).await {
+                failed += 1;
+                eprintln!(
+                    "{} VM {}: {:#}",
+                    self.output.paint(Color::Red, "✗"),
+                    guest.vmid,
+                    e
+                );
+            }
+        }
+
+        if failed > 0 {
+            anyhow::bail!("Operation failed on {} of {} VMs", failed, guests.len());
+        }
+        Ok(())
+    }
+
+    pub async fn list_snapshots(
+        &self,
+        selection: &Selection,
+        tree: bool,
+        disks: bool,
+        template: Option<&str>,
+    ) -> Result<()> {
+        if tree && self.output.format() != OutputFormat::Table {
+            anyhow::bail!(
+                "--tree only works with the table output; the parent field of each snapshot holds the hierarchy"
+            );
+        }
+        let guests = self.cluster.resolve(selection).await?;
+        if let [guest] = guests.as_slice() {
+            return self
+                .list_guest_snapshots(guest, tree, disks, template)
+                .await;
+        }
+
+        let mut responses = Vec::new();
+        for guest in &guests {
+            let response: serde_json::Value = self
+                .client
+                .get(&format!("{}/snapshot", guest.path()))
+                .await?;
+            responses.push((guest, response));
+        }
+
+        let raw: Vec<&serde_json::Value> = responses.iter().map(|(_, r)| r).collect();
+        if self.output.print_raw(&raw)? {
+            return Ok(());
+        }
+
+        let mut rows = Vec::new();
+        for (guest, response) in responses {
+            let snapshots: Vec<Snapshot> = serde_json::from_value(response)?;
+
+            if tree {
+                println!(
+                    "Snapshot tree for VM {} on node {}:",
+                    guest.vmid, guest.node
+                );
+                print!("{}", render_tree(&self.output, &snapshots));
+                continue;
+            }
+
+            let mut snapshots: Vec<Snapshot> = snapshots
+                .into_iter()
+                .filter(|s| s.name != "current")
+                .collect();
+            if disks {
+                self.load_disks(guest, &mut snapshots).await?;
+            }
+
+            rows.extend(snapshots.into_iter().map(|snapshot| GuestSnapshot {
+                vmid: guest.vmid,
+                node: guest.node.clone(),
+                snapshot,
+            }));
+        }
+
+        if tree && self.output.format() == OutputFormat::Table {
+            return Ok(());
+        }
+
+        if let Some(template) = template {
+            return self.output.print_template(&rows, template);
+        }
+
+        let columns = disks.then(|| with_disks_column(GuestSnapshot::default_columns()));
+        self.output.print_list_columns(
+            &format!("Snapshots for {} VMs:", guests.len()),
+            &rows,
+            columns.as_deref(),
+        )
+    }
+
+    /// Fills in the disks stored in each snapshot, one API call per snapshot.
+    async fn load_disks(&self, guest: &Guest, snapshots: &mut [Snapshot]) -> Result<()> {
+        for snapshot in snapshots {
+            let config: serde_json::Value = self
+                .client
+                .get(&format!(
+                    "{}/snapshot/{}/config",
+                    guest.path(),
+                    snapshot.name
+                ))
+                .await?;
+            snapshot.disks = Some(parse_disks(&config));
+        }
+        Ok(())
+    }
+
+    async fn list_guest_snapshots(
+        &self,
+        guest: &Guest,
+        tree: bool,
+        disks: bool,
+        template: Option<&str>,
+    ) -> Result<()> {
+        let (node, vmid) = (&guest.node, guest.vmid);
+
+        let response: serde_json::Value = self
+            .client
+            .get(&format!("{}/snapshot", guest.path()))
+            .await?;
+        if self.output.print_raw(&response)? {
+            return Ok(());
+        }
+
+        let snapshots: Vec<Snapshot> = serde_json::from_value(response)?;
+
+        if tree {
+            println!("Snapshot tree for VM {} on node {}:", vmid, node);
+            print!("{}", render_tree(&self.output, &snapshots));
+            return Ok(());
+        }
+
+        let mut snapshots: Vec<Snapshot> = snapshots
+            .into_iter()
+            .filter(|s| s.name != "current")
+            .collect();
+        if disks {
+            self.load_disks(guest, &mut snapshots).await?;
+        }
+
+        if let Some(template) = template {
+            return self.output.print_template(&snapshots, template);
+        }
+
+        let columns = disks.then(|| with_disks_column(Snapshot::default_columns()));
+        self.output.print_list_columns(
+            &format!("Snapshots for VM {} on node {}:", vmid, node),
+            &snapshots,
+            columns.as_deref(),
+        )
+    }
+
+    pub async fn rollback_snapshot(
+        &self,
+        vm_identifier: &str,
+        snapname: &str,
+        start: bool,
+        assume_yes: bool,
+    ) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let (node, vmid) = (guest.node.clone(), guest.vmid);
+
+        self.confirm(
+            assume_yes,
+            &format!(
+                "This will discard all changes to VM {} since '{}'. Continue?",
+                vmid, snapname
+            ),
+        )?;
+
+        #[derive(Serialize)]
+        struct RollbackRequest {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            start: Option<u8>,
+        }
+
+        let request = RollbackRequest {
+            start: if start { Some(1) } else { None },
+        };
+
+        let task_id: String = self
+            .client
+            .post(
+                &format!("{}/snapshot/{}/rollback", guest.path(), snapname),
+                &request,
+            )
+            .await?;
+
+        self.output.print_raw(&task_id)?;
+        self.output.message(&format!(
+            "Rolling back VM {} to snapshot '{}' on node {}...",
+            vmid, snapname, node
+        ));
+        self.tasks.wait(&node, &task_id).await?;
+
+        Ok(())
+    }
+
+    /// Verifies one snapshot, or all of them: each must have a readable
+    /// config, a known parent and all its volumes present on storage.
+    pub async fn verify_snapshots(
+        &self,
+        vm_identifier: &str,
+        snapname: Option<&str>,
+    ) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+
+        let snapshots: Vec<Snapshot> = self
+            .client
+            .get(&format!("{}/snapshot", guest.path()))
+            .await?;
+        let names: HashSet<&str> = snapshots.iter().map(|s| s.name.as_str()).collect();
+
+        let targets: Vec<&Snapshot> = match snapname {
+            Some(snapname) => {
+                let Some(snapshot) = snapshots.iter().find(|s| s.name == snapname) else {
+                    anyhow::bail!("Snapshot '{}' not found on VM {}", snapname, guest.vmid);
+                };
+                vec![snapshot]
+            }
+            None => snapshots.iter().filter(|s| s.name != "current").collect(),
+        };
+        if targets.is_empty() {
+            self.output
+                .message(&format!("VM {} has no snapshots to verify", guest.vmid));
+            return Ok(());
+        }
+
+        let mut configs = Vec::new();
+        for snapshot in &targets {
+            let config: Result<serde_json::Value, _> = self
+                .client
+                .get(&format!(
+                    "{}/snapshot/{}/config",
+                    guest.path(),
+                    snapshot.name
+                ))
+                .await;
+            configs.push(config);
+        }
+
+        // List each storage once, however many snapshots use it.
+        let storages: HashSet<String> = configs
+            .iter()
+            .flatten()
+            .flat_map(snapshot_volumes)
+            .filter_map(|(_, volume)| volume.split_once(':').map(|(s, _)| s.to_string()))
+            .collect();
+        let mut contents: HashMap<String, Result<HashSet<String>, String>> = HashMap::new();
+        for storage in storages {
+            #[derive(Deserialize)]
+            struct Content {
+                volid: String,
+            }
+
+            let content: Result<Vec<Content>, _> = self
+                .client
+                .get(&format!(
+                    "/nodes/{}/storage/{}/content?vmid={}",
+                    guest.node, storage, guest.vmid
+                ))
+                .await;
+            let volids = content
+                .map(|c| c.into_iter().map(|c| c.volid).collect())
+                .map_err(|e| e.message());
+            contents.insert(storage, volids);
+        }
+
+        let mut results = Vec::new();
+        for (snapshot, config) in targets.iter().zip(configs) {
+            let mut problems = Vec::new();
+            if let Some(parent) = &snapshot.parent
+                && !names.contains(parent.as_str())
+            {
+                problems.push(format!("parent snapshot '{}' is missing", parent));
+            }
+
+            match config {
+                Ok(config) => problems.extend(snapshot_problems(&config, |volume| {
+                    let storage = volume.split_once(':').map_or(volume, |(s, _)| s);
+                    match contents.get(storage) {
+                        Some(Ok(volids)) => Ok(volids.contains(volume)),
+                        Some(Err(e)) => Err(e.clone()),
+                        None => Err("storage not listed".to_string()),
+                    }
+                })),
+                Err(e) => problems.push(format!("configuration unreadable: {}", e.message())),
+            }
+
+            results.push(Verification {
+                snapshot: snapshot.name.clone(),
+                ok: problems.is_empty(),
+                problems,
+            });
+        }
+
+        let failed = results.iter().filter(|r| !r.ok).count();
+        self.output.print_list(
+            &format!("Snapshots of VM {} ({}):", guest.vmid, guest.node),
+            &results,
+        )?;
+        if failed > 0 {
+            anyhow::bail!(
+                "{} of {} snapshots failed verification",
+                failed,
+                results.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Prints the snapshot the VM's current state is based on, followed by
+    /// its ancestors back to the root.
+    pub async fn show_lineage(&self, vm_identifier: &str) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let snapshots: Vec<Snapshot> = self
+            .client
+            .get(&format!("{}/snapshot", guest.path()))
+            .await?;
+
+        if self.output.print_raw(&snapshots)? {
+            return Ok(());
+        }
+
+        let chain: Vec<Snapshot> = lineage(&snapshots).into_iter().cloned().collect();
+        let Some(base) = chain.first() else {
+            self.output.message(&format!(
+                "The current state of VM {} is not based on any snapshot",
+                guest.vmid
+            ));
+            return Ok(());
+        };
+
+        self.output.print_list(
+            &format!(
+                "VM {} is based on '{}' ({} snapshots back to the root):",
+                guest.vmid,
+                base.name,
+                chain.len()
+            ),
+            &chain,
+        )
+    }
+
+    /// Replaces the description of an existing snapshot.
+    pub async fn edit_description(
+        &self,
+        vm_identifier: &str,
+        snapname: &str,
+        description: &str,
+    ) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+
+        #[derive(Serialize)]
+        struct ConfigRequest<'a> {
+            description: &'a str,
+        }
+
+        self.client
+            .put::<_, serde_json::Value>(
+                &format!("{}/snapshot/{}/config", guest.path(), snapname),
+                &ConfigRequest { description },
+            )
+            .await?;
+
+        self.output.message(&format!(
+            "Updated the description of snapshot '{}' of VM {}",
+            snapname, guest.vmid
+        ));
+        Ok(())
+    }
+
+    /// Moves a disk of a guest to another storage, printing the task log as
+    /// it goes. The source copy is kept as an unused disk unless
+    /// `delete_source` is set.
+    pub async fn move_disk(
+        &self,
+        vm_identifier: &str,
+        disk: &str,
+        storage: &str,
+        format: Option<&str>,
+        delete_source: bool,
+    ) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+
+        #[derive(Serialize)]
+        struct MoveRequest<'a> {
+            /// Named `volume` for containers.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            disk: Option<&'a str>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            volume: Option<&'a str>,
+            storage: &'a str,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            format: Option<&'a str>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            delete: Option<u8>,
+        }
+
+        let (endpoint, request) = if guest.is_container() {
+            if format.is_some() {
+                anyhow::bail!("--format only applies to VM disks, not container volumes");
+            }
+            let request = MoveRequest {
+                disk: None,
+                volume: Some(disk),
+                storage,
+                format: None,
+                delete: delete_source.then_some(1),
+            };
+            (format!("{}/move_volume", guest.path()), request)
+        } else {
+            let request = MoveRequest {
+                disk: Some(disk),
+                volume: None,
+                storage,
+                format,
+                delete: delete_source.then_some(1),
+            };
+            (format!("{}/move_disk", guest.path()), request)
+        };
+
+        let task_id: String = self.client.post(&endpoint, &request).await?;
+        self.output.print_raw(&task_id)?;
+        self.output.message(&format!(
+            "Moving disk {} of VM {} to storage {}...",
+            disk, guest.vmid, storage
+        ));
+        self.tasks.follow(&guest.node, &task_id).await
+    }
+
+    /// Allocates a new disk of `size` bytes on `storage` and attaches it to
+    /// the first free slot of `bus`. Containers get a mount point at
+    /// `mount_path` instead.
+    pub async fn attach_disk(
+        &self,
+        vm_identifier: &str,
+        storage: &str,
+        size: u64,
+        bus: DiskBus,
+        mount_path: Option<&str>,
+        backup: bool,
+    ) -> Result<()> {
+        const GIB: u64 = 1 << 30;
+        if size == 0 || !size.is_multiple_of(GIB) {
+            anyhow::bail!("New disks are allocated in whole GiB, e.g. 50G");
+        }
+
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let endpoint = format!("{}/config", guest.path());
+        let config: serde_json::Value = self.client.get(&endpoint).await?;
+
+        // `STORAGE:SIZE` asks Proxmox to allocate a new volume of SIZE GiB.
+        let mut value = format!("{}:{}", storage, size / GIB);
+        let slot = if guest.is_container() {
+            let Some(path) = mount_path else {
+                anyhow::bail!("VM {} is a container; give --mount PATH", guest.vmid);
+            };
+            let Some(slot) = vmconfig::free_mount_point(&config) else {
+                anyhow::bail!("VM {} has no free mount point", guest.vmid);
+            };
+            // Unlike VM disks, mount points are left out of backups by default.
+            value.push_str(&format!(",mp={}", path));
+            if backup {
+                value.push_str(",backup=1");
+            }
+            slot
+        } else {
+            if mount_path.is_some() {
+                anyhow::bail!("--mount only applies to containers");
+            }
+            let Some(slot) = bus.free_slot(&config) else {
+                anyhow::bail!("VM {} has no free {:?} slot", guest.vmid, bus);
+            };
+            if !backup {
+                value.push_str(",backup=0");
+            }
+            slot
+        };
+        let request = serde_json::json!({ slot.as_str(): value });
+        let _: serde_json::Value = self.client.put(&endpoint, &request).await?;
+
+        self.output.message(&format!(
+            "Attached a {} disk on {} to VM {} as {}",
+            self.output.format_bytes(size),
+            storage,
+            guest.vmid,
+            slot
+        ));
+        Ok(())
+    }
+
+    /// Detaches a disk. Proxmox keeps the volume as an unused disk, which is
+    /// removed as well when `destroy` is set.
+    pub async fn detach_disk(&self, vm_identifier: &str, disk: &str, destroy: bool) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let endpoint = format!("{}/config", guest.path());
+        let config: serde_json::Value = self.client.get(&endpoint).await?;
+        let Some(volume) = parse_disks(&config)
+            .into_iter()
+            .find(|d| d.name == disk)
+            .map(|d| d.volume)
+        else {
+            anyhow::bail!("VM {} has no disk {}", guest.vmid, disk);
+        };
+
+        let _: serde_json::Value = self
+            .client
+            .put(&endpoint, &serde_json::json!({ "delete": disk }))
+            .await?;
+        self.output.message(&format!(
+            "Detached {} ({}) from VM {}",
+            disk, volume, guest.vmid
+        ));
+        if !destroy || self.client.is_dry_run() {
+            return Ok(());
+        }
+
+        let config: serde_json::Value = self.client.get(&endpoint).await?;
+        let unused = config.as_object().and_then(|options| {
+            options.iter().find_map(|(key, value)| {
+                (key.starts_with("unused") && value.as_str() == Some(volume.as_str()))
+                    .then(|| key.clone())
+            })
+        });
+        let Some(unused) = unused else {
+            anyhow::bail!(
+                "Volume {} is no longer listed as unused on VM {}; not destroying it",
+                volume,
+                guest.vmid
+            );
+        };
+
+        let _: serde_json::Value = self
+            .client
+            .put(&endpoint, &serde_json::json!({ "delete": unused }))
+            .await?;
+        self.output.message(&format!("Destroyed volume {}", volume));
+        Ok(())
+    }
+
+    /// Shows the notes of a guest, or changes them as `edit` says.
+    pub async fn notes(&self, vm_identifier: &str, edit: NotesEdit) -> Result<()> {
+        #[derive(Deserialize)]
+        struct NotesConfig {
+            #[serde(default)]
+            description: String,
+        }
+
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let endpoint = format!("{}/config", guest.path());
+        let current = self.client.get::<NotesConfig>(&endpoint).await?.description;
+
+        let notes = match edit {
+            NotesEdit::Show => {
+                if !self.output.print_raw(&current)? {
+                    println!("{}", current);
+                }
+                return Ok(());
+            }
+            NotesEdit::Set(text) => text,
+            NotesEdit::Append(text) => vmconfig::append_note(&current, &text),
+            NotesEdit::Editor => editor::edit(&current, &format!("notes-{}", guest.vmid))?,
+        };
+        if notes.trim_end() == current.trim_end() {
+            self.output
+                .message(&format!("Notes of VM {} unchanged", guest.vmid));
+            return Ok(());
+        }
+
+        let request = if notes.trim().is_empty() {
+            serde_json::json!({ "delete": "description" })
+        } else {
+            serde_json::json!({ "description": notes })
+        };
+        let _: serde_json::Value = self.client.put(&endpoint, &request).await?;
+        self.output
+            .message(&format!("Updated the notes of VM {}", guest.vmid));
+        Ok(())
+    }
+
+    /// Adds and removes tags of a guest, then shows the resulting tags.
+    pub async fn edit_tags(
+        &self,
+        vm_identifier: &str,
+        add: &[String],
+        remove: &[String],
+    ) -> Result<()> {
+        #[derive(Deserialize)]
+        struct TagsConfig {
+            tags: Option<String>,
+        }
+
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let endpoint = format!("{}/config", guest.path());
+        let config: TagsConfig = self.client.get(&endpoint).await?;
+
+        let tags = vmconfig::edit_tags(config.tags.as_deref(), add, remove)?;
+        if tags == config.tags.unwrap_or_default() {
+            self.output
+                .message(&format!("Tags of VM {} unchanged", guest.vmid));
+            return Ok(());
+        }
+
+        let request = if tags.is_empty() {
+            serde_json::json!({ "delete": "tags" })
+        } else {
+            serde_json::json!({ "tags": tags })
+        };
+        let _: serde_json::Value = self.client.put(&endpoint, &request).await?;
+        self.output.message(&format!(
+            "Tags of VM {}: {}",
+            guest.vmid,
+            if tags.is_empty() { "(none)" } else { &tags }
+        ));
+        Ok(())
+    }
+
+    /// Lists the tags of a guest, or every tag in the cluster with the
+    /// number of guests carrying it.
+    pub async fn list_tags(&self, vm_identifier: Option<&str>) -> Result<()> {
+        let guests = match vm_identifier {
+            Some(vm) => vec![self.cluster.find_vm(vm).await?],
+            None => self.cluster.vms().await?,
+        };
+
+        let mut counts: Vec<TagCount> = Vec::new();
+        for tag in guests.iter().flat_map(|g| g.tags()) {
+            match counts.iter_mut().find(|c| c.tag.eq_ignore_ascii_case(tag)) {
+                Some(count) => count.vms += 1,
+                None => counts.push(TagCount {
+                    tag: tag.to_string(),
+                    vms: 1,
+                }),
+            }
+        }
+        counts.sort_by_key(|c| c.tag.to_ascii_lowercase());
+
+        let title = match &guests[..] {
+            [guest] if vm_identifier.is_some() => format!("Tags of VM {}:", guest.vmid),
+            _ => "Tags:".to_string(),
+        };
+        self.output.print_list(&title, &counts)
+    }
+
+    /// Shows the configuration of a guest, or the options matching `filter`.
+    pub async fn show_config(&self, vm_identifier: &str, filter: Option<&str>) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let config: serde_json::Value =
+            self.client.get(&format!("{}/config", guest.path())).await?;
+        if self.output.print_raw(&config)? {
+            return Ok(());
+        }
+
+        let entries = vmconfig::entries(&config, filter)?;
+        if let Some(filter) = filter
+            && entries.is_empty()
+        {
+            anyhow::bail!("VM {} has no option matching '{}'", guest.vmid, filter);
+        }
+        self.output
+            .print_list(&format!("Configuration of VM {}:", guest.vmid), &entries)
+    }
+
+    /// Lists configuration changes that only take effect at the next reboot.
+    /// A snapshot taken before then records the configured values, not the
+    /// ones the guest is running with.
+    pub async fn show_pending(&self, vm_identifier: &str) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let response: serde_json::Value = self
+            .client
+            .get(&format!("{}/pending", guest.path()))
+            .await?;
+        if self.output.print_raw(&response)? {
+            return Ok(());
+        }
+
+        let changes = vmconfig::pending_changes(serde_json::from_value(response)?);
+        self.output
+            .print_list(&format!("Pending changes of VM {}:", guest.vmid), &changes)?;
+        if !changes.is_empty() {
+            self.output.message(&format!(
+                "Reboot VM {} to apply {} pending changes",
+                guest.vmid,
+                changes.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Sets and removes configuration options of a guest, then lists what
+    /// changed, or in a dry run what would change.
+    pub async fn set_config(
+        &self,
+        vm_identifier: &str,
+        settings: &[(String, String)],
+        delete: &[String],
+    ) -> Result<()> {
+        if settings.is_empty() && delete.is_empty() {
+            anyhow::bail!("Nothing to change; give key=value pairs or --delete");
+        }
+
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let endpoint = format!("{}/config", guest.path());
+        let before: serde_json::Value = self.client.get(&endpoint).await?;
+
+        let mut request: serde_json::Map<String, serde_json::Value> = settings
+            .iter()
+            .map(|(key, value)| (key.clone(), serde_json::Value::String(value.clone())))
+            .collect();
+        if !delete.is_empty() {
+            request.insert("delete".to_string(), delete.join(",").into());
+        }
+        let _: serde_json::Value = self.client.put(&endpoint, &request).await?;
+
+        let mut after = before.clone();
+        if let Some(options) = after.as_object_mut() {
+            for key in delete {
+                options.remove(key);
+            }
+            for (key, value) in settings {
+                options.insert(key.clone(), value.clone().into());
+            }
+        }
+        let changes = diff_configs(&before, &after);
+        let title = if self.client.is_dry_run() {
+            format!("Options that would change on VM {} (preview):", guest.vmid)
+        } else {
+            format!("Changed options of VM {}:", guest.vmid)
+        };
+        self.output.print_list(&title, &changes)
+    }
+
+    /// Prints a section of the cloud-init configuration Proxmox generates
+    /// for a VM.
+    pub async fn cloudinit_dump(
+        &self,
+        vm_identifier: &str,
+        section: CloudInitSection,
+    ) -> Result<()> {
+        let guest = self.cloudinit_vm(vm_identifier).await?;
+        let dump: String = self
+            .client
+            .get(&format!(
+                "{}/cloudinit/dump?type={}",
+                guest.path(),
+                section.as_str()
+            ))
+            .await?;
+        print!("{}", dump);
+        if !dump.ends_with('\n') {
+            println!();
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the cloud-init drive of a VM from its configuration and
+    /// lists the settings that changed since the drive was last written.
+    pub async fn cloudinit_regenerate(&self, vm_identifier: &str) -> Result<()> {
+        let guest = self.cloudinit_vm(vm_identifier).await?;
+        let endpoint = format!("{}/cloudinit", guest.path());
+        let pending: Vec<PendingEntry> = self.client.get(&endpoint).await?;
+        let changes = vmconfig::pending_changes(pending);
+
+        let _: serde_json::Value = self.client.put(&endpoint, &()).await?;
+        if changes.is_empty() {
+            self.output.message(&format!(
+                "Regenerated the cloud-init drive of VM {}, which was up to date",
+                guest.vmid
+            ));
+            return Ok(());
+        }
+        self.output.print_list(
+            &format!("Cloud-init changes written to VM {}:", guest.vmid),
+            &changes,
+        )?;
+        self.output
+            .message("The guest applies them when cloud-init next runs, usually at boot");
+        Ok(())
+    }
+
+    async fn cloudinit_vm(&self, vm_identifier: &str) -> Result<Guest> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        if guest.is_container() {
+            anyhow::bail!(
+                "VM {} is a container, which has no cloud-init drive",
+                guest.vmid
+            );
+        }
+        Ok(guest)
+    }
+
+    /// Changes the CPU and memory of a guest. A running VM applies what its
+    /// hotplug settings allow at once and the rest at its next reboot, or
+    /// with `live` nothing unless all of it can be applied at once.
+    pub async fn set_resources(
+        &self,
+        vm_identifier: &str,
+        resources: &ResourceOptions,
+        live: bool,
+    ) -> Result<()> {
+        if resources.keys().is_empty() {
+            anyhow::bail!("Nothing to change; give --cores, --vcpus or --memory");
+        }
+
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        if guest.is_container() && resources.vcpus.is_some() {
+            anyhow::bail!(
+                "VM {} is a container, which has no vcpus setting; use --cores",
+                guest.vmid
+            );
+        }
+
+        let endpoint = format!("{}/config", guest.path());
+        let config: serde_json::Value = self.client.get(&endpoint).await?;
+        let status: serde_json::Value = self
+            .client
+            .get(&format!("{}/status/current", guest.path()))
+            .await?;
+        let running = status.get("status").and_then(|v| v.as_str()) == Some("running");
+
+        // Containers take CPU and memory changes while running.
+        let cold = if running && !guest.is_container() {
+            resources.cold_keys(&config)
+        } else {
+            Vec::new()
+        };
+        if live && !cold.is_empty() {
+            let hotplug = vmconfig::hotplug(&config);
+            anyhow::bail!(
+                "VM {} cannot change {} while running (hotplug: {}); \
+                 drop --live to apply it at the next reboot",
+                guest.vmid,
+                cold.join(", "),
+                if hotplug.is_empty() {
+                    "none".to_string()
+                } else {
+                    hotplug.join(",")
+                }
+            );
+        }
+
+        let _: serde_json::Value = self.client.put(&endpoint, resources).await?;
+        self.output.message(&format!(
+            "Set {} of VM {}",
+            resources.keys().join(", "),
+            guest.vmid
+        ));
+        if !running || self.client.is_dry_run() {
+            return Ok(());
+        }
+
+        // What is left pending is what actually awaits a reboot.
+        let pending: Vec<PendingEntry> = self
+            .client
+            .get(&format!("{}/pending", guest.path()))
+            .await?;
+        let waiting: Vec<String> = vmconfig::pending_changes(pending)
+            .into_iter()
+            .map(|change| change.key)
+            .filter(|key| resources.keys().contains(&key.as_str()))
+            .collect();
+        if !waiting.is_empty() {
+            self.output.warn(&format!(
+                "VM {} must be rebooted to apply {}",
+                guest.vmid,
+                waiting.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    /// Shows how the configuration stored in `from` differs from the one in
+    /// `to`, or from the current configuration when `to` is omitted.
+    pub async fn diff_snapshots(
+        &self,
+        vm_identifier: &str,
+        from: &str,
+        to: Option<&str>,
+    ) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+
+        let old: serde_json::Value = self
+            .client
+            .get(&format!("{}/snapshot/{}/config", guest.path(), from))
+            .await?;
+        let new: serde_json::Value = match to {
+            Some(to) => {
+                self.client
+                    .get(&format!("{}/snapshot/{}/config", guest.path(), to))
+                    .await?
+            }
+            None => self.client.get(&format!("{}/config", guest.path())).await?,
+        };
+        let to = to.unwrap_or("current");
+
+        let raw = serde_json::json!({
+            "from": { "name": from, "config": old },
+            "to": { "name": to, "config": new },
+        });
+        if self.output.print_raw(&raw)? {
+            return Ok(());
+        }
+
+        let changes = diff_configs(&old, &new);
+
+        if self.output.format() != OutputFormat::Table {
+            return self.output.print_list("", &changes);
+        }
+
+        if changes.is_empty() {
+            self.output.message(&format!(
+                "No configuration changes between '{}' and '{}'",
+                from, to
+            ));
+            return Ok(());
+        }
+
+        println!("--- {}", from);
+        println!("+++ {}", to);
+        for change in &changes {
+            if let Some(old) = &change.old {
+                let line = format!("-{}: {}", change.key, old);
+                println!("{}", self.output.paint(Color::Red, &line));
+            }
+            if let Some(new) = &change.new {
+                let line = format!("+{}: {}", change.key, new);
+                println!("{}", self.output.paint(Color::Green, &line));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn show_vm_info(&self, vm_identifier: &str) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let (node, vmid) = (guest.node.clone(), guest.vmid);
+
+        let info: serde_json::Value = self
+            .client
+            .get(&format!("{}/status/current", guest.path()))
+            .await?;
+        if self.output.print_raw(&info)? {
+            return Ok(());
+        }
+
+        let config: serde_json::Value =
+            self.client.get(&format!("{}/config", guest.path())).await?;
+
+        // Stopped guests and VMs without a guest agent have no addresses to
+        // report, which is no reason to fail.
+        let addresses = match info.get("status").and_then(|v| v.as_str()) {
+            Some("running") => self.guest_addresses(&guest).await.unwrap_or_default(),
+            _ => Vec::new(),
+        };
+
+        let vm_info = VmInfo {
+            node,
+            vmid,
+            name: info.get("name").and_then(|v| v.as_str()).map(String::from),
+            status: info
+                .get("status")
+                .and_then(|v| v.as_str())
+                .map(String::from),
+            cpu: info.get("cpu").and_then(|v| v.as_f64()),
+            mem: info.get("mem").and_then(|v| v.as_u64()),
+            maxmem: info.get("maxmem").and_then(|v| v.as_u64()),
+            disks: parse_disks(&config),
+            addresses: addresses
+                .iter()
+                .filter(|a| !a.is_loopback())
+                .map(|a| a.address.clone())
+                .collect(),
+        };
+
+        self.output.print_record("VM Information:", &vm_info)
+    }
+
+    /// Lists the IP addresses of a guest, without loopback ones unless `all`
+    /// is set.
+    pub async fn show_addresses(&self, vm_identifier: &str, all: bool) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let addresses = self.guest_addresses(&guest).await.with_context(|| {
+            format!(
+                "Could not read the addresses of VM {}; is it running{}?",
+                guest.vmid,
+                if guest.is_container() {
+                    ""
+                } else {
+                    " with the guest agent"
+                }
+            )
+        })?;
+
+        let addresses: Vec<InterfaceAddress> = addresses
+            .into_iter()
+            .filter(|a| all || !a.is_loopback())
+            .collect();
+        self.output
+            .print_list(&format!("Addresses of VM {}:", guest.vmid), &addresses)
+    }
+
+    /// Asks the guest agent, or LXC for containers, for the guest's
+    /// addresses.
+    async fn guest_addresses(&self, guest: &Guest) -> Result<Vec<InterfaceAddress>> {
+        if guest.is_container() {
+            let interfaces = self
+                .client
+                .get(&format!("{}/interfaces", guest.path()))
+                .await?;
+            return Ok(agent::container_addresses(interfaces));
+        }
+        let interfaces = self
+            .client
+            .get(&format!("{}/agent/network-get-interfaces", guest.path()))
+            .await?;
+        Ok(agent::vm_addresses(interfaces))
+    }
+
+    /// Runs a command in a VM through the guest agent and prints its output.
+    /// Returns the command's exit code, as the caller exits with it.
+    pub async fn agent_exec(
+        &self,
+        vm_identifier: &str,
+        command: &[String],
+        timeout: Option<u64>,
+    ) -> Result<i32> {
+        let guest = self.agent_vm(vm_identifier).await?;
+
+        // Each word of the command is sent as its own `command` field.
+        let request: Vec<(&str, &str)> = command
+            .iter()
+            .map(|arg| ("command", arg.as_str()))
+            .collect();
+        let started: ExecStarted = self
             .client
-            .post(&format!("/nodes/{}/qemu/{}/snapshot", node, vmid), &request)
+            .post(&format!("{}/agent/exec", guest.path()), &request)
             .await?;
 
-        println!(
-            "Creating snapshot '{}' on node {} for VM {}...",
-            snapname, node, vmid
-        );
-        self.wait_for_task(&node, &task_id).await?;
+        let started_at = std::time::Instant::now();
+        let status = loop {
+            let status: ExecStatus = self
+                .client
+                .get(&format!(
+                    "{}/agent/exec-status?pid={}",
+                    guest.path(),
+                    started.pid
+                ))
+                .await?;
+            if status.exited {
+                break status;
+            }
+            if let Some(timeout) = timeout
+                && started_at.elapsed().as_secs() >= timeout
+            {
+                anyhow::bail!(
+                    "Command still running in VM {} after {} (pid {})",
+                    guest.vmid,
+                    format_duration(timeout),
+                    started.pid
+                );
+            }
+            sleep(Duration::from_secs(1)).await;
+        };
 
-        Ok(())
+        let result = ExecResult::from(status);
+        if !self.output.print_document(&result)? {
+            print!("{}", result.stdout);
+            eprint!("{}", result.stderr);
+        }
+        if result.truncated {
+            self.output
+                .warn("The guest agent truncated the output of the command");
+        }
+        Ok(result.exitcode)
     }
 
-    pub async fn delete_snapshot(&self, vm_identifier: &str, snapname: &str) -> Result<()> {
-        let (node, vmid) = self.cluster.find_vm_node(vm_identifier).await?;
-
-        let task_id = self
-            .client
-            .delete(&format!(
-                "/nodes/{}/qemu/{}/snapshot/{}",
-                node, vmid, snapname
-            ))
-            .await?;
+    /// Polls a guest until it reaches `state`, failing once `timeout`
+    /// seconds have passed.
+    pub async fn wait_for_status(
+        &self,
+        vm_identifier: &str,
+        state: GuestState,
+        timeout: Option<u64>,
+    ) -> Result<()> {
+        #[derive(Deserialize)]
+        struct CurrentStatus {
+            status: String,
+            #[serde(default)]
+            qmpstatus: Option<String>,
+        }
 
-        println!(
-            "Deleting snapshot '{}' on node {} for VM {}...",
-            snapname, node, vmid
-        );
-        self.wait_for_task(&node, &task_id).await?;
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let started_at = std::time::Instant::now();
+        let mut waiting = false;
+        loop {
+            let current: CurrentStatus = self
+                .client
+                .get(&format!("{}/status/current", guest.path()))
+                .await?;
+            if state.matches(&current.status, current.qmpstatus.as_deref()) {
+                break;
+            }
+            if let Some(timeout) = timeout
+                && started_at.elapsed().as_secs() >= timeout
+            {
+                anyhow::bail!(
+                    "VM {} is still {} after {}",
+                    guest.vmid,
+                    current.qmpstatus.unwrap_or(current.status),
+                    format_duration(timeout)
+                );
+            }
+            if !waiting {
+                self.output.message(&format!(
+                    "Waiting for VM {} to be {}...",
+                    guest.vmid,
+                    state.as_str()
+                ));
+                waiting = true;
+            }
+            sleep(Duration::from_secs(2)).await;
+        }
 
+        self.output
+            .message(&format!("VM {} is {}", guest.vmid, state.as_str()));
         Ok(())
     }
 
-    pub async fn list_snapshots(&self, vm_identifier: &str) -> Result<()> {
-        let (node, vmid) = self.cluster.find_vm_node(vm_identifier).await?;
-
-        #[derive(Deserialize)]
-        struct Snapshot {
-            name: String,
-            description: Option<String>,
-            snaptime: Option<i64>,
-        }
+    pub async fn check_vm_status(&self, vm_identifier: &str) -> Result<()> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let (node, vmid) = (guest.node.clone(), guest.vmid);
 
-        let snapshots: Vec<Snapshot> = self
+        let status: serde_json::Value = self
             .client
-            .get(&format!("/nodes/{}/qemu/{}/snapshot", node, vmid))
+            .get(&format!("{}/status/current", guest.path()))
             .await?;
+        if self.output.print_raw(&status)? {
+            return Ok(());
+        }
 
-        println!("Snapshots for VM {} on node {}:", vmid, node);
-        for snap in snapshots.iter().filter(|s| s.name != "current") {
-            let time = snap
-                .snaptime
-                .map(|t| {
-                    chrono::DateTime::from_timestamp(t, 0)
-                        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
-                        .unwrap_or_else(|| "Unknown".to_string())
-                })
-                .unwrap_or_else(|| "Unknown".to_string());
+        let vm_status = status
+            .get("status")
+            .and_then(|v| v.as_str())
+            .unwrap_or("unknown");
+        let name = status
+            .get("name")
+            .and_then(|v| v.as_str())
+            .unwrap_or("Unknown");
 
-            println!(
-                "- {} [{}] (Created: {})",
-                snap.name,
-                snap.description.as_deref().unwrap_or("No description"),
-                time
-            );
+        println!("VM ID: {}", vmid);
+        println!("Name: {}", name);
+        println!("Node: {}", node);
+        println!("Status: {}", self.output.paint_status(vm_status));
+
+        if vm_status == "running"
+            && let Some(uptime) = status.get("uptime").and_then(|v| v.as_u64())
+        {
+            println!("Uptime: {}", format_duration(uptime));
+        }
+
+        // The agent decides which snapshot modes are safe: without it
+        // there is no file system freeze and RAM-state snapshots tend to hang.
+        if !guest.is_container() {
+            let (agent, fsfreeze) = self.agent_health(&guest, vm_status == "running").await?;
+            println!("Guest agent: {}", agent);
+            if let Some(fsfreeze) = fsfreeze {
+                println!(
+                    "FS freeze: {}",
+                    if fsfreeze {
+                        self.output.paint(Color::Green, "supported")
+                    } else {
+                        self.output.paint(Color::Yellow, "not supported")
+                    }
+                );
+            }
         }
 
         Ok(())
     }
 
-    pub async fn rollback_snapshot(&self, vm_identifier: &str, snapname: &str) -> Result<()> {
-        let (node, vmid) = self.cluster.find_vm_node(vm_identifier).await?;
-
-        let task_id: String = self
+    /// Reads a file inside a VM through the guest agent, writing it to
+    /// `output` or printing it.
+    pub async fn agent_file_read(
+        &self,
+        vm_identifier: &str,
+        file: &str,
+        output: Option<&Path>,
+    ) -> Result<()> {
+        let guest = self.agent_vm(vm_identifier).await?;
+        let read: FileContent = self
             .client
-            .post(
-                &format!(
-                    "/nodes/{}/qemu/{}/snapshot/{}/rollback",
-                    node, vmid, snapname
-                ),
-                &(),
-            )
+            .get(&format!(
+                "{}/agent/file-read?file={}",
+                guest.path(),
+                url_encode(file)
+            ))
             .await?;
+        if read.truncated {
+            self.output
+                .warn(&format!("{} is too large and was truncated", file));
+        }
 
-        println!(
-            "Rolling back VM {} to snapshot '{}' on node {}...",
-            vmid, snapname, node
-        );
-        self.wait_for_task(&node, &task_id).await?;
-
+        match output {
+            Some(path) => {
+                fs::write(path, &read.content)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                self.output.message(&format!(
+                    "Saved {} of VM {} to {}",
+                    file,
+                    guest.vmid,
+                    path.display()
+                ));
+            }
+            None => print!("{}", read.content),
+        }
         Ok(())
     }
 
-    pub async fn show_vm_info(&self, vm_identifier: &str) -> Result<()> {
-        let (node, vmid) = self.cluster.find_vm_node(vm_identifier).await?;
+    /// Writes `content` to a file inside a VM through the guest agent.
+    pub async fn agent_file_write(
+        &self,
+        vm_identifier: &str,
+        file: &str,
+        content: &str,
+    ) -> Result<()> {
+        // The API refuses larger contents.
+        const MAX_CONTENT: usize = 60 * 1024;
+        if content.len() > MAX_CONTENT {
+            anyhow::bail!(
+                "Files written through the guest agent are limited to 60 KiB, got {}",
+                self.output.format_bytes(content.len() as u64)
+            );
+        }
 
-        let info: serde_json::Value = self
+        #[derive(Serialize)]
+        struct FileWrite<'a> {
+            file: &'a str,
+            content: &'a str,
+        }
+
+        let guest = self.agent_vm(vm_identifier).await?;
+        let _: serde_json::Value = self
             .client
-            .get(&format!("/nodes/{}/qemu/{}/status/current", node, vmid))
+            .post(
+                &format!("{}/agent/file-write", guest.path()),
+                &FileWrite { file, content },
+            )
             .await?;
+        self.output
+            .message(&format!("Wrote {} in VM {}", file, guest.vmid));
+        Ok(())
+    }
 
-        println!("VM Information:");
-        println!("  Node: {}", node);
-        println!("  VMID: {}", vmid);
-
-        if let Some(name) = info.get("name").and_then(|v| v.as_str()) {
-            println!("  Name: {}", name);
+    /// Finds a VM for a guest agent command, refusing containers.
+    async fn agent_vm(&self, vm_identifier: &str) -> Result<Guest> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        if guest.is_container() {
+            anyhow::bail!(
+                "VM {} is a container, which has no QEMU guest agent",
+                guest.vmid
+            );
         }
+        Ok(guest)
+    }
 
-        if let Some(status) = info.get("status").and_then(|v| v.as_str()) {
-            println!("  Status: {}", status);
+    /// Describes the state of a VM's guest agent, and whether it can freeze
+    /// file systems when that is known.
+    async fn agent_health(&self, guest: &Guest, running: bool) -> Result<(String, Option<bool>)> {
+        let config: serde_json::Value =
+            self.client.get(&format!("{}/config", guest.path())).await?;
+        if !agent_enabled(&config) {
+            return Ok((self.output.paint(Color::Yellow, "not enabled"), None));
         }
-
-        if let Some(cpu) = info.get("cpu").and_then(|v| v.as_f64()) {
-            println!("  CPU Usage: {:.2}%", cpu * 100.0);
+        if !running {
+            return Ok(("enabled, VM not running".to_string(), None));
         }
 
-        if let Some(mem) = info.get("mem").and_then(|v| v.as_u64()) {
-            if let Some(maxmem) = info.get("maxmem").and_then(|v| v.as_u64()) {
-                println!(
-                    "  Memory: {} MB / {} MB ({:.1}%)",
-                    mem / 1048576,
-                    maxmem / 1048576,
-                    (mem as f64 / maxmem as f64) * 100.0
-                );
-            }
+        let ping = self
+            .client
+            .post::<_, serde_json::Value>(&format!("{}/agent/ping", guest.path()), &())
+            .await;
+        if ping.is_err() {
+            return Ok((self.output.paint(Color::Red, "not responding"), None));
         }
 
-        Ok(())
+        // Old agents may not answer `info`; they still respond to pings.
+        match self
+            .client
+            .get::<AgentInfo>(&format!("{}/agent/info", guest.path()))
+            .await
+        {
+            Ok(info) => Ok((
+                format!(
+                    "{} (version {})",
+                    self.output.paint(Color::Green, "responding"),
+                    info.result.version
+                ),
+                Some(info.result.supports_fsfreeze()),
+            )),
+            Err(_) => Ok((self.output.paint(Color::Green, "responding"), None)),
+        }
     }
 
-    pub async fn check_vm_status(&self, vm_identifier: &str) -> Result<()> {
-        let (node, vmid) = self.cluster.find_vm_node(vm_identifier).await?;
+    /// Gathers status, snapshot age and optionally guest agent reachability
+    /// and evaluates them as a Nagios plugin would.
+    pub async fn check_nagios(
+        &self,
+        vm_identifier: &str,
+        thresholds: &Thresholds,
+        check_agent: bool,
+    ) -> Result<(NagiosState, String)> {
+        let guest = self.cluster.find_vm(vm_identifier).await?;
+        let vmid = guest.vmid;
 
         let status: serde_json::Value = self
             .client
-            .get(&format!("/nodes/{}/qemu/{}/status/current", node, vmid))
+            .get(&format!("{}/status/current", guest.path()))
+            .await?;
+        let snapshots: Vec<Snapshot> = self
+            .client
+            .get(&format!("{}/snapshot", guest.path()))
             .await?;
 
+        let now = chrono::Utc::now().timestamp();
+        let snapshots: Vec<&Snapshot> = snapshots.iter().filter(|s| s.name != "current").collect();
+        let newest_snapshot_age = snapshots
+            .iter()
+            .filter_map(|s| s.snaptime)
+            .max()
+            .map(|t| (now - t).max(0) as u64);
+
         let vm_status = status
             .get("status")
             .and_then(|v| v.as_str())
-            .unwrap_or("unknown");
-        let name = status
-            .get("name")
-            .and_then(|v| v.as_str())
-            .unwrap_or("Unknown");
+            .unwrap_or("unknown")
+            .to_string();
 
-        println!("VM ID: {}", vmid);
-        println!("Name: {}", name);
-        println!("Node: {}", node);
-        println!("Status: {}", vm_status);
+        // Containers have no guest agent.
+        let agent_ok = if !check_agent || guest.is_container() {
+            None
+        } else if vm_status == "running" {
+            Some(
+                self.client
+                    .post::<_, serde_json::Value>(&format!("{}/agent/ping", guest.path()), &())
+                    .await
+                    .is_ok(),
+            )
+        } else {
+            Some(false)
+        };
+
+        let input = CheckInput {
+            vmid,
+            name: status
+                .get("name")
+                .and_then(|v| v.as_str())
+                .unwrap_or("Unknown")
+                .to_string(),
+            status: vm_status,
+            snapshot_count: snapshots.len(),
+            newest_snapshot_age,
+            agent_ok,
+        };
 
-        if vm_status == "running" {
-            if let Some(uptime) = status.get("uptime").and_then(|v| v.as_u64()) {
-                let days = uptime / 86400;
-                let hours = (uptime % 86400) / 3600;
-                let minutes = (uptime % 3600) / 60;
-                println!("Uptime: {}d {}h {}m", days, hours, minutes);
+        Ok(nagios::evaluate(&input, thresholds))
+    }
+
+    /// Reports the VMs that violate `limits` and fails when there are any.
+    /// Without a VM, pool or tag, every VM in the cluster is audited.
+    pub async fn audit(&self, selection: &Selection, limits: &AuditLimits) -> Result<()> {
+        if limits.is_empty() {
+            anyhow::bail!("No audit limits given; use --max-age and/or --max-count");
+        }
+
+        let guests = if selection.is_empty() {
+            self.cluster.vms().await?
+        } else {
+            self.cluster.resolve(selection).await?
+        };
+
+        let inventory = self.fetch_all_snapshots(&guests).await?;
+
+        let now = chrono::Utc::now().timestamp();
+        let mut findings = Vec::new();
+        for (guest, snapshots) in guests.iter().zip(&inventory) {
+            let snapshots: Vec<&Snapshot> =
+                snapshots.iter().filter(|s| s.name != "current").collect();
+            let newest_age = snapshots
+                .iter()
+                .filter_map(|s| s.snaptime)
+                .max()
+                .map(|t| (now - t).max(0) as u64);
+
+            let problems = audit::violations(snapshots.len(), newest_age, limits);
+            if !problems.is_empty() {
+                findings.push(AuditFinding {
+                    vmid: guest.vmid,
+                    name: guest.name.clone(),
+                    node: guest.node.clone(),
+                    snapshots: snapshots.len(),
+                    newest_age,
+                    problems,
+                });
             }
         }
 
+        if findings.is_empty() {
+            self.output
+                .message(&format!("All {} VMs pass the audit", guests.len()));
+            return Ok(());
+        }
+
+        self.output.print_list(
+            &format!("{} of {} VMs fail the audit:", findings.len(), guests.len()),
+            &findings,
+        )?;
+        anyhow::bail!("{} VMs fail the snapshot audit", findings.len())
+    }
+
+    /// Writes the snapshot metadata of the selected VMs, or of every VM
+    /// without a selection, as JSON to `file` or stdout.
+    pub async fn export_inventory(
+        &self,
+        selection: Option<&Selection>,
+        file: Option<&Path>,
+    ) -> Result<()> {
+        let guests = match selection {
+            Some(selection) => self.cluster.resolve(selection).await?,
+            None => self.cluster.vms().await?,
+        };
+        let snapshots = self.fetch_all_snapshots(&guests).await?;
+
+        let vms: Vec<InventoryVm> = guests
+            .iter()
+            .zip(snapshots)
+            .map(|(guest, snapshots)| {
+                let snapshots = snapshots
+                    .into_iter()
+                    .filter(|s| s.name != "current")
+                    .map(|s| InventorySnapshot {
+                        name: s.name,
+                        snaptime: s.snaptime,
+                        description: s.description,
+                        parent: s.parent,
+                        vmstate: s.vmstate,
+                    })
+                    .collect();
+                InventoryVm::new(guest, snapshots)
+            })
+            .collect();
+        let total: usize = vms.iter().map(|vm| vm.snapshots.len()).sum();
+
+        let inventory = Inventory {
+            version: inventory::VERSION,
+            exported_at: chrono::Utc::now().timestamp(),
+            vms,
+        };
+        let json = serde_json::to_string_pretty(&inventory)?;
+
+        match file {
+            Some(path) => {
+                fs::write(path, format!("{}\n", json))
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                self.output.message(&format!(
+                    "Exported {} snapshots of {} VMs to {}",
+                    total,
+                    inventory.vms.len(),
+                    path.display()
+                ));
+            }
+            None => println!("{}", json),
+        }
         Ok(())
     }
 
-    pub async fn list_vms(&self, node_filter: Option<&str>) -> Result<()> {
-        #[derive(Deserialize)]
-        struct VmResource {
-            node: String,
-            vmid: u32,
-            name: Option<String>,
-            status: String,
-            #[serde(rename = "type")]
-            _resource_type: String,
-            _cpu: Option<f64>,
-            _mem: Option<u64>,
-            _maxmem: Option<u64>,
+    /// Compares an exported inventory with the cluster and, with
+    /// `recreate_missing`, creates the snapshots that no longer exist. The
+    /// new snapshots keep the original name and description but capture the
+    /// VM as it is now.
+    pub async fn import_inventory(&self, file: &Path, recreate_missing: bool) -> Result<()> {
+        let text = fs::read_to_string(file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        let inventory: Inventory = serde_json::from_str(&text)
+            .with_context(|| format!("Invalid inventory file {}", file.display()))?;
+        if inventory.version > inventory::VERSION {
+            anyhow::bail!(
+                "Inventory format version {} is newer than this pve-tool supports",
+                inventory.version
+            );
+        }
+
+        let guests = self.cluster.vms().await?;
+        let mut results = Vec::new();
+        let mut missing = 0;
+        let mut failed = 0;
+        for vm in &inventory.vms {
+            let result = |snapshot: &str, status: &str| ImportResult {
+                vmid: vm.vmid,
+                name: vm.name.clone(),
+                snapshot: snapshot.to_string(),
+                status: status.to_string(),
+            };
+
+            let Some(guest) = vm.find(&guests) else {
+                failed += 1;
+                results.push(result("-", "VM not found in cluster"));
+                continue;
+            };
+
+            let existing: Vec<Snapshot> =
+                match self.client.get(&format!("{}/snapshot", guest.path())).await {
+                    Ok(existing) => existing,
+                    Err(e) => {
+                        failed += 1;
+                        results.push(result("-", &format!("cannot list snapshots: {}", e)));
+                        continue;
+                    }
+                };
+            let existing: HashSet<String> = existing.into_iter().map(|s| s.name).collect();
+
+            for snapshot in vm.snapshots.iter().filter(|s| existing.contains(&s.name)) {
+                results.push(result(&snapshot.name, "present"));
+            }
+
+            for snapshot in vm.missing(&existing) {
+                missing += 1;
+                if !recreate_missing {
+                    results.push(result(&snapshot.name, "missing"));
+                    continue;
+                }
+
+                let description = snapshot.description.clone().unwrap_or_default();
+                let status = match self
+                    .create_snapshot(guest, &snapshot.name, &description, false)
+                    .await
+                {
+                    Ok(()) => "recreated".to_string(),
+                    Err(e) => {
+                        failed += 1;
+                        format!("failed: {:#}", e)
+                    }
+                };
+                results.push(result(&snapshot.name, &status));
+            }
         }
 
-        let resources: Vec<VmResource> = self.client.get("/cluster/resources?type=vm").await?;
+        let exported = chrono::DateTime::from_timestamp(inventory.exported_at, 0)
+            .map(|t| self.output.format_time(t))
+            .unwrap_or_else(|| "an unknown time".to_string());
+        self.output
+            .print_list(&format!("Inventory exported at {}:", exported), &results)?;
+
+        if failed > 0 {
+            anyhow::bail!("{} VMs or snapshots could not be restored", failed);
+        }
+        if missing > 0 && !recreate_missing {
+            self.output.message(&format!(
+                "{} snapshots are missing; run with --recreate-missing to create them",
+                missing
+            ));
+        }
+        Ok(())
+    }
 
-        let filtered: Vec<_> = if let Some(node) = node_filter {
-            resources.into_iter().filter(|r| r.node == node).collect()
+    /// Summarizes the snapshots of every VM (or the selected ones) by node
+    /// and pool, listing those older than `older_than` seconds.
+    pub async fn report_snapshots(
+        &self,
+        selection: &Selection,
+        older_than: Option<u64>,
+    ) -> Result<()> {
+        let guests = if selection.is_empty() {
+            self.cluster.vms().await?
         } else {
-            resources
+            self.cluster.resolve(selection).await?
         };
 
-        if filtered.is_empty() {
-            println!("No VMs found");
+        let inventory: Vec<(Guest, Vec<(String, i64)>)> = guests
+            .iter()
+            .cloned()
+            .zip(self.fetch_all_snapshots(&guests).await?)
+            .map(|(guest, snapshots)| {
+                let snapshots = snapshots
+                    .into_iter()
+                    .filter(|s| s.name != "current")
+                    .filter_map(|s| Some((s.name, s.snaptime?)))
+                    .collect();
+                (guest, snapshots)
+            })
+            .collect();
+
+        let report = report::build(&inventory, chrono::Utc::now().timestamp(), older_than);
+        if self.output.print_document(&report)? {
             return Ok(());
         }
 
-        println!("VMs in cluster:");
-        println!(
-            "{:<8} {:<20} {:<10} {:<10}",
-            "VMID", "Name", "Node", "Status"
-        );
-        println!("{}", "-".repeat(50));
-
-        for vm in filtered {
+        if self.output.format() == OutputFormat::Table {
             println!(
-                "{:<8} {:<20} {:<10} {:<10}",
-                vm.vmid,
-                vm.name.unwrap_or_else(|| "-".to_string()),
-                vm.node,
-                vm.status
+                "{}",
+                self.output.label(&format!(
+                    "{} snapshots across {} VMs",
+                    report.snapshots, report.vms
+                ))
             );
+            for (label, entry) in [("Oldest", &report.oldest), ("Newest", &report.newest)] {
+                if let Some(entry) = entry {
+                    println!(
+                        "{}: '{}' on VM {} ({})",
+                        label,
+                        entry.snapshot,
+                        entry.vmid,
+                        self.output.format_time(
+                            chrono::DateTime::from_timestamp(entry.snaptime, 0).unwrap_or_default()
+                        )
+                    );
+                }
+            }
+            println!();
+        }
+
+        self.output.print_list("By node:", &report.by_node)?;
+        self.output.print_list("By pool:", &report.by_pool)?;
+        if let Some(older_than) = older_than {
+            self.output.print_list(
+                &format!("Snapshots older than {}:", format_duration(older_than)),
+                &report.stale,
+            )?;
         }
 
         Ok(())
     }
 
-    async fn wait_for_task(&self, node: &str, task_id: &str) -> Result<()> {
-        loop {
-            #[derive(Deserialize)]
-            struct TaskStatus {
-                status: String,
-                exitstatus: Option<String>,
-            }
+    /// Fetches the snapshots of every guest, a few requests at a time.
+    async fn fetch_all_snapshots(&self, guests: &[Guest]) -> Result<Vec<Vec<Snapshot>>> {
+        const PARALLEL_REQUESTS: usize = 8;
 
-            let status: TaskStatus = self
-                .client
-                .get(&format!("/nodes/{}/tasks/{}/status", node, task_id))
-                .await?;
+        let semaphore = Arc::new(Semaphore::new(PARALLEL_REQUESTS));
+        let mut tasks = JoinSet::new();
+        for (index, guest) in guests.iter().enumerate() {
+            let client = self.client.clone();
+            let semaphore = semaphore.clone();
+            let endpoint = format!("{}/snapshot", guest.path());
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire().await;
+                (index, client.get::<Vec<Snapshot>>(&endpoint).await)
+            });
+        }
 
-            match status.status.as_str() {
-                "stopped" => {
-                    if status.exitstatus.as_deref() == Some("OK") {
-                        println!("\n✓ Task completed successfully");
-                        return Ok(());
-                    } else {
-                        anyhow::bail!("Task failed: {:?}", status.exitstatus);
+        let mut results: Vec<Vec<Snapshot>> = guests.iter().map(|_| Vec::new()).collect();
+        while let Some(joined) = tasks.join_next().await {
+            let (index, snapshots) = joined?;
+            results[index] = snapshots?;
+        }
+        Ok(results)
+    }
+
+    /// Fills in the QEMU status of the running VMs among `vms`, as the
+    /// cluster resources list paused VMs as running. A VM whose status cannot
+    /// be read is left out with a warning instead of failing the listing.
+    async fn read_qmpstatus(&self, vms: Vec<VmResource>) -> Result<Vec<VmResource>> {
+        const PARALLEL_REQUESTS: usize = 8;
+
+        let semaphore = Arc::new(Semaphore::new(PARALLEL_REQUESTS));
+        let mut tasks = JoinSet::new();
+        for (index, vm) in vms.iter().enumerate() {
+            if vm.status != "running" || vm.guest_type != "qemu" {
+                continue;
+            }
+            let client = self.client.clone();
+            let semaphore = semaphore.clone();
+            let endpoint = format!("/nodes/{}/qemu/{}/status/current", vm.node, vm.vmid);
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire().await;
+                (index, client.get::<serde_json::Value>(&endpoint).await)
+            });
+        }
+
+        let mut vms: Vec<Option<VmResource>> = vms.into_iter().map(Some).collect();
+        while let Some(joined) = tasks.join_next().await {
+            let (index, status) = joined?;
+            match status {
+                Ok(status) => {
+                    if let Some(vm) = &mut vms[index] {
+                        vm.qmpstatus = status
+                            .get("qmpstatus")
+                            .and_then(|v| v.as_str())
+                            .map(String::from);
                     }
                 }
-                "running" => {
-                    print!(".");
-                    std::io::Write::flush(&mut std::io::stdout())?;
-                    sleep(Duration::from_secs(2)).await;
+                Err(e) => {
+                    if let Some(vm) = vms[index].take() {
+                        self.output.warn(&format!(
+                            "Cannot read the status of VM {} on node {}: {}",
+                            vm.vmid, vm.node, e
+                        ));
+                    }
                 }
-                _ => anyhow::bail!("Unknown task status: {}", status.status),
             }
         }
+        Ok(vms.into_iter().flatten().collect())
+    }
+
+    pub async fn list_vms(
+        &self,
+        filter: &VmFilter,
+        columns: Option<&[VmColumn]>,
+        sort: Option<VmColumn>,
+        reverse: bool,
+        template: Option<&str>,
+    ) -> Result<()> {
+        let response: serde_json::Value = self.client.get("/cluster/resources?type=vm").await?;
+        if self.output.print_raw(&response)? {
+            return Ok(());
+        }
+
+        let resources: Vec<VmResource> = serde_json::from_value(response)?;
+        let mut filtered: Vec<_> = resources
+            .into_iter()
+            .filter(|r| filter.matches_resource(r))
+            .collect();
+        if filter.needs_qmpstatus() {
+            filtered = self.read_qmpstatus(filtered).await?;
+        }
+        filtered.retain(|r| filter.matches_status(r));
+
+        if let Some(sort) = sort {
+            filtered.sort_by(|a, b| match sort {
+                VmColumn::Vmid => a.vmid.cmp(&b.vmid),
+                VmColumn::Name => a.name.cmp(&b.name),
+                VmColumn::Node => a.node.cmp(&b.node).then(a.vmid.cmp(&b.vmid)),
+                VmColumn::Status => a.status.cmp(&b.status).then(a.vmid.cmp(&b.vmid)),
+                VmColumn::Mem => a.mem.cmp(&b.mem),
+                VmColumn::Cpu => a.cpu.unwrap_or(0.0).total_cmp(&b.cpu.unwrap_or(0.0)),
+                VmColumn::Disk => a.maxdisk.cmp(&b.maxdisk),
+                VmColumn::Uptime => a.uptime.cmp(&b.uptime),
+            });
+        }
+        if reverse {
+            filtered.reverse();
+        }
+
+        if let Some(template) = template {
+            return self.output.print_template(&filtered, template);
+        }
+
+        let columns: Option<Vec<&str>> = columns.map(|c| c.iter().map(|c| c.key()).collect());
+        self.output
+            .print_list_columns("VMs in cluster:", &filtered, columns.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(name: &str, parent: Option<&str>) -> Snapshot {
+        Snapshot {
+            name: name.to_string(),
+            description: None,
+            snaptime: None,
+            vmstate: false,
+            parent: parent.map(String::from),
+            disks: None,
+        }
+    }
+
+    #[test]
+    fn test_render_tree() {
+        let snapshots = vec![
+            snapshot("base", None),
+            snapshot("pre-upgrade", Some("base")),
+            snapshot("experiment", Some("base")),
+            snapshot("current", Some("pre-upgrade")),
+        ];
+
+        assert_eq!(
+            render_tree(&Output::default(), &snapshots),
+            "base (Unknown)\n\
+             ├─ pre-upgrade (Unknown)\n\
+             │  └─ NOW (current state)\n\
+             └─ experiment (Unknown)\n"
+        );
+    }
+
+    #[test]
+    fn test_deletion_order() {
+        let snapshots = vec![
+            snapshot("base", None),
+            snapshot("pre-upgrade", Some("base")),
+            snapshot("post-upgrade", Some("pre-upgrade")),
+            snapshot("experiment", Some("base")),
+            snapshot("current", Some("post-upgrade")),
+        ];
+        let names: Vec<&str> = deletion_order(&snapshots)
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect();
+        assert_eq!(names[0], "post-upgrade");
+        assert_eq!(names[3], "base");
+        assert!(!names.contains(&"current"));
+    }
+
+    #[test]
+    fn test_lineage() {
+        let snapshots = vec![
+            snapshot("base", None),
+            snapshot("pre-upgrade", Some("base")),
+            snapshot("experiment", Some("base")),
+            snapshot("current", Some("pre-upgrade")),
+        ];
+        let names: Vec<&str> = lineage(&snapshots)
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["pre-upgrade", "base"]);
+
+        assert!(lineage(&[snapshot("current", None)]).is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_vmstate_flag() {
+        let snapshots: Vec<Snapshot> = serde_json::from_value(serde_json::json!([
+            {"name": "with-ram", "snaptime": 1700000000, "vmstate": 1},
+            {"name": "disk-only", "snaptime": 1700000100},
+            {"name": "current", "running": 1},
+        ]))
+        .unwrap();
+        assert!(snapshots[0].vmstate);
+        assert!(!snapshots[1].vmstate);
+        assert!(!snapshots[2].vmstate);
+    }
+
+    #[test]
+    fn test_agent_enabled() {
+        assert!(agent_enabled(&serde_json::json!({"agent": "1"})));
+        assert!(agent_enabled(&serde_json::json!({"agent": 1})));
+        assert!(agent_enabled(
+            &serde_json::json!({"agent": "enabled=1,fstrim_cloned_disks=1"})
+        ));
+        assert!(!agent_enabled(&serde_json::json!({"agent": "0"})));
+        assert!(!agent_enabled(
+            &serde_json::json!({"agent": "enabled=0,type=virtio"})
+        ));
+        assert!(!agent_enabled(&serde_json::json!({})));
+    }
+
+    #[test]
+    fn test_diff_configs() {
+        let old = serde_json::json!({
+            "memory": 4096,
+            "cores": 2,
+            "net1": "virtio=AA:BB,bridge=vmbr1",
+            "snaptime": 1700000000,
+            "digest": "abc",
+        });
+        let new = serde_json::json!({
+            "memory": 8192,
+            "cores": 2,
+            "scsi1": "local-lvm:vm-100-disk-1,size=10G",
+            "digest": "def",
+        });
+
+        let changes = diff_configs(&old, &new);
+        assert_eq!(
+            changes,
+            vec![
+                ConfigChange {
+                    key: "memory".to_string(),
+                    old: Some("4096".to_string()),
+                    new: Some("8192".to_string()),
+                },
+                ConfigChange {
+                    key: "net1".to_string(),
+                    old: Some("virtio=AA:BB,bridge=vmbr1".to_string()),
+                    new: None,
+                },
+                ConfigChange {
+                    key: "scsi1".to_string(),
+                    old: None,
+                    new: Some("local-lvm:vm-100-disk-1,size=10G".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_snapshot_problems() {
+        let config = serde_json::json!({
+            "scsi0": "local-lvm:vm-100-disk-0,size=32G",
+            "scsi1": "local-lvm:vm-100-disk-1,size=8G",
+            "ide2": "local:iso/debian.iso,media=cdrom",
+            "vmstate": "local-lvm:vm-100-state-pre",
+        });
+        let present = ["local-lvm:vm-100-disk-0", "local-lvm:vm-100-state-pre"];
+        let problems = snapshot_problems(&config, |volume| Ok(present.contains(&volume)));
+        assert_eq!(
+            problems,
+            vec!["scsi1 volume local-lvm:vm-100-disk-1 not found on storage"]
+        );
+
+        let config = serde_json::json!({"snapstate": "prepare"});
+        let problems = snapshot_problems(&config, |_| Ok(true));
+        assert_eq!(
+            problems,
+            vec![
+                "left in state 'prepare' by an interrupted task",
+                "configuration lists no disks",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_disks() {
+        let config = serde_json::json!({
+            "name": "web",
+            "scsi0": "local-lvm:vm-100-disk-0,iothread=1,size=32G",
+            "virtio1": "ceph:vm-100-disk-1,size=512M",
+            "ide2": "local:iso/debian.iso,media=cdrom,size=600M",
+            "ide0": "none,media=cdrom",
+            "scsihw": "virtio-scsi-single",
+            "net0": "virtio=AA:BB:CC:DD:EE:FF,bridge=vmbr0",
+        });
+
+        assert_eq!(
+            parse_disks(&config),
+            vec![
+                VmDisk {
+                    name: "scsi0".to_string(),
+                    volume: "local-lvm:vm-100-disk-0".to_string(),
+                    size: Some(32 << 30),
+                },
+                VmDisk {
+                    name: "virtio1".to_string(),
+                    volume: "ceph:vm-100-disk-1".to_string(),
+                    size: Some(512 << 20),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_vm_filter() {
+        let resources: Vec<VmResource> = serde_json::from_value(serde_json::json!([
+            {"node": "pve1", "vmid": 100, "name": "web-1", "type": "qemu",
+             "status": "running", "tags": "prod;web", "pool": "frontend"},
+            {"node": "pve2", "vmid": 101, "name": "db-1", "type": "qemu", "status": "stopped"},
+            {"node": "pve1", "vmid": 200, "name": "web-ct", "type": "lxc", "status": "running"},
+            {"node": "pve2", "vmid": 102, "name": "web-2", "type": "qemu",
+             "status": "running", "qmpstatus": "paused"},
+        ]))
+        .unwrap();
+        let select = |filter: VmFilter| -> Vec<u32> {
+            resources
+                .iter()
+                .filter(|r| filter.matches_resource(r) && filter.matches_status(r))
+                .map(|r| r.vmid)
+                .collect()
+        };
+
+        assert_eq!(select(VmFilter::default()), vec![100, 101, 200, 102]);
+        assert_eq!(
+            select(VmFilter {
+                status: Some(GuestState::Running),
+                name: Some(NamePattern::regex("^web-").unwrap()),
+                ..Default::default()
+            }),
+            vec![100, 200]
+        );
+        assert_eq!(
+            select(VmFilter {
+                status: Some(GuestState::Paused),
+                ..Default::default()
+            }),
+            vec![102]
+        );
+        assert_eq!(
+            select(VmFilter {
+                guest_type: Some(GuestType::Lxc),
+                ..Default::default()
+            }),
+            vec![200]
+        );
+        assert_eq!(
+            select(VmFilter {
+                tags: vec!["WEB".to_string()],
+                pool: Some("frontend".to_string()),
+                ..Default::default()
+            }),
+            vec![100]
+        );
     }
 }
@@ -0,0 +1,105 @@
+/// Quotes `word` for a POSIX shell. ssh joins the remote command into one
+/// string for the login shell, so every word must survive that.
+pub fn shell_quote(word: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:,@%+".contains(c);
+    if !word.is_empty() && word.chars().all(safe) {
+        return word.to_string();
+    }
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
+
+/// Arguments to `ssh` that run `command` in container `vmid` with `pct exec`
+/// on the node at `host`. `tty` allocates a terminal for interactive use.
+pub fn pct_exec_args(
+    user: &str,
+    host: &str,
+    vmid: u32,
+    command: &[String],
+    tty: bool,
+) -> Vec<String> {
+    let mut args = Vec::new();
+    if tty {
+        args.push("-t".to_string());
+    }
+    // `--` keeps a user or host starting with `-` from being read as an
+    // option.
+    args.push("--".to_string());
+    args.push(format!("{}@{}", user, host));
+    args.extend(["pct", "exec", &vmid.to_string(), "--"].map(str::to_string));
+    args.extend(command.iter().map(|word| shell_quote(word)));
+    args
+}
+
+/// Arguments to `ssh` that turn the HA maintenance mode of `node` on or off
+/// with `ha-manager` on the node at `host`, as the API cannot set it.
+pub fn ha_maintenance_args(user: &str, host: &str, node: &str, enable: bool) -> Vec<String> {
+    let action = if enable { "enable" } else { "disable" };
+    vec![
+        "--".to_string(),
+        format!("{}@{}", user, host),
+        "ha-manager".to_string(),
+        "crm-command".to_string(),
+        "node-maintenance".to_string(),
+        action.to_string(),
+        shell_quote(node),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("systemctl"), "systemctl");
+        assert_eq!(shell_quote("/var/lib/mysql"), "/var/lib/mysql");
+        assert_eq!(shell_quote("a b"), "'a b'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote("$HOME;"), "'$HOME;'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn test_pct_exec_args() {
+        let command = vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            "sync; fsfreeze".to_string(),
+        ];
+        assert_eq!(
+            pct_exec_args("root", "10.0.0.1", 200, &command, false),
+            vec![
+                "--",
+                "root@10.0.0.1",
+                "pct",
+                "exec",
+                "200",
+                "--",
+                "sh",
+                "-c",
+                "'sync; fsfreeze'"
+            ]
+        );
+        assert_eq!(pct_exec_args("root", "pve1", 200, &command, true)[0], "-t");
+    }
+
+    #[test]
+    fn test_ha_maintenance_args() {
+        assert_eq!(
+            ha_maintenance_args("root", "10.0.0.1", "pve1", true),
+            vec![
+                "--",
+                "root@10.0.0.1",
+                "ha-manager",
+                "crm-command",
+                "node-maintenance",
+                "enable",
+                "pve1"
+            ]
+        );
+        assert_eq!(
+            ha_maintenance_args("root", "pve1", "pve1", false)[5],
+            "disable"
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+
+use crate::client::ProxmoxClient;
+use crate::cluster::ClusterManager;
+use crate::output::Output;
+use crate::tasks::TaskManager;
+
+/// Manages the content of storages.
+pub struct StorageManager {
+    client: ProxmoxClient,
+    cluster: ClusterManager,
+    tasks: TaskManager,
+    output: Output,
+}
+
+/// Content kinds `storage upload` accepts, matching the `content` parameter
+/// of `POST /nodes/{node}/storage/{storage}/upload`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum UploadContent {
+    /// An ISO image for VM installs.
+    Iso,
+    /// A container template.
+    Vztmpl,
+}
+
+impl UploadContent {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UploadContent::Iso => "iso",
+            UploadContent::Vztmpl => "vztmpl",
+        }
+    }
+
+    /// File extensions the API accepts for this content.
+    fn extensions(self) -> &'static [&'static str] {
+        match self {
+            UploadContent::Iso => &[".iso", ".img"],
+            UploadContent::Vztmpl => &[".tar.gz", ".tar.xz", ".tar.zst"],
+        }
+    }
+
+    /// Checks `file_name` before sending gigabytes the API would refuse.
+    pub fn check_file_name(self, file_name: &str) -> Result<()> {
+        let lower = file_name.to_ascii_lowercase();
+        if !self.extensions().iter().any(|ext| lower.ends_with(ext)) {
+            anyhow::bail!(
+                "{} content must be a {} file, got {}",
+                self.as_str(),
+                self.extensions().join(", "),
+                file_name
+            );
+        }
+        Ok(())
+    }
+}
+
+impl StorageManager {
+    pub fn new(client: ProxmoxClient, output: Output) -> Self {
+        let cluster = ClusterManager::new(client.clone(), output.clone());
+        let tasks = TaskManager::new(client.clone(), output.clone());
+        Self {
+            client,
+            cluster,
+            tasks,
+            output,
+        }
+    }
+
+    /// Uploads an ISO image or container template to `storage`, drawing a
+    /// progress bar while it is sent, then waits for the node to move it
+    /// into place.
+    pub async fn upload(
+        &self,
+        node: Option<&str>,
+        storage: &str,
+        path: &Path,
+        content: UploadContent,
+    ) -> Result<()> {
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow::anyhow!("Invalid file name: {}", path.display()))?;
+        content.check_file_name(file_name)?;
+        let file = tokio::fs::File::open(path)
+            .await
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let size = file.metadata().await?.len();
+
+        let node = self.cluster.node_or_first(node).await?;
+        self.output.message(&format!(
+            "Uploading {} ({}) to storage {} on node {}...",
+            file_name,
+            self.output.format_bytes(size),
+            storage,
+            node
+        ));
+        let output = self.output.clone();
+        let task_id: String = self
+            .client
+            .upload(
+                &format!("/nodes/{}/storage/{}/upload", node, storage),
+                &[("content", content.as_str())],
+                file_name,
+                file,
+                size,
+                move |sent| output.transfer(sent, size),
+            )
+            .await?;
+        self.output.print_raw(&task_id)?;
+        self.tasks.wait(&node, &task_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_file_name() {
+        assert!(UploadContent::Iso.check_file_name("debian-12.iso").is_ok());
+        assert!(UploadContent::Iso.check_file_name("TOOLS.ISO").is_ok());
+        assert!(UploadContent::Iso.check_file_name("image.qcow2").is_err());
+        assert!(
+            UploadContent::Vztmpl
+                .check_file_name("alpine-3.20-default_20240908_amd64.tar.xz")
+                .is_ok()
+        );
+        assert!(UploadContent::Vztmpl.check_file_name("alpine.iso").is_err());
+    }
+}
@@ -0,0 +1,394 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::config::{Config, RetentionConfig};
+use crate::daemon::Job;
+use crate::naming;
+use crate::output::Output;
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+/// A generated unit file.
+pub struct Unit {
+    pub file_name: String,
+    pub contents: String,
+}
+
+/// Generates units for every schedule in `config` and prints them, or writes
+/// them to `dir`. `base` is the pve-tool command line up to the subcommand.
+pub fn install(
+    config: &Config,
+    cluster: Option<&str>,
+    base: &[String],
+    dir: Option<&Path>,
+    output: &Output,
+) -> Result<()> {
+    if config.schedules.is_empty() {
+        anyhow::bail!("No [[schedules]] configured in the config file");
+    }
+
+    let retention = config.retention(cluster);
+    let mut all = Vec::new();
+    for (index, schedule) in config.schedules.iter().enumerate() {
+        let job = Job::parse(index, schedule)?;
+        all.extend(units(&job, base, &retention)?);
+    }
+
+    let Some(dir) = dir else {
+        for (i, unit) in all.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            println!("# {}\n{}", unit.file_name, unit.contents);
+        }
+        return Ok(());
+    };
+
+    for unit in &all {
+        let path = dir.join(&unit.file_name);
+        fs::write(&path, &unit.contents)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        output.message(&format!("Wrote {}", path.display()));
+    }
+
+    let timers: Vec<&str> = all
+        .iter()
+        .map(|unit| unit.file_name.as_str())
+        .filter(|name| name.ends_with(".timer"))
+        .collect();
+    output.message(&format!(
+        "Activate with: systemctl daemon-reload && systemctl enable --now {}",
+        timers.join(" ")
+    ));
+    Ok(())
+}
+
+/// Builds the service and timer for `job`. `base` is the pve-tool command
+/// line up to the subcommand, e.g. the binary and its `--config` option.
+pub fn units(job: &Job, base: &[String], retention: &RetentionConfig) -> Result<Vec<Unit>> {
+    let calendar = on_calendar(&job.config.schedule)?;
+    let unit_name = format!("pve-tool-{}", naming::sanitize(&job.name));
+
+    let mut selection = Vec::new();
+    if let Some(vm) = &job.config.vm {
+        selection.push(vm.clone());
+    }
+    if let Some(pool) = &job.config.pool {
+        selection.extend(["--pool".to_string(), pool.clone()]);
+    }
+    if !job.config.tags.is_empty() {
+        selection.extend(["--tag".to_string(), job.config.tags.join(",")]);
+    }
+    if let Some(guest_type) = job.config.guest_type {
+        // On the command line, --type only narrows a pool or tag selection.
+        if job.config.pool.is_none() && job.config.tags.is_empty() {
+            anyhow::bail!(
+                "Schedule '{}' uses type = \"{}\" without a pool or tags, which \
+                 systemd units cannot express; add one, or run it with 'pve-tool daemon'",
+                job.name,
+                guest_type.as_str()
+            );
+        }
+        selection.extend(["--type".to_string(), guest_type.as_str().to_string()]);
+    }
+
+    let mut create: Vec<String> = base.to_vec();
+    create.push("create".to_string());
+    create.extend(selection.iter().cloned());
+    if let Some(template) = &job.config.name_template {
+        create.extend(["--name-template".to_string(), template.clone()]);
+    }
+    if let Some(description) = &job.config.description {
+        create.extend(["-d".to_string(), description.clone()]);
+    }
+    create.extend([
+        "--reason".to_string(),
+        job.name.clone(),
+        "--if-not-exists".to_string(),
+    ]);
+    if job.config.vmstate {
+        create.push("-m".to_string());
+    }
+
+    let mut prune: Vec<String> = base.to_vec();
+    prune.push("prune".to_string());
+    prune.extend(selection);
+    let prune = match job.config.retention {
+        Some(policy) if !policy.is_empty() => {
+            for (flag, value) in [
+                ("--keep-last", policy.keep_last),
+                ("--keep-daily", policy.keep_daily),
+                ("--keep-weekly", policy.keep_weekly),
+                ("--keep-monthly", policy.keep_monthly),
+            ] {
+                if let Some(value) = value {
+                    prune.extend([flag.to_string(), value.to_string()]);
+                }
+            }
+            Some(prune)
+        }
+        Some(_) => None,
+        None if retention.default.is_empty() && retention.vms.is_empty() => None,
+        None => {
+            prune.push("--apply-config".to_string());
+            Some(prune)
+        }
+    };
+
+    // A oneshot service runs its ExecStart lines in order and stops at the
+    // first failure, so a failed snapshot skips pruning as in the daemon.
+    let mut service = format!(
+        "[Unit]\n\
+         Description=pve-tool schedule {name}\n\
+         Wants=network-online.target\n\
+         After=network-online.target\n\
+         \n\
+         [Service]\n\
+         Type=oneshot\n\
+         ExecStart={create}\n",
+        name = job.name,
+        create = exec_line(&create),
+    );
+    if let Some(prune) = prune {
+        service.push_str(&format!("ExecStart={}\n", exec_line(&prune)));
+    }
+
+    let timer = format!(
+        "[Unit]\n\
+         Description=Run pve-tool schedule {name} ({schedule})\n\
+         \n\
+         [Timer]\n\
+         OnCalendar={calendar}\n\
+         Persistent=true\n\
+         \n\
+         [Install]\n\
+         WantedBy=timers.target\n",
+        name = job.name,
+        schedule = job.config.schedule,
+    );
+
+    Ok(vec![
+        Unit {
+            file_name: format!("{}.service", unit_name),
+            contents: service,
+        },
+        Unit {
+            file_name: format!("{}.timer", unit_name),
+            contents: timer,
+        },
+    ])
+}
+
+/// Translates a five-field cron expression into a systemd `OnCalendar=`
+/// value. Expressions systemd cannot represent are rejected.
+pub fn on_calendar(cron: &str) -> Result<String> {
+    let shorthand = match cron.trim() {
+        "@hourly" => Some("hourly"),
+        "@daily" | "@midnight" => Some("daily"),
+        "@weekly" => Some("weekly"),
+        "@monthly" => Some("monthly"),
+        "@yearly" | "@annually" => Some("yearly"),
+        _ => None,
+    };
+    if let Some(shorthand) = shorthand {
+        return Ok(shorthand.to_string());
+    }
+
+    let unsupported =
+        |reason: &str| anyhow::anyhow!("Cannot convert '{}' to systemd: {}", cron, reason);
+
+    let fields: Vec<&str> = cron.split_whitespace().collect();
+    let [minute, hour, day, month, weekday] = fields[..] else {
+        return Err(unsupported("expected five fields"));
+    };
+
+    // Cron runs when either day field matches, systemd only when both do.
+    if day != "*" && weekday != "*" {
+        return Err(unsupported(
+            "restricting both the day of month and the day of week is not supported",
+        ));
+    }
+
+    let minute = field(minute, 0).map_err(|e| unsupported(&e))?;
+    let hour = field(hour, 0).map_err(|e| unsupported(&e))?;
+    let day = field(day, 1).map_err(|e| unsupported(&e))?;
+    let month = field(month, 1).map_err(|e| unsupported(&e))?;
+
+    let mut calendar = String::new();
+    if weekday != "*" {
+        calendar.push_str(&weekdays(weekday).map_err(|e| unsupported(&e))?);
+        calendar.push(' ');
+    }
+    calendar.push_str(&format!("*-{}-{} {}:{}:00", month, day, hour, minute));
+    Ok(calendar)
+}
+
+/// Converts a numeric cron field. `first` is the field's lowest value, which
+/// systemd needs as the start of a `*/step` repetition.
+fn field(value: &str, first: u32) -> Result<String, String> {
+    if value == "*" {
+        return Ok("*".to_string());
+    }
+
+    let parts: Vec<String> = value
+        .split(',')
+        .map(|part| {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, Some(number(step)?)),
+                None => (part, None),
+            };
+            match (range.split_once('-'), step) {
+                (Some(_), Some(_)) => Err(format!("stepped range '{}' is not supported", part)),
+                (Some((start, end)), None) => {
+                    Ok(format!("{:02}..{:02}", number(start)?, number(end)?))
+                }
+                (None, Some(step)) if range == "*" => Ok(format!("{:02}/{}", first, step)),
+                (None, Some(step)) => Ok(format!("{:02}/{}", number(range)?, step)),
+                (None, None) => Ok(format!("{:02}", number(range)?)),
+            }
+        })
+        .collect::<Result<_, String>>()?;
+
+    Ok(parts.join(","))
+}
+
+fn weekdays(value: &str) -> Result<String, String> {
+    let day = |s: &str| -> Result<&str, String> {
+        if let Ok(n) = s.parse::<usize>() {
+            return WEEKDAYS
+                .get(n % 7)
+                .copied()
+                .filter(|_| n <= 7)
+                .ok_or_else(|| format!("invalid day of week '{}'", s));
+        }
+        WEEKDAYS
+            .iter()
+            .find(|d| d.eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| format!("invalid day of week '{}'", s))
+    };
+
+    let parts: Vec<String> = value
+        .split(',')
+        .map(|part| {
+            if part.contains('/') {
+                return Err(format!("stepped day of week '{}' is not supported", part));
+            }
+            match part.split_once('-') {
+                Some((start, end)) => Ok(format!("{}..{}", day(start)?, day(end)?)),
+                None => Ok(day(part)?.to_string()),
+            }
+        })
+        .collect::<Result<_, String>>()?;
+
+    Ok(parts.join(","))
+}
+
+fn number(value: &str) -> Result<u32, String> {
+    value
+        .parse()
+        .map_err(|_| format!("unsupported cron value '{}'", value))
+}
+
+/// Joins `args` into an `ExecStart=` command line, quoting as systemd
+/// expects and escaping its `%` specifiers and `$` variables.
+fn exec_line(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| {
+            let escaped = arg.replace('%', "%%").replace('$', "$$");
+            let plain = !escaped.is_empty()
+                && !escaped
+                    .chars()
+                    .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\' | ';'));
+            if plain {
+                escaped
+            } else {
+                format!("\"{}\"", escaped.replace('\\', "\\\\").replace('"', "\\\""))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cluster::GuestType;
+    use crate::config::ScheduleConfig;
+    use crate::prune::RetentionPolicy;
+
+    #[test]
+    fn test_on_calendar() {
+        assert_eq!(on_calendar("0 2 * * *").unwrap(), "*-*-* 02:00:00");
+        assert_eq!(on_calendar("*/15 * * * *").unwrap(), "*-*-* *:00/15:00");
+        assert_eq!(
+            on_calendar("30 1,13 * * 1-5").unwrap(),
+            "Mon..Fri *-*-* 01,13:30:00"
+        );
+        assert_eq!(on_calendar("0 3 1 */3 *").unwrap(), "*-01/3-01 03:00:00");
+        assert_eq!(on_calendar("0 4 * * sun").unwrap(), "Sun *-*-* 04:00:00");
+        assert_eq!(on_calendar("@daily").unwrap(), "daily");
+    }
+
+    #[test]
+    fn test_on_calendar_unsupported() {
+        assert!(on_calendar("0 2 1 * 1").is_err());
+        assert!(on_calendar("0 1-10/2 * * *").is_err());
+        assert!(on_calendar("0 2 * *").is_err());
+    }
+
+    #[test]
+    fn test_units() {
+        let schedule = ScheduleConfig {
+            name: Some("nightly db".to_string()),
+            schedule: "0 2 * * *".to_string(),
+            vm: None,
+            pool: None,
+            tags: vec!["db".to_string(), "critical".to_string()],
+            guest_type: None,
+            name_template: Some("nightly-{date}".to_string()),
+            description: Some("Nightly at 100%".to_string()),
+            vmstate: false,
+            retention: Some(RetentionPolicy {
+                keep_daily: Some(7),
+                ..Default::default()
+            }),
+        };
+        let job = Job::parse(0, &schedule).unwrap();
+        let base = vec!["/usr/bin/pve-tool".to_string()];
+        let units = units(&job, &base, &RetentionConfig::default()).unwrap();
+
+        assert_eq!(units[0].file_name, "pve-tool-nightly-db.service");
+        assert!(units[0].contents.contains(
+            "ExecStart=/usr/bin/pve-tool create --tag db,critical --name-template nightly-{date} \
+             -d \"Nightly at 100%%\" --reason \"nightly db\" --if-not-exists\n"
+        ));
+        assert!(
+            units[0]
+                .contents
+                .contains("ExecStart=/usr/bin/pve-tool prune --tag db,critical --keep-daily 7\n")
+        );
+        assert_eq!(units[1].file_name, "pve-tool-nightly-db.timer");
+        assert!(units[1].contents.contains("OnCalendar=*-*-* 02:00:00\n"));
+    }
+
+    #[test]
+    fn test_units_type_needs_pool_or_tags() {
+        let schedule = ScheduleConfig {
+            name: Some("containers".to_string()),
+            schedule: "0 * * * *".to_string(),
+            vm: None,
+            pool: None,
+            tags: Vec::new(),
+            guest_type: Some(GuestType::Lxc),
+            name_template: None,
+            description: None,
+            vmstate: false,
+            retention: None,
+        };
+        let job = Job::parse(0, &schedule).unwrap();
+        let base = vec!["/usr/bin/pve-tool".to_string()];
+        assert!(units(&job, &base, &RetentionConfig::default()).is_err());
+    }
+}
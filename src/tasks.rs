@@ -0,0 +1,210 @@
+use anyhow::Result;
+use serde::Deserialize;
+use tokio::time::{Duration, sleep};
+
+use crate::client::ProxmoxClient;
+use crate::output::{Color, Output};
+use crate::prompt;
+
+/// Polls and prints Proxmox tasks, the UPIDs returned by long operations.
+#[derive(Clone)]
+pub struct TaskManager {
+    client: ProxmoxClient,
+    output: Output,
+}
+
+/// What to print while waiting for a task.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Progress {
+    Dots,
+    /// The task log, as messages.
+    Log,
+    /// The task log as is, one line per line on stdout, for `tasks log`.
+    RawLog,
+}
+
+pub enum TaskState {
+    Running,
+    /// The task stopped, successfully or with the error it reported.
+    Finished(Result<()>),
+}
+
+/// The node a task runs on, from its UPID, e.g. `pve1` in
+/// `UPID:pve1:0002A5B1:0B2D6F6E:67000000:vzdump:100:root@pam:`.
+pub fn upid_node(upid: &str) -> Result<&str> {
+    let mut fields = upid.split(':');
+    match (fields.next(), fields.next()) {
+        (Some("UPID"), Some(node)) if !node.is_empty() => Ok(node),
+        _ => anyhow::bail!(
+            "Invalid task ID {}; expected a UPID:<node>:... string",
+            upid
+        ),
+    }
+}
+
+impl TaskManager {
+    pub fn new(client: ProxmoxClient, output: Output) -> Self {
+        Self { client, output }
+    }
+
+    /// Prints the log of a task, which may have been started elsewhere.
+    /// With `follow`, keeps printing new lines until the task ends and
+    /// fails if the task does.
+    pub async fn log(&self, upid: &str, follow: bool) -> Result<()> {
+        let node = upid_node(upid)?;
+        if follow {
+            return self.watch(node, upid, Progress::RawLog).await;
+        }
+        self.print_log(node, upid, 0, true).await?;
+        Ok(())
+    }
+
+    /// Stops a running task, e.g. a snapshot with RAM state that hangs.
+    pub async fn stop(&self, upid: &str, assume_yes: bool) -> Result<()> {
+        let node = upid_node(upid)?;
+        if let TaskState::Finished(_) = self.state(node, upid).await? {
+            self.output
+                .message(&format!("Task {} is not running", upid));
+            return Ok(());
+        }
+        let question = format!(
+            "Stop task {}? The operation it runs is left half done.",
+            upid
+        );
+        if !assume_yes && !self.client.is_dry_run() && !prompt::confirm(&question)? {
+            anyhow::bail!("Aborted");
+        }
+        let _: serde_json::Value = self
+            .client
+            .delete(&format!("/nodes/{}/tasks/{}", node, upid))
+            .await?;
+        self.output.message(&format!("Stopped task {}", upid));
+        Ok(())
+    }
+
+    pub async fn wait(&self, node: &str, task_id: &str) -> Result<()> {
+        self.watch(node, task_id, Progress::Dots).await
+    }
+
+    /// Like `wait`, but prints the task log instead of progress
+    /// dots, for long tasks such as migrations.
+    pub async fn follow(&self, node: &str, task_id: &str) -> Result<()> {
+        self.watch(node, task_id, Progress::Log).await
+    }
+
+    async fn watch(&self, node: &str, task_id: &str, progress: Progress) -> Result<()> {
+        if self.client.is_dry_run() {
+            return Ok(());
+        }
+        let mut printed = 0;
+        loop {
+            let state = self.state(node, task_id).await?;
+
+            // Read the log after the status so that a stopped task's last
+            // lines are printed before its result.
+            if progress != Progress::Dots {
+                let raw = progress == Progress::RawLog;
+                printed += self.print_log(node, task_id, printed, raw).await?;
+            }
+
+            match state {
+                TaskState::Finished(result) => {
+                    result?;
+                    self.output.message(&format!(
+                        "\n{}",
+                        self.output
+                            .paint(Color::Green, "✓ Task completed successfully")
+                    ));
+                    return Ok(());
+                }
+                TaskState::Running => {
+                    if progress == Progress::Dots {
+                        self.output.progress()?;
+                    }
+                    sleep(Duration::from_secs(2)).await;
+                }
+            }
+        }
+    }
+
+    /// Polls a task once. Tasks of a dry run finish immediately.
+    pub async fn state(&self, node: &str, task_id: &str) -> Result<TaskState> {
+        if self.client.is_dry_run() {
+            return Ok(TaskState::Finished(Ok(())));
+        }
+
+        #[derive(Deserialize)]
+        struct TaskStatus {
+            status: String,
+            exitstatus: Option<String>,
+        }
+
+        let status: TaskStatus = self
+            .client
+            .get(&format!("/nodes/{}/tasks/{}/status", node, task_id))
+            .await?;
+
+        match status.status.as_str() {
+            "stopped" if status.exitstatus.as_deref() == Some("OK") => {
+                Ok(TaskState::Finished(Ok(())))
+            }
+            "stopped" => Ok(TaskState::Finished(Err(anyhow::anyhow!(
+                "Task failed: {:?}",
+                status.exitstatus
+            )))),
+            "running" => Ok(TaskState::Running),
+            _ => anyhow::bail!("Unknown task status: {}", status.status),
+        }
+    }
+
+    /// Prints the task log lines from `start` on and returns how many there
+    /// were. With `raw`, the lines go to stdout as is, even in quiet mode.
+    async fn print_log(&self, node: &str, task_id: &str, start: usize, raw: bool) -> Result<usize> {
+        const PAGE: usize = 500;
+
+        #[derive(Deserialize)]
+        struct LogLine {
+            t: String,
+        }
+
+        let mut count = 0;
+        loop {
+            let lines: Vec<LogLine> = self
+                .client
+                .get(&format!(
+                    "/nodes/{}/tasks/{}/log?start={}&limit={}",
+                    node,
+                    task_id,
+                    start + count,
+                    PAGE
+                ))
+                .await?;
+            for line in &lines {
+                if raw {
+                    println!("{}", line.t);
+                } else {
+                    self.output.message(&format!("  {}", line.t));
+                }
+            }
+            count += lines.len();
+            if lines.len() < PAGE {
+                return Ok(count);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upid_node() {
+        assert_eq!(
+            upid_node("UPID:pve1:0002A5B1:0B2D6F6E:67000000:vzdump:100:root@pam:").unwrap(),
+            "pve1"
+        );
+        assert!(upid_node("pve1:0002A5B1").is_err());
+        assert!(upid_node("UPID::0002A5B1").is_err());
+    }
+}
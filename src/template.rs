@@ -0,0 +1,104 @@
+use anyhow::Result;
+
+/// Substitutes `{key}` placeholders in `template` using `lookup`.
+///
+/// `{{` and `}}` produce literal braces. Unknown placeholders are an error so
+/// that typos do not silently produce empty fields.
+pub fn render(template: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut out = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let mut key = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => key.push(c),
+                        None => anyhow::bail!("Unclosed placeholder '{{{}' in template", key),
+                    }
+                }
+                let value = lookup(key.trim())
+                    .ok_or_else(|| anyhow::anyhow!("Unknown placeholder '{{{}}}'", key))?;
+                out.push_str(&value);
+            }
+            c => out.push(c),
+        }
+    }
+
+    Ok(out)
+}
+
+/// Looks up `key` in a serialized object, rendering scalars without quotes
+/// and missing or null fields as empty strings.
+pub fn json_lookup(value: &serde_json::Value, key: &str) -> Option<String> {
+    let object = value.as_object()?;
+    match object.get(key) {
+        Some(serde_json::Value::String(s)) => Some(s.clone()),
+        Some(serde_json::Value::Null) => Some(String::new()),
+        Some(other) => Some(other.to_string()),
+        None => None,
+    }
+}
+
+/// Expands the `\t`, `\n` and `\\` escapes users type on the command line.
+pub fn unescape(input: &str) -> String {
+    let mut out = String::new();
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_placeholders() {
+        let value = serde_json::json!({"vmid": 100, "name": "web", "tags": null});
+        let result = render("{vmid}:{name}:{tags}", |k| json_lookup(&value, k)).unwrap();
+        assert_eq!(result, "100:web:");
+    }
+
+    #[test]
+    fn test_render_escaped_braces() {
+        let result = render("{{literal}} {x}", |_| Some("1".to_string())).unwrap();
+        assert_eq!(result, "{literal} 1");
+    }
+
+    #[test]
+    fn test_render_errors() {
+        assert!(render("{missing}", |_| None).is_err());
+        assert!(render("{open", |_| Some(String::new())).is_err());
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape(r"{vmid}\t{name}\n"), "{vmid}\t{name}\n");
+        assert_eq!(unescape(r"a\\b\x"), r"a\b\x");
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::output::{Output, Tabular};
+
+/// A node or guest from `/cluster/resources`. The I/O fields are counters
+/// since the guest started.
+#[derive(Debug, Deserialize)]
+pub struct Resource {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub resource_type: String,
+    #[serde(default)]
+    pub node: Option<String>,
+    #[serde(default)]
+    pub vmid: Option<u32>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub status: Option<String>,
+    /// Fraction of `maxcpu` in use, 0 to 1.
+    #[serde(default)]
+    pub cpu: f64,
+    #[serde(default)]
+    pub maxcpu: f64,
+    #[serde(default)]
+    pub mem: u64,
+    #[serde(default)]
+    pub maxmem: u64,
+    #[serde(default)]
+    pub netin: u64,
+    #[serde(default)]
+    pub netout: u64,
+    #[serde(default)]
+    pub diskread: u64,
+    #[serde(default)]
+    pub diskwrite: u64,
+}
+
+impl Resource {
+    fn is_guest(&self) -> bool {
+        matches!(self.resource_type.as_str(), "qemu" | "lxc")
+    }
+
+    fn counters(&self) -> [u64; 4] {
+        [self.netin, self.netout, self.diskread, self.diskwrite]
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TopNode {
+    pub node: String,
+    pub status: String,
+    pub cpu: f64,
+    pub maxcpu: f64,
+    pub mem: u64,
+    pub maxmem: u64,
+    pub running: usize,
+}
+
+impl Tabular for TopNode {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("node", "Node"),
+            ("status", "Status"),
+            ("cpu", "CPU"),
+            ("mem", "Memory"),
+            ("running", "Running"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        vec![
+            self.node.clone(),
+            self.status.clone(),
+            format_cpu(self.cpu, self.maxcpu),
+            format_memory(output, self.mem, self.maxmem),
+            self.running.to_string(),
+        ]
+    }
+}
+
+/// A running guest with its I/O rates in bytes per second, which are only
+/// known from the second sample on.
+#[derive(Debug, Serialize)]
+pub struct TopGuest {
+    pub vmid: u32,
+    pub name: String,
+    pub node: String,
+    #[serde(rename = "type")]
+    pub guest_type: String,
+    pub cpu: f64,
+    pub maxcpu: f64,
+    pub mem: u64,
+    pub maxmem: u64,
+    pub netin: Option<u64>,
+    pub netout: Option<u64>,
+    pub diskread: Option<u64>,
+    pub diskwrite: Option<u64>,
+}
+
+impl TopGuest {
+    /// Cores in use, which ranks a busy 8-core VM above an idle 1-core one.
+    fn load(&self) -> f64 {
+        self.cpu * self.maxcpu
+    }
+}
+
+impl Tabular for TopGuest {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("vmid", "VMID"),
+            ("name", "Name"),
+            ("node", "Node"),
+            ("cpu", "CPU"),
+            ("mem", "Memory"),
+            ("net", "Net in/out"),
+            ("disk", "Disk read/write"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        let rates = |read: Option<u64>, write: Option<u64>| match (read, write) {
+            (Some(read), Some(write)) => format!(
+                "{}/s / {}/s",
+                output.format_bytes(read),
+                output.format_bytes(write)
+            ),
+            _ => "-".to_string(),
+        };
+        vec![
+            self.vmid.to_string(),
+            self.name.clone(),
+            self.node.clone(),
+            format_cpu(self.cpu, self.maxcpu),
+            format_memory(output, self.mem, self.maxmem),
+            rates(self.netin, self.netout),
+            rates(self.diskread, self.diskwrite),
+        ]
+    }
+}
+
+/// One refresh of `top`.
+#[derive(Debug, Serialize)]
+pub struct Sample {
+    pub nodes: Vec<TopNode>,
+    /// Number of running guests, of which `guests` holds the busiest.
+    pub running: usize,
+    pub guests: Vec<TopGuest>,
+}
+
+/// Keeps the I/O counters of the previous refresh, so that each refresh
+/// needs a single `/cluster/resources` call to compute the rates.
+#[derive(Debug, Default)]
+pub struct Top {
+    previous: HashMap<String, [u64; 4]>,
+}
+
+impl Top {
+    /// Builds the sample for `resources`, fetched `elapsed` seconds after
+    /// the previous ones, keeping the `limit` busiest guests.
+    pub fn update(&mut self, resources: Vec<Resource>, elapsed: f64, limit: usize) -> Sample {
+        let mut nodes = Vec::new();
+        let mut guests = Vec::new();
+        let mut counters = HashMap::with_capacity(self.previous.len());
+
+        for resource in resources {
+            match resource.resource_type.as_str() {
+                "node" => nodes.push(TopNode {
+                    node: resource.node.clone().unwrap_or_default(),
+                    status: resource.status.clone().unwrap_or_default(),
+                    cpu: resource.cpu,
+                    maxcpu: resource.maxcpu,
+                    mem: resource.mem,
+                    maxmem: resource.maxmem,
+                    running: 0,
+                }),
+                _ if resource.is_guest() && resource.status.as_deref() == Some("running") => {
+                    let current = resource.counters();
+                    // A counter that went down belongs to a restarted guest.
+                    let rates = self
+                        .previous
+                        .get(&resource.id)
+                        .filter(|_| elapsed > 0.0)
+                        .and_then(|previous| {
+                            let mut rates = [0; 4];
+                            for (i, rate) in rates.iter_mut().enumerate() {
+                                *rate =
+                                    (current[i].checked_sub(previous[i])? as f64 / elapsed) as u64;
+                            }
+                            Some(rates)
+                        });
+                    counters.insert(resource.id.clone(), current);
+                    guests.push(TopGuest {
+                        vmid: resource.vmid.unwrap_or_default(),
+                        name: resource.name.unwrap_or_default(),
+                        node: resource.node.unwrap_or_default(),
+                        guest_type: resource.resource_type,
+                        cpu: resource.cpu,
+                        maxcpu: resource.maxcpu,
+                        mem: resource.mem,
+                        maxmem: resource.maxmem,
+                        netin: rates.map(|r| r[0]),
+                        netout: rates.map(|r| r[1]),
+                        diskread: rates.map(|r| r[2]),
+                        diskwrite: rates.map(|r| r[3]),
+                    });
+                }
+                _ => {}
+            }
+        }
+        // Guests that stopped or left the cluster are forgotten.
+        self.previous = counters;
+
+        for node in &mut nodes {
+            node.running = guests.iter().filter(|g| g.node == node.node).count();
+        }
+        nodes.sort_by(|a, b| a.node.cmp(&b.node));
+
+        let running = guests.len();
+        guests.sort_by(|a, b| {
+            b.load()
+                .total_cmp(&a.load())
+                .then_with(|| b.mem.cmp(&a.mem))
+                .then_with(|| a.vmid.cmp(&b.vmid))
+        });
+        guests.truncate(limit);
+
+        Sample {
+            nodes,
+            running,
+            guests,
+        }
+    }
+}
+
+fn format_cpu(cpu: f64, maxcpu: f64) -> String {
+    if maxcpu > 0.0 {
+        format!("{:.1}% of {}", cpu * 100.0, maxcpu)
+    } else {
+        "-".to_string()
+    }
+}
+
+fn format_memory(output: &Output, mem: u64, maxmem: u64) -> String {
+    if maxmem > 0 {
+        format!(
+            "{} / {}",
+            output.format_bytes(mem),
+            output.format_bytes(maxmem)
+        )
+    } else {
+        "-".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resources(netin: u64) -> Vec<Resource> {
+        serde_json::from_value(serde_json::json!([
+            { "id": "node/pve1", "type": "node", "node": "pve1", "status": "online",
+              "cpu": 0.25, "maxcpu": 16, "mem": 100, "maxmem": 1000 },
+            { "id": "qemu/100", "type": "qemu", "vmid": 100, "node": "pve1", "status": "running",
+              "cpu": 0.5, "maxcpu": 1, "netin": netin },
+            { "id": "qemu/101", "type": "qemu", "vmid": 101, "node": "pve1", "status": "running",
+              "cpu": 0.25, "maxcpu": 4 },
+            { "id": "lxc/200", "type": "lxc", "vmid": 200, "node": "pve1", "status": "stopped" },
+            { "id": "storage/pve1/local", "type": "storage", "node": "pve1" }
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_update() {
+        let mut top = Top::default();
+        let sample = top.update(resources(1000), 0.0, 10);
+        assert_eq!(sample.nodes.len(), 1);
+        assert_eq!(sample.nodes[0].running, 2);
+        assert_eq!(sample.running, 2);
+        // One of four cores ranks above half of one.
+        assert_eq!(sample.guests[0].vmid, 101);
+        assert_eq!(sample.guests[1].netin, None);
+
+        let sample = top.update(resources(7000), 3.0, 1);
+        assert_eq!(sample.running, 2);
+        assert_eq!(sample.guests.len(), 1);
+        let sample = top.update(resources(13000), 3.0, 10);
+        assert_eq!(sample.guests[1].netin, Some(2000));
+        assert_eq!(sample.guests[1].diskwrite, Some(0));
+
+        // The guest restarted, so its counters started over.
+        let sample = top.update(resources(500), 3.0, 10);
+        assert_eq!(sample.guests[1].netin, None);
+    }
+}
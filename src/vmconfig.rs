@@ -0,0 +1,413 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::cluster::split_tags;
+use crate::output::{Color, Output, Tabular};
+use crate::pattern::NamePattern;
+
+/// One option of a VM configuration.
+#[derive(Debug, Serialize)]
+pub struct ConfigEntry {
+    pub key: String,
+    pub value: String,
+}
+
+impl Tabular for ConfigEntry {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[("key", "Key"), ("value", "Value")]
+    }
+
+    fn row(&self, _output: &Output) -> Vec<String> {
+        vec![self.key.clone(), self.value.clone()]
+    }
+}
+
+/// Lists the options of `config` sorted by key, keeping those that match
+/// `filter`: an exact key, or a glob such as `net*`.
+pub fn entries(config: &serde_json::Value, filter: Option<&str>) -> Result<Vec<ConfigEntry>> {
+    let pattern = filter
+        .filter(|f| NamePattern::is_glob(f))
+        .map(NamePattern::glob)
+        .transpose()?;
+
+    let mut entries: Vec<ConfigEntry> = config
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(key, _)| key.as_str() != "digest")
+        .filter(|(key, _)| match (&pattern, filter) {
+            (Some(pattern), _) => pattern.is_match(key),
+            (None, Some(filter)) => key.as_str() == filter,
+            (None, None) => true,
+        })
+        .map(|(key, value)| ConfigEntry {
+            key: key.clone(),
+            value: match value {
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            },
+        })
+        .collect();
+    entries.sort_by(|a, b| a.key.cmp(&b.key));
+    Ok(entries)
+}
+
+/// An entry of the `pending` endpoint: the running value of an option and
+/// the value it will take at the next reboot.
+#[derive(Debug, Deserialize)]
+pub struct PendingEntry {
+    pub key: String,
+    #[serde(default)]
+    pub value: Option<serde_json::Value>,
+    #[serde(default)]
+    pub pending: Option<serde_json::Value>,
+    /// 1 when the option is removed at the next reboot, 2 when forced.
+    #[serde(default)]
+    pub delete: Option<u8>,
+}
+
+/// An option whose running value differs from its configured one.
+#[derive(Debug, Serialize)]
+pub struct PendingChange {
+    pub key: String,
+    pub current: Option<String>,
+    pub pending: Option<String>,
+}
+
+impl Tabular for PendingChange {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("key", "Key"),
+            ("current", "Current"),
+            ("pending", "Pending"),
+        ]
+    }
+
+    fn row(&self, output: &Output) -> Vec<String> {
+        vec![
+            output.paint(Color::Yellow, &self.key),
+            self.current.clone().unwrap_or_else(|| "-".to_string()),
+            self.pending
+                .clone()
+                .unwrap_or_else(|| "(removed)".to_string()),
+        ]
+    }
+}
+
+/// Keeps the entries that will change at the next reboot, sorted by key.
+pub fn pending_changes(entries: Vec<PendingEntry>) -> Vec<PendingChange> {
+    let text = |v: serde_json::Value| match v {
+        serde_json::Value::String(s) => s,
+        other => other.to_string(),
+    };
+
+    let mut changes: Vec<PendingChange> = entries
+        .into_iter()
+        .filter(|e| e.pending.is_some() || e.delete.is_some_and(|d| d > 0))
+        .map(|e| PendingChange {
+            key: e.key,
+            current: e.value.map(text),
+            pending: e.pending.filter(|_| e.delete.unwrap_or(0) == 0).map(text),
+        })
+        .collect();
+    changes.sort_by(|a, b| a.key.cmp(&b.key));
+    changes
+}
+
+/// CPU and memory settings for `set-resources`, sent only when set.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ResourceOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cores: Option<u32>,
+    /// Active vCPUs of a VM, the setting CPU hotplug changes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vcpus: Option<u32>,
+    /// Memory in MiB.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<u64>,
+}
+
+impl ResourceOptions {
+    /// Configuration keys this changes.
+    pub fn keys(&self) -> Vec<&'static str> {
+        [
+            ("cores", self.cores.is_some()),
+            ("vcpus", self.vcpus.is_some()),
+            ("memory", self.memory.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(key, set)| set.then_some(key))
+        .collect()
+    }
+
+    /// Keys a running VM with `config` only applies after a reboot. The
+    /// core count never changes live; vCPUs need CPU hotplug, and memory
+    /// needs memory hotplug with NUMA enabled.
+    pub fn cold_keys(&self, config: &serde_json::Value) -> Vec<&'static str> {
+        let hotplug = hotplug(config);
+        let numa = config
+            .get("numa")
+            .is_some_and(|v| v.as_u64() == Some(1) || v.as_str() == Some("1"));
+        self.keys()
+            .into_iter()
+            .filter(|key| match *key {
+                "vcpus" => !hotplug.contains(&"cpu"),
+                "memory" => !(hotplug.contains(&"memory") && numa),
+                _ => true,
+            })
+            .collect()
+    }
+}
+
+/// Hotplug features enabled in a VM configuration.
+pub fn hotplug(config: &serde_json::Value) -> Vec<&str> {
+    const DEFAULT: [&str; 3] = ["network", "disk", "usb"];
+    match config.get("hotplug").and_then(|v| v.as_str()) {
+        None | Some("1") => DEFAULT.to_vec(),
+        Some("0") => Vec::new(),
+        Some(features) => features.split(',').map(str::trim).collect(),
+    }
+}
+
+/// Controller a new disk is attached to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum DiskBus {
+    #[default]
+    Scsi,
+    Virtio,
+    Sata,
+    Ide,
+}
+
+impl DiskBus {
+    fn prefix(self) -> &'static str {
+        match self {
+            DiskBus::Scsi => "scsi",
+            DiskBus::Virtio => "virtio",
+            DiskBus::Sata => "sata",
+            DiskBus::Ide => "ide",
+        }
+    }
+
+    /// Number of devices the controller takes.
+    fn slots(self) -> u32 {
+        match self {
+            DiskBus::Scsi => 31,
+            DiskBus::Virtio => 16,
+            DiskBus::Sata => 6,
+            DiskBus::Ide => 4,
+        }
+    }
+
+    /// The first key of this bus not used in `config`, e.g. `scsi2`.
+    pub fn free_slot(self, config: &serde_json::Value) -> Option<String> {
+        (0..self.slots())
+            .map(|n| format!("{}{}", self.prefix(), n))
+            .find(|key| config.get(key).is_none())
+    }
+}
+
+/// The first mount point key not used in a container `config`, e.g. `mp1`.
+pub fn free_mount_point(config: &serde_json::Value) -> Option<String> {
+    (0..256)
+        .map(|n| format!("mp{}", n))
+        .find(|key| config.get(key).is_none())
+}
+
+/// Applies `add` and `remove` to a `tags` value and returns the new one,
+/// sorted and in the `;`-separated form Proxmox writes. Tags compare
+/// case-insensitively, like in the web interface.
+pub fn edit_tags(current: Option<&str>, add: &[String], remove: &[String]) -> Result<String> {
+    for tag in add {
+        let valid = tag
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+            && tag
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+' | '.'));
+        if !valid {
+            anyhow::bail!(
+                "Invalid tag '{}'; use letters, digits and _ - + . only",
+                tag
+            );
+        }
+    }
+
+    let mut tags: Vec<String> = split_tags(current.unwrap_or_default())
+        .map(String::from)
+        .collect();
+    tags.retain(|t| !remove.iter().any(|r| r.eq_ignore_ascii_case(t)));
+    for tag in add {
+        if !tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+            tags.push(tag.clone());
+        }
+    }
+    tags.sort_by_key(|t| t.to_ascii_lowercase());
+    Ok(tags.join(";"))
+}
+
+/// How many guests carry a tag, for `tag list`.
+#[derive(Debug, Serialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub vms: usize,
+}
+
+impl Tabular for TagCount {
+    fn columns() -> &'static [(&'static str, &'static str)] {
+        &[("tag", "Tag"), ("vms", "VMs")]
+    }
+
+    fn row(&self, _output: &Output) -> Vec<String> {
+        vec![self.tag.clone(), self.vms.to_string()]
+    }
+}
+
+/// Adds `text` to notes as a new line.
+pub fn append_note(notes: &str, text: &str) -> String {
+    if notes.is_empty() {
+        return text.to_string();
+    }
+    format!("{}\n{}", notes.trim_end_matches('\n'), text)
+}
+
+/// Parses a `key=value` argument of `config set`.
+pub fn parse_assignment(input: &str) -> Result<(String, String)> {
+    match input.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => anyhow::bail!("Expected key=value, got '{}'", input),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries_filter() {
+        let config = serde_json::json!({
+            "memory": 2048,
+            "net0": "virtio=AA:BB,bridge=vmbr0",
+            "net1": "virtio=CC:DD,bridge=vmbr1",
+            "digest": "abc",
+        });
+        let keys = |filter| -> Vec<String> {
+            entries(&config, filter)
+                .unwrap()
+                .into_iter()
+                .map(|e| e.key)
+                .collect()
+        };
+        assert_eq!(keys(None), vec!["memory", "net0", "net1"]);
+        assert_eq!(keys(Some("net*")), vec!["net0", "net1"]);
+        assert_eq!(keys(Some("memory")), vec!["memory"]);
+        assert_eq!(entries(&config, Some("memory")).unwrap()[0].value, "2048");
+    }
+
+    #[test]
+    fn test_pending_changes() {
+        let entries: Vec<PendingEntry> = serde_json::from_value(serde_json::json!([
+            {"key": "memory", "value": 2048, "pending": 4096},
+            {"key": "cores", "value": 2},
+            {"key": "balloon", "value": 1024, "delete": 1},
+            {"key": "net1", "pending": "virtio,bridge=vmbr1"},
+        ]))
+        .unwrap();
+        let changes = pending_changes(entries);
+        let summary: Vec<(&str, Option<&str>, Option<&str>)> = changes
+            .iter()
+            .map(|c| (c.key.as_str(), c.current.as_deref(), c.pending.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("balloon", Some("1024"), None),
+                ("memory", Some("2048"), Some("4096")),
+                ("net1", None, Some("virtio,bridge=vmbr1")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_free_slot() {
+        let config = serde_json::json!({
+            "scsi0": "local-lvm:vm-100-disk-0,size=32G",
+            "scsi1": "local-lvm:vm-100-disk-1,size=8G",
+            "scsi3": "local-lvm:vm-100-disk-2,size=8G",
+            "ide2": "none,media=cdrom",
+        });
+        assert_eq!(DiskBus::Scsi.free_slot(&config).as_deref(), Some("scsi2"));
+        assert_eq!(DiskBus::Ide.free_slot(&config).as_deref(), Some("ide0"));
+
+        let full = serde_json::json!({"ide0": "", "ide1": "", "ide2": "", "ide3": ""});
+        assert_eq!(DiskBus::Ide.free_slot(&full), None);
+    }
+
+    #[test]
+    fn test_free_mount_point() {
+        let config = serde_json::json!({
+            "rootfs": "local-lvm:vm-200-disk-0,size=8G",
+            "mp0": "local-lvm:vm-200-disk-1,mp=/srv,size=16G",
+        });
+        assert_eq!(free_mount_point(&config).as_deref(), Some("mp1"));
+    }
+
+    #[test]
+    fn test_edit_tags() {
+        let tags = |add: &[&str], remove: &[&str]| {
+            let add: Vec<String> = add.iter().map(|t| t.to_string()).collect();
+            let remove: Vec<String> = remove.iter().map(|t| t.to_string()).collect();
+            edit_tags(Some("web,prod;Backup"), &add, &remove)
+        };
+        assert_eq!(
+            tags(&["critical"], &[]).unwrap(),
+            "Backup;critical;prod;web"
+        );
+        assert_eq!(tags(&["PROD"], &["backup"]).unwrap(), "prod;web");
+        assert_eq!(tags(&[], &["web", "prod", "backup"]).unwrap(), "");
+        assert!(tags(&["bad tag"], &[]).is_err());
+        assert!(tags(&["-dash"], &[]).is_err());
+    }
+
+    #[test]
+    fn test_append_note() {
+        assert_eq!(append_note("", "upgraded"), "upgraded");
+        assert_eq!(
+            append_note("Web server\n", "Snapshotted before PHP 8.3"),
+            "Web server\nSnapshotted before PHP 8.3"
+        );
+    }
+
+    #[test]
+    fn test_parse_assignment() {
+        assert_eq!(
+            parse_assignment("net0=virtio,bridge=vmbr0").unwrap(),
+            ("net0".to_string(), "virtio,bridge=vmbr0".to_string())
+        );
+        assert!(parse_assignment("memory").is_err());
+        assert!(parse_assignment("=4096").is_err());
+    }
+
+    #[test]
+    fn test_cold_keys() {
+        let resources = ResourceOptions {
+            cores: Some(8),
+            vcpus: Some(4),
+            memory: Some(16384),
+        };
+        assert_eq!(resources.keys(), vec!["cores", "vcpus", "memory"]);
+        assert_eq!(
+            resources.cold_keys(&serde_json::json!({})),
+            vec!["cores", "vcpus", "memory"]
+        );
+
+        let hotplug = serde_json::json!({"hotplug": "network,disk,cpu,memory", "numa": 1});
+        assert_eq!(resources.cold_keys(&hotplug), vec!["cores"]);
+
+        // Memory hotplug does nothing without NUMA.
+        let no_numa = serde_json::json!({"hotplug": "cpu,memory"});
+        assert_eq!(resources.cold_keys(&no_numa), vec!["cores", "memory"]);
+    }
+}
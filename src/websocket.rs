@@ -0,0 +1,167 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Frames larger than this are refused rather than buffered.
+const MAX_PAYLOAD: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl Opcode {
+    fn from_bits(bits: u8) -> io::Result<Self> {
+        Ok(match bits {
+            0x0 => Opcode::Continuation,
+            0x1 => Opcode::Text,
+            0x2 => Opcode::Binary,
+            0x8 => Opcode::Close,
+            0x9 => Opcode::Ping,
+            0xA => Opcode::Pong,
+            other => return Err(invalid(format!("unknown websocket opcode {:#x}", other))),
+        })
+    }
+
+    fn bits(self) -> u8 {
+        match self {
+            Opcode::Continuation => 0x0,
+            Opcode::Text => 0x1,
+            Opcode::Binary => 0x2,
+            Opcode::Close => 0x8,
+            Opcode::Ping => 0x9,
+            Opcode::Pong => 0xA,
+        }
+    }
+}
+
+/// A websocket frame. Only as much of RFC 6455 is implemented as the
+/// Proxmox console websockets need: no extensions, and no reassembly of
+/// fragmented messages.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub opcode: Opcode,
+    pub payload: Vec<u8>,
+}
+
+/// A fresh `Sec-WebSocket-Key` handshake header value.
+pub fn handshake_key() -> String {
+    let mut key = [0u8; 16];
+    fastrand::fill(&mut key);
+    BASE64.encode(key)
+}
+
+/// Encodes a single final frame. Clients must mask what they send.
+pub fn encode_frame(opcode: Opcode, payload: &[u8], mask: [u8; 4]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 14);
+    frame.push(0x80 | opcode.bits());
+    match payload.len() {
+        len @ 0..=125 => frame.push(0x80 | len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(0x80 | 127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(&mask);
+    frame.extend(
+        payload
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| byte ^ mask[i % 4]),
+    );
+    frame
+}
+
+/// Sends `payload` as one masked frame.
+pub async fn write_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    opcode: Opcode,
+    payload: &[u8],
+) -> io::Result<()> {
+    let mut mask = [0u8; 4];
+    fastrand::fill(&mut mask);
+    writer
+        .write_all(&encode_frame(opcode, payload, mask))
+        .await?;
+    writer.flush().await
+}
+
+/// Reads the next frame. Fragmented messages are returned frame by frame,
+/// the later ones as [`Opcode::Continuation`].
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Frame> {
+    let mut header = [0u8; 2];
+    reader.read_exact(&mut header).await?;
+    let opcode = Opcode::from_bits(header[0] & 0x0F)?;
+    let masked = header[1] & 0x80 != 0;
+
+    let len = match header[1] & 0x7F {
+        126 => u64::from(reader.read_u16().await?),
+        127 => reader.read_u64().await?,
+        len => u64::from(len),
+    };
+    if len > MAX_PAYLOAD {
+        return Err(invalid(format!(
+            "websocket frame of {} bytes is too large",
+            len
+        )));
+    }
+
+    let mut mask = [0u8; 4];
+    if masked {
+        reader.read_exact(&mut mask).await?;
+    }
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload).await?;
+    if masked {
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+    }
+
+    Ok(Frame { opcode, payload })
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_frame() {
+        let frame = encode_frame(Opcode::Binary, b"ab", [1, 2, 3, 4]);
+        assert_eq!(frame, vec![0x82, 0x82, 1, 2, 3, 4, b'a' ^ 1, b'b' ^ 2]);
+
+        let frame = encode_frame(Opcode::Binary, &[0; 300], [0; 4]);
+        assert_eq!(&frame[..4], &[0x82, 0xFE, 0x01, 0x2C]);
+        assert_eq!(frame.len(), 4 + 4 + 300);
+    }
+
+    #[tokio::test]
+    async fn test_read_frame_roundtrip() {
+        let mut bytes = encode_frame(Opcode::Text, b"OK", [9, 8, 7, 6]);
+        // Servers send unmasked frames.
+        bytes.extend_from_slice(&[0x89, 0x00]);
+
+        let mut reader = bytes.as_slice();
+        let frame = read_frame(&mut reader).await.unwrap();
+        assert_eq!(frame.opcode, Opcode::Text);
+        assert_eq!(frame.payload, b"OK");
+        let frame = read_frame(&mut reader).await.unwrap();
+        assert_eq!(frame.opcode, Opcode::Ping);
+        assert!(frame.payload.is_empty());
+        assert!(read_frame(&mut reader).await.is_err());
+    }
+}
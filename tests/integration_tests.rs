@@ -86,7 +86,60 @@ fn test_list_vms_help() {
         .arg("--help")
         .assert()
         .success()
-        .stdout(predicate::str::contains("--node"));
+        .stdout(predicate::str::contains("--node"))
+        .stdout(predicate::str::contains("--columns"))
+        .stdout(predicate::str::contains("--sort"));
+}
+
+#[test]
+fn test_list_vms_invalid_column() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .arg("list-vms")
+        .arg("--columns")
+        .arg("vmid,swap")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid value 'swap'"));
+}
+
+#[test]
+fn test_create_requires_vm_or_pool() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .arg("create")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("<VM>"));
+
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.arg("create")
+        .arg("--help")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("--pool"))
+        .stdout(predicate::str::contains("--tag"));
+}
+
+#[test]
+fn test_list_tag_help() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.arg("list")
+        .arg("--help")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("--tag"));
+}
+
+#[test]
+fn test_rollback_help() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.arg("rollback")
+        .arg("--help")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("--start"))
+        .stdout(predicate::str::contains("--yes"));
 }
 
 #[test]
@@ -110,6 +163,47 @@ fn test_all_subcommands_help() {
         "test",
         "list-vms",
         "list-nodes",
+        "metrics",
+        "prune",
+        "audit",
+        "report",
+        "daemon",
+        "schedule",
+        "verify",
+        "export",
+        "import",
+        "snapshot",
+        "current",
+        "start",
+        "stop",
+        "shutdown",
+        "reboot",
+        "suspend",
+        "resume",
+        "hibernate",
+        "migrate",
+        "evacuate",
+        "clone",
+        "template",
+        "provision",
+        "destroy",
+        "config",
+        "pending",
+        "disk",
+        "tag",
+        "notes",
+        "ip",
+        "agent",
+        "console",
+        "firewall",
+        "set-resources",
+        "cloudinit",
+        "unlock",
+        "wait-status",
+        "bulk",
+        "lxc",
+        "node",
+        "list-storage",
     ];
 
     for subcommand in subcommands {
@@ -163,3 +257,464 @@ fn test_required_arguments() {
         .assert()
         .failure();
 }
+
+#[test]
+fn test_output_format_flag() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.arg("list-vms")
+        .arg("--help")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("--output"));
+
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .arg("--output")
+        .arg("xml")
+        .arg("list-vms")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid value"));
+}
+
+#[test]
+fn test_structured_error_output() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["-H", "127.0.0.1", "-p", "1", "--output", "json", "list-vms"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(r#""code":"connection""#))
+        .stderr(predicate::str::contains(
+            r#""endpoint":"/cluster/resources?type=vm""#,
+        ));
+}
+
+#[test]
+fn test_prune_requires_policy() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["-H", "127.0.0.1", "-p", "1", "prune", "100"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No retention options given"));
+}
+
+#[test]
+fn test_prune_apply_config_conflicts_with_flags() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["prune", "--apply-config", "--keep-last", "3"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn test_delete_invalid_regex() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args([
+            "-H",
+            "127.0.0.1",
+            "-p",
+            "1",
+            "delete",
+            "100",
+            "(",
+            "--regex",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid regular expression"));
+}
+
+#[test]
+fn test_create_invalid_snapname() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args([
+            "-H",
+            "127.0.0.1",
+            "-p",
+            "1",
+            "create",
+            "100",
+            "-s",
+            "pre upgrade",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid snapshot name"));
+}
+
+#[test]
+fn test_create_replace_conflicts_with_if_not_exists() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["create", "100", "--replace", "--if-not-exists"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn test_audit_requires_limits() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["-H", "127.0.0.1", "-p", "1", "audit"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No audit limits given"));
+}
+
+#[test]
+fn test_freeze_timeout_requires_fsfreeze() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["create", "100", "--freeze-timeout", "1m"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--fsfreeze"));
+}
+
+#[test]
+fn test_check_nagios_unknown_on_error() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["-H", "127.0.0.1", "-p", "1"])
+        .args(["check", "100", "--format", "nagios", "--warn-age", "2d"])
+        .assert()
+        .code(3)
+        .stdout(predicate::str::starts_with("UNKNOWN - "));
+}
+
+#[test]
+fn test_daemon_invalid_schedule() {
+    let mut config_file = NamedTempFile::new().unwrap();
+    writeln!(
+        config_file,
+        r#"
+[[schedules]]
+name = "nightly"
+schedule = "0 25 * * *"
+pool = "production"
+"#
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["-H", "127.0.0.1", "-p", "1"])
+        .arg("--config")
+        .arg(config_file.path())
+        .arg("daemon")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Invalid schedule '0 25 * * *' for 'nightly'",
+        ));
+}
+
+#[test]
+fn test_schedule_install_systemd() {
+    let mut config_file = NamedTempFile::new().unwrap();
+    writeln!(
+        config_file,
+        r#"
+[[schedules]]
+name = "nightly"
+schedule = "0 2 * * *"
+pool = "production"
+retention = {{ keep_daily = 7 }}
+"#
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.arg("--config")
+        .arg(config_file.path())
+        .args(["schedule", "install-systemd"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("# pve-tool-nightly.timer"))
+        .stdout(predicate::str::contains("OnCalendar=*-*-* 02:00:00"))
+        .stdout(predicate::str::contains(
+            "prune --pool production --keep-daily 7",
+        ));
+}
+
+#[test]
+fn test_export_requires_selection() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["-H", "127.0.0.1", "-p", "1", "export"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("<VM>"));
+
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["-H", "127.0.0.1", "-p", "1", "export", "100", "--all"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn test_snapshot_edit_requires_description() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["snapshot", "edit", "100", "pre-upgrade"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--description"));
+}
+
+#[test]
+fn test_snapshot_diff_help() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.args(["snapshot", "diff", "--help"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Snapshot to compare from"));
+}
+
+#[test]
+fn test_delete_all_conflicts_with_snapname() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["delete", "100", "pre-upgrade", "--all"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn test_multiple_clusters_limited_to_queries() {
+    let mut config_file = NamedTempFile::new().unwrap();
+    writeln!(
+        config_file,
+        r#"
+[clusters.prod]
+hosts = ["127.0.0.1:1"]
+token = "test-token"
+
+[clusters.dr]
+hosts = ["127.0.0.1:1"]
+token = "test-token"
+"#
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.arg("--config")
+        .arg(config_file.path())
+        .args(["--cluster", "prod,dr", "create", "100"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Only list-vms, audit and report can run on several clusters",
+        ));
+
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.arg("--config")
+        .arg(config_file.path())
+        .args(["--all-clusters", "list-vms"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cluster dr:"))
+        .stderr(predicate::str::contains("cluster prod:"))
+        .stderr(predicate::str::contains(
+            "Command failed on 2 of 2 clusters",
+        ));
+}
+
+#[test]
+fn test_create_stdin_requires_vms() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args([
+            "-H",
+            "127.0.0.1",
+            "-p",
+            "1",
+            "create",
+            "--stdin",
+            "-s",
+            "maint",
+        ])
+        .write_stdin("# nothing to do\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No VMs read from stdin"));
+}
+
+#[test]
+fn test_force_stop_requires_timeout() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["shutdown", "100", "--force-stop"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--timeout"));
+}
+
+#[test]
+fn test_evacuate_requires_target_or_balance() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["evacuate", "pve1"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--target"));
+}
+
+#[test]
+fn test_maintenance_target_requires_evacuate() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["node", "maintenance", "enter", "pve1", "--target", "pve2"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--evacuate"));
+}
+
+#[test]
+fn test_prune_rejects_keep_zero() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["prune", "100", "--keep-last", "0"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("0 is not in 1.."));
+}
+
+#[test]
+fn test_type_requires_pool_or_tag() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["stop", "--type", "qemu"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--pool <POOL>|--tag <TAG>"));
+}
+
+#[test]
+fn test_backup_rejects_unknown_mode() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["backup", "run", "100", "--mode", "live"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "possible values: snapshot, suspend, stop",
+        ));
+}
+
+#[test]
+fn test_warn_pct_is_a_percentage() {
+    for command in [&["report", "storage"][..], &["health"][..]] {
+        let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+        cmd.env("PROXMOX_API_TOKEN", "test-token")
+            .args(command)
+            .args(["--warn-pct", "150"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("not a percentage from 0 to 100"));
+    }
+}
+
+#[test]
+fn test_list_tree_needs_table_output() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["--output", "json", "list", "100", "--tree"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "--tree only works with the table output",
+        ));
+}
+
+#[test]
+fn test_list_vms_rejects_unknown_status() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["list-vms", "--status", "runing"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "possible values: running, stopped, paused",
+        ));
+}
+
+#[test]
+fn test_clone_full_conflicts_with_linked() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["clone", "9000", "--newid", "120", "--full", "--linked"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn test_config_set_rejects_bare_key() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["config", "set", "100", "memory"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Expected key=value"));
+}
+
+#[test]
+fn test_notes_options_conflict() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["notes", "100", "--set", "a", "--append", "b"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn test_agent_exec_requires_command() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["agent", "exec", "100"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("COMMAND"));
+}
+
+#[test]
+fn test_agent_file_write_requires_content() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["agent", "file-write", "100", "/tmp/marker"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--content"));
+}
+
+#[test]
+fn test_console_listen_only_with_vnc() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["console", "100", "--term", "--listen", "5900"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn test_console_output_file_only_with_spice() {
+    let mut cmd = Command::cargo_bin("pve-tool").unwrap();
+    cmd.env("PROXMOX_API_TOKEN", "test-token")
+        .args(["console", "100", "--vnc", "-o", "vm.vv"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}